@@ -0,0 +1,58 @@
+//! A minimal frontend built on the public API, drawing with `pixels` and `winit`.
+//!
+//! ```sh
+//! cargo run --example frontend -- roms/hello.gb
+//! ```
+
+use anyhow::Result;
+use gbemu::emulator::Emulator;
+use gbemu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use pixels::{Pixels, SurfaceTexture};
+use winit::dpi::LogicalSize;
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::WindowBuilder;
+
+fn main() -> Result<()> {
+    let filename = match std::env::args().nth(1) {
+        Some(filename) => filename,
+        None => anyhow::bail!("usage: frontend <rom>"),
+    };
+
+    let mut emu = Emulator::from_rom_byte(std::fs::read(filename)?)?;
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("gbemu example")
+        .with_inner_size(LogicalSize::new(
+            SCREEN_WIDTH as f64 * 2.0,
+            SCREEN_HEIGHT as f64 * 2.0,
+        ))
+        .build(&event_loop)?;
+
+    let mut pixels = {
+        let size = window.inner_size();
+        let surface_texture = SurfaceTexture::new(size.width, size.height, &window);
+        Pixels::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, surface_texture)?
+    };
+
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent {
+            event: WindowEvent::CloseRequested,
+            ..
+        } => *control_flow = ControlFlow::Exit,
+        Event::MainEventsCleared => {
+            emu.run_frame().unwrap();
+            window.request_redraw();
+        }
+        Event::RedrawRequested(_) => {
+            // RGBA8888 by default, as pixels expects
+            pixels.get_frame().copy_from_slice(&emu.framebuffer());
+
+            if pixels.render().is_err() {
+                *control_flow = ControlFlow::Exit;
+            }
+        }
+        _ => {}
+    });
+}
@@ -0,0 +1,30 @@
+//! Run a ROM without a window and save the last frame as a PNG image.
+//!
+//! ```sh
+//! cargo run --example headless -- roms/hello.gb 60 screenshot.png
+//! ```
+
+use anyhow::Result;
+use gbemu::emulator::Emulator;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let filename = match args.first() {
+        Some(filename) => filename,
+        None => anyhow::bail!("usage: headless <rom> [frames] [output]"),
+    };
+    let frames: usize = args.get(1).map(|s| s.parse()).transpose()?.unwrap_or(60);
+    let output = args.get(2).map(String::as_str).unwrap_or("screenshot.png");
+
+    let mut emu = Emulator::from_rom_byte(std::fs::read(filename)?)?;
+
+    for _ in 0..frames {
+        emu.run_frame()?;
+    }
+
+    emu.screenshot(output)?;
+
+    println!("saved frame {} to {}", frames, output);
+
+    Ok(())
+}
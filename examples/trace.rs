@@ -0,0 +1,47 @@
+//! Print the CPU registers before every executed instruction.
+//!
+//! ```sh
+//! cargo run --example trace -- roms/hello.gb 1000
+//! cargo run --example trace -- --trace-format bgb roms/hello.gb 1000
+//! ```
+//!
+//! `--trace-format` is one of `doctor` (default), `bgb` or `sameboy`, to diff
+//! against a log from that emulator.
+
+use anyhow::Result;
+use gbemu::emulator::Emulator;
+use gbemu::trace::TraceFormat;
+
+fn main() -> Result<()> {
+    let mut format = TraceFormat::Doctor;
+    let mut args = Vec::new();
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--trace-format" => match iter.next() {
+                Some(value) => format = value.parse()?,
+                None => anyhow::bail!("--trace-format requires a value"),
+            },
+            _ => args.push(arg),
+        }
+    }
+
+    let filename = match args.first() {
+        Some(filename) => filename,
+        None => {
+            anyhow::bail!("usage: trace [--trace-format doctor|bgb|sameboy] <rom> [instructions]")
+        }
+    };
+    let count: usize = args.get(1).map(|s| s.parse()).transpose()?.unwrap_or(1000);
+
+    let mut emu = Emulator::from_rom_byte(std::fs::read(filename)?)?;
+
+    for _ in 0..count {
+        println!("{}", emu.trace_line(format));
+
+        emu.step()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,34 @@
+//! Random reads and writes over the whole address space. Every access must be
+//! handled without panicking or indexing out of bounds.
+//!
+//! Input is a stream of 3 byte commands: address (little endian) and a value.
+//! Odd values are written, even values trigger a read.
+
+#![no_main]
+use gbemu::bus::{Bus, MemoryBus};
+use gbemu::cartridge::Cartridge;
+use gbemu::gpu::Gpu;
+use gbemu::ram::{BankedRam, Ram};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut bus = Bus::new(
+        Cartridge::new(vec![0; 0x8000]).unwrap(),
+        BankedRam::new(0x2000, 2),
+        Ram::with_size(0x7F),
+        Ram::with_size(0xA0),
+        BankedRam::new(0x1000, 8),
+        Gpu::new(),
+    );
+
+    for command in data.chunks_exact(3) {
+        let address = u16::from_le_bytes([command[0], command[1]]);
+        let value = command[2];
+
+        if value & 1 == 1 {
+            bus.write_byte(address, value);
+        } else {
+            bus.read_byte(address);
+        }
+    }
+});
@@ -0,0 +1,24 @@
+//! Run arbitrary bytes as a ROM. The core must never panic, whatever the
+//! cartridge contains.
+
+#![no_main]
+use gbemu::emulator::Emulator;
+use libfuzzer_sys::fuzz_target;
+
+const MAX_STEPS: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    // Images without a header are rejected up front
+    let mut emu = match Emulator::from_rom_byte(data.to_vec()) {
+        Ok(emu) => emu,
+        Err(_) => return,
+    };
+    // Unimplemented opcodes are reported through telemetry, not panics
+    emu.set_skip_unimplemented(true);
+
+    for _ in 0..MAX_STEPS {
+        if emu.step().is_err() {
+            break;
+        }
+    }
+});
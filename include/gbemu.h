@@ -0,0 +1,95 @@
+#ifndef GBEMU_H
+#define GBEMU_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define GBEMU_SCREEN_WIDTH 160
+
+#define GBEMU_SCREEN_HEIGHT 144
+
+#define GBEMU_BUTTON_RIGHT 0
+
+#define GBEMU_BUTTON_LEFT 1
+
+#define GBEMU_BUTTON_UP 2
+
+#define GBEMU_BUTTON_DOWN 3
+
+#define GBEMU_BUTTON_A 4
+
+#define GBEMU_BUTTON_B 5
+
+#define GBEMU_BUTTON_SELECT 6
+
+#define GBEMU_BUTTON_START 7
+
+/**
+ * An emulator, without a cartridge until [`gbemu_load_rom`].
+ */
+typedef struct Gbemu Gbemu;
+
+/**
+ * Create an emulator. Free it with [`gbemu_destroy`].
+ */
+struct Gbemu *gbemu_create(void);
+
+/**
+ * Free an emulator created by [`gbemu_create`]. Does nothing for `NULL`.
+ */
+void gbemu_destroy(struct Gbemu *gbemu);
+
+/**
+ * Power on with the ROM image `rom` of `len` bytes, which is copied. On
+ * error the running cartridge, if any, keeps running.
+ *
+ * # Safety
+ *
+ * `rom` must point to `len` readable bytes.
+ */
+bool gbemu_load_rom(struct Gbemu *gbemu, const uint8_t *rom, size_t len);
+
+/**
+ * Run until the PPU completes a frame. A panic of the core is reported as
+ * an error, the emulator has to load a ROM again after one.
+ */
+bool gbemu_run_frame(struct Gbemu *gbemu);
+
+/**
+ * The screen as RGBA8888, [`GBEMU_SCREEN_WIDTH`] x [`GBEMU_SCREEN_HEIGHT`]
+ * pixels row by row. White until a ROM is loaded.
+ */
+const uint8_t *gbemu_framebuffer(const struct Gbemu *gbemu);
+
+/**
+ * Press or release `button`, one of the `GBEMU_BUTTON_*` constants.
+ */
+bool gbemu_set_button(struct Gbemu *gbemu, uint32_t button, bool pressed);
+
+/**
+ * Save the state of the machine, see [`Emulator::save_state`]. Returns the
+ * state and stores its length in `len`, `NULL` without a ROM.
+ */
+const uint8_t *gbemu_save_state(struct Gbemu *gbemu, size_t *len);
+
+/**
+ * Restore a state of `len` bytes saved by [`gbemu_save_state`]. On error
+ * the emulator is left as it was.
+ *
+ * # Safety
+ *
+ * `state` must point to `len` readable bytes.
+ */
+bool gbemu_load_state(struct Gbemu *gbemu, const uint8_t *state, size_t len);
+
+/**
+ * Message of the last error, empty before the first one.
+ */
+const char *gbemu_last_error(const struct Gbemu *gbemu);
+
+#endif  /* GBEMU_H */
@@ -0,0 +1,129 @@
+//! Python bindings of the core, e.g. for game-playing AI research:
+//!```text
+//! import numpy as np
+//! from gbemu import Gbemu
+//!
+//! gb = Gbemu(open("game.gb", "rb").read())
+//! gb.press("start")
+//! screen = np.asarray(gb.step_frame())  # (144, 160, 4) RGBA uint8
+//! gb.release("start")
+//! lives = gb.peek(0xC0A5)
+//! gb.poke(0xC0A5, 9)
+//! ```
+//! Build and install into the active environment with `maturin develop
+//! --release` in this directory. numpy is not a dependency, frames only
+//! implement its array interface.
+
+use gbemu::emulator::Emulator;
+use gbemu::joypad::Button;
+use gbemu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+/// A Game Boy running a ROM image. Runs are deterministic: the same input
+/// gives the same frames, the RTC follows the emulated time and battery
+/// saves are not kept.
+#[pyclass(unsendable)]
+struct Gbemu {
+    emulator: Emulator,
+}
+
+#[pymethods]
+impl Gbemu {
+    #[new]
+    fn new(rom: Vec<u8>) -> PyResult<Gbemu> {
+        let mut emulator =
+            Emulator::from_rom_byte(rom).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        emulator.set_deterministic(true);
+
+        Ok(Gbemu { emulator })
+    }
+
+    /// Run until the PPU completes a frame and return the screen.
+    fn step_frame(&mut self, py: Python) -> PyResult<Frame> {
+        self.emulator
+            .try_run_frame()
+            .map_err(|report| PyRuntimeError::new_err(report.to_string()))?;
+
+        Ok(Frame {
+            rgba: PyBytes::new(py, &self.emulator.framebuffer()).unbind(),
+        })
+    }
+
+    /// Hold `button` (right, left, up, down, a, b, select or start) until
+    /// it is released.
+    fn press(&mut self, button: &str) -> PyResult<()> {
+        self.emulator.set_button_state(parse_button(button)?, true);
+        Ok(())
+    }
+
+    fn release(&mut self, button: &str) -> PyResult<()> {
+        self.emulator.set_button_state(parse_button(button)?, false);
+        Ok(())
+    }
+
+    /// Byte at `address`, read without side effects.
+    fn peek(&self, address: u16) -> u8 {
+        self.emulator.peek(address)
+    }
+
+    /// Patch the byte at `address`, ROM included.
+    fn poke(&mut self, address: u16, value: u8) {
+        self.emulator.poke(address, value)
+    }
+
+    /// Frames completed since power on.
+    #[getter]
+    fn frame_count(&self) -> usize {
+        self.emulator.last_frame().number
+    }
+}
+
+/// The screen as RGBA8888. `np.asarray(frame)` is a (144, 160, 4) uint8
+/// array, `bytes(frame)` the pixels row by row.
+#[pyclass(frozen)]
+struct Frame {
+    rgba: Py<PyBytes>,
+}
+
+#[pymethods]
+impl Frame {
+    /// numpy array interface, version 3
+    #[getter]
+    fn __array_interface__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let interface = PyDict::new(py);
+        interface.set_item("shape", (SCREEN_HEIGHT, SCREEN_WIDTH, 4))?;
+        interface.set_item("typestr", "|u1")?;
+        interface.set_item("data", self.rgba.clone_ref(py))?;
+        interface.set_item("version", 3)?;
+
+        Ok(interface)
+    }
+
+    fn __bytes__(&self, py: Python) -> Py<PyBytes> {
+        self.rgba.clone_ref(py)
+    }
+}
+
+fn parse_button(name: &str) -> PyResult<Button> {
+    Ok(match name {
+        "right" => Button::Right,
+        "left" => Button::Left,
+        "up" => Button::Up,
+        "down" => Button::Down,
+        "a" => Button::A,
+        "b" => Button::B,
+        "select" => Button::Select,
+        "start" => Button::Start,
+        _ => return Err(PyValueError::new_err(format!("unknown button {}", name))),
+    })
+}
+
+#[pymodule]
+#[pyo3(name = "gbemu")]
+fn gbemu_py(module: &Bound<PyModule>) -> PyResult<()> {
+    module.add_class::<Gbemu>()?;
+    module.add_class::<Frame>()?;
+    Ok(())
+}
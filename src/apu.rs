@@ -0,0 +1,516 @@
+//! Audio processing unit: two square channels, a wave channel and a noise
+//! channel, mixed into stereo samples.
+//! Ref https://gbdev.io/pandocs/Audio.html
+//! Ref https://gbdev.io/pandocs/Audio_Registers.html
+
+mod noise;
+mod square;
+mod wave;
+
+use crate::state::{StateReader, StateWriter};
+use crate::wav::WavWriter;
+use crate::{HalfWord, Word};
+use anyhow::Result;
+use noise::Noise;
+use square::Square;
+use wave::Wave;
+
+/// Dot clock the channels run on (4.19 MHz, also in CGB double speed mode).
+pub const CLOCK_RATE: usize = 4_194_304;
+/// Stereo samples generated per second.
+pub const SAMPLE_RATE: usize = 48_000;
+// Samples kept until they are taken, older ones are dropped
+const MAX_BUFFERED_SAMPLES: usize = SAMPLE_RATE;
+// Samples per call to the audio sink, about 10 ms
+const SINK_BATCH: usize = 512;
+// Share of the high-pass filter capacitor charge kept per dot
+const DMG_CHARGE_FACTOR: f32 = 0.999958;
+const CGB_CHARGE_FACTOR: f32 = 0.998943;
+
+/// Receives the audio as it is generated, for frontends and tools that
+/// don't poll [`Apu::take_samples`]. The left and right channels have the
+/// same length, at [`SAMPLE_RATE`] and from -1.0 to 1.0.
+pub trait AudioSink {
+    fn push_samples(&mut self, left: &[f32], right: &[f32]);
+}
+
+impl<F: FnMut(&[f32], &[f32])> AudioSink for F {
+    fn push_samples(&mut self, left: &[f32], right: &[f32]) {
+        self(left, right)
+    }
+}
+
+/// Sound registers
+///```text
+/// FF10-FF14  NR10-NR14  Channel 1, square with sweep
+/// FF16-FF19  NR21-NR24  Channel 2, square
+/// FF1A-FF1E  NR30-NR34  Channel 3, wave
+/// FF20-FF23  NR41-NR44  Channel 4, noise
+/// FF24       NR50       Bit 6-4: left volume, Bit 2-0: right volume
+/// FF25       NR51       Bit 7-4: channels 4-1 on the left,
+///                       Bit 3-0: channels 4-1 on the right
+/// FF26       NR52       Bit 7: power, Bit 3-0: channels 4-1 on (read only)
+/// FF30-FF3F  Wave RAM
+/// ```
+pub struct Apu {
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    nr50: HalfWord,
+    nr51: HalfWord,
+    power: bool,
+    // Step of the 512 Hz frame sequencer, 0-7
+    sequencer_step: usize,
+    // Last level of the DIV bit that clocks the frame sequencer
+    div_bit: bool,
+    // Dots times SAMPLE_RATE since the last sample
+    sample_counter: usize,
+    // Dots times SAMPLE_RATE per sample, CLOCK_RATE at normal speed. None
+    // mutes the audio.
+    sample_period: Option<usize>,
+    // Charge of the output capacitors, left and right
+    capacitors: [f32; 2],
+    // Share of the charge kept per sample
+    charge_factor: f32,
+    samples: Vec<[f32; 2]>,
+    sink: Option<Box<dyn AudioSink + Send>>,
+    // Samples not passed to the sink yet
+    left: Vec<f32>,
+    right: Vec<f32>,
+    // Gets every sample as well, whether or not there is a sink
+    capture: Option<WavWriter>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Apu {
+        Apu {
+            square1: Square::new(),
+            square2: Square::new(),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            nr50: 0,
+            nr51: 0,
+            power: false,
+            sequencer_step: 0,
+            div_bit: false,
+            sample_counter: 0,
+            sample_period: Some(CLOCK_RATE),
+            capacitors: [0.0; 2],
+            charge_factor: charge_factor(DMG_CHARGE_FACTOR),
+            samples: Vec::new(),
+            sink: None,
+            left: Vec::with_capacity(SINK_BATCH),
+            right: Vec::with_capacity(SINK_BATCH),
+            capture: None,
+        }
+    }
+
+    /// CGB wave RAM can be accessed while channel 3 plays, and the output
+    /// capacitors charge faster.
+    pub fn set_cgb(&mut self, cgb: bool) {
+        self.wave.set_cgb(cgb);
+        self.charge_factor = charge_factor(if cgb {
+            CGB_CHARGE_FACTOR
+        } else {
+            DMG_CHARGE_FACTOR
+        });
+    }
+
+    /// Advance by `dots` dot clocks, generating samples at [`SAMPLE_RATE`].
+    pub fn step(&mut self, dots: usize) {
+        if self.power {
+            self.square1.step(dots);
+            self.square2.step(dots);
+            self.wave.step(dots);
+            self.noise.step(dots);
+        }
+
+        let sample_period = match self.sample_period {
+            Some(sample_period) => sample_period,
+            None => return,
+        };
+
+        self.sample_counter += dots * SAMPLE_RATE;
+        while self.sample_counter >= sample_period {
+            self.sample_counter -= sample_period;
+
+            let sample = self.high_pass(self.mix());
+            if let Some(capture) = self.capture.as_mut() {
+                capture.write_sample(sample);
+            }
+            match self.sink.as_mut() {
+                Some(sink) => {
+                    self.left.push(sample[0]);
+                    self.right.push(sample[1]);
+                    if self.left.len() == SINK_BATCH {
+                        sink.push_samples(&self.left, &self.right);
+                        self.left.clear();
+                        self.right.clear();
+                    }
+                }
+                None if self.samples.len() < MAX_BUFFERED_SAMPLES => self.samples.push(sample),
+                None => {}
+            }
+        }
+    }
+
+    /// Generate the samples for `speed` times the emulated time, so the
+    /// audio keeps up with fast-forward at a higher pitch. An infinite speed
+    /// mutes the audio, slow speeds generate at most one sample per dot.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.sample_counter = 0;
+        self.sample_period = if speed.is_finite() {
+            Some(((CLOCK_RATE as f64 * speed).round() as usize).max(SAMPLE_RATE))
+        } else {
+            None
+        };
+    }
+
+    /// Pass the samples to `sink` as they are generated instead of
+    /// buffering them for [`Apu::take_samples`]. Replaces the previous sink.
+    pub fn set_audio_sink(&mut self, sink: Option<Box<dyn AudioSink + Send>>) {
+        self.sink = sink;
+        self.left.clear();
+        self.right.clear();
+    }
+
+    pub fn take_audio_sink(&mut self) -> Option<Box<dyn AudioSink + Send>> {
+        self.sink.take()
+    }
+
+    /// Also write every sample to `capture`. Returns the previous one.
+    pub fn set_capture(&mut self, capture: Option<WavWriter>) -> Option<WavWriter> {
+        std::mem::replace(&mut self.capture, capture)
+    }
+
+    /// The frame sequencer steps on the falling edge of DIV bit 4 (bit 5 in
+    /// double speed mode), at 512 Hz. Length counters are clocked on even
+    /// steps, the sweep on steps 2 and 6, envelopes on step 7.
+    pub fn clock_frame_sequencer(&mut self, div_bit: bool) {
+        let falling_edge = self.div_bit && !div_bit;
+        self.div_bit = div_bit;
+        if !falling_edge || !self.power {
+            return;
+        }
+
+        match self.sequencer_step {
+            0 | 2 | 4 | 6 => {
+                self.square1.clock_length();
+                self.square2.clock_length();
+                self.wave.clock_length();
+                self.noise.clock_length();
+                if self.sequencer_step == 2 || self.sequencer_step == 6 {
+                    self.square1.clock_sweep();
+                }
+            }
+            7 => {
+                self.square1.clock_envelope();
+                self.square2.clock_envelope();
+                self.noise.clock_envelope();
+            }
+            _ => {}
+        }
+        self.sequencer_step = (self.sequencer_step + 1) % 8;
+    }
+
+    /// Take the stereo samples generated since the last call, from -1.0 to
+    /// 1.0.
+    pub fn take_samples(&mut self) -> Vec<[f32; 2]> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// The registers, channels and filter. Samples not taken yet are not
+    /// part of it.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        self.square1.save_state(state);
+        self.square2.save_state(state);
+        self.wave.save_state(state);
+        self.noise.save_state(state);
+        state.u8(self.nr50);
+        state.u8(self.nr51);
+        state.bool(self.power);
+        state.usize(self.sequencer_step);
+        state.bool(self.div_bit);
+        state.usize(self.sample_counter);
+        state.f32(self.capacitors[0]);
+        state.f32(self.capacitors[1]);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.square1.load_state(state)?;
+        self.square2.load_state(state)?;
+        self.wave.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.nr50 = state.u8()?;
+        self.nr51 = state.u8()?;
+        self.power = state.bool()?;
+        self.sequencer_step = state.usize()? % 8;
+        self.div_bit = state.bool()?;
+        self.sample_counter = state.usize()?;
+        self.capacitors = [state.f32()?, state.f32()?];
+
+        Ok(())
+    }
+
+    /// `address` relative to NR10 (0xFF10).
+    pub fn read(&self, address: Word) -> HalfWord {
+        match address {
+            0x00..=0x04 => self.square1.read(address),
+            0x05..=0x09 => self.square2.read(address - 0x05),
+            0x0A..=0x0E => self.wave.read(address - 0x0A),
+            0x0F..=0x13 => self.noise.read(address - 0x0F),
+            0x14 => self.nr50,
+            0x15 => self.nr51,
+            0x16 => {
+                (self.power as HalfWord) << 7
+                    | (self.noise.enabled() as HalfWord) << 3
+                    | (self.wave.enabled() as HalfWord) << 2
+                    | (self.square2.enabled() as HalfWord) << 1
+                    | self.square1.enabled() as HalfWord
+            }
+            0x20..=0x2F => self.wave.read_ram(address - 0x20),
+            _ => 0xFF,
+        }
+    }
+
+    /// Digital outputs (0-15) of the channels, read only, on CGB
+    ///```text
+    /// FF76  PCM12  Bit 7-4: channel 2, Bit 3-0: channel 1
+    /// FF77  PCM34  Bit 7-4: channel 4, Bit 3-0: channel 3
+    /// ```
+    /// `address` relative to PCM12.
+    /// Ref https://gbdev.io/pandocs/Audio_Registers.html
+    pub fn read_pcm(&self, address: Word) -> HalfWord {
+        match address {
+            0 => self.square2.output() << 4 | self.square1.output(),
+            _ => self.noise.output() << 4 | self.wave.output(),
+        }
+    }
+
+    /// `address` relative to NR10 (0xFF10).
+    pub fn write(&mut self, address: Word, byte: HalfWord) {
+        match address {
+            0x16 => self.set_power(byte & 0x80 == 0x80),
+            // Wave RAM is still accessible while powered off
+            0x20..=0x2F => self.wave.write_ram(address - 0x20, byte),
+            // The other registers ignore writes while powered off
+            _ if !self.power => {}
+            0x00..=0x04 => self.square1.write(address, byte),
+            // Channel 2 has no sweep register
+            0x05 => {}
+            0x06..=0x09 => self.square2.write(address - 0x05, byte),
+            0x0A..=0x0E => self.wave.write(address - 0x0A, byte),
+            // Nor does channel 4 have a register before its length
+            0x0F => {}
+            0x10..=0x13 => self.noise.write(address - 0x0F, byte),
+            0x14 => self.nr50 = byte,
+            0x15 => self.nr51 = byte,
+            _ => {}
+        }
+    }
+
+    /// Powering off clears every register but wave RAM.
+    fn set_power(&mut self, power: bool) {
+        if power == self.power {
+            return;
+        }
+
+        if !power {
+            self.square1 = Square::new();
+            self.square2 = Square::new();
+            self.wave.power_off();
+            self.noise = Noise::new();
+            self.nr50 = 0;
+            self.nr51 = 0;
+        } else {
+            self.sequencer_step = 0;
+        }
+        self.power = power;
+    }
+
+    /// Mix the channels by NR51 and scale by the NR50 volumes.
+    fn mix(&self) -> [f32; 2] {
+        let outputs = [
+            dac(self.square1.output(), self.square1.dac_enabled()),
+            dac(self.square2.output(), self.square2.dac_enabled()),
+            dac(self.wave.output(), self.wave.dac_enabled()),
+            dac(self.noise.output(), self.noise.dac_enabled()),
+        ];
+
+        let (mut left, mut right) = (0.0, 0.0);
+        for (channel, output) in outputs.iter().enumerate() {
+            if self.nr51 & (0x10 << channel) != 0 {
+                left += output;
+            }
+            if self.nr51 & (0x01 << channel) != 0 {
+                right += output;
+            }
+        }
+
+        let left_volume = ((self.nr50 >> 4 & 0x07) + 1) as f32 / 8.0;
+        let right_volume = ((self.nr50 & 0x07) + 1) as f32 / 8.0;
+        [left / 4.0 * left_volume, right / 4.0 * right_volume]
+    }
+
+    /// Remove the DC offset like the capacitors on the analog output: they
+    /// charge towards the input level, and the output is the difference.
+    /// A DAC turning on or off pops and then fades out. With every DAC off
+    /// the capacitors hold their charge and the output is silent.
+    /// Ref https://gbdev.io/pandocs/Audio_details.html#obscure-behavior
+    fn high_pass(&mut self, sample: [f32; 2]) -> [f32; 2] {
+        let dacs_enabled = self.square1.dac_enabled()
+            || self.square2.dac_enabled()
+            || self.wave.dac_enabled()
+            || self.noise.dac_enabled();
+        if !dacs_enabled {
+            return [0.0; 2];
+        }
+
+        let mut output = [0.0; 2];
+        for (side, level) in sample.iter().enumerate() {
+            output[side] = level - self.capacitors[side];
+            self.capacitors[side] = level - output[side] * self.charge_factor;
+        }
+        output
+    }
+}
+
+/// Share of the capacitor charge kept per sample, from the share kept per dot.
+fn charge_factor(per_dot: f32) -> f32 {
+    per_dot.powf(CLOCK_RATE as f32 / SAMPLE_RATE as f32)
+}
+
+/// Convert a digital channel output (0-15) to an analog level from 1.0 to
+/// -1.0. A DAC that is off outputs 0.0.
+fn dac(output: HalfWord, enabled: bool) -> f32 {
+    if !enabled {
+        return 0.0;
+    }
+    1.0 - output as f32 / 7.5
+}
+
+/// Turns a channel off once it has played for the time set by its length
+/// register, if enabled by bit 6 of NRx4.
+#[derive(Debug)]
+struct Length {
+    max: usize,
+    counter: usize,
+    enabled: bool,
+}
+
+impl Length {
+    fn new(max: usize) -> Length {
+        Length {
+            max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    /// The channel plays for `max - value` steps of 256 Hz.
+    fn load(&mut self, value: HalfWord) {
+        self.counter = self.max - value as usize;
+    }
+
+    /// Triggering a channel with an expired length plays it for the longest time.
+    fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    /// Returns whether the length ran out, which turns the channel off.
+    fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        self.counter == 0
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.usize(self.counter);
+        state.bool(self.enabled);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.counter = state.usize()?.min(self.max);
+        self.enabled = state.bool()?;
+
+        Ok(())
+    }
+}
+
+/// Volume envelope of NRx2
+///```text
+/// Bit 7-4: initial volume
+/// Bit 3:   1 = increase, 0 = decrease
+/// Bit 2-0: steps of 64 Hz between volume changes, 0 = no change
+/// ```
+/// Bits 7-3 all clear turn the DAC of the channel off.
+#[derive(Debug, Default)]
+struct Envelope {
+    register: HalfWord,
+    volume: HalfWord,
+    // Steps left before the next volume change
+    timer: HalfWord,
+}
+
+impl Envelope {
+    fn read(&self) -> HalfWord {
+        self.register
+    }
+
+    fn write(&mut self, byte: HalfWord) {
+        self.register = byte;
+    }
+
+    fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.register & 0x07;
+    }
+
+    fn clock(&mut self) {
+        let period = self.register & 0x07;
+        if period == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = period;
+
+        if self.register & 0x08 == 0x08 {
+            self.volume = (self.volume + 1).min(15);
+        } else {
+            self.volume = self.volume.saturating_sub(1);
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.register);
+        state.u8(self.volume);
+        state.u8(self.timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.register = state.u8()?;
+        self.volume = state.u8()? & 0x0F;
+        self.timer = state.u8()?;
+
+        Ok(())
+    }
+}
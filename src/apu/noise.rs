@@ -0,0 +1,152 @@
+//! Channel 4, pseudo-random noise from a linear feedback shift register.
+//! Each clock shifts the 15-bit LFSR right and feeds the XOR of its lowest
+//! two bits back into bit 14, and in 7-bit mode also into bit 6, which gives
+//! a short period and a more tonal sound.
+//! Ref https://gbdev.io/pandocs/Audio_Registers.html#sound-channel-4--noise
+
+use super::{Envelope, Length};
+use crate::state::{StateReader, StateWriter};
+use crate::{HalfWord, Word};
+use anyhow::Result;
+
+// Dots per LFSR clock for each divisor code, before the shift
+const DIVISORS: [usize; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// Registers, `register` relative to NR40 (which doesn't exist)
+///```text
+/// NR41  Bit 5-0: length (write only)
+/// NR42  Volume envelope
+/// NR43  Bit 7-4: clock shift, Bit 3: LFSR width, Bit 2-0: clock divisor
+/// NR44  Bit 7: trigger, Bit 6: length enable
+/// ```
+#[derive(Debug)]
+pub(super) struct Noise {
+    enabled: bool,
+    length: Length,
+    envelope: Envelope,
+    nr43: HalfWord,
+    lfsr: Word,
+    // Dots until the next LFSR clock
+    timer: usize,
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise {
+            enabled: false,
+            length: Length::new(64),
+            envelope: Envelope::default(),
+            nr43: 0,
+            lfsr: 0x7FFF,
+            timer: 0,
+        }
+    }
+
+    pub fn read(&self, register: Word) -> HalfWord {
+        match register {
+            2 => self.envelope.read(),
+            3 => self.nr43,
+            4 => (self.length.enabled as HalfWord) << 6,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, register: Word, byte: HalfWord) {
+        match register {
+            1 => self.length.load(byte & 0x3F),
+            2 => {
+                self.envelope.write(byte);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.nr43 = byte,
+            _ => {
+                self.length.enabled = byte & 0x40 == 0x40;
+                if byte & 0x80 == 0x80 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+    }
+
+    /// Dots per LFSR clock.
+    fn period(&self) -> usize {
+        DIVISORS[(self.nr43 & 0x07) as usize] << (self.nr43 >> 4)
+    }
+
+    pub fn step(&mut self, dots: usize) {
+        let mut dots = dots;
+        while dots >= self.timer {
+            dots -= self.timer;
+            self.timer = self.period();
+
+            // Clock shifts 14 and 15 stop the LFSR
+            if self.nr43 >> 4 >= 14 {
+                continue;
+            }
+
+            let bit = (self.lfsr ^ self.lfsr >> 1) & 0x01;
+            self.lfsr = self.lfsr >> 1 | bit << 14;
+            if self.nr43 & 0x08 == 0x08 {
+                self.lfsr = self.lfsr & !0x40 | bit << 6;
+            }
+        }
+        self.timer -= dots;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// Digital output, 0-15: the envelope volume while bit 0 of the LFSR is
+    /// clear.
+    pub fn output(&self) -> HalfWord {
+        if !self.enabled || self.lfsr & 0x01 == 0x01 {
+            return 0;
+        }
+        self.envelope.volume
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.enabled);
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.u8(self.nr43);
+        state.u16(self.lfsr);
+        state.usize(self.timer);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.enabled = state.bool()?;
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.nr43 = state.u8()?;
+        self.lfsr = state.u16()? & 0x7FFF;
+        self.timer = state.usize()?;
+
+        Ok(())
+    }
+}
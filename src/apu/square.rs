@@ -0,0 +1,257 @@
+//! Channels 1 and 2, square waves with 4 duty cycles. Channel 1 also has a
+//! frequency sweep.
+//! Ref https://gbdev.io/pandocs/Audio_Registers.html#sound-channel-1--pulse-with-period-sweep
+
+use super::{Envelope, Length};
+use crate::state::{StateReader, StateWriter};
+use crate::{HalfWord, Word};
+use anyhow::Result;
+
+// Waveform of each duty cycle, 8 steps per period
+const DUTY_PATTERNS: [[HalfWord; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75%
+];
+
+/// Registers, `register` relative to NRx0
+///```text
+/// NR10  Sweep (channel 1 only), Bit 6-4: steps of 128 Hz between frequency
+///       changes, Bit 3: 1 = decrease, Bit 2-0: shift
+/// NRx1  Bit 7-6: duty cycle, Bit 5-0: length (write only)
+/// NRx2  Volume envelope
+/// NRx3  Lower 8 bits of the frequency (write only)
+/// NRx4  Bit 7: trigger, Bit 6: length enable,
+///       Bit 2-0: upper 3 bits of the frequency (write only)
+/// ```
+#[derive(Debug)]
+pub(super) struct Square {
+    enabled: bool,
+    nr10: HalfWord,
+    duty: usize,
+    length: Length,
+    envelope: Envelope,
+    frequency: Word,
+    // Dots until the next duty step
+    timer: usize,
+    duty_step: usize,
+    sweep: Sweep,
+}
+
+/// Frequency sweep state, latched on trigger.
+/// Ref https://gbdev.io/pandocs/Audio_details.html#pulse-channel-with-sweep-ch1
+#[derive(Debug, Default)]
+struct Sweep {
+    enabled: bool,
+    // The sweep works on a copy of the frequency
+    shadow: Word,
+    // Steps left before the next frequency change
+    timer: HalfWord,
+    // A decreasing frequency was calculated since the trigger
+    negated: bool,
+}
+
+impl Square {
+    pub fn new() -> Square {
+        Square {
+            enabled: false,
+            nr10: 0,
+            duty: 0,
+            length: Length::new(64),
+            envelope: Envelope::default(),
+            frequency: 0,
+            timer: 0,
+            duty_step: 0,
+            sweep: Sweep::default(),
+        }
+    }
+
+    pub fn read(&self, register: Word) -> HalfWord {
+        match register {
+            0 => self.nr10,
+            1 => (self.duty as HalfWord) << 6,
+            2 => self.envelope.read(),
+            4 => (self.length.enabled as HalfWord) << 6,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, register: Word, byte: HalfWord) {
+        match register {
+            0 => {
+                self.nr10 = byte;
+                // Leaving decrease mode after a decreasing calculation
+                // turns the channel off
+                if self.sweep.negated && byte & 0x08 == 0 {
+                    self.enabled = false;
+                }
+            }
+            1 => {
+                self.duty = (byte >> 6) as usize;
+                self.length.load(byte & 0x3F);
+            }
+            2 => {
+                self.envelope.write(byte);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = self.frequency & 0x700 | byte as Word,
+            _ => {
+                self.frequency = self.frequency & 0xFF | ((byte & 0x07) as Word) << 8;
+                self.length.enabled = byte & 0x40 == 0x40;
+                if byte & 0x80 == 0x80 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+
+        self.sweep = Sweep {
+            enabled: self.sweep_period() != 0 || self.sweep_shift() != 0,
+            shadow: self.frequency,
+            timer: self.sweep_timer_period(),
+            negated: false,
+        };
+        // With a shift the overflow check runs at once
+        if self.sweep_shift() != 0 {
+            self.next_sweep_frequency();
+        }
+    }
+
+    fn sweep_period(&self) -> HalfWord {
+        self.nr10 >> 4 & 0x07
+    }
+
+    // A period of 0 reloads the timer with 8
+    fn sweep_timer_period(&self) -> HalfWord {
+        match self.sweep_period() {
+            0 => 8,
+            period => period,
+        }
+    }
+
+    fn sweep_shift(&self) -> HalfWord {
+        self.nr10 & 0x07
+    }
+
+    /// Calculate the next frequency from the shadow frequency. Overflowing
+    /// 2047 turns the channel off.
+    fn next_sweep_frequency(&mut self) -> Word {
+        let delta = self.sweep.shadow >> self.sweep_shift();
+        let frequency = if self.nr10 & 0x08 == 0x08 {
+            self.sweep.negated = true;
+            self.sweep.shadow - delta
+        } else {
+            self.sweep.shadow + delta
+        };
+
+        if frequency > 2047 {
+            self.enabled = false;
+        }
+        frequency
+    }
+
+    /// Clocked at 128 Hz by the frame sequencer.
+    pub fn clock_sweep(&mut self) {
+        self.sweep.timer = self.sweep.timer.saturating_sub(1);
+        if self.sweep.timer > 0 {
+            return;
+        }
+        self.sweep.timer = self.sweep_timer_period();
+
+        if !self.sweep.enabled || self.sweep_period() == 0 {
+            return;
+        }
+
+        let frequency = self.next_sweep_frequency();
+        if frequency <= 2047 && self.sweep_shift() != 0 {
+            self.sweep.shadow = frequency;
+            self.frequency = frequency;
+            // The new frequency is checked for overflow again, but not used
+            self.next_sweep_frequency();
+        }
+    }
+
+    /// Dots per duty step.
+    fn period(&self) -> usize {
+        (2048 - self.frequency as usize) * 4
+    }
+
+    pub fn step(&mut self, dots: usize) {
+        let mut dots = dots;
+        while dots >= self.timer {
+            dots -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.timer -= dots;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// Digital output, 0-15.
+    pub fn output(&self) -> HalfWord {
+        if !self.enabled {
+            return 0;
+        }
+        DUTY_PATTERNS[self.duty][self.duty_step] * self.envelope.volume
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.enabled);
+        state.u8(self.nr10);
+        state.usize(self.duty);
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.u16(self.frequency);
+        state.usize(self.timer);
+        state.usize(self.duty_step);
+        state.bool(self.sweep.enabled);
+        state.u16(self.sweep.shadow);
+        state.u8(self.sweep.timer);
+        state.bool(self.sweep.negated);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.enabled = state.bool()?;
+        self.nr10 = state.u8()?;
+        self.duty = state.usize()? & 0x03;
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.frequency = state.u16()? & 0x7FF;
+        self.timer = state.usize()?;
+        self.duty_step = state.usize()? % 8;
+        self.sweep = Sweep {
+            enabled: state.bool()?,
+            shadow: state.u16()? & 0x7FF,
+            timer: state.u8()?,
+            negated: state.bool()?,
+        };
+
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+//! Channel 3, playing the 32 4-bit samples of wave RAM.
+//! Ref https://gbdev.io/pandocs/Audio_Registers.html#sound-channel-3--wave-output
+//!
+//! While the channel plays, the CPU can only reach the byte of wave RAM
+//! being played, whatever the address. On DMG even that only works right
+//! when the channel reads it, otherwise reads return 0xFF and writes are
+//! ignored.
+//! Ref https://gbdev.io/pandocs/Audio_details.html#obscure-behavior
+
+use super::Length;
+use crate::state::{StateReader, StateWriter};
+use crate::{HalfWord, Word};
+use anyhow::Result;
+
+// On DMG the CPU reaches wave RAM during the machine cycle the channel reads it
+const DMG_ACCESS_DOTS: usize = 4;
+
+/// Registers, `register` relative to NR30
+///```text
+/// NR30  Bit 7: DAC on
+/// NR31  Length (write only)
+/// NR32  Bit 6-5: volume (0: mute, 1: 100%, 2: 50%, 3: 25%)
+/// NR33  Lower 8 bits of the frequency (write only)
+/// NR34  Bit 7: trigger, Bit 6: length enable,
+///       Bit 2-0: upper 3 bits of the frequency (write only)
+/// ```
+#[derive(Debug)]
+pub(super) struct Wave {
+    enabled: bool,
+    dac: bool,
+    length: Length,
+    volume: HalfWord,
+    frequency: Word,
+    // Dots until the next sample
+    timer: usize,
+    // Sample being played, 0-31. Each byte holds 2 samples, upper nibble first.
+    position: usize,
+    sample: HalfWord,
+    // Dots since the channel last read wave RAM
+    since_read: usize,
+    ram: [HalfWord; 16],
+    cgb: bool,
+}
+
+impl Wave {
+    pub fn new() -> Wave {
+        Wave {
+            enabled: false,
+            dac: false,
+            length: Length::new(256),
+            volume: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            since_read: 0,
+            ram: [0; 16],
+            cgb: false,
+        }
+    }
+
+    pub fn set_cgb(&mut self, cgb: bool) {
+        self.cgb = cgb;
+    }
+
+    /// Clear the registers, keeping wave RAM.
+    pub fn power_off(&mut self) {
+        *self = Wave {
+            ram: self.ram,
+            cgb: self.cgb,
+            ..Wave::new()
+        };
+    }
+
+    pub fn read(&self, register: Word) -> HalfWord {
+        match register {
+            0 => (self.dac as HalfWord) << 7,
+            2 => self.volume << 5,
+            4 => (self.length.enabled as HalfWord) << 6,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, register: Word, byte: HalfWord) {
+        match register {
+            0 => {
+                self.dac = byte & 0x80 == 0x80;
+                if !self.dac {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(byte),
+            2 => self.volume = byte >> 5 & 0x03,
+            3 => self.frequency = self.frequency & 0x700 | byte as Word,
+            _ => {
+                self.frequency = self.frequency & 0xFF | ((byte & 0x07) as Word) << 8;
+                self.length.enabled = byte & 0x40 == 0x40;
+                if byte & 0x80 == 0x80 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    pub fn read_ram(&self, offset: Word) -> HalfWord {
+        match self.accessible_byte(offset) {
+            Some(index) => self.ram[index],
+            None => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, offset: Word, byte: HalfWord) {
+        if let Some(index) = self.accessible_byte(offset) {
+            self.ram[index] = byte;
+        }
+    }
+
+    /// Byte of wave RAM the CPU reaches at `offset`, if any.
+    fn accessible_byte(&self, offset: Word) -> Option<usize> {
+        if !self.enabled {
+            Some(offset as usize)
+        } else if self.cgb || self.since_read < DMG_ACCESS_DOTS {
+            Some(self.position / 2)
+        } else {
+            None
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac;
+        self.length.trigger();
+        self.timer = self.period();
+        self.position = 0;
+        // Wave RAM isn't read until the first sample
+        self.since_read = DMG_ACCESS_DOTS;
+    }
+
+    /// Dots per sample.
+    fn period(&self) -> usize {
+        (2048 - self.frequency as usize) * 2
+    }
+
+    pub fn step(&mut self, dots: usize) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut dots = dots;
+        self.since_read += dots;
+        while dots >= self.timer {
+            dots -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            self.since_read = dots;
+
+            let byte = self.ram[self.position / 2];
+            self.sample = if self.position & 1 == 0 {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+        self.timer -= dots;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac
+    }
+
+    /// Digital output, 0-15.
+    pub fn output(&self) -> HalfWord {
+        if !self.enabled || self.volume == 0 {
+            return 0;
+        }
+        self.sample >> (self.volume - 1)
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.enabled);
+        state.bool(self.dac);
+        self.length.save_state(state);
+        state.u8(self.volume);
+        state.u16(self.frequency);
+        state.usize(self.timer);
+        state.usize(self.position);
+        state.u8(self.sample);
+        state.usize(self.since_read);
+        state.bytes(&self.ram);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.enabled = state.bool()?;
+        self.dac = state.bool()?;
+        self.length.load_state(state)?;
+        self.volume = state.u8()? & 0x03;
+        self.frequency = state.u16()? & 0x7FF;
+        self.timer = state.usize()?;
+        self.position = state.usize()? % 32;
+        self.sample = state.u8()? & 0x0F;
+        self.since_read = state.usize()?;
+        state.bytes_into(&mut self.ram)
+    }
+}
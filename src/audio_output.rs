@@ -0,0 +1,92 @@
+//! Sound on the default output device of the host, through cpal.
+//!
+//! The device callback plays an [`AudioRing`] with a [`RingReader`], at the
+//! rate of the device. Pace the emulation by the same ring with
+//! [`Pacing::Audio`](crate::emulator::Pacing::Audio).
+
+use crate::audio_ring::{AudioRing, RingReader};
+use anyhow::Result;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+
+/// Stereo samples in the ring, about 85 ms at 48 kHz. The emulation fills
+/// it up to half, about 43 ms ahead of the device.
+pub const RING_CAPACITY: usize = 4096;
+
+/// An open output stream, playing until dropped.
+pub struct AudioOutput {
+    ring: AudioRing,
+    _stream: Stream,
+}
+
+impl AudioOutput {
+    /// Start playing a new ring on the default output device.
+    pub fn open() -> Result<AudioOutput> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| anyhow::anyhow!("no audio output device"))?;
+        let supported = device.default_output_config()?;
+        let format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+
+        let ring = AudioRing::new(RING_CAPACITY);
+        let reader = RingReader::new(ring.clone(), config.sample_rate.0 as f64);
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, reader)?,
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, reader)?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, reader)?,
+            format => anyhow::bail!("unsupported audio sample format {:?}", format),
+        };
+        stream.play()?;
+        log::info!(
+            "audio output at {} Hz, {} channels",
+            config.sample_rate.0,
+            config.channels
+        );
+
+        Ok(AudioOutput {
+            ring,
+            _stream: stream,
+        })
+    }
+
+    /// The ring the device plays, for [`Pacing::Audio`](crate::emulator::Pacing::Audio).
+    pub fn ring(&self) -> AudioRing {
+        self.ring.clone()
+    }
+}
+
+/// A stream of `T` samples filled by `reader`. Left and right go to the
+/// first two channels, mono devices get their average.
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &Device,
+    config: &StreamConfig,
+    mut reader: RingReader,
+) -> Result<Stream> {
+    let channels = config.channels as usize;
+    let mut frames = Vec::new();
+
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            frames.resize(data.len() / channels, [0.0; 2]);
+            reader.fill(&mut frames);
+
+            for (slots, frame) in data.chunks_mut(channels).zip(&frames) {
+                for (channel, slot) in slots.iter_mut().enumerate() {
+                    let sample = match (channels, channel) {
+                        (1, _) => (frame[0] + frame[1]) / 2.0,
+                        (_, 0) => frame[0],
+                        (_, 1) => frame[1],
+                        _ => 0.0,
+                    };
+                    *slot = T::from_sample(sample);
+                }
+            }
+        },
+        |e| log::error!("audio output: {}", e),
+        None,
+    )?;
+
+    Ok(stream)
+}
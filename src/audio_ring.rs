@@ -0,0 +1,145 @@
+//! Ring buffer between the emulation and an audio device callback, for
+//! audio-driven sync.
+//!
+//! As the audio sink of the APU, the ring blocks the emulation while it is
+//! half full, so the emulation runs exactly as fast as the device plays and
+//! the device always has half a ring of samples to play. The
+//! device callback takes samples out with [`AudioRing::fill`], at
+//! [`crate::apu::SAMPLE_RATE`], or with a [`RingReader`] at the device rate.
+
+use crate::apu::{AudioSink, SAMPLE_RATE};
+use crate::resampler::Resampler;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Longest wait for room in the ring. After that the device is assumed to
+/// be gone, and the oldest samples are dropped instead of hanging.
+const MAX_WAIT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub struct AudioRing {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    samples: Mutex<VecDeque<[f32; 2]>>,
+    // Signaled when the device takes samples
+    room: Condvar,
+    capacity: usize,
+}
+
+impl AudioRing {
+    /// A ring of `capacity` stereo samples, filled up to half of it. About 4
+    /// device callbacks worth keeps the latency low without underruns.
+    pub fn new(capacity: usize) -> AudioRing {
+        AudioRing {
+            shared: Arc::new(Shared {
+                samples: Mutex::new(VecDeque::with_capacity(capacity)),
+                room: Condvar::new(),
+                capacity,
+            }),
+        }
+    }
+
+    /// Fill `output` with the oldest samples, for the device callback. An
+    /// underrun is filled with silence. Returns the number of samples taken.
+    pub fn fill(&self, output: &mut [[f32; 2]]) -> usize {
+        let mut samples = self.shared.samples.lock().unwrap();
+
+        let taken = output.len().min(samples.len());
+        for (slot, sample) in output.iter_mut().zip(samples.drain(..taken)) {
+            *slot = sample;
+        }
+        output[taken..].iter_mut().for_each(|slot| *slot = [0.0; 2]);
+
+        self.shared.room.notify_one();
+        taken
+    }
+
+    /// How full the ring is, from 0.0 to 1.0.
+    pub fn fill_level(&self) -> f64 {
+        let samples = self.shared.samples.lock().unwrap();
+        samples.len() as f64 / self.shared.capacity as f64
+    }
+}
+
+impl AudioSink for AudioRing {
+    /// Blocks while the ring is half full, until the device made room for
+    /// the samples.
+    fn push_samples(&mut self, left: &[f32], right: &[f32]) {
+        let shared = &self.shared;
+        let limit = shared.capacity / 2;
+        let mut samples = shared.samples.lock().unwrap();
+
+        for (&l, &r) in left.iter().zip(right) {
+            if samples.len() >= limit {
+                let (guard, timeout) = shared
+                    .room
+                    .wait_timeout_while(samples, MAX_WAIT, |samples| samples.len() >= limit)
+                    .unwrap();
+                samples = guard;
+
+                if timeout.timed_out() {
+                    samples.pop_front();
+                }
+            }
+            samples.push_back([l, r]);
+        }
+    }
+}
+
+/// The device side of a ring at the rate of the device. Resamples with a
+/// [`Resampler`] steered by the fill level of the ring. A device clock
+/// running up to 0.5% fast settles the ring below half full instead of
+/// draining it, a slow one is absorbed by the blocking producer.
+pub struct RingReader {
+    ring: AudioRing,
+    resampler: Resampler,
+    // Samples taken out of the ring, at SAMPLE_RATE
+    input: Vec<[f32; 2]>,
+    // Resampled samples left over from the last fill
+    resampled: Vec<[f32; 2]>,
+}
+
+impl RingReader {
+    pub fn new(ring: AudioRing, device_rate: f64) -> RingReader {
+        RingReader {
+            ring,
+            resampler: Resampler::new(SAMPLE_RATE as f64, device_rate),
+            input: Vec::new(),
+            resampled: Vec::new(),
+        }
+    }
+
+    /// Current ring samples taken per device sample, see
+    /// [`Resampler::step`].
+    pub fn step(&self) -> f64 {
+        self.resampler.step()
+    }
+
+    /// Fill `output` at the device rate, for the device callback. An
+    /// underrun is filled with silence.
+    pub fn fill(&mut self, output: &mut [[f32; 2]]) {
+        self.resampler.update_fill_level(self.ring.fill_level());
+
+        while self.resampled.len() < output.len() {
+            let missing = output.len() - self.resampled.len();
+            let wanted = (missing as f64 * self.resampler.step()).ceil() as usize + 1;
+            self.input.resize(wanted, [0.0; 2]);
+
+            let taken = self.ring.fill(&mut self.input);
+            if taken == 0 {
+                break;
+            }
+            self.resampler
+                .process(&self.input[..taken], &mut self.resampled);
+        }
+
+        let ready = output.len().min(self.resampled.len());
+        output[..ready].copy_from_slice(&self.resampled[..ready]);
+        output[ready..].fill([0.0; 2]);
+        self.resampled.drain(..ready);
+    }
+}
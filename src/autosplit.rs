@@ -0,0 +1,165 @@
+//! Automatic speedrun timing.
+//!
+//! An [`AutoSplitter`] watches memory once per frame and sends start, split
+//! and reset commands to LiveSplit's server component (TCP, port 16834 by
+//! default). Rules are plain text, one per line:
+//!
+//! ```text
+//! # event  address  condition
+//! start    C0A0     == 01
+//! split    D361     changed
+//! split    D35E     > 0F
+//! reset    FF80     == 00
+//! ```
+//!
+//! An event fires on the frame its condition becomes true.
+//! Ref https://github.com/LiveSplit/LiveSplit.Server
+
+use crate::bus::{Bus, MemoryBus};
+use crate::{HalfWord, Word};
+use anyhow::{Context, Result};
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitEvent {
+    Start,
+    Split,
+    Reset,
+}
+
+impl SplitEvent {
+    /// Command understood by the LiveSplit server.
+    fn command(&self) -> &'static str {
+        match self {
+            SplitEvent::Start => "starttimer",
+            SplitEvent::Split => "split",
+            SplitEvent::Reset => "reset",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Equal(HalfWord),
+    NotEqual(HalfWord),
+    Greater(HalfWord),
+    Less(HalfWord),
+    Changed,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rule {
+    pub event: SplitEvent,
+    pub address: Word,
+    pub condition: Condition,
+}
+
+struct Watch {
+    rule: Rule,
+    previous: Option<HalfWord>,
+    matched: bool,
+}
+
+pub struct AutoSplitter {
+    watches: Vec<Watch>,
+    output: Box<dyn Write + Send>,
+}
+
+impl AutoSplitter {
+    /// Send the events of `rules` to `output`, usually a `TcpStream` to LiveSplit.
+    pub fn new(rules: Vec<Rule>, output: Box<dyn Write + Send>) -> AutoSplitter {
+        let watches = rules
+            .into_iter()
+            .map(|rule| Watch {
+                rule,
+                previous: None,
+                matched: false,
+            })
+            .collect();
+
+        AutoSplitter { watches, output }
+    }
+
+    /// Check the watched addresses, called once per frame.
+    pub fn update(&mut self, bus: &Bus) -> Result<Vec<SplitEvent>> {
+        let mut events = Vec::new();
+
+        for watch in &mut self.watches {
+            let value = bus.peek_byte(watch.rule.address);
+            let matched = match watch.rule.condition {
+                Condition::Equal(n) => value == n,
+                Condition::NotEqual(n) => value != n,
+                Condition::Greater(n) => value > n,
+                Condition::Less(n) => value < n,
+                Condition::Changed => matches!(watch.previous, Some(p) if p != value),
+            };
+
+            if matched && !watch.matched {
+                events.push(watch.rule.event);
+            }
+            watch.matched = matched;
+            watch.previous = Some(value);
+        }
+
+        for event in &events {
+            write!(self.output, "{}\r\n", event.command())?;
+        }
+        self.output.flush()?;
+
+        Ok(events)
+    }
+}
+
+/// Parse rules in the format described in the module documentation.
+pub fn parse_rules(source: &str) -> Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let rule = parse_rule(line).with_context(|| format!("line {}: {}", i + 1, line))?;
+        rules.push(rule);
+    }
+
+    Ok(rules)
+}
+
+fn parse_rule(line: &str) -> Result<Rule> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    let event = match fields.first().copied() {
+        Some("start") => SplitEvent::Start,
+        Some("split") => SplitEvent::Split,
+        Some("reset") => SplitEvent::Reset,
+        _ => anyhow::bail!("expected start, split or reset"),
+    };
+
+    let address = match fields.get(1) {
+        Some(address) => Word::from_str_radix(address, 16)?,
+        None => anyhow::bail!("missing address"),
+    };
+
+    let value = || -> Result<HalfWord> {
+        match fields.get(3) {
+            Some(value) => Ok(HalfWord::from_str_radix(value, 16)?),
+            None => anyhow::bail!("missing value"),
+        }
+    };
+    let condition = match fields.get(2).copied() {
+        Some("==") => Condition::Equal(value()?),
+        Some("!=") => Condition::NotEqual(value()?),
+        Some(">") => Condition::Greater(value()?),
+        Some("<") => Condition::Less(value()?),
+        Some("changed") => Condition::Changed,
+        _ => anyhow::bail!("expected ==, !=, >, < or changed"),
+    };
+
+    Ok(Rule {
+        event,
+        address,
+        condition,
+    })
+}
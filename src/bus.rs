@@ -1,13 +1,87 @@
-use crate::cartridge::Cartridge;
-use crate::ram::Ram;
-use crate::SharedGpu;
-use crate::{split_word, HalfWord, Word};
+use crate::apu::{Apu, CLOCK_RATE};
+use crate::cartridge::{Cartridge, Model};
+use crate::cheat::Cheats;
+use crate::event_viewer::{DmaEvent, DmaKind, EventViewer};
+use crate::fault::FaultInjector;
+use crate::gpu::Gpu;
+use crate::hdma::{self, Hdma};
+use crate::infrared::{InfraredPort, IrDevice};
+use crate::interrupt::{Interrupt, Interrupts};
+use crate::io;
+use crate::joypad::{Button, Joypad};
+use crate::mbc;
+use crate::ram::{BankedRam, Ram};
+use crate::serial::{LinkCable, Serial};
+use crate::state::{StateReader, StateWriter};
+use crate::telemetry::{Telemetry, Unimplemented};
+use crate::timer::Timer;
+use crate::{join_half_words, split_word, HalfWord, Word};
+use anyhow::Result;
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::path::Path;
 
+/// The memory map as the CPU and the PPU see it.
+///
+/// Implemented by [`Bus`], and by anything else the core should run
+/// against, e.g. a flat 64 KiB RAM in tests or a custom memory map.
+pub trait MemoryBus {
+    fn read_byte(&self, address: Word) -> HalfWord;
+
+    fn write_byte(&mut self, address: Word, byte: HalfWord);
+
+    fn write_word(&mut self, address: Word, word: Word) {
+        let (upper, lower) = split_word(word);
+
+        self.write_byte(address, lower);
+        self.write_byte(address.wrapping_add(1), upper);
+    }
+
+    /// Read without side effects or access restrictions, for the PPU and tracing.
+    fn peek_byte(&self, address: Word) -> HalfWord {
+        self.read_byte(address)
+    }
+
+    /// Advance the peripherals by `cycles` clock cycles, once per machine cycle.
+    fn tick(&mut self, _cycles: usize) {}
+
+    /// Interrupts that are both requested and enabled.
+    fn pending_interrupts(&self) -> HalfWord {
+        0
+    }
+
+    /// Take the highest priority pending interrupt for servicing.
+    fn acknowledge_interrupt(&mut self) -> Option<Interrupt> {
+        None
+    }
+
+    /// The CPU starts executing the instruction at `pc`.
+    fn set_pc(&mut self, _pc: Word) {}
+
+    /// The CPU ran into `feature`, see [`crate::telemetry`].
+    fn record_unimplemented(&mut self, _feature: Unimplemented) {}
+
+    /// Apply the DMG OAM corruption bug to the OAM row the PPU is reading,
+    /// if it is in mode 2.
+    fn corrupt_oam(&mut self, _kind: OamCorruption) {}
+
+    /// Clock cycles the CPU has to sit idle for, e.g. while HDMA copies to
+    /// VRAM. Taking them resets the count.
+    fn take_stall_cycles(&mut self) -> usize {
+        0
+    }
+
+    /// STOP: switch the CPU speed if KEY1 requested it. Returns whether the
+    /// speed was switched.
+    fn switch_speed(&mut self) -> bool {
+        false
+    }
+}
 
 /// Memory map
 /// Ref http://marc.rawer.de/Gameboy/Docs/GBCPUman.pdf
 /// Ref https://w.atwiki.jp/gbspec/pages/13.html
-///```
+///```text
 /// Interrupt Enable Register
 /// --------------------------- FFFF
 /// Internal RAM
@@ -36,86 +110,1017 @@ use crate::{split_word, HalfWord, Word};
 pub struct Bus {
     h_ram: Ram,
     oam_ram: Ram,
-    mirror_ram: Ram,
-    working_ram: Ram,
-    video_ram: Ram,
+    working_ram: BankedRam,
+    video_ram: BankedRam,
     cartridge: Cartridge,
-    gpu: SharedGpu,
+    gpu: Gpu,
+    apu: Apu,
+    interrupts: Interrupts,
+    infrared: InfraredPort,
+    timer: Timer,
+    serial: Serial,
+    joypad: Joypad,
+    // Last value written to DMA (0xFF46)
+    dma: HalfWord,
+    dma_transfer: Option<DmaTransfer>,
+    // Last value written to SVBK (0xFF70), 0 selects bank 1
+    svbk: HalfWord,
+    hdma: Hdma,
+    // CGB double speed mode (KEY1 bit 7)
+    double_speed: bool,
+    // A speed switch is armed for the next STOP (KEY1 bit 0)
+    speed_switch: bool,
+    // Whether the PPU was in H-blank on the last machine cycle
+    hblank: bool,
+    // Clock cycles the CPU is stopped for by HDMA
+    stall_cycles: usize,
+    // Dots since power on, the time base of the emulated RTC
+    time: u64,
+    // The cartridge RTC runs on `time` instead of the host clock
+    emulated_rtc: bool,
+    // Shadows the cartridge until 0xFF50 is written
+    boot_rom: Option<BootRom>,
+    cheats: Cheats,
+    faults: RefCell<Option<FaultInjector>>,
+    // Whether `faults` is set, checked before borrowing it on every access
+    fault_injection: bool,
+    event_viewer: Option<EventViewer>,
+    telemetry: Telemetry,
+    // Address of the instruction being executed, for the telemetry
+    pc: Word,
+    // CPU writes into these ranges are kept in `watched_writes`
+    write_watch: Vec<RangeInclusive<Word>>,
+    watched_writes: Vec<(Word, HalfWord)>,
+    model: Model,
 }
 
 impl Bus {
     pub fn new(
         cartridge: Cartridge,
-        video_ram: Ram,
+        video_ram: BankedRam,
         h_ram: Ram,
         oam_ram: Ram,
-        mirror_ram: Ram,
-        working_ram: Ram,
-        gpu: SharedGpu,
+        working_ram: BankedRam,
+        gpu: Gpu,
     ) -> Bus {
-        Bus { h_ram, oam_ram, mirror_ram, working_ram, video_ram, cartridge, gpu }
+        Bus {
+            h_ram,
+            oam_ram,
+            working_ram,
+            video_ram,
+            cartridge,
+            gpu,
+            apu: Apu::default(),
+            interrupts: Interrupts::default(),
+            infrared: InfraredPort::default(),
+            timer: Timer::default(),
+            serial: Serial::default(),
+            joypad: Joypad::default(),
+            dma: 0xFF,
+            dma_transfer: None,
+            svbk: 0,
+            hdma: Hdma::default(),
+            double_speed: false,
+            speed_switch: false,
+            hblank: false,
+            stall_cycles: 0,
+            time: 0,
+            emulated_rtc: false,
+            boot_rom: None,
+            cheats: Cheats::default(),
+            faults: RefCell::new(None),
+            fault_injection: false,
+            event_viewer: None,
+            telemetry: Telemetry::default(),
+            pc: 0,
+            write_watch: Vec::new(),
+            watched_writes: Vec::new(),
+            model: Model::Dmg,
+        }
     }
 
-    pub fn read_byte(&self, address: Word) -> u8 {
-        let device = Device::resolve_bus_address(address);
+    /// Hardware model, which decides whether the CGB registers exist.
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.gpu.set_cgb(model == Model::Cgb);
+        self.apu.set_cgb(model == Model::Cgb);
+    }
 
-        match device {
+    /// Map `boot_rom` over the cartridge until the program writes 0xFF50.
+    pub fn install_boot_rom(&mut self, boot_rom: BootRom) {
+        self.boot_rom = Some(boot_rom);
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    /// Remove the cheats, e.g. to move them to a new bus.
+    pub fn take_cheats(&mut self) -> Cheats {
+        std::mem::take(&mut self.cheats)
+    }
+
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        self.cheats = cheats;
+    }
+
+    /// Perform the GameShark writes, once per frame at VBlank.
+    pub fn apply_cheat_writes(&mut self) {
+        let writes: Vec<_> = self.cheats.ram_writes().collect();
+        for (address, value) in writes {
+            self.poke(address, value);
+        }
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cartridge
+    }
+
+    pub fn gpu(&self) -> &Gpu {
+        &self.gpu
+    }
+
+    pub fn gpu_mut(&mut self) -> &mut Gpu {
+        &mut self.gpu
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    /// Every component behind the bus. Cheats, fault injection and the
+    /// boot ROM image are not part of it, only whether it is still mapped.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        self.h_ram.save_state(state);
+        self.oam_ram.save_state(state);
+        self.working_ram.save_state(state);
+        self.video_ram.save_state(state);
+        state.u64(self.time);
+        self.cartridge.save_state(state);
+        self.gpu.save_state(state);
+        self.apu.save_state(state);
+        self.interrupts.save_state(state);
+        self.infrared.save_state(state);
+        self.timer.save_state(state);
+        self.serial.save_state(state);
+        self.joypad.save_state(state);
+        state.u8(self.dma);
+        state.bool(self.dma_transfer.is_some());
+        if let Some(transfer) = &self.dma_transfer {
+            state.u16(transfer.source);
+            state.u16(transfer.offset);
+            state.bool(transfer.setup);
+        }
+        state.u8(self.svbk);
+        self.hdma.save_state(state);
+        state.bool(self.double_speed);
+        state.bool(self.speed_switch);
+        state.bool(self.hblank);
+        state.usize(self.stall_cycles);
+        state.bool(self.boot_rom.is_some());
+    }
+
+    /// A state taken while the boot ROM was mapped can only be loaded with
+    /// the boot ROM installed.
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.h_ram.load_state(state)?;
+        self.oam_ram.load_state(state)?;
+        self.working_ram.load_state(state)?;
+        self.video_ram.load_state(state)?;
+        // The RTC in the cartridge state is brought up to this time
+        self.time = state.u64()?;
+        self.update_rtc();
+        self.cartridge.load_state(state)?;
+        self.gpu.load_state(state)?;
+        self.apu.load_state(state)?;
+        self.interrupts.load_state(state)?;
+        self.infrared.load_state(state, self.time)?;
+        self.timer.load_state(state)?;
+        self.serial.load_state(state)?;
+        self.joypad.load_state(state)?;
+        self.dma = state.u8()?;
+        self.dma_transfer = if state.bool()? {
+            Some(DmaTransfer {
+                source: state.u16()?,
+                offset: state.u16()? % OAM_SIZE,
+                setup: state.bool()?,
+            })
+        } else {
+            None
+        };
+        self.svbk = state.u8()?;
+        self.hdma.load_state(state)?;
+        self.double_speed = state.bool()?;
+        self.speed_switch = state.bool()?;
+        self.hblank = state.bool()?;
+        self.stall_cycles = state.usize()?;
+        match (state.bool()?, self.boot_rom.is_some()) {
+            (true, false) => anyhow::bail!("the save state needs the boot ROM"),
+            (false, _) => self.boot_rom = None,
+            (true, true) => {}
+        }
+
+        Ok(())
+    }
+
+    /// CGB double speed mode, where the CPU runs at twice the dot clock.
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    pub fn video_ram(&self) -> &BankedRam {
+        &self.video_ram
+    }
+
+    /// Bank and offset within the bank of `address` relative to 0xC000.
+    /// 0xC000-0xCFFF is always bank 0, 0xD000-0xDFFF is the bank selected
+    /// by SVBK (always bank 1 on DMG).
+    fn working_ram_bank(&self, address: Word) -> (usize, Word) {
+        if address < WORKING_RAM_BANK_SIZE {
+            (0, address)
+        } else {
+            (self.working_ram.bank(), address - WORKING_RAM_BANK_SIZE)
+        }
+    }
+
+    /// Inject faults into bus accesses, for robustness testing only.
+    pub fn set_fault_injector(&mut self, faults: Option<FaultInjector>) {
+        self.fault_injection = faults.is_some();
+        *self.faults.get_mut() = faults;
+    }
+
+    pub fn fault_injector_mut(&mut self) -> Option<&mut FaultInjector> {
+        self.faults.get_mut().as_mut()
+    }
+
+    pub fn take_fault_injector(&mut self) -> Option<FaultInjector> {
+        self.fault_injection = false;
+        self.faults.get_mut().take()
+    }
+
+    /// The unimplemented features this core touched.
+    pub fn telemetry(&self) -> &Telemetry {
+        &self.telemetry
+    }
+
+    pub fn set_telemetry(&mut self, telemetry: Telemetry) {
+        self.telemetry = telemetry;
+    }
+
+    fn record(&self, feature: Unimplemented) {
+        self.telemetry.record(feature, self.pc);
+    }
+
+    fn write_cartridge(&mut self, address: Word, byte: HalfWord) {
+        // TODO Writes to the ROM area select banks on cartridges with a mapper
+        if self.cartridge.unknown_mbc() && address < 0x8000 {
+            self.record(Unimplemented::MapperRegister(address & 0xE000));
+        }
+
+        self.cartridge.write(address, byte)
+    }
+
+    /// Record the DMA activity of every frame, see [`crate::event_viewer`].
+    pub fn set_event_viewer(&mut self, viewer: Option<EventViewer>) {
+        self.event_viewer = viewer;
+    }
+
+    pub fn event_viewer(&self) -> Option<&EventViewer> {
+        self.event_viewer.as_ref()
+    }
+
+    pub fn take_event_viewer(&mut self) -> Option<EventViewer> {
+        self.event_viewer.take()
+    }
+
+    /// Keep the CPU writes into `ranges` for [`Bus::take_watched_writes`].
+    /// An empty list stops watching.
+    pub fn set_write_watch(&mut self, ranges: Vec<RangeInclusive<Word>>) {
+        self.write_watch = ranges;
+        self.watched_writes.clear();
+    }
+
+    /// The watched writes since the last call, oldest first.
+    pub fn take_watched_writes(&mut self) -> Vec<(Word, HalfWord)> {
+        std::mem::take(&mut self.watched_writes)
+    }
+
+    fn read_device(&self, address: Word) -> u8 {
+        let device = match &self.boot_rom {
+            Some(boot_rom) if boot_rom.maps(address) => Device::BootRom(address),
+            _ => Device::resolve_bus_address(address),
+        };
+
+        let byte = match device {
             Device::HRam(address) => self.h_ram.read(address),
             Device::OamRam(address) => self.oam_ram.read(address),
-            Device::MirrorRam(address) => self.mirror_ram.read(address),
-            Device::WorkingRam(address) => self.working_ram.read(address),
+            Device::WorkingRam(address) => {
+                let (bank, address) = self.working_ram_bank(address);
+                self.working_ram.read_bank(bank, address)
+            }
             Device::VideoRam(address) => self.video_ram.read(address),
+            Device::Cartridge(address) if address < 0x8000 => {
+                self.cheats.patch_rom(address, self.cartridge.read(address))
+            }
             Device::Cartridge(address) => self.cartridge.read(address),
-            Device::Gpu(address) => self.gpu.lock().unwrap().read(address),
-            Device::Timer(_) => todo!(),
-            Device::P1 => todo!(),
-            Device::DIV => todo!(),
-            Device::IF => todo!(),
-            Device::Unimplement => 0,
+            Device::BootRom(address) => self.boot_rom.as_ref().map_or(0xFF, |b| b.read(address)),
+            // Write only
+            Device::Boot => 0xFF,
+            Device::Gpu(address) => self.gpu.read(address),
+            Device::Apu(address) => self.apu.read(address),
+            // RP is only there on CGB
+            Device::Infrared if self.model != Model::Cgb => 0xFF,
+            Device::Infrared => self.infrared.read(self.time),
+            Device::Timer(address) => self.timer.read(address),
+            Device::Serial(address) => self.serial.read(address),
+            Device::P1 => self.joypad.read(),
+            Device::Dma => self.dma,
+            // WRAM banking only exists on CGB
+            Device::Svbk if self.model != Model::Cgb => 0xFF,
+            Device::Svbk => self.svbk,
+            // VRAM banking only exists on CGB
+            Device::Vbk if self.model != Model::Cgb => 0xFF,
+            Device::Vbk => self.video_ram.bank() as HalfWord,
+            // Speed switching only exists on CGB
+            Device::Key1 if self.model != Model::Cgb => 0xFF,
+            Device::Key1 => (self.double_speed as HalfWord) << 7 | self.speed_switch as HalfWord,
+            // VRAM DMA only exists on CGB
+            Device::Hdma(_) if self.model != Model::Cgb => 0xFF,
+            Device::Hdma(address) => self.hdma.read(address),
+            // Color palettes only exist on CGB
+            Device::Palette(_) if self.model != Model::Cgb => 0xFF,
+            Device::Palette(address) => self.gpu.read_palette(address),
+            // Channel outputs are only readable on CGB
+            Device::Pcm(_) if self.model != Model::Cgb => 0xFF,
+            Device::Pcm(address) => self.apu.read_pcm(address),
+            // Upper 3 bits are unused and read as 1
+            Device::IF => self.interrupts.flags | 0xE0,
+            Device::IE => self.interrupts.enable,
+            Device::Unusable => 0xFF,
+            Device::Unimplement => {
+                self.record(Unimplemented::IoRegister(address));
+                0xFF
+            }
+        };
+
+        if (0xFF00..0xFF80).contains(&address) {
+            byte | io::unused_bits(address)
+        } else {
+            byte
         }
     }
 
-    pub fn write_byte(&mut self, address: Word, byte: HalfWord) {
+    fn write_device(&mut self, address: Word, byte: HalfWord) {
         let device = Device::resolve_bus_address(address);
 
         match device {
             Device::HRam(address) => self.h_ram.write(address, byte),
             Device::OamRam(address) => self.oam_ram.write(address, byte),
-            Device::MirrorRam(address) => self.mirror_ram.write(address, byte),
-            Device::WorkingRam(address) => self.working_ram.write(address, byte),
+            Device::WorkingRam(address) => {
+                let (bank, address) = self.working_ram_bank(address);
+                self.working_ram.write_bank(bank, address, byte)
+            }
             Device::VideoRam(address) => self.video_ram.write(address, byte),
-            Device::Cartridge(address) => self.cartridge.write(address, byte),
-            Device::Gpu(address) => self.gpu.lock().unwrap().write(address, byte),
-            Device::Timer(_) => todo!(),
-            Device::P1 => todo!(),
-            Device::DIV => todo!(),
-            Device::IF => todo!(),
-            Device::Unimplement => log::warn!("unimplemented addr {}", address),
+            Device::Cartridge(address) => self.write_cartridge(address, byte),
+            // Writes to the boot ROM area reach the cartridge mapper
+            Device::BootRom(address) => self.write_cartridge(address, byte),
+            Device::Boot => {
+                // Any non-zero write unmaps the boot ROM until power off
+                if byte != 0 {
+                    self.boot_rom = None;
+                }
+            }
+            Device::Gpu(address) => self.gpu.write(address, byte),
+            Device::Apu(address) => self.apu.write(address, byte),
+            Device::Infrared if self.model != Model::Cgb => {}
+            Device::Infrared => self.infrared.write(byte, self.time),
+            Device::Timer(address) => self.timer.write(address, byte),
+            Device::Serial(address) => self.serial.write(address, byte),
+            Device::P1 => {
+                if self.joypad.write(byte) {
+                    self.interrupts.request(Interrupt::Joypad);
+                }
+            }
+            Device::Dma => self.start_dma(byte),
+            Device::Svbk if self.model != Model::Cgb => {}
+            Device::Svbk => {
+                // Bank 0 is always at 0xC000-0xCFFF, selecting it selects bank 1
+                self.svbk = byte & 0x07;
+                self.working_ram.select_bank((self.svbk as usize).max(1));
+            }
+            Device::Vbk if self.model != Model::Cgb => {}
+            Device::Vbk => self.video_ram.select_bank(byte as usize & 0x01),
+            Device::Key1 if self.model != Model::Cgb => {}
+            Device::Key1 => self.speed_switch = byte & 0x01 != 0,
+            Device::Palette(_) if self.model != Model::Cgb => {}
+            Device::Palette(address) => self.gpu.write_palette(address, byte),
+            // Read only
+            Device::Pcm(_) => {}
+            Device::Hdma(_) if self.model != Model::Cgb => {}
+            Device::Hdma(address) => {
+                if self.hdma.write(address, byte) {
+                    // General purpose DMA copies everything at once
+                    let blocks = self.hdma.remaining_blocks();
+                    let (source, destination) = self.copy_hdma_block();
+                    for _ in 1..blocks {
+                        self.copy_hdma_block();
+                    }
+                    let length = blocks as Word * hdma::BLOCK_SIZE;
+                    self.record_dma(DmaKind::GeneralPurpose, source, destination, length, blocks);
+                }
+            }
+            Device::IF => self.interrupts.flags = byte & 0x1F,
+            Device::IE => self.interrupts.enable = byte,
+            Device::Unusable => {}
+            Device::Unimplement => {
+                self.record(Unimplemented::IoRegister(address));
+                log::warn!("unimplemented addr {}", address)
+            }
         }
     }
 
-    pub fn write_word(&mut self, address: Word, word: Word) {
-        let (upper, lower) = split_word(word);
+    /// OAM DMA: copy 0xA0 bytes from XX00-XX9F into OAM, one byte per
+    /// machine cycle after a machine cycle of setup.
+    /// Ref https://gbdev.io/pandocs/OAM_DMA_Transfer.html
+    fn start_dma(&mut self, byte: HalfWord) {
+        self.dma = byte;
 
-        self.write_byte(address, lower);
-        self.write_byte(address + 1, upper);
+        // Restarting a running transfer starts over from the new source
+        let source = join_half_words(byte, 0x00);
+        self.dma_transfer = Some(DmaTransfer {
+            source,
+            offset: 0,
+            setup: true,
+        });
+        self.record_dma(DmaKind::Oam, source, 0xFE00, OAM_SIZE, 0);
+    }
+
+    /// Show a transfer of `blocks` VRAM DMA blocks, or OAM DMA, in the
+    /// event viewer.
+    fn record_dma(
+        &mut self,
+        kind: DmaKind,
+        source: Word,
+        destination: Word,
+        length: Word,
+        blocks: usize,
+    ) {
+        // Machine cycles the CPU is stopped for
+        let stall = blocks * (HDMA_BLOCK_CYCLES << self.double_speed as usize) / 4;
+        if let Some(viewer) = self.event_viewer.as_mut() {
+            let (line, dot) = self
+                .gpu
+                .position()
+                .map_or((0, 0), |(line, dot, _)| (line, dot));
+            let event = DmaEvent {
+                kind,
+                line,
+                dot,
+                source,
+                destination,
+                length,
+            };
+            viewer.transfer(event, stall);
+        }
+    }
+
+    fn step_dma(&mut self) {
+        let transfer = match self.dma_transfer.as_mut() {
+            Some(transfer) => transfer,
+            None => return,
+        };
+
+        if transfer.setup {
+            transfer.setup = false;
+            return;
+        }
+
+        let (source, offset) = (transfer.source, transfer.offset);
+        transfer.offset += 1;
+        if transfer.offset == OAM_SIZE {
+            self.dma_transfer = None;
+        }
+
+        let byte = self.read_device(source.wrapping_add(offset));
+        self.oam_ram.write(offset, byte);
+    }
+
+    /// While OAM DMA is running the CPU can only access HRAM, the rest of the
+    /// bus reads 0xFF and ignores writes.
+    fn dma_blocks(&self, address: Word) -> bool {
+        match &self.dma_transfer {
+            Some(transfer) => !transfer.setup && !(0xFF80..0xFFFF).contains(&address),
+            None => false,
+        }
+    }
+
+    /// Copy the next 16 byte block of a VRAM DMA transfer into the selected
+    /// VRAM bank, stopping the CPU for 8 machine cycles (16 in double speed
+    /// mode, the copy speed does not change). Returns the source and
+    /// destination of the block.
+    /// Ref https://gbdev.io/pandocs/CGB_Registers.html#lcd-vram-dma-transfers
+    fn copy_hdma_block(&mut self) -> (Word, Word) {
+        let (source, destination) = self.hdma.next_block();
+
+        for offset in 0..hdma::BLOCK_SIZE {
+            let byte = self.read_device(source.wrapping_add(offset));
+            self.video_ram.write(destination - 0x8000 + offset, byte);
+        }
+
+        self.stall_cycles += HDMA_BLOCK_CYCLES << self.double_speed as usize;
+
+        (source, destination)
+    }
+
+    /// Read `address` for debuggers, cheats and tests, bypassing OAM DMA
+    /// blocking and fault injection. Unimplemented I/O registers read as 0xFF
+    /// without being recorded, only what the ROM touches is.
+    pub fn peek(&self, address: Word) -> HalfWord {
+        match Device::resolve_bus_address(address) {
+            Device::Unimplement => 0xFF,
+            _ => self.read_device(address),
+        }
+    }
+
+    /// The whole address space as [`Bus::peek`] reads it, a lot faster than
+    /// peeking every address, e.g. to snapshot it for scripts. ROM is copied
+    /// a bank at a time as [`Mbc::rom_bank`](crate::mbc::Mbc::rom_bank) maps
+    /// it. Unimplemented I/O registers read as 0xFF without being recorded.
+    pub fn copy_memory(&self, memory: &mut [HalfWord]) {
+        let rom = &self.cartridge.data;
+        for start in [0x0000, mbc::ROM_BANK_SIZE] {
+            let bank = self.cartridge.mbc().rom_bank(start as Word);
+            let offset = mbc::rom_offset(rom.len(), bank, start as Word);
+            let window = &mut memory[start..start + mbc::ROM_BANK_SIZE];
+            // Images smaller than the address space read as an unconnected bus
+            let source = rom.get(offset..).unwrap_or_default();
+            let len = source.len().min(window.len());
+            window[..len].copy_from_slice(&source[..len]);
+            window[len..].fill(0xFF);
+        }
+        for address in self.cheats.rom_addresses().filter(|&a| a < 0x8000) {
+            let byte = self.cartridge.read(address);
+            memory[address as usize] = self.cheats.patch_rom(address, byte);
+        }
+        if let Some(boot_rom) = &self.boot_rom {
+            for address in (0..0x0900).filter(|&a| boot_rom.maps(a)) {
+                memory[address as usize] = boot_rom.read(address);
+            }
+        }
+        for (address, byte) in memory[0x8000..0xA000].iter_mut().enumerate() {
+            *byte = self.video_ram.read(address as Word);
+        }
+        for (address, byte) in memory[0xA000..0xC000].iter_mut().enumerate() {
+            *byte = self.cartridge.read(0xA000 + address as Word);
+        }
+        for (address, byte) in memory[0xC000..0xE000].iter_mut().enumerate() {
+            let (bank, address) = self.working_ram_bank(address as Word);
+            *byte = self.working_ram.read_bank(bank, address);
+        }
+        memory.copy_within(0xC000..0xDE00, 0xE000);
+        for (address, byte) in memory[0xFE00..0xFEA0].iter_mut().enumerate() {
+            *byte = self.oam_ram.read(address as Word);
+        }
+        memory[0xFEA0..0xFF00].fill(0xFF);
+        for address in 0xFF00..=0xFFFF {
+            memory[address as usize] = self.peek(address);
+        }
+    }
+
+    /// Write `address` for debuggers, cheats and tests, bypassing OAM DMA
+    /// blocking and fault injection. Memory, including cartridge ROM, is
+    /// patched in place, and DMA, IF and IE only store the value. Other I/O
+    /// registers are written as the CPU would.
+    pub fn poke(&mut self, address: Word, byte: HalfWord) {
+        match Device::resolve_bus_address(address) {
+            Device::Cartridge(address) => self.cartridge.poke(address, byte),
+            Device::Dma => self.dma = byte,
+            Device::IF => self.interrupts.flags = byte & 0x1F,
+            Device::IE => self.interrupts.enable = byte,
+            _ => self.write_device(address, byte),
+        }
+    }
+
+    /// Bytes from `start` to `end` inclusive, read like `peek`.
+    pub fn dump_range(&self, start: Word, end: Word) -> Vec<u8> {
+        (start..=end).map(|address| self.peek(address)).collect()
+    }
+
+    /// Every byte sent over the serial port.
+    pub fn serial_output(&self) -> &[u8] {
+        self.serial.output()
+    }
+
+    pub fn set_button_state(&mut self, button: Button, pressed: bool) {
+        if self.joypad.set_button_state(button, pressed) {
+            self.interrupts.request(Interrupt::Joypad);
+        }
+    }
+
+    /// The pressed buttons, see [`Joypad::buttons`].
+    pub fn buttons(&self) -> HalfWord {
+        self.joypad.buttons()
+    }
+
+    pub fn set_buttons(&mut self, buttons: HalfWord) {
+        if self.joypad.set_buttons(buttons) {
+            self.interrupts.request(Interrupt::Joypad);
+        }
+    }
+
+    /// Set the pressed buttons without requesting the joypad interrupt, as
+    /// they were held before a state was saved.
+    pub fn restore_buttons(&mut self, buttons: HalfWord) {
+        self.joypad.set_buttons(buttons);
+    }
+
+    /// Connect the infrared port to another instance.
+    pub fn connect_ir(&mut self, device: Box<dyn IrDevice>) {
+        self.infrared.connect(device)
+    }
+
+    pub fn take_ir(&mut self) -> Box<dyn IrDevice> {
+        self.infrared.take_device(self.time)
+    }
+
+    /// Plug a link cable into the serial port.
+    pub fn connect_link(&mut self, link: Box<dyn LinkCable>) {
+        self.serial.connect(link)
+    }
+
+    pub fn take_link(&mut self) -> Box<dyn LinkCable> {
+        self.serial.take_link()
+    }
+
+    /// Run the cartridge RTC on the emulated time since power on instead of
+    /// the host clock, so it reads the same on every run.
+    pub fn set_emulated_rtc(&mut self, enabled: bool) {
+        self.emulated_rtc = enabled;
+        if !enabled {
+            self.cartridge.mbc_mut().set_emulated_time(None);
+        }
+        self.update_rtc();
+    }
+
+    fn update_rtc(&mut self) {
+        if self.emulated_rtc {
+            let seconds = self.time / CLOCK_RATE as u64;
+            self.cartridge.mbc_mut().set_emulated_time(Some(seconds));
+        }
+    }
+
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupts.request(interrupt)
+    }
+
+    /// Apply the OAM corruption bug to `row`.
+    /// OAM is treated as 20 rows of four 16 bit words.
+    /// Ref https://gbdev.io/pandocs/OAM_Corruption_Bug.html
+    fn corrupt_oam_at(&mut self, kind: OamCorruption, row: usize) {
+        // The first row (the first two objects) is never corrupted
+        if row == 0 || row >= OAM_ROWS {
+            return;
+        }
+
+        match kind {
+            OamCorruption::Write => self.corrupt_oam_row(row, |a, b, c| ((a ^ c) & (b ^ c)) ^ c),
+            OamCorruption::Read => self.corrupt_oam_row(row, |a, b, c| b | (a & c)),
+            OamCorruption::ReadIncrease => {
+                if (4..OAM_ROWS - 1).contains(&row) {
+                    let a = self.read_oam_word(row - 2, 0);
+                    let b = self.read_oam_word(row - 1, 0);
+                    let c = self.read_oam_word(row, 0);
+                    let d = self.read_oam_word(row - 2, 2);
+                    self.write_oam_word(row - 1, 0, (b & (a | c | d)) | (a & c & d));
+
+                    for word in 0..4 {
+                        let value = self.read_oam_word(row - 1, word);
+                        self.write_oam_word(row, word, value);
+                        self.write_oam_word(row - 2, word, value);
+                    }
+                }
+
+                self.corrupt_oam_row(row, |a, b, c| b | (a & c));
+            }
+        }
+    }
+
+    fn corrupt_oam_row(&mut self, row: usize, pattern: impl Fn(Word, Word, Word) -> Word) {
+        let a = self.read_oam_word(row, 0);
+        let b = self.read_oam_word(row - 1, 0);
+        let c = self.read_oam_word(row - 1, 2);
+        self.write_oam_word(row, 0, pattern(a, b, c));
+
+        for word in 1..4 {
+            let value = self.read_oam_word(row - 1, word);
+            self.write_oam_word(row, word, value);
+        }
+    }
+
+    fn read_oam_word(&self, row: usize, word: usize) -> Word {
+        let address = (row * 8 + word * 2) as Word;
+        join_half_words(self.oam_ram.read(address + 1), self.oam_ram.read(address))
+    }
+
+    fn write_oam_word(&mut self, row: usize, word: usize, value: Word) {
+        let address = (row * 8 + word * 2) as Word;
+        let (upper, lower) = split_word(value);
+
+        self.oam_ram.write(address, lower);
+        self.oam_ram.write(address + 1, upper);
     }
 }
 
+impl MemoryBus for Bus {
+    fn read_byte(&self, address: Word) -> HalfWord {
+        if self.dma_blocks(address) {
+            return 0xFF;
+        }
+
+        let byte = self.read_device(address);
+        if !self.fault_injection {
+            return byte;
+        }
+
+        match self.faults.borrow_mut().as_mut() {
+            Some(faults) => faults.on_read(address, byte),
+            None => byte,
+        }
+    }
+
+    fn write_byte(&mut self, address: Word, byte: HalfWord) {
+        if self.dma_blocks(address) {
+            return;
+        }
+        if self
+            .write_watch
+            .iter()
+            .any(|range| range.contains(&address))
+        {
+            self.watched_writes.push((address, byte));
+        }
+        if !self.fault_injection {
+            return self.write_device(address, byte);
+        }
+
+        let faults = match self.faults.get_mut() {
+            Some(faults) => faults,
+            None => return self.write_device(address, byte),
+        };
+
+        let due = faults.due_writes();
+        let byte = faults.on_write(address, byte);
+
+        for (address, byte) in due {
+            self.write_device(address, byte);
+        }
+        if let Some(byte) = byte {
+            self.write_device(address, byte);
+        }
+    }
+
+    /// Read without fault injection and OAM DMA, for the PPU and tracing.
+    fn peek_byte(&self, address: Word) -> HalfWord {
+        self.peek(address)
+    }
+
+    /// Advance the peripherals clocked by the CPU.
+    /// `cycles` are CPU clock cycles. They run at twice the dot clock in
+    /// double speed mode, so the PPU sees half of them then.
+    fn tick(&mut self, cycles: usize) {
+        let dots = if self.double_speed { 2 } else { 4 };
+
+        for _ in 0..cycles / 4 {
+            let frames = self.gpu.frame_count();
+            if let Some(viewer) = self.event_viewer.as_mut() {
+                let oam_dma = matches!(&self.dma_transfer, Some(transfer) if !transfer.setup);
+                viewer.record(self.gpu.position(), dots, oam_dma);
+            }
+
+            self.step_dma();
+            self.interrupts.flags |= self.gpu.step(&self.video_ram, &self.oam_ram, dots);
+            self.apu.step(dots);
+
+            // H-blank DMA copies one block at the start of every H-blank
+            let hblank = self.gpu.in_hblank();
+            if hblank && !self.hblank && self.hdma.active() {
+                let (source, destination) = self.copy_hdma_block();
+                self.record_dma(DmaKind::HBlank, source, destination, hdma::BLOCK_SIZE, 1);
+            }
+            self.hblank = hblank;
+
+            if self.gpu.frame_count() != frames {
+                if let Some(viewer) = self.event_viewer.as_mut() {
+                    viewer.end_frame();
+                }
+            }
+        }
+
+        let second = self.time / CLOCK_RATE as u64;
+        self.time += (cycles / 4 * dots) as u64;
+        if self.time / CLOCK_RATE as u64 != second {
+            self.update_rtc();
+        }
+
+        if self.timer.tick(cycles) {
+            self.interrupts.request(Interrupt::Timer);
+        }
+        // DIV bit 4 clocks the frame sequencer, bit 5 in double speed mode
+        let div_bit = if self.double_speed { 0x20 } else { 0x10 };
+        self.apu
+            .clock_frame_sequencer(self.timer.read(0) & div_bit != 0);
+        if self.serial.tick(cycles) {
+            self.interrupts.request(Interrupt::Serial);
+        }
+    }
+
+    fn take_stall_cycles(&mut self) -> usize {
+        std::mem::take(&mut self.stall_cycles)
+    }
+
+    fn switch_speed(&mut self) -> bool {
+        if self.model != Model::Cgb || !self.speed_switch {
+            return false;
+        }
+
+        self.double_speed = !self.double_speed;
+        self.speed_switch = false;
+        self.stall_cycles += SPEED_SWITCH_CYCLES;
+
+        true
+    }
+
+    fn pending_interrupts(&self) -> HalfWord {
+        self.interrupts.pending()
+    }
+
+    fn acknowledge_interrupt(&mut self) -> Option<Interrupt> {
+        self.interrupts.acknowledge()
+    }
+
+    fn set_pc(&mut self, pc: Word) {
+        self.pc = pc;
+    }
+
+    fn record_unimplemented(&mut self, feature: Unimplemented) {
+        self.record(feature);
+    }
+
+    fn corrupt_oam(&mut self, kind: OamCorruption) {
+        if let Some(row) = self.gpu.oam_scan_row() {
+            self.corrupt_oam_at(kind, row);
+        }
+    }
+}
+
+/// Format `bytes` read from `start` as a hexdump, 16 bytes per line.
+///```text
+/// C000  00 01 02 03 04 05 06 07  08 09 0A 0B 0C 0D 0E 0F  |................|
+/// ```
+pub fn hexdump(start: Word, bytes: &[u8]) -> String {
+    let mut out = String::new();
+
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let address = start.wrapping_add(i as Word * 16);
+        out.push_str(&format!("{:04X} ", address));
+
+        for column in 0..16 {
+            if column == 8 {
+                out.push(' ');
+            }
+            match line.get(column) {
+                Some(byte) => out.push_str(&format!(" {:02X}", byte)),
+                None => out.push_str("   "),
+            }
+        }
+
+        let ascii: String = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        out.push_str(&format!("  |{}|\n", ascii));
+    }
+
+    out
+}
+
+/// Boot ROM image, mapped over the cartridge at power on.
+///```text
+/// 0000-00FF  DMG and CGB
+/// 0200-08FF  CGB only, the cartridge header in between stays visible
+/// ```
+/// Ref https://gbdev.io/pandocs/Power_Up_Sequence.html
+#[derive(Debug, Clone)]
+pub struct BootRom {
+    data: Vec<u8>,
+}
+
+impl BootRom {
+    pub fn from_bytes(bytes: &[u8]) -> Result<BootRom> {
+        match bytes.len() {
+            DMG_BOOT_ROM_SIZE | CGB_BOOT_ROM_SIZE => Ok(BootRom {
+                data: bytes.to_vec(),
+            }),
+            size => anyhow::bail!(
+                "boot ROM must be {} (DMG) or {} (CGB) bytes, got {}",
+                DMG_BOOT_ROM_SIZE,
+                CGB_BOOT_ROM_SIZE,
+                size
+            ),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<BootRom> {
+        BootRom::from_bytes(&std::fs::read(path)?)
+    }
+
+    fn maps(&self, address: Word) -> bool {
+        match address {
+            0x0000..0x0100 => true,
+            0x0200..0x0900 => self.data.len() == CGB_BOOT_ROM_SIZE,
+            _ => false,
+        }
+    }
+
+    fn read(&self, address: Word) -> HalfWord {
+        self.data.get(address as usize).copied().unwrap_or(0xFF)
+    }
+}
+
+const DMG_BOOT_ROM_SIZE: usize = 0x100;
+const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+const OAM_ROWS: usize = 20;
+const OAM_SIZE: Word = 0xA0;
+const WORKING_RAM_BANK_SIZE: Word = 0x1000;
+// 8 machine cycles per 16 byte block
+const HDMA_BLOCK_CYCLES: usize = 32;
+// The CPU is stopped for 2050 machine cycles while the clock switches
+// Ref https://gbdev.io/pandocs/CGB_Registers.html#ff4d--key1-cgb-mode-only-prepare-speed-switch
+const SPEED_SWITCH_CYCLES: usize = 2050 * 4;
+
+#[derive(Debug)]
+struct DmaTransfer {
+    source: Word,
+    // Next byte to copy
+    offset: Word,
+    // The first machine cycle after the write to 0xFF46 copies nothing
+    setup: bool,
+}
+
+/// Kind of CPU access that triggers the OAM corruption bug.
+#[derive(Debug, Clone, Copy)]
+pub enum OamCorruption {
+    /// A write, or a 16 bit increment/decrement of a pointer into OAM
+    Write,
+    Read,
+    /// A read combined with a pointer increment/decrement (`LD A, (HL+)` etc.)
+    ReadIncrease,
+}
+
 type Address = Word;
 #[derive(Debug)]
 enum Device {
     HRam(Address),
     OamRam(Address),
-    MirrorRam(Address),
     WorkingRam(Address),
+    Svbk,
+    Vbk,
+    Key1,
+    Hdma(Address),
+    Palette(Address),
+    Pcm(Address),
     VideoRam(Address),
     Cartridge(Address),
+    /// Only while a boot ROM is mapped, resolved by the bus
+    BootRom(Address),
+    /// FF50, unmaps the boot ROM
+    Boot,
     Gpu(Address),
+    Apu(Address),
+    Infrared,
     P1,
+    Dma,
     IF,
-    DIV,
+    IE,
     Timer(Address),
+    Serial(Address),
+    /// FEA0-FEFF, reads 0xFF and ignores writes
+    Unusable,
     Unimplement,
 }
 
@@ -126,30 +1131,33 @@ impl Device {
             0x8000..0xA000 => Device::VideoRam(addr - 0x8000),
             0xA000..0xC000 => Device::Cartridge(addr),
             0xC000..0xE000 => Device::WorkingRam(addr - 0xC000),
-            0xE000..0xFE00 => Device::MirrorRam(addr - 0xE000),
+            // Echo RAM mirrors working RAM
+            0xE000..0xFE00 => Device::WorkingRam(addr - 0xE000),
             0xFE00..0xFEA0 => Device::OamRam(addr - 0xFE00),
-            0xFF80..=0xFFFF => Device::HRam(addr - 0xFF80),
-            0xFF40..0xFF80 => Device::Gpu(addr - 0xFF40),
-            0xFF00 => {
-                // TODO Padの実装が入る
-                log::warn!("TODO: implement Pad device");
-                Device::Unimplement
-            }
-            0xFF04 => {
-                // TODO DIV の実装が入る
-                log::warn!("TODO: implement DIV register");
-                Device::Unimplement
-            }
-            0xFF05..0xFF08 => {
-                // TODO Timerの実装が入る
-                log::warn!("TODO: implement timer");
-                Device::Unimplement
-            }
-            0xFF0F => {
-                // TODO IF の実装が入る
-                log::warn!("TODO: implement IF device");
-                Device::Unimplement
-            }
+            0xFEA0..0xFF00 => Device::Unusable,
+            0xFF00..0xFF80 => Device::resolve_io_register(addr),
+            0xFF80..0xFFFF => Device::HRam(addr - 0xFF80),
+            io::IE => Device::IE,
+        }
+    }
+
+    fn resolve_io_register(addr: Word) -> Device {
+        match addr {
+            io::P1 => Device::P1,
+            io::SB..=io::SC => Device::Serial(addr - io::SB),
+            io::DIV..=io::TAC => Device::Timer(addr - io::DIV),
+            io::IF => Device::IF,
+            io::NR10..=io::WAVE_RAM_END => Device::Apu(addr - io::NR10),
+            io::DMA => Device::Dma,
+            io::LCDC..=io::WX => Device::Gpu(addr - io::LCDC),
+            io::BOOT => Device::Boot,
+            io::RP => Device::Infrared,
+            io::SVBK => Device::Svbk,
+            io::KEY1 => Device::Key1,
+            io::VBK => Device::Vbk,
+            io::HDMA1..=io::HDMA5 => Device::Hdma(addr - io::HDMA1),
+            io::BCPS..=io::OCPD => Device::Palette(addr - io::BCPS),
+            io::PCM12..=io::PCM34 => Device::Pcm(addr - io::PCM12),
             _ => Device::Unimplement,
         }
     }
@@ -1,19 +1,463 @@
+use crate::licensee::Licensee;
+use crate::mbc::{self, Mbc, NoMbc};
+use crate::state::{StateReader, StateWriter};
 use crate::{HalfWord, Word};
+use anyhow::Result;
+use std::fmt;
+use std::str::FromStr;
 
 pub struct Cartridge {
     pub data: Vec<u8>,
+    mbc: Box<dyn Mbc>,
+    // The cartridge type has no built in mapper, it is treated as ROM only
+    unknown_mbc: bool,
+    header: CartridgeHeader,
 }
 
 impl Cartridge {
-    pub fn new(data: Vec<u8>) -> Cartridge {
-        Cartridge { data }
+    /// Pick the mapper from the cartridge type in the header.
+    pub fn new(data: Vec<u8>) -> Result<Cartridge> {
+        let (data, header) = validate_rom_size(data)?;
+
+        let (mbc, unknown_mbc) = match mbc::for_cartridge(&header, &data) {
+            Some(mbc) => (mbc, false),
+            None => {
+                let ram = vec![0; header.ram_size.unwrap_or(0)];
+                (Box::new(NoMbc::new(ram)) as Box<dyn Mbc>, true)
+            }
+        };
+
+        Ok(Cartridge {
+            data,
+            mbc,
+            unknown_mbc,
+            header,
+        })
+    }
+
+    /// Use a custom mapper, whatever the header says.
+    pub fn with_mbc(data: Vec<u8>, mbc: Box<dyn Mbc>) -> Result<Cartridge> {
+        let (data, header) = validate_rom_size(data)?;
+
+        Ok(Cartridge {
+            data,
+            mbc,
+            unknown_mbc: false,
+            header,
+        })
+    }
+
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+
+    pub fn mbc(&self) -> &dyn Mbc {
+        self.mbc.as_ref()
+    }
+
+    /// Contents of the battery save file, including the RTC state.
+    pub fn save_data(&self) -> Vec<u8> {
+        self.mbc.save_data()
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.mbc.load_save_data(data)
+    }
+
+    pub fn mbc_mut(&mut self) -> &mut dyn Mbc {
+        self.mbc.as_mut()
+    }
+
+    /// The mapper state and RAM, the ROM is not part of it.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        self.mbc.save_state(state)
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.mbc.load_state(state)
     }
 
     pub fn read(&self, address: Word) -> u8 {
-        self.data[address as usize]
+        if address < 0x8000 {
+            self.mbc.read_rom(&self.data, address)
+        } else {
+            self.mbc.read_ram(address)
+        }
     }
 
     pub fn write(&mut self, address: Word, byte: HalfWord) {
-        self.data[address as usize] = byte
+        self.mbc.write(address, byte)
+    }
+
+    /// Whether the cartridge type has no built in mapper, and is treated as
+    /// ROM only.
+    pub fn unknown_mbc(&self) -> bool {
+        self.unknown_mbc
+    }
+
+    /// Patch the byte at `address` as currently mapped, without mapper side
+    /// effects.
+    pub fn poke(&mut self, address: Word, byte: HalfWord) {
+        if address < 0x8000 {
+            let offset = mbc::rom_offset(self.data.len(), self.mbc.rom_bank(address), address);
+            if let Some(b) = self.data.get_mut(offset) {
+                *b = byte
+            }
+        } else if let Some(offset) = self.mbc.ram_offset(address) {
+            self.mbc.ram_mut()[offset] = byte
+        }
+    }
+}
+
+/// The cartridge header ends at 0x014F.
+const HEADER_END: usize = 0x0150;
+
+/// Reject images too small to hold a header, and pad images smaller than the
+/// ROM size in the header (common with homebrew) to that size, or to a power
+/// of two number of banks if the size is unknown. Padding reads as 0xFF like
+/// an unconnected bus. Returns the image with its parsed header.
+fn validate_rom_size(mut data: Vec<u8>) -> Result<(Vec<u8>, CartridgeHeader)> {
+    if data.len() < HEADER_END {
+        anyhow::bail!(
+            "ROM image is {} bytes, too small to contain a cartridge header ({} bytes)",
+            data.len(),
+            HEADER_END
+        );
+    }
+
+    let header = CartridgeHeader::parse(&data);
+    let size = match header.rom_size {
+        Some(size) => size,
+        None => {
+            log::warn!("unknown ROM size code {:02X} in the header", data[0x0148]);
+            let banks = data.len().div_ceil(mbc::ROM_BANK_SIZE);
+            banks.next_power_of_two().max(2) * mbc::ROM_BANK_SIZE
+        }
+    };
+
+    if data.len() > size {
+        log::warn!(
+            "ROM image is {} bytes, larger than the {} bytes in the header",
+            data.len(),
+            size
+        );
+    } else if data.len() < size {
+        log::warn!(
+            "ROM image is {} bytes, padding it to the {} bytes in the header",
+            data.len(),
+            size
+        );
+        data.resize(size, 0xFF);
+    }
+
+    Ok((data, header))
+}
+
+/// Hardware model to emulate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    Dmg,
+    Sgb,
+    Cgb,
+}
+
+impl FromStr for Model {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Model> {
+        match s {
+            "dmg" => Ok(Model::Dmg),
+            "sgb" => Ok(Model::Sgb),
+            "cgb" => Ok(Model::Cgb),
+            _ => anyhow::bail!("unknown model {} (dmg, sgb, cgb)", s),
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Model::Dmg => write!(f, "dmg"),
+            Model::Sgb => write!(f, "sgb"),
+            Model::Cgb => write!(f, "cgb"),
+        }
+    }
+}
+
+/// The cartridge header (0x0100-0x014F)
+/// Ref https://gbdev.io/pandocs/The_Cartridge_Header.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    /// Upper case ASCII, up to 16 characters (15 on CGB aware games)
+    pub title: String,
+    pub cgb: CgbFlag,
+    /// SGB functions are supported, which also needs the old licensee code 0x33
+    pub sgb: bool,
+    /// Mapper and extra hardware (0x0147)
+    pub cartridge_type: HalfWord,
+    /// ROM size in bytes, `None` for unknown size codes (0x0148)
+    pub rom_size: Option<usize>,
+    /// External RAM size in bytes, `None` for unknown size codes (0x0149)
+    pub ram_size: Option<usize>,
+    pub destination: Destination,
+    pub licensee: Licensee,
+    /// Checksum over 0x0134-0x014C, checked by the boot ROM (0x014D)
+    pub header_checksum: HalfWord,
+    /// Big endian sum of every ROM byte except these two (0x014E-0x014F)
+    pub global_checksum: Word,
+}
+
+impl CartridgeHeader {
+    /// The cartridge keeps its RAM (and RTC) powered by a battery.
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+        )
     }
+
+    /// Name of the mapper and extra hardware in the cartridge type, `None`
+    /// for unassigned types.
+    pub fn cartridge_type_name(&self) -> Option<&'static str> {
+        let name = match self.cartridge_type {
+            0x00 => "ROM only",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => return None,
+        };
+
+        Some(name)
+    }
+
+    /// Missing bytes of images smaller than the header read as 0.
+    pub fn parse(rom: &[u8]) -> CartridgeHeader {
+        let byte = |address: usize| rom.get(address).copied().unwrap_or(0);
+
+        let cgb = match byte(0x0143) {
+            0xC0 => CgbFlag::Only,
+            0x80 => CgbFlag::Enhanced,
+            _ => CgbFlag::None,
+        };
+
+        // The last title byte became the CGB flag
+        let title_end = if cgb == CgbFlag::None { 0x0144 } else { 0x0143 };
+        let title = (0x0134..title_end)
+            .map(byte)
+            .take_while(|&b| b != 0)
+            .map(|b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '?'
+                }
+            })
+            .collect();
+
+        let rom_size = match byte(0x0148) {
+            code @ 0x00..=0x08 => Some(0x8000 << code),
+            _ => None,
+        };
+
+        let ram_size = match byte(0x0149) {
+            0x00 => Some(0),
+            // Unused by any known cartridge
+            0x01 => Some(0x800),
+            0x02 => Some(0x2000),
+            0x03 => Some(0x8000),
+            0x04 => Some(0x20000),
+            0x05 => Some(0x10000),
+            _ => None,
+        };
+
+        let destination = match byte(0x014A) {
+            0x00 => Destination::Japan,
+            _ => Destination::Overseas,
+        };
+
+        let licensee = match byte(0x014B) {
+            0x33 => Licensee::New([byte(0x0144), byte(0x0145)]),
+            code => Licensee::Old(code),
+        };
+
+        CartridgeHeader {
+            title,
+            cgb,
+            sgb: byte(0x0146) == 0x03 && byte(0x014B) == 0x33,
+            cartridge_type: byte(0x0147),
+            rom_size,
+            ram_size,
+            destination,
+            licensee,
+            header_checksum: byte(0x014D),
+            global_checksum: (byte(0x014E) as Word) << 8 | byte(0x014F) as Word,
+        }
+    }
+}
+
+/// CGB flag (0x0143)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgbFlag {
+    /// Made for DMG
+    None,
+    /// Uses CGB functions, but also runs on DMG
+    Enhanced,
+    Only,
+}
+
+/// Destination code (0x014A)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+/// The selected model can not show the game the way it was meant to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderWarning {
+    /// The game only shows a screen asking for a Game Boy Color
+    CgbOnly { destination: Destination },
+    /// The game runs, but in monochrome
+    CgbEnhanced,
+    /// The game runs without its border and palettes
+    SgbEnhanced,
+}
+
+impl HeaderWarning {
+    pub fn suggested_model(&self) -> Model {
+        match self {
+            HeaderWarning::CgbOnly { .. } | HeaderWarning::CgbEnhanced => Model::Cgb,
+            HeaderWarning::SgbEnhanced => Model::Sgb,
+        }
+    }
+}
+
+impl fmt::Display for HeaderWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderWarning::CgbOnly { destination } => {
+                let screen = match destination {
+                    Destination::Japan => "a Japanese",
+                    Destination::Overseas => "an English",
+                };
+                write!(f, "Game Boy Color only game, it will only show {} message asking for a Game Boy Color", screen)?
+            }
+            HeaderWarning::CgbEnhanced => {
+                write!(f, "Game Boy Color enhanced game, it will run in monochrome")?
+            }
+            HeaderWarning::SgbEnhanced => write!(
+                f,
+                "Super Game Boy enhanced game, its border and palettes will be missing"
+            )?,
+        }
+
+        write!(f, " (try --model {})", self.suggested_model())
+    }
+}
+
+/// Check the CGB flag (0x0143), SGB flag (0x0146) and destination code
+/// (0x014A) of `rom` against the model it is going to run on.
+/// Ref https://gbdev.io/pandocs/The_Cartridge_Header.html
+pub fn header_warnings(rom: &[u8], model: Model) -> Vec<HeaderWarning> {
+    let header = CartridgeHeader::parse(rom);
+    let mut warnings = Vec::new();
+
+    match header.cgb {
+        CgbFlag::Only if model != Model::Cgb => warnings.push(HeaderWarning::CgbOnly {
+            destination: header.destination,
+        }),
+        CgbFlag::Enhanced if model != Model::Cgb => warnings.push(HeaderWarning::CgbEnhanced),
+        _ => {}
+    }
+
+    if header.sgb && model == Model::Dmg {
+        warnings.push(HeaderWarning::SgbEnhanced);
+    }
+
+    warnings
+}
+
+/// A checksum in the header does not match the ROM image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumMismatch {
+    /// The boot ROM locks up on real hardware
+    Header {
+        expected: HalfWord,
+        computed: HalfWord,
+    },
+    /// Ignored by real hardware, but a sign of a corrupt or patched image
+    Global { expected: Word, computed: Word },
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChecksumMismatch::Header { expected, computed } => write!(
+                f,
+                "header checksum is {:02X}, the header says {:02X}",
+                computed, expected
+            ),
+            ChecksumMismatch::Global { expected, computed } => write!(
+                f,
+                "global checksum is {:04X}, the header says {:04X}",
+                computed, expected
+            ),
+        }
+    }
+}
+
+/// Verify the header checksum (0x014D) and the global checksum (0x014E-0x014F)
+/// of `rom`.
+/// Ref https://gbdev.io/pandocs/The_Cartridge_Header.html#014d--header-checksum
+pub fn checksum_mismatches(rom: &[u8]) -> Vec<ChecksumMismatch> {
+    let header = CartridgeHeader::parse(rom);
+    let mut mismatches = Vec::new();
+
+    let computed = (0x0134..=0x014C)
+        .map(|address| rom.get(address).copied().unwrap_or(0))
+        .fold(0u8, |x, b| x.wrapping_sub(b).wrapping_sub(1));
+    if computed != header.header_checksum {
+        mismatches.push(ChecksumMismatch::Header {
+            expected: header.header_checksum,
+            computed,
+        });
+    }
+
+    let computed = rom
+        .iter()
+        .enumerate()
+        .filter(|(address, _)| !(0x014E..=0x014F).contains(address))
+        .fold(0u16, |sum, (_, &b)| sum.wrapping_add(b as Word));
+    if computed != header.global_checksum {
+        mismatches.push(ChecksumMismatch::Global {
+            expected: header.global_checksum,
+            computed,
+        });
+    }
+
+    mismatches
 }
@@ -0,0 +1,187 @@
+//! Game Genie and GameShark cheat codes.
+//!
+//! Game Genie codes patch bytes read from cartridge ROM, GameShark codes
+//! write a byte to RAM once per frame (at VBlank).
+
+use crate::{HalfWord, Word};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cheat {
+    /// Replace the ROM byte at `address` with `data`, only when it is
+    /// `compare` if given
+    GameGenie {
+        address: Word,
+        data: HalfWord,
+        compare: Option<HalfWord>,
+    },
+    /// Write `value` to `address` every frame
+    GameShark { address: Word, value: HalfWord },
+}
+
+impl FromStr for Cheat {
+    type Err = anyhow::Error;
+
+    /// Game Genie: `ABC-DEF` or `ABC-DEF-GHI`, GameShark: `01VVLLHH`.
+    fn from_str(s: &str) -> anyhow::Result<Cheat> {
+        let digits: Vec<u8> = s
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(|| anyhow::anyhow!("invalid cheat code {}, not hexadecimal", s))?;
+
+        match (digits.len(), s.contains('-')) {
+            (6, _) | (9, true) => Ok(Cheat::game_genie(&digits)),
+            (8, false) => Cheat::game_shark(&digits),
+            _ => anyhow::bail!(
+                "invalid cheat code {} (Game Genie: ABC-DEF or ABC-DEF-GHI, GameShark: 01VVLLHH)",
+                s
+            ),
+        }
+    }
+}
+
+impl Cheat {
+    /// Ref https://gbdev.gg8.se/wiki/articles/Gameboy_Game_Genie_Codes
+    fn game_genie(d: &[u8]) -> Cheat {
+        let data = d[0] << 4 | d[1];
+        let address = ((d[5] ^ 0x0F) as Word) << 12
+            | (d[2] as Word) << 8
+            | (d[3] as Word) << 4
+            | d[4] as Word;
+        // Digit 7 is only there to detect typos on the real device
+        let compare = d.get(8).map(|&i| (d[6] << 4 | i).rotate_right(2) ^ 0xBA);
+
+        Cheat::GameGenie {
+            address,
+            data,
+            compare,
+        }
+    }
+
+    /// Ref https://gbdev.gg8.se/wiki/articles/Gameboy_Game_Shark_Codes
+    fn game_shark(d: &[u8]) -> anyhow::Result<Cheat> {
+        let byte = |i: usize| d[i] << 4 | d[i + 1];
+
+        // Other types select a CGB working RAM bank
+        match byte(0) {
+            0x00 | 0x01 => {}
+            kind => anyhow::bail!("unsupported GameShark code type {:02X}", kind),
+        }
+
+        let address = (byte(6) as Word) << 8 | byte(4) as Word;
+        // Writing would patch the ROM image for good, Game Genie codes do that
+        if address < 0x8000 {
+            anyhow::bail!("GameShark code writes to ROM at {:04X}", address);
+        }
+
+        Ok(Cheat::GameShark {
+            value: byte(2),
+            address,
+        })
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cheat::GameGenie {
+                address,
+                data,
+                compare: Some(compare),
+            } => write!(f, "{:04X}={:02X} if {:02X}", address, data, compare),
+            Cheat::GameGenie { address, data, .. } => write!(f, "{:04X}={:02X}", address, data),
+            Cheat::GameShark { address, value } => {
+                write!(f, "{:04X}={:02X} every frame", address, value)
+            }
+        }
+    }
+}
+
+/// The cheats in use, keyed by the code they were entered as.
+#[derive(Debug, Default)]
+pub struct Cheats {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    code: String,
+    cheat: Cheat,
+    enabled: bool,
+}
+
+impl Cheats {
+    /// Add an enabled cheat. Adding a code twice has no effect.
+    pub fn add(&mut self, code: &str) -> anyhow::Result<Cheat> {
+        let code = code.trim().to_ascii_uppercase();
+        let cheat = code.parse()?;
+
+        if !self.entries.iter().any(|e| e.code == code) {
+            self.entries.push(Entry {
+                code,
+                cheat,
+                enabled: true,
+            });
+        }
+
+        Ok(cheat)
+    }
+
+    /// Returns whether the code was in use.
+    pub fn remove(&mut self, code: &str) -> bool {
+        let code = code.trim().to_ascii_uppercase();
+        let len = self.entries.len();
+        self.entries.retain(|e| e.code != code);
+
+        self.entries.len() != len
+    }
+
+    /// Returns whether the code was in use.
+    pub fn set_enabled(&mut self, code: &str, enabled: bool) -> bool {
+        let code = code.trim().to_ascii_uppercase();
+        match self.entries.iter_mut().find(|e| e.code == code) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply the Game Genie codes to `byte` read from ROM at `address`.
+    pub fn patch_rom(&self, address: Word, byte: HalfWord) -> HalfWord {
+        self.enabled()
+            .find_map(|cheat| match *cheat {
+                Cheat::GameGenie {
+                    address: a,
+                    data,
+                    compare,
+                } if a == address && compare.unwrap_or(byte) == byte => Some(data),
+                _ => None,
+            })
+            .unwrap_or(byte)
+    }
+
+    /// Addresses patched by the Game Genie codes.
+    pub fn rom_addresses(&self) -> impl Iterator<Item = Word> + '_ {
+        self.enabled().filter_map(|cheat| match *cheat {
+            Cheat::GameGenie { address, .. } => Some(address),
+            _ => None,
+        })
+    }
+
+    /// The GameShark writes to perform at VBlank.
+    pub fn ram_writes(&self) -> impl Iterator<Item = (Word, HalfWord)> + '_ {
+        self.enabled().filter_map(|cheat| match *cheat {
+            Cheat::GameShark { address, value } => Some((address, value)),
+            _ => None,
+        })
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &Cheat> {
+        self.entries.iter().filter(|e| e.enabled).map(|e| &e.cheat)
+    }
+}
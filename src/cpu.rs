@@ -1,4 +1,6 @@
-use crate::SharedBus;
+use crate::bus::{MemoryBus, OamCorruption};
+use crate::state::{StateReader, StateWriter};
+use crate::telemetry::Unimplemented;
 use crate::{join_half_words, split_word, HalfWord, Word};
 use anyhow::Result;
 
@@ -80,13 +82,17 @@ struct FlagRegister {
 impl FlagRegister {
     pub fn from_byte(byte: u8) -> FlagRegister {
         FlagRegister {
-            z: (byte >> 6) == 1,
-            n: (byte >> 5) == 1,
-            h: (byte >> 4) == 1,
-            c: (byte >> 3) == 1,
+            z: byte & 0x80 == 0x80,
+            n: byte & 0x40 == 0x40,
+            h: byte & 0x20 == 0x20,
+            c: byte & 0x10 == 0x10,
         }
     }
 
+    pub fn to_byte(&self) -> u8 {
+        (self.z as u8) << 7 | (self.n as u8) << 6 | (self.h as u8) << 5 | (self.c as u8) << 4
+    }
+
     pub fn set_z(&mut self, flag: bool) {
         self.z = flag
     }
@@ -114,6 +120,21 @@ impl FlagRegister {
     }
 }
 
+/// Snapshot of the CPU registers, e.g. for tracing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuState {
+    pub a: HalfWord,
+    pub f: HalfWord,
+    pub b: HalfWord,
+    pub c: HalfWord,
+    pub d: HalfWord,
+    pub e: HalfWord,
+    pub h: HalfWord,
+    pub l: HalfWord,
+    pub sp: Word,
+    pub pc: Word,
+}
+
 // ref http://marc.rawer.de/Gameboy/Docs/GBCPUman.pdf
 const INIT_PC: Word = 0x100;
 const INIT_SP: Word = 0xFFFE;
@@ -122,13 +143,28 @@ pub struct Cpu {
     registers: Registers,
     pc: Word,
     sp: Word,
-    bus: SharedBus,
 
     halted: bool,
+    // Interrupt master enable
+    ime: bool,
+    // EI enables interrupts after the following instruction
+    ei_pending: bool,
+    // Elapsed clock cycles (T-cycles). One machine cycle is 4 clock cycles.
+    cycles: usize,
+    // Emulate the DMG OAM corruption bug
+    oam_corruption: bool,
+    // Treat unimplemented opcodes as NOP instead of panicking
+    skip_unimplemented: bool,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu::new()
+    }
 }
 
 impl Cpu {
-    pub fn new(bus: SharedBus) -> Self {
+    pub fn new() -> Self {
         Cpu {
             pc: INIT_PC,
             sp: INIT_SP,
@@ -142,181 +178,259 @@ impl Cpu {
                 h: 0x00,
                 l: 0x0D,
             },
-            bus,
             halted: false,
+            ime: false,
+            ei_pending: false,
+            cycles: 0,
+            oam_corruption: false,
+            skip_unimplemented: false,
         }
     }
 
-    pub fn step(&mut self) -> Result<()> {
+    /// Power cycle, keeping the accuracy settings.
+    pub fn reset(&mut self) {
+        *self = Cpu {
+            oam_corruption: self.oam_corruption,
+            skip_unimplemented: self.skip_unimplemented,
+            ..Cpu::new()
+        };
+    }
+
+    pub fn step(&mut self, bus: &mut impl MemoryBus) -> Result<()> {
+        // The CPU is stopped while HDMA copies to VRAM
+        let stall = bus.take_stall_cycles();
+        if stall > 0 {
+            for _ in 0..stall / 4 {
+                self.tick(bus);
+            }
+            return Ok(());
+        }
+
+        if self.handle_interrupt(bus) {
+            return Ok(());
+        }
+
         if self.halted {
+            self.tick(bus);
             return Ok(());
         }
 
-        let opcode = self.fetch();
+        let ei_pending = self.ei_pending;
+
+        bus.set_pc(self.pc);
+        let opcode = self.fetch(bus);
 
-        self.execute(opcode);
+        self.execute(bus, opcode);
+
+        if ei_pending && self.ei_pending {
+            self.ime = true;
+            self.ei_pending = false;
+        }
 
         Ok(())
     }
 
-    fn fetch(&mut self) -> Opecode {
-        let opcode = self.bus_read_byte(self.pc);
-        self.pc += 1;
+    /// Service the highest priority pending interrupt, if any.
+    /// Returns whether an interrupt was dispatched.
+    fn handle_interrupt(&mut self, bus: &mut impl MemoryBus) -> bool {
+        let pending = bus.pending_interrupts();
+        if pending == 0 {
+            return false;
+        }
+
+        // A pending interrupt ends HALT even when IME is off
+        self.halted = false;
+
+        if !self.ime {
+            return false;
+        }
+        self.ime = false;
+
+        // Dispatch takes 5 machine cycles: 2 wait states, 2 pushes and the jump
+        self.tick(bus);
+        self.tick(bus);
+
+        let (upper, lower) = split_word(self.pc);
+        self.push(bus, upper);
+
+        // The interrupt is only chosen after the upper byte is pushed. If that
+        // push overwrote IE and nothing is pending anymore, the dispatch is
+        // cancelled and execution continues at 0x0000 (mooneye ie_push).
+        let interrupt = bus.acknowledge_interrupt();
+
+        self.push(bus, lower);
+
+        self.pc = match interrupt {
+            Some(interrupt) => interrupt.vector(),
+            None => 0x0000,
+        };
+        self.tick(bus);
+
+        true
+    }
+
+    fn fetch(&mut self, bus: &mut impl MemoryBus) -> Opecode {
+        let opcode = self.bus_read_byte(bus, self.pc);
+        self.pc = self.pc.wrapping_add(1);
 
         opcode
     }
 
-    fn fetch_operands(&mut self, length_in_bytes: usize) -> Vec<u8> {
-        (0..length_in_bytes).map(|_| self.fetch()).collect()
+    fn fetch_operands(&mut self, bus: &mut impl MemoryBus, length_in_bytes: usize) -> Vec<u8> {
+        (0..length_in_bytes).map(|_| self.fetch(bus)).collect()
     }
 
     // opcode list https://izik1.github.io/gbops/
-    fn execute(&mut self, opcode: Opecode) {
+    fn execute(&mut self, bus: &mut impl MemoryBus, opcode: Opecode) {
         match opcode {
             //  ------------ 0x0N ----------------
             0x00 => {} // NOP
             0x01 => {
                 // LD BC, u16
-                let operands = self.fetch_operands(2);
+                let operands = self.fetch_operands(bus, 2);
                 self.ldn_u16(TargetRegister::B, TargetRegister::C, operands)
             }
-            0x02 => self.ldrr_r(TargetRegister::B, TargetRegister::C, TargetRegister::A), // LD (BC),A
-            0x03 => self.inc_u16(TargetRegister::B, TargetRegister::C),                   // INC BC
-            0x04 => self.inc_u8(TargetRegister::B),                                       // INC B
-            0x05 => self.dec_u8(TargetRegister::B),                                       // DEC B
+            0x02 => self.ldrr_r(bus, TargetRegister::B, TargetRegister::C, TargetRegister::A), // LD (BC),A
+            0x03 => self.inc_u16(bus, TargetRegister::B, TargetRegister::C), // INC BC
+            0x04 => self.inc_u8(TargetRegister::B),                          // INC B
+            0x05 => self.dec_u8(TargetRegister::B),                          // DEC B
             0x06 => {
                 // LD B,u8
-                let operands = self.fetch_operands(1);
+                let operands = self.fetch_operands(bus, 1);
                 self.ldn_u8(TargetRegister::B, operands)
             }
             0x07 => self.rlca(), // RLCA
             0x08 => {
                 // LD (u16), SP
-                let operands = self.fetch_operands(2);
-                self.ldnn_sp(operands);
+                let operands = self.fetch_operands(bus, 2);
+                self.ldnn_sp(bus, operands);
             }
-            0x09 => self.addhl_rr(TargetRegister::B, TargetRegister::C), // ADD HL, BC
-            0x0A => self.ldr_rr(TargetRegister::A, TargetRegister::B, TargetRegister::C), // LD A, (BC)
-            0x0B => self.dec_u16(TargetRegister::B, TargetRegister::C),                   // DEC BC
-            0x0C => self.inc_u8(TargetRegister::C),                                       // INC C
-            0x0D => self.dec_u8(TargetRegister::C),                                       // DEC C
+            0x09 => self.addhl_rr(bus, TargetRegister::B, TargetRegister::C), // ADD HL, BC
+            0x0A => self.ldr_rr(bus, TargetRegister::A, TargetRegister::B, TargetRegister::C), // LD A, (BC)
+            0x0B => self.dec_u16(bus, TargetRegister::B, TargetRegister::C), // DEC BC
+            0x0C => self.inc_u8(TargetRegister::C),                          // INC C
+            0x0D => self.dec_u8(TargetRegister::C),                          // DEC C
             0x0E => {
                 // LD C,u8
-                let operands = self.fetch_operands(1);
+                let operands = self.fetch_operands(bus, 1);
                 self.ldn_u8(TargetRegister::C, operands)
             }
             0x0F => self.rrca(), // RRCA
 
             //  ------------ 0X1N ----------------
-            0x10 => todo!(), // 0x10, "STOP", 1, 0, func(cpu *CPU, operands []byte) { cpu.stop() }},
+            0x10 => {
+                // STOP
+                self.fetch_operands(bus, 1);
+                self.stop(bus)
+            }
             0x11 => {
                 // LD DE, u16
-                let operands = self.fetch_operands(2);
+                let operands = self.fetch_operands(bus, 2);
                 self.ldn_u16(TargetRegister::D, TargetRegister::E, operands)
             }
-            0x12 => todo!(),
-            0x13 => todo!(),
-            0x14 => todo!(),
-            0x15 => todo!(),
+            0x12 => self.unimplemented(bus, opcode),
+            0x13 => self.unimplemented(bus, opcode),
+            0x14 => self.unimplemented(bus, opcode),
+            0x15 => self.unimplemented(bus, opcode),
             0x16 => {
                 // LD D, u8
-                let operands = self.fetch_operands(1);
+                let operands = self.fetch_operands(bus, 1);
                 self.ldn_u8(TargetRegister::D, operands)
             }
-            0x17 => todo!(),
+            0x17 => self.unimplemented(bus, opcode),
             0x18 => {
                 // JR i8
-                let operands = self.fetch_operands(1);
-                self.jr_i8(operands);
+                let operands = self.fetch_operands(bus, 1);
+                self.jr_i8(bus, operands);
             }
-            0x19 => todo!(),
-            0x1A => self.ldr_rr(TargetRegister::A, TargetRegister::D, TargetRegister::E), // LD A, (DE)
-            0x1B => todo!(),
-            0x1C => todo!(),
-            0x1D => todo!(),
+            0x19 => self.unimplemented(bus, opcode),
+            0x1A => self.ldr_rr(bus, TargetRegister::A, TargetRegister::D, TargetRegister::E), // LD A, (DE)
+            0x1B => self.unimplemented(bus, opcode),
+            0x1C => self.unimplemented(bus, opcode),
+            0x1D => self.unimplemented(bus, opcode),
             0x1E => {
                 // LD E,u8
-                let operands = self.fetch_operands(1);
+                let operands = self.fetch_operands(bus, 1);
                 self.ldn_u8(TargetRegister::E, operands)
             }
-            0x1F => todo!(),
+            0x1F => self.unimplemented(bus, opcode),
 
             //  ------------ 0X2N ----------------
             0x20 => {
                 // JR NZ, u8
-                let operands = self.fetch_operands(1);
-                self.jrcc_i8(self.registers.f.get_z(), false, operands);
+                let operands = self.fetch_operands(bus, 1);
+                self.jrcc_i8(bus, self.registers.f.get_z(), false, operands);
             }
             0x21 => {
                 // LD HL, u16
-                let operands = self.fetch_operands(2);
+                let operands = self.fetch_operands(bus, 2);
                 self.ldn_u16(TargetRegister::H, TargetRegister::L, operands)
             }
-            0x22 => self.ld_inc_hl_a(),
+            0x22 => self.ld_inc_hl_a(bus),
             // LD (HL+), A
-            0x23 => todo!(),
-            0x24 => todo!(),
-            0x25 => todo!(),
+            0x23 => self.unimplemented(bus, opcode),
+            0x24 => self.unimplemented(bus, opcode),
+            0x25 => self.unimplemented(bus, opcode),
             0x26 => {
                 // LD E, u8
-                let operands = self.fetch_operands(1);
+                let operands = self.fetch_operands(bus, 1);
                 self.ldn_u8(TargetRegister::E, operands)
             }
-            0x27 => todo!(),
+            0x27 => self.unimplemented(bus, opcode),
             0x28 => {
                 // JR Z, u8
-                let operands = self.fetch_operands(1);
-                self.jrcc_i8(self.registers.f.get_z(), true, operands);
+                let operands = self.fetch_operands(bus, 1);
+                self.jrcc_i8(bus, self.registers.f.get_z(), true, operands);
             }
-            0x29 => todo!(),
-            0x2A => self.ld_inc_a_hl(), // LD A, (HL+)
-            0x2B => todo!(),
-            0x2C => todo!(),
-            0x2D => todo!(),
+            0x29 => self.unimplemented(bus, opcode),
+            0x2A => self.ld_inc_a_hl(bus), // LD A, (HL+)
+            0x2B => self.unimplemented(bus, opcode),
+            0x2C => self.unimplemented(bus, opcode),
+            0x2D => self.unimplemented(bus, opcode),
             0x2E => {
                 // LD L,u8
-                let operands = self.fetch_operands(1);
+                let operands = self.fetch_operands(bus, 1);
                 self.ldn_u8(TargetRegister::L, operands)
             }
-            0x2F => todo!(),
+            0x2F => self.unimplemented(bus, opcode),
 
             //  ------------ 0X3N ----------------
             0x30 => {
                 // JR NC, u8
-                let operands = self.fetch_operands(1);
-                self.jrcc_i8(self.registers.f.get_c(), false, operands);
+                let operands = self.fetch_operands(bus, 1);
+                self.jrcc_i8(bus, self.registers.f.get_c(), false, operands);
             }
             0x31 => {
                 // LD SP, u16
-                let operands = self.fetch_operands(2);
+                let operands = self.fetch_operands(bus, 2);
                 self.ldsp_u16(operands)
             }
-            0x32 => self.ld_dec_hl_a(), // LD (HL-),A
-            0x33 => todo!(),
-            0x34 => todo!(),
-            0x35 => todo!(),
+            0x32 => self.ld_dec_hl_a(bus), // LD (HL-),A
+            0x33 => self.unimplemented(bus, opcode),
+            0x34 => self.unimplemented(bus, opcode),
+            0x35 => self.unimplemented(bus, opcode),
             0x36 => {
                 // LD (HL),u8 - 0x36
-                let operands = self.fetch_operands(1);
-                self.ldrr_u8(TargetRegister::H, TargetRegister::L, operands);
+                let operands = self.fetch_operands(bus, 1);
+                self.ldrr_u8(bus, TargetRegister::H, TargetRegister::L, operands);
             }
-            0x37 => todo!(),
+            0x37 => self.unimplemented(bus, opcode),
             0x38 => {
                 // JR C, u8
-                let operands = self.fetch_operands(1);
-                self.jrcc_i8(self.registers.f.get_c(), true, operands);
+                let operands = self.fetch_operands(bus, 1);
+                self.jrcc_i8(bus, self.registers.f.get_c(), true, operands);
             }
-            0x39 => todo!(),
-            0x3A => self.ld_dec_a_hl(), // LD A, (HL-)
-            0x3B => todo!(),
-            0x3C => todo!(),
-            0x3D => todo!(),
+            0x39 => self.unimplemented(bus, opcode),
+            0x3A => self.ld_dec_a_hl(bus), // LD A, (HL-)
+            0x3B => self.unimplemented(bus, opcode),
+            0x3C => self.unimplemented(bus, opcode),
+            0x3D => self.unimplemented(bus, opcode),
             0x3E => {
                 // LD A,u8
-                let operands = self.fetch_operands(1);
+                let operands = self.fetch_operands(bus, 1);
                 self.ldn_u8(TargetRegister::A, operands)
             }
-            0x3F => todo!(),
+            0x3F => self.unimplemented(bus, opcode),
 
             //  ------------ 0X4N ----------------
             0x40 => self.ldrr(TargetRegister::B, TargetRegister::B), // LD B, B
@@ -325,7 +439,7 @@ impl Cpu {
             0x43 => self.ldrr(TargetRegister::B, TargetRegister::E), // LD B, E
             0x44 => self.ldrr(TargetRegister::B, TargetRegister::H), // LD B, H
             0x45 => self.ldrr(TargetRegister::B, TargetRegister::L), // LD B, L
-            0x46 => self.ldr_rr(TargetRegister::B, TargetRegister::H, TargetRegister::L), // LD B,(HL)
+            0x46 => self.ldr_rr(bus, TargetRegister::B, TargetRegister::H, TargetRegister::L), // LD B,(HL)
 
             0x47 => self.ldrr(TargetRegister::B, TargetRegister::A), // LD B, A
             0x48 => self.ldrr(TargetRegister::C, TargetRegister::B), // LD C, B
@@ -334,8 +448,8 @@ impl Cpu {
             0x4B => self.ldrr(TargetRegister::C, TargetRegister::E), // LD C, E
             0x4C => self.ldrr(TargetRegister::C, TargetRegister::H), // LD C, H
             0x4D => self.ldrr(TargetRegister::C, TargetRegister::L), // LD C, L
-            0x4E => self.ldr_rr(TargetRegister::C, TargetRegister::H, TargetRegister::L), // LD C,(HL)
-            0x4F => self.ldrr(TargetRegister::C, TargetRegister::A),                      // LD C, A
+            0x4E => self.ldr_rr(bus, TargetRegister::C, TargetRegister::H, TargetRegister::L), // LD C,(HL)
+            0x4F => self.ldrr(TargetRegister::C, TargetRegister::A), // LD C, A
 
             //  ------------ 0X5N ----------------
             0x50 => self.ldrr(TargetRegister::D, TargetRegister::B), // LD D, B
@@ -344,7 +458,7 @@ impl Cpu {
             0x53 => self.ldrr(TargetRegister::D, TargetRegister::E), // LD D, E
             0x54 => self.ldrr(TargetRegister::D, TargetRegister::H), // LD D, H
             0x55 => self.ldrr(TargetRegister::D, TargetRegister::L), // LD D, L
-            0x56 => self.ldr_rr(TargetRegister::D, TargetRegister::H, TargetRegister::L), // LD D,(HL)
+            0x56 => self.ldr_rr(bus, TargetRegister::D, TargetRegister::H, TargetRegister::L), // LD D,(HL)
 
             0x57 => self.ldrr(TargetRegister::D, TargetRegister::A), // LD D, A
             0x58 => self.ldrr(TargetRegister::E, TargetRegister::B), // LD E, B
@@ -353,8 +467,8 @@ impl Cpu {
             0x5B => self.ldrr(TargetRegister::E, TargetRegister::E), // LD E, E
             0x5C => self.ldrr(TargetRegister::E, TargetRegister::H), // LD E, H
             0x5D => self.ldrr(TargetRegister::E, TargetRegister::L), // LD E, L
-            0x5E => self.ldr_rr(TargetRegister::E, TargetRegister::H, TargetRegister::L), // LD E,(HL)
-            0x5F => self.ldrr(TargetRegister::E, TargetRegister::A),                      // LD E, A
+            0x5E => self.ldr_rr(bus, TargetRegister::E, TargetRegister::H, TargetRegister::L), // LD E,(HL)
+            0x5F => self.ldrr(TargetRegister::E, TargetRegister::A), // LD E, A
 
             //  ------------ 0X6N ----------------
             0x60 => self.ldrr(TargetRegister::H, TargetRegister::B), // LD H, B
@@ -363,26 +477,26 @@ impl Cpu {
             0x63 => self.ldrr(TargetRegister::H, TargetRegister::E), // LD H, E
             0x64 => self.ldrr(TargetRegister::H, TargetRegister::H), // LD H, H
             0x65 => self.ldrr(TargetRegister::H, TargetRegister::L), // LD H, L
-            0x66 => self.ldr_rr(TargetRegister::H, TargetRegister::H, TargetRegister::L), // LD H,(HL)
-            0x67 => self.ldrr(TargetRegister::H, TargetRegister::A),                      // LD H, A
-            0x68 => self.ldrr(TargetRegister::L, TargetRegister::B),                      // LD L, B
-            0x69 => self.ldrr(TargetRegister::L, TargetRegister::C),                      // LD L, C
-            0x6A => self.ldrr(TargetRegister::L, TargetRegister::D),                      // LD L, D
-            0x6B => self.ldrr(TargetRegister::L, TargetRegister::E),                      // LD L, E
-            0x6C => self.ldrr(TargetRegister::L, TargetRegister::H),                      // LD L, H
-            0x6D => self.ldrr(TargetRegister::L, TargetRegister::L),                      // LD L, L
-            0x6E => self.ldr_rr(TargetRegister::L, TargetRegister::H, TargetRegister::L), // LD L,(HL)
-            0x6F => self.ldrr(TargetRegister::L, TargetRegister::A),                      // LD L, A
+            0x66 => self.ldr_rr(bus, TargetRegister::H, TargetRegister::H, TargetRegister::L), // LD H,(HL)
+            0x67 => self.ldrr(TargetRegister::H, TargetRegister::A), // LD H, A
+            0x68 => self.ldrr(TargetRegister::L, TargetRegister::B), // LD L, B
+            0x69 => self.ldrr(TargetRegister::L, TargetRegister::C), // LD L, C
+            0x6A => self.ldrr(TargetRegister::L, TargetRegister::D), // LD L, D
+            0x6B => self.ldrr(TargetRegister::L, TargetRegister::E), // LD L, E
+            0x6C => self.ldrr(TargetRegister::L, TargetRegister::H), // LD L, H
+            0x6D => self.ldrr(TargetRegister::L, TargetRegister::L), // LD L, L
+            0x6E => self.ldr_rr(bus, TargetRegister::L, TargetRegister::H, TargetRegister::L), // LD L,(HL)
+            0x6F => self.ldrr(TargetRegister::L, TargetRegister::A), // LD L, A
 
             //  ------------ 0X7N ----------------
-            0x70 => self.ldrr_r(TargetRegister::H, TargetRegister::L, TargetRegister::B), // LD (HL),B
-            0x71 => self.ldrr_r(TargetRegister::H, TargetRegister::L, TargetRegister::C), // LD (HL),C
-            0x72 => self.ldrr_r(TargetRegister::H, TargetRegister::L, TargetRegister::D), // LD (HL),D
-            0x73 => self.ldrr_r(TargetRegister::H, TargetRegister::L, TargetRegister::E), // LD (HL),E
-            0x74 => self.ldrr_r(TargetRegister::H, TargetRegister::L, TargetRegister::H), // LD (HL),H
-            0x75 => self.ldrr_r(TargetRegister::H, TargetRegister::L, TargetRegister::L), // LD (HL),L
-            0x76 => self.halt(),                                                          // HALT
-            0x77 => self.ldrr_r(TargetRegister::H, TargetRegister::L, TargetRegister::A), // LD (HL),A
+            0x70 => self.ldrr_r(bus, TargetRegister::H, TargetRegister::L, TargetRegister::B), // LD (HL),B
+            0x71 => self.ldrr_r(bus, TargetRegister::H, TargetRegister::L, TargetRegister::C), // LD (HL),C
+            0x72 => self.ldrr_r(bus, TargetRegister::H, TargetRegister::L, TargetRegister::D), // LD (HL),D
+            0x73 => self.ldrr_r(bus, TargetRegister::H, TargetRegister::L, TargetRegister::E), // LD (HL),E
+            0x74 => self.ldrr_r(bus, TargetRegister::H, TargetRegister::L, TargetRegister::H), // LD (HL),H
+            0x75 => self.ldrr_r(bus, TargetRegister::H, TargetRegister::L, TargetRegister::L), // LD (HL),L
+            0x76 => self.halt(), // HALT
+            0x77 => self.ldrr_r(bus, TargetRegister::H, TargetRegister::L, TargetRegister::A), // LD (HL),A
 
             0x78 => self.ldrr(TargetRegister::A, TargetRegister::B), // LD A, B
             0x79 => self.ldrr(TargetRegister::A, TargetRegister::C), // LD A, C
@@ -390,192 +504,224 @@ impl Cpu {
             0x7B => self.ldrr(TargetRegister::A, TargetRegister::E), // LD A, E
             0x7C => self.ldrr(TargetRegister::A, TargetRegister::H), // LD A, H
             0x7D => self.ldrr(TargetRegister::A, TargetRegister::L), // LD A, L
-            0x7E => self.ldr_rr(TargetRegister::A, TargetRegister::H, TargetRegister::L), // LD A, (HL)
-            0x7F => self.ldrr(TargetRegister::A, TargetRegister::A),                      // LD A, A
+            0x7E => self.ldr_rr(bus, TargetRegister::A, TargetRegister::H, TargetRegister::L), // LD A, (HL)
+            0x7F => self.ldrr(TargetRegister::A, TargetRegister::A), // LD A, A
 
             //  ------------ 0X8N ----------------
-            0x80 => todo!(),
-            0x81 => todo!(),
-            0x82 => todo!(),
-            0x83 => todo!(),
-            0x84 => todo!(),
-            0x85 => todo!(),
-            0x86 => todo!(),
-            0x87 => todo!(),
-            0x88 => todo!(),
-            0x89 => todo!(),
-            0x8A => todo!(),
-            0x8B => todo!(),
-            0x8C => todo!(),
-            0x8D => todo!(),
-            0x8E => todo!(),
-            0x8F => todo!(),
+            0x80 => self.unimplemented(bus, opcode),
+            0x81 => self.unimplemented(bus, opcode),
+            0x82 => self.unimplemented(bus, opcode),
+            0x83 => self.unimplemented(bus, opcode),
+            0x84 => self.unimplemented(bus, opcode),
+            0x85 => self.unimplemented(bus, opcode),
+            0x86 => self.unimplemented(bus, opcode),
+            0x87 => self.unimplemented(bus, opcode),
+            0x88 => self.unimplemented(bus, opcode),
+            0x89 => self.unimplemented(bus, opcode),
+            0x8A => self.unimplemented(bus, opcode),
+            0x8B => self.unimplemented(bus, opcode),
+            0x8C => self.unimplemented(bus, opcode),
+            0x8D => self.unimplemented(bus, opcode),
+            0x8E => self.unimplemented(bus, opcode),
+            0x8F => self.unimplemented(bus, opcode),
 
             //  ------------ 0X9N ----------------
-            0x90 => todo!(),
-            0x91 => todo!(),
-            0x92 => todo!(),
-            0x93 => todo!(),
-            0x94 => todo!(),
-            0x95 => todo!(),
-            0x96 => todo!(),
-            0x97 => todo!(),
-            0x98 => todo!(),
-            0x99 => todo!(),
-            0x9A => todo!(),
-            0x9B => todo!(),
-            0x9C => todo!(),
-            0x9D => todo!(),
-            0x9E => todo!(),
-            0x9F => todo!(),
+            0x90 => self.unimplemented(bus, opcode),
+            0x91 => self.unimplemented(bus, opcode),
+            0x92 => self.unimplemented(bus, opcode),
+            0x93 => self.unimplemented(bus, opcode),
+            0x94 => self.unimplemented(bus, opcode),
+            0x95 => self.unimplemented(bus, opcode),
+            0x96 => self.unimplemented(bus, opcode),
+            0x97 => self.unimplemented(bus, opcode),
+            0x98 => self.unimplemented(bus, opcode),
+            0x99 => self.unimplemented(bus, opcode),
+            0x9A => self.unimplemented(bus, opcode),
+            0x9B => self.unimplemented(bus, opcode),
+            0x9C => self.unimplemented(bus, opcode),
+            0x9D => self.unimplemented(bus, opcode),
+            0x9E => self.unimplemented(bus, opcode),
+            0x9F => self.unimplemented(bus, opcode),
 
             //  ------------ 0XAN ----------------
-            0xA0 => todo!(),
-            0xA1 => todo!(),
-            0xA2 => todo!(),
-            0xA3 => todo!(),
-            0xA4 => todo!(),
-            0xA5 => todo!(),
-            0xA6 => todo!(),
-            0xA7 => todo!(),
+            0xA0 => self.unimplemented(bus, opcode),
+            0xA1 => self.unimplemented(bus, opcode),
+            0xA2 => self.unimplemented(bus, opcode),
+            0xA3 => self.unimplemented(bus, opcode),
+            0xA4 => self.unimplemented(bus, opcode),
+            0xA5 => self.unimplemented(bus, opcode),
+            0xA6 => self.unimplemented(bus, opcode),
+            0xA7 => self.unimplemented(bus, opcode),
             0xA8 => self.xora_r(TargetRegister::B), // XOR A, B
             0xA9 => self.xora_r(TargetRegister::C), // XOR A, C
             0xAA => self.xora_r(TargetRegister::D), // XOR A, D
             0xAB => self.xora_r(TargetRegister::E), // XOR A, E
             0xAC => self.xora_r(TargetRegister::H), // XOR A, H
             0xAD => self.xora_r(TargetRegister::L), // XOR A, L
-            0xAE => self.xora_u16(self.read_hl()),  // XOR A, (HL)
+            0xAE => self.xora_u16(bus, self.read_hl()), // XOR A, (HL)
             0xAF => self.xora_r(TargetRegister::A), // XOR A, A
 
             //  ------------ 0XBN ----------------
-            0xB0 => todo!(),
-            0xB1 => todo!(),
-            0xB2 => todo!(),
-            0xB3 => todo!(),
-            0xB4 => todo!(),
-            0xB5 => todo!(),
-            0xB6 => todo!(),
-            0xB7 => todo!(),
-            0xB8 => todo!(),
-            0xB9 => todo!(),
-            0xBA => todo!(),
-            0xBB => todo!(),
-            0xBC => todo!(),
-            0xBD => todo!(),
-            0xBE => todo!(),
-            0xBF => todo!(),
+            0xB0 => self.unimplemented(bus, opcode),
+            0xB1 => self.unimplemented(bus, opcode),
+            0xB2 => self.unimplemented(bus, opcode),
+            0xB3 => self.unimplemented(bus, opcode),
+            0xB4 => self.unimplemented(bus, opcode),
+            0xB5 => self.unimplemented(bus, opcode),
+            0xB6 => self.unimplemented(bus, opcode),
+            0xB7 => self.unimplemented(bus, opcode),
+            0xB8 => self.unimplemented(bus, opcode),
+            0xB9 => self.unimplemented(bus, opcode),
+            0xBA => self.unimplemented(bus, opcode),
+            0xBB => self.unimplemented(bus, opcode),
+            0xBC => self.unimplemented(bus, opcode),
+            0xBD => self.unimplemented(bus, opcode),
+            0xBE => self.unimplemented(bus, opcode),
+            0xBF => self.unimplemented(bus, opcode),
 
             //  ------------ 0XCN ----------------
-            0xC0 => self.retcc(self.registers.f.get_z(), false), // RET NZ
-            0xC1 => todo!(),
-            0xC2 => todo!(),
+            0xC0 => self.retcc(bus, self.registers.f.get_z(), false), // RET NZ
+            0xC1 => self.unimplemented(bus, opcode),
+            0xC2 => self.unimplemented(bus, opcode),
             0xC3 => {
                 // JP u16
-                let operands = self.fetch_operands(2);
-                self.jp_u16(operands);
+                let operands = self.fetch_operands(bus, 2);
+                self.jp_u16(bus, operands);
             }
             0xC4 => {
                 // CALL NZ, u16 - 0xCD
-                let operands = self.fetch_operands(2);
-                self.callcc_u16(self.registers.f.get_z(), false, operands);
+                let operands = self.fetch_operands(bus, 2);
+                self.callcc_u16(bus, self.registers.f.get_z(), false, operands);
+            }
+            0xC5 => self.unimplemented(bus, opcode),
+            0xC6 => self.unimplemented(bus, opcode),
+            0xC7 => self.unimplemented(bus, opcode),
+            0xC8 => self.retcc(bus, self.registers.f.get_z(), true), // RET Z
+            0xC9 => self.ret(bus),                                   // RET
+            0xCA => self.unimplemented(bus, opcode),
+            0xCB => {
+                // PREFIX CB
+                let cb_opcode = self.fetch(bus);
+                self.execute_cb(bus, cb_opcode);
             }
-            0xC5 => todo!(),
-            0xC6 => todo!(),
-            0xC7 => todo!(),
-            0xC8 => self.retcc(self.registers.f.get_z(), true), // RET Z
-            0xC9 => self.ret(),                                 // RET
-            0xCA => todo!(),
-            0xCB => todo!(),
             0xCC => {
                 // CALL Z, u16
-                let operands = self.fetch_operands(2);
-                self.callcc_u16(self.registers.f.get_z(), true, operands);
+                let operands = self.fetch_operands(bus, 2);
+                self.callcc_u16(bus, self.registers.f.get_z(), true, operands);
             }
             0xCD => {
                 // CALL u16 - 0xCD
-                let operands = self.fetch_operands(2);
-                self.call_u16(operands);
+                let operands = self.fetch_operands(bus, 2);
+                self.call_u16(bus, operands);
             }
-            0xCE => todo!(),
-            0xCF => todo!(),
+            0xCE => self.unimplemented(bus, opcode),
+            0xCF => self.unimplemented(bus, opcode),
 
             //  ------------ 0XDN ----------------
-            0xD0 => self.retcc(self.registers.f.get_c(), false), // RET NC
-            0xD1 => todo!(),
-            0xD2 => todo!(),
-            0xD3 => todo!(),
+            0xD0 => self.retcc(bus, self.registers.f.get_c(), false), // RET NC
+            0xD1 => self.unimplemented(bus, opcode),
+            0xD2 => self.unimplemented(bus, opcode),
+            0xD3 => self.unimplemented(bus, opcode),
             0xD4 => {
                 // CALL NC, u16 - 0xCD
-                let operands = self.fetch_operands(2);
-                self.callcc_u16(self.registers.f.get_c(), false, operands);
+                let operands = self.fetch_operands(bus, 2);
+                self.callcc_u16(bus, self.registers.f.get_c(), false, operands);
             }
-            0xD5 => todo!(),
-            0xD6 => todo!(),
-            0xD7 => todo!(),
-            0xD8 => self.retcc(self.registers.f.get_c(), true), // RET C
-            0xD9 => todo!(),
-            0xDA => todo!(),
-            0xDB => todo!(),
+            0xD5 => self.unimplemented(bus, opcode),
+            0xD6 => self.unimplemented(bus, opcode),
+            0xD7 => self.unimplemented(bus, opcode),
+            0xD8 => self.retcc(bus, self.registers.f.get_c(), true), // RET C
+            0xD9 => self.reti(bus),                                  // RETI
+            0xDA => self.unimplemented(bus, opcode),
+            0xDB => self.unimplemented(bus, opcode),
             0xDC => {
                 // CALL C, u16 - 0xCD
-                let operands = self.fetch_operands(2);
-                self.callcc_u16(self.registers.f.get_c(), true, operands);
+                let operands = self.fetch_operands(bus, 2);
+                self.callcc_u16(bus, self.registers.f.get_c(), true, operands);
             }
-            0xDD => todo!(),
-            0xDE => todo!(),
-            0xDF => todo!(),
+            0xDD => self.unimplemented(bus, opcode),
+            0xDE => self.unimplemented(bus, opcode),
+            0xDF => self.unimplemented(bus, opcode),
 
             //  ------------ 0XEN ----------------
             0xE0 => {
                 // LD (FF00+u8),A
-                let operands = self.fetch_operands(1);
-                self.ldn_a(operands);
+                let operands = self.fetch_operands(bus, 1);
+                self.ldn_a(bus, operands);
             }
-            0xE1 => todo!(),
-            0xE2 => self.ldc_a(), // LD (0xFF00+C),A
-            0xE3 => todo!(),
-            0xE4 => todo!(),
-            0xE5 => todo!(),
-            0xE6 => todo!(),
-            0xE7 => todo!(),
-            0xE8 => todo!(),
-            0xE9 => todo!(),
-            0xEA => todo!(),
-            0xEB => todo!(),
-            0xEC => todo!(),
-            0xED => todo!(),
-            0xEE => todo!(),
-            0xEF => todo!(),
+            0xE1 => self.unimplemented(bus, opcode),
+            0xE2 => self.ldc_a(bus), // LD (0xFF00+C),A
+            0xE3 => self.unimplemented(bus, opcode),
+            0xE4 => self.unimplemented(bus, opcode),
+            0xE5 => self.unimplemented(bus, opcode),
+            0xE6 => self.unimplemented(bus, opcode),
+            0xE7 => self.unimplemented(bus, opcode),
+            0xE8 => self.unimplemented(bus, opcode),
+            0xE9 => self.unimplemented(bus, opcode),
+            0xEA => self.unimplemented(bus, opcode),
+            0xEB => self.unimplemented(bus, opcode),
+            0xEC => self.unimplemented(bus, opcode),
+            0xED => self.unimplemented(bus, opcode),
+            0xEE => self.unimplemented(bus, opcode),
+            0xEF => self.unimplemented(bus, opcode),
 
             //  ------------ 0XFN ----------------
             0xF0 => {
                 // LD A (0xFF00 + u8)
-                let operands = self.fetch_operands(1);
-                self.ldu8_a(operands);
+                let operands = self.fetch_operands(bus, 1);
+                self.ldu8_a(bus, operands);
             }
-            0xF1 => todo!(),
-            0xF2 => self.lda_c(), // LD A, (0xFF00+C)
-            0xF3 => { /*TODO 割り込み処理を実装したらDIも実装する*/ } // DI disable intruppt
-            0xF4 => todo!(),
-            0xF5 => todo!(),
-            0xF6 => todo!(),
-            0xF7 => todo!(),
-            0xF8 => todo!(),
-            0xF9 => todo!(),
-            0xFA => todo!(),
-            0xFB => todo!(),
-            0xFC => todo!(),
-            0xFD => todo!(),
+            0xF1 => self.unimplemented(bus, opcode),
+            0xF2 => self.lda_c(bus), // LD A, (0xFF00+C)
+            0xF3 => self.di(),       // DI disable intruppt
+            0xF4 => self.unimplemented(bus, opcode),
+            0xF5 => self.unimplemented(bus, opcode),
+            0xF6 => self.unimplemented(bus, opcode),
+            0xF7 => self.unimplemented(bus, opcode),
+            0xF8 => self.unimplemented(bus, opcode),
+            0xF9 => self.unimplemented(bus, opcode),
+            0xFA => self.unimplemented(bus, opcode),
+            0xFB => self.ei(), // EI enable intruppt
+            0xFC => self.unimplemented(bus, opcode),
+            0xFD => self.unimplemented(bus, opcode),
             0xFE => {
                 // CP A, u8
-                let operands = self.fetch_operands(1);
+                let operands = self.fetch_operands(bus, 1);
                 self.cp_u8(operands);
             }
-            0xFF => todo!(),
+            0xFF => self.unimplemented(bus, opcode),
             // _ => bail!("not implemented opcode {:X}", opcode),
         }
     }
 
+    fn execute_cb(&mut self, bus: &mut impl MemoryBus, cb_opcode: Opecode) {
+        // TODO implement the CB prefixed instructions
+        self.unimplemented_cb(bus, cb_opcode)
+    }
+
+    fn unimplemented(&mut self, bus: &mut impl MemoryBus, opcode: Opecode) {
+        bus.record_unimplemented(Unimplemented::Opcode(opcode));
+
+        if !self.skip_unimplemented {
+            todo!("opcode {:02X} at {:04X}", opcode, self.pc.wrapping_sub(1))
+        }
+
+        // Skip the operands so execution at least continues at the next instruction
+        self.fetch_operands(bus, operand_length(opcode));
+    }
+
+    fn unimplemented_cb(&mut self, bus: &mut impl MemoryBus, cb_opcode: Opecode) {
+        bus.record_unimplemented(Unimplemented::CbOpcode(cb_opcode));
+
+        if !self.skip_unimplemented {
+            todo!(
+                "opcode CB {:02X} at {:04X}",
+                cb_opcode,
+                self.pc.wrapping_sub(2)
+            )
+        }
+    }
+
     fn ldn_u16(&mut self, reg1: TargetRegister, reg2: TargetRegister, ops: Operands) {
         self.registers.write(reg1, ops[1]);
         self.registers.write(reg2, ops[0]);
@@ -585,27 +731,29 @@ impl Cpu {
         self.registers.write(reg, ops[0]);
     }
 
-    fn ldn_a(&mut self, operands: Operands) {
+    fn ldn_a(&mut self, bus: &mut impl MemoryBus, operands: Operands) {
         self.bus_write_byte(
+            bus,
             0xFF00 + operands[0] as u16,
             self.registers.read(TargetRegister::A),
         )
     }
 
-    fn ldu8_a(&mut self, operands: Operands) {
-        let byte = self.bus_read_byte(0xFF00 + operands[0] as u16);
+    fn ldu8_a(&mut self, bus: &mut impl MemoryBus, operands: Operands) {
+        let byte = self.bus_read_byte(bus, 0xFF00 + operands[0] as u16);
         self.registers.write(TargetRegister::A, byte);
     }
 
-    fn ldc_a(&mut self) {
+    fn ldc_a(&mut self, bus: &mut impl MemoryBus) {
         self.bus_write_byte(
+            bus,
             0xFF00 + self.registers.read(TargetRegister::C) as u16,
             self.registers.read(TargetRegister::A),
         )
     }
 
-    fn lda_c(&mut self) {
-        let byte = self.bus_read_byte(0xFF00 + self.registers.read(TargetRegister::C) as u16);
+    fn lda_c(&mut self, bus: &mut impl MemoryBus) {
+        let byte = self.bus_read_byte(bus, 0xFF00 + self.registers.read(TargetRegister::C) as u16);
         self.registers.write(TargetRegister::A, byte);
     }
 
@@ -658,6 +806,7 @@ impl Cpu {
 
     fn ldrr_r(
         &mut self,
+        bus: &mut impl MemoryBus,
         upper_reg: TargetRegister,
         lower_reg: TargetRegister,
         byte_reg: TargetRegister,
@@ -668,11 +817,12 @@ impl Cpu {
         );
 
         let byte = self.registers.read(byte_reg);
-        self.bus_write_byte(address, byte);
+        self.bus_write_byte(bus, address, byte);
     }
 
     fn ldr_rr(
         &mut self,
+        bus: &mut impl MemoryBus,
         dest_reg: TargetRegister,
         upper_reg: TargetRegister,
         lower_reg: TargetRegister,
@@ -682,12 +832,13 @@ impl Cpu {
             self.registers.read(lower_reg),
         );
 
-        let byte = self.bus_read_byte(address);
+        let byte = self.bus_read_byte(bus, address);
         self.registers.write(dest_reg, byte);
     }
 
     fn ldrr_u8(
         &mut self,
+        bus: &mut impl MemoryBus,
         upper_reg: TargetRegister,
         lower_reg: TargetRegister,
         operands: Operands,
@@ -697,12 +848,14 @@ impl Cpu {
             self.registers.read(lower_reg),
         );
 
-        self.bus_write_byte(address, operands[0]);
+        self.bus_write_byte(bus, address, operands[0]);
     }
 
-    fn inc_u16(&mut self, reg1: TargetRegister, reg2: TargetRegister) {
+    fn inc_u16(&mut self, bus: &mut impl MemoryBus, reg1: TargetRegister, reg2: TargetRegister) {
         let mut word = join_half_words(self.registers.read(reg1), self.registers.read(reg2));
-        word += 1;
+        self.corrupt_oam(bus, word, OamCorruption::Write);
+        word = word.wrapping_add(1);
+        self.tick(bus);
 
         let (upper, lower) = split_word(word);
 
@@ -710,9 +863,11 @@ impl Cpu {
         self.registers.write(reg2, lower);
     }
 
-    fn dec_u16(&mut self, reg1: TargetRegister, reg2: TargetRegister) {
+    fn dec_u16(&mut self, bus: &mut impl MemoryBus, reg1: TargetRegister, reg2: TargetRegister) {
         let mut word = join_half_words(self.registers.read(reg1), self.registers.read(reg2));
-        word -= 1;
+        self.corrupt_oam(bus, word, OamCorruption::Write);
+        word = word.wrapping_sub(1);
+        self.tick(bus);
 
         let (upper, lower) = split_word(word);
 
@@ -735,7 +890,7 @@ impl Cpu {
     }
 
     fn inc(&mut self, byte: HalfWord) -> HalfWord {
-        let incremented = byte + 1;
+        let incremented = byte.wrapping_add(1);
 
         self.registers.f.set_n(false);
 
@@ -776,13 +931,18 @@ impl Cpu {
         decremented
     }
 
-    fn ldnn_sp(&mut self, operands: Operands) {
+    fn ldnn_sp(&mut self, bus: &mut impl MemoryBus, operands: Operands) {
         let address = join_half_words(operands[1], operands[0]);
 
-        self.bus_write_word(address, self.sp);
+        self.bus_write_word(bus, address, self.sp);
     }
 
-    fn addhl_rr(&mut self, upper_reg: TargetRegister, lower_reg: TargetRegister) {
+    fn addhl_rr(
+        &mut self,
+        bus: &mut impl MemoryBus,
+        upper_reg: TargetRegister,
+        lower_reg: TargetRegister,
+    ) {
         let hl = self.read_hl();
 
         let rr = join_half_words(
@@ -792,6 +952,7 @@ impl Cpu {
 
         let result = self.add_words(hl, rr);
         self.set_hl(result);
+        self.tick(bus);
     }
 
     fn add_words(&mut self, a: Word, b: Word) -> Word {
@@ -825,12 +986,13 @@ impl Cpu {
         self.sp = join_half_words(operands[1], operands[0])
     }
 
-    fn jp_u16(&mut self, operands: Operands) {
-        self.pc = join_half_words(operands[1], operands[0])
+    fn jp_u16(&mut self, bus: &mut impl MemoryBus, operands: Operands) {
+        self.pc = join_half_words(operands[1], operands[0]);
+        self.tick(bus);
     }
 
     // fn lda_u8(&mut self, operands: Operands) {
-    //     let byte = self.bus.bus_read_byte(0xFF00 + operands[0] as u16);
+    //     let byte = bus.bus_read_byte(0xFF00 + operands[0] as u16);
     //     self.registers.write(TargetRegister::A, byte);
     // }
 
@@ -859,62 +1021,60 @@ impl Cpu {
         }
     }
 
-    fn jrcc_i8(&mut self, flag: bool, is_set: bool, operands: Operands) {
+    fn jrcc_i8(&mut self, bus: &mut impl MemoryBus, flag: bool, is_set: bool, operands: Operands) {
         let n = operands[0] as i8;
 
         if flag == is_set {
-            if n < 0 {
-                self.pc -= -n as u16;
-            } else {
-                self.pc += n as u16;
-            }
+            self.pc = self.pc.wrapping_add(n as u16);
+            self.tick(bus);
         }
     }
 
-    fn jr_i8(&mut self, operands: Operands) {
+    fn jr_i8(&mut self, bus: &mut impl MemoryBus, operands: Operands) {
         let n = operands[0] as i8;
 
-        if n < 0 {
-            self.pc -= -n as u16;
-        } else {
-            self.pc += n as u16;
-        }
+        self.pc = self.pc.wrapping_add(n as u16);
+        self.tick(bus);
     }
 
-    fn ld_inc_hl_a(&mut self) {
+    fn ld_inc_hl_a(&mut self, bus: &mut impl MemoryBus) {
         let mut addr = self.read_hl();
+        self.corrupt_oam(bus, addr, OamCorruption::Write);
 
-        self.bus_write_byte(addr, self.registers.read(TargetRegister::A));
-        addr += 1;
+        self.bus_write_byte(bus, addr, self.registers.read(TargetRegister::A));
+        addr = addr.wrapping_add(1);
 
         self.set_hl(addr);
     }
 
-    fn ld_dec_hl_a(&mut self) {
+    fn ld_dec_hl_a(&mut self, bus: &mut impl MemoryBus) {
         let mut addr = self.read_hl();
+        self.corrupt_oam(bus, addr, OamCorruption::Write);
 
-        self.bus_write_byte(addr, self.registers.read(TargetRegister::A));
-        addr -= 1;
+        self.bus_write_byte(bus, addr, self.registers.read(TargetRegister::A));
+        addr = addr.wrapping_sub(1);
 
         self.set_hl(addr);
     }
 
-    fn ld_inc_a_hl(&mut self) {
+    fn ld_inc_a_hl(&mut self, bus: &mut impl MemoryBus) {
         let mut addr = self.read_hl();
+        self.corrupt_oam(bus, addr, OamCorruption::ReadIncrease);
 
-        let byte = self.bus_read_byte(addr);
+        let byte = self.bus_read_byte(bus, addr);
         self.registers.write(TargetRegister::A, byte);
-        addr += 1;
+        addr = addr.wrapping_add(1);
 
         self.set_hl(addr);
     }
 
-    fn ld_dec_a_hl(&mut self) {
+    fn ld_dec_a_hl(&mut self, bus: &mut impl MemoryBus) {
         let mut addr = self.read_hl();
+        self.corrupt_oam(bus, addr, OamCorruption::ReadIncrease);
 
-        let byte = self.bus_read_byte(addr);
+        let byte = self.bus_read_byte(bus, addr);
         self.registers.write(TargetRegister::A, byte);
-        addr -= 1;
+        addr = addr.wrapping_sub(1);
 
         self.set_hl(addr);
     }
@@ -928,8 +1088,8 @@ impl Cpu {
         self.registers.write(TargetRegister::A, byte);
     }
 
-    fn xora_u16(&mut self, addr: Word) {
-        let value = self.bus_read_byte(addr);
+    fn xora_u16(&mut self, bus: &mut impl MemoryBus, addr: Word) {
+        let value = self.bus_read_byte(bus, addr);
         let byte = self.xor(self.registers.read(TargetRegister::A), value);
 
         self.registers.write(TargetRegister::A, byte);
@@ -950,40 +1110,65 @@ impl Cpu {
         bit
     }
 
-    fn ret(&mut self) {
-        let (upper, lower) = (self.pop(), self.pop());
+    fn ret(&mut self, bus: &mut impl MemoryBus) {
+        let (lower, upper) = (self.pop(bus), self.pop(bus));
 
         self.pc = join_half_words(upper, lower);
+        self.tick(bus);
     }
 
-    fn retcc(&mut self, flag: bool, is_set: bool) {
+    fn retcc(&mut self, bus: &mut impl MemoryBus, flag: bool, is_set: bool) {
+        // The condition check takes one extra machine cycle.
+        self.tick(bus);
+
         if flag == is_set {
-            self.ret();
+            self.ret(bus);
         }
     }
 
-    fn call_u16(&mut self, operands: Operands) {
+    fn reti(&mut self, bus: &mut impl MemoryBus) {
+        self.ret(bus);
+        self.ime = true;
+    }
+
+    fn di(&mut self) {
+        self.ime = false;
+        self.ei_pending = false;
+    }
+
+    fn ei(&mut self) {
+        self.ei_pending = true;
+    }
+
+    fn call_u16(&mut self, bus: &mut impl MemoryBus, operands: Operands) {
         let (upper, lower) = (self.pc >> 8, self.pc & 0xFF);
-        self.push(upper as u8);
-        self.push(lower as u8);
+        self.tick(bus);
+        self.push(bus, upper as u8);
+        self.push(bus, lower as u8);
 
         self.pc = join_half_words(operands[1], operands[0])
     }
 
-    fn callcc_u16(&mut self, flag: bool, is_set: bool, operands: Operands) {
+    fn callcc_u16(
+        &mut self,
+        bus: &mut impl MemoryBus,
+        flag: bool,
+        is_set: bool,
+        operands: Operands,
+    ) {
         if flag == is_set {
-            self.call_u16(operands);
+            self.call_u16(bus, operands);
         }
     }
 
-    fn push(&mut self, half_word: HalfWord) {
-        self.sp -= 1;
-        self.bus_write_byte(self.sp, half_word)
+    fn push(&mut self, bus: &mut impl MemoryBus, half_word: HalfWord) {
+        self.sp = self.sp.wrapping_sub(1);
+        self.bus_write_byte(bus, self.sp, half_word)
     }
 
-    fn pop(&mut self) -> HalfWord {
-        let byte = self.bus_read_byte(self.sp);
-        self.sp += 1;
+    fn pop(&mut self, bus: &mut impl MemoryBus) -> HalfWord {
+        let byte = self.bus_read_byte(bus, self.sp);
+        self.sp = self.sp.wrapping_add(1);
 
         byte
     }
@@ -1006,18 +1191,157 @@ impl Cpu {
         self.halted = true
     }
 
-    pub fn bus_read_byte(&self, address: Word) -> u8 {
-        let bus = self.bus.lock().unwrap();
-        bus.read_byte(address)
+    /// Perform the CGB speed switch if KEY1 armed it, otherwise enter low
+    /// power mode. Waking up on a button press only is not emulated, STOP
+    /// behaves like HALT.
+    fn stop(&mut self, bus: &mut impl MemoryBus) {
+        if !bus.switch_speed() {
+            self.halted = true
+        }
+    }
+
+    pub fn set_state(&mut self, state: CpuState) {
+        self.registers.a = state.a;
+        self.registers.f = FlagRegister::from_byte(state.f);
+        self.registers.b = state.b;
+        self.registers.c = state.c;
+        self.registers.d = state.d;
+        self.registers.e = state.e;
+        self.registers.h = state.h;
+        self.registers.l = state.l;
+        self.sp = state.sp;
+        self.pc = state.pc;
+    }
+
+    /// The registers and the elapsed cycles, without the accuracy settings.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        let registers = self.state();
+        for &register in &[
+            registers.a,
+            registers.f,
+            registers.b,
+            registers.c,
+            registers.d,
+            registers.e,
+            registers.h,
+            registers.l,
+        ] {
+            state.u8(register);
+        }
+        state.u16(self.sp);
+        state.u16(self.pc);
+        state.bool(self.halted);
+        state.bool(self.ime);
+        state.bool(self.ei_pending);
+        state.usize(self.cycles);
     }
 
-    pub fn bus_write_byte(&mut self, address: Word, byte: HalfWord) {
-        let mut bus = self.bus.lock().unwrap();
-        bus.write_byte(address, byte)
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.set_state(CpuState {
+            a: state.u8()?,
+            f: state.u8()?,
+            b: state.u8()?,
+            c: state.u8()?,
+            d: state.u8()?,
+            e: state.u8()?,
+            h: state.u8()?,
+            l: state.u8()?,
+            sp: state.u16()?,
+            pc: state.u16()?,
+        });
+        self.halted = state.bool()?;
+        self.ime = state.bool()?;
+        self.ei_pending = state.bool()?;
+        self.cycles = state.usize()?;
+
+        Ok(())
     }
 
-    pub fn bus_write_word(&mut self, address: Word, word: Word) {
-        let mut bus = self.bus.lock().unwrap();
-        bus.write_word(address, word)
+    pub fn set_skip_unimplemented(&mut self, enabled: bool) {
+        self.skip_unimplemented = enabled
+    }
+
+    pub fn set_oam_corruption(&mut self, enabled: bool) {
+        self.oam_corruption = enabled
+    }
+
+    /// Reproduce the DMG OAM corruption bug when a 16 bit increment/decrement
+    /// uses a pointer into 0xFE00-0xFEFF while the PPU is in mode 2.
+    /// Must be called before the machine cycle of the access is ticked.
+    fn corrupt_oam(&mut self, bus: &mut impl MemoryBus, address: Word, kind: OamCorruption) {
+        if !self.oam_corruption || !(0xFE00..=0xFEFF).contains(&address) {
+            return;
+        }
+
+        bus.corrupt_oam(kind);
+    }
+
+    /// Advance the rest of the system by one machine cycle (4 clock cycles).
+    /// Every bus access costs one machine cycle, and instructions with
+    /// internal delays call this directly.
+    fn tick(&mut self, bus: &mut impl MemoryBus) {
+        self.cycles += 4;
+        bus.tick(4);
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// Interrupt master enable, set by EI and RETI.
+    pub fn ime(&self) -> bool {
+        self.ime
+    }
+
+    pub fn set_ime(&mut self, enabled: bool) {
+        self.ime = enabled;
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            a: self.registers.a,
+            f: self.registers.f.to_byte(),
+            b: self.registers.b,
+            c: self.registers.c,
+            d: self.registers.d,
+            e: self.registers.e,
+            h: self.registers.h,
+            l: self.registers.l,
+            sp: self.sp,
+            pc: self.pc,
+        }
+    }
+
+    pub fn bus_read_byte(&mut self, bus: &mut impl MemoryBus, address: Word) -> u8 {
+        let byte = bus.read_byte(address);
+        self.tick(bus);
+
+        byte
+    }
+
+    pub fn bus_write_byte(&mut self, bus: &mut impl MemoryBus, address: Word, byte: HalfWord) {
+        bus.write_byte(address, byte);
+        self.tick(bus);
+    }
+
+    pub fn bus_write_word(&mut self, bus: &mut impl MemoryBus, address: Word, word: Word) {
+        let (upper, lower) = split_word(word);
+
+        self.bus_write_byte(bus, address, lower);
+        self.bus_write_byte(bus, address.wrapping_add(1), upper);
+    }
+}
+
+/// Number of operand bytes following an opcode.
+fn operand_length(opcode: Opecode) -> usize {
+    match opcode {
+        0x01 | 0x08 | 0x11 | 0x21 | 0x31 => 2,
+        0xC2 | 0xC3 | 0xC4 | 0xCA | 0xCC | 0xCD => 2,
+        0xD2 | 0xD4 | 0xDA | 0xDC | 0xEA | 0xFA => 2,
+        0x06 | 0x0E | 0x10 | 0x16 | 0x18 | 0x1E => 1,
+        0x20 | 0x26 | 0x28 | 0x2E | 0x30 | 0x36 | 0x38 | 0x3E => 1,
+        0xC6 | 0xCB | 0xCE | 0xD6 | 0xDE => 1,
+        0xE0 | 0xE6 | 0xE8 | 0xEE | 0xF0 | 0xF6 | 0xF8 | 0xFE => 1,
+        _ => 0,
     }
 }
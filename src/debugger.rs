@@ -0,0 +1,295 @@
+//! Interactive debugger on the terminal, for bringing up homebrew.
+//!
+//! Reads gdb style commands from stdin, `help` lists them: continue, step,
+//! step over, breakpoints, watchpoints, examine memory, disassemble and
+//! registers. Addresses are hex, with or without `$` or `0x`, counts and
+//! lengths decimal. An empty line repeats the last command. LD B,B in the
+//! ROM stops like a breakpoint. The event viewer records every frame, see
+//! [`crate::event_viewer`].
+
+use crate::emulator::{Break, Emulator, CYCLES_PER_FRAME};
+use crate::event_viewer::EventViewerOptions;
+use crate::png;
+use crate::trace::TraceFormat;
+use crate::{disasm, Word};
+use anyhow::Result;
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+const HELP: &str = "\
+c, continue             run until a breakpoint, a watchpoint or Enter
+s, step [count]         execute instructions
+n, next                 step over CALL and RST
+b, break [address]      break at address, list the breakpoints without
+delete address          remove a breakpoint
+w, watch [start[-end]]  break after the CPU writes there, list without
+unwatch start[-end]     remove a watchpoint
+x address [length]      examine memory, 64 bytes by default
+dis [address] [count]   disassemble 10 instructions, from PC by default
+r, registers            show the registers
+events [file]           DMA transfers of the last frame, the event map to a PNG
+q, quit";
+
+/// Run the debugger on `emulator` until `quit` or the end of stdin.
+pub fn start(mut emulator: Emulator) -> Result<()> {
+    let lines = stdin_lines();
+    emulator.set_source_breakpoints(true);
+    emulator.set_event_viewer(Some(EventViewerOptions::default()));
+    println!("gbemu debugger, `help` lists the commands");
+    print_location(&emulator);
+
+    let mut last = String::new();
+    loop {
+        print!("(gbemu) ");
+        io::stdout().flush()?;
+
+        let line = match lines.recv() {
+            Ok(line) if line.trim().is_empty() => last.clone(),
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        match command(&mut emulator, &lines, &line) {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(e) => println!("error: {}", e),
+        }
+        last = line;
+    }
+
+    if let Err(e) = emulator.stop_movie_recording() {
+        log::error!("failed to finish the movie: {}", e);
+    }
+    log::info!("{}", emulator.telemetry().summary());
+
+    Ok(())
+}
+
+/// Run `line`, returns whether to quit.
+fn command(emulator: &mut Emulator, lines: &Receiver<String>, line: &str) -> Result<bool> {
+    let mut words = line.split_whitespace();
+    let name = match words.next() {
+        Some(name) => name,
+        None => return Ok(false),
+    };
+    let args: Vec<&str> = words.collect();
+
+    match (name, args.as_slice()) {
+        ("c", []) | ("continue", []) => run(emulator, lines),
+        ("s", _) | ("step", _) => {
+            let count = match args.first() {
+                Some(count) => count.parse()?,
+                None => 1,
+            };
+            for _ in 0..count {
+                if let Err(report) = emulator.try_step() {
+                    println!("{}", report);
+                    break;
+                }
+            }
+            print_location(emulator);
+        }
+        ("n", []) | ("next", []) => {
+            let pc = emulator.cpu_state().pc;
+            if disasm::is_call(emulator.peek(pc)) {
+                // A temporary breakpoint after the call
+                let after = pc.wrapping_add(emulator.disassemble(pc).length as Word);
+                let kept = emulator.remove_breakpoint(after);
+                emulator.add_breakpoint(after);
+                run(emulator, lines);
+                if !kept {
+                    emulator.remove_breakpoint(after);
+                }
+            } else {
+                if let Err(report) = emulator.try_step() {
+                    println!("{}", report);
+                }
+                print_location(emulator);
+            }
+        }
+        ("b", []) | ("break", []) => {
+            for address in emulator.breakpoints() {
+                println!("${:04X}", address);
+            }
+        }
+        ("b", [address]) | ("break", [address]) => {
+            emulator.add_breakpoint(parse_address(address)?);
+        }
+        ("delete", [address]) => {
+            if !emulator.remove_breakpoint(parse_address(address)?) {
+                anyhow::bail!("no breakpoint at {}", address);
+            }
+        }
+        ("w", []) | ("watch", []) => {
+            for range in emulator.watchpoints() {
+                println!("{}", format_range(range));
+            }
+        }
+        ("w", [range]) | ("watch", [range]) => emulator.add_watchpoint(parse_range(range)?),
+        ("unwatch", [range]) => {
+            if !emulator.remove_watchpoint(&parse_range(range)?) {
+                anyhow::bail!("{} is not watched", range);
+            }
+        }
+        ("x", [address, rest @ ..]) if rest.len() <= 1 => {
+            let start = parse_address(address)?;
+            let length: usize = match rest.first() {
+                Some(length) => length.parse()?,
+                None => 64,
+            };
+            examine(emulator, start, length);
+        }
+        ("dis", _) if args.len() <= 2 => {
+            let mut address = match args.first() {
+                Some(address) => parse_address(address)?,
+                None => emulator.cpu_state().pc,
+            };
+            let count = match args.get(1) {
+                Some(count) => count.parse()?,
+                None => 10,
+            };
+            for _ in 0..count {
+                println!("{}", format_instruction(emulator, address));
+                address = address.wrapping_add(emulator.disassemble(address).length as Word);
+            }
+        }
+        ("r", []) | ("registers", []) => {
+            println!("{}", emulator.trace_line(TraceFormat::SameBoy));
+            println!("cycles {}", emulator.cycles());
+        }
+        ("events", _) if args.len() <= 1 => {
+            let viewer = match emulator.event_viewer() {
+                Some(viewer) => viewer,
+                None => anyhow::bail!("the event viewer is off"),
+            };
+            for transfer in viewer.transfers() {
+                println!("{}", transfer);
+            }
+            if let Some(path) = args.first() {
+                let image = viewer.render();
+                png::write_rgba(path, image.width, image.height, &image.rgba)?;
+            }
+        }
+        ("q", []) | ("quit", []) => return Ok(true),
+        ("help", []) => println!("{}", HELP),
+        _ => anyhow::bail!("unknown command {}, see help", line.trim()),
+    }
+
+    Ok(false)
+}
+
+/// Run until a break, a crash or a line on stdin.
+fn run(emulator: &mut Emulator, lines: &Receiver<String>) {
+    println!("running, Enter to break");
+
+    loop {
+        match emulator.try_run_to_break(CYCLES_PER_FRAME) {
+            Ok(Some(Break::Breakpoint(_))) | Ok(Some(Break::SourceBreakpoint(_))) => break,
+            Ok(Some(Break::Watchpoint { address, value })) => {
+                println!("write ${:02X} to ${:04X}", value, address);
+                break;
+            }
+            Ok(None) => match lines.try_recv() {
+                Ok(_) | Err(TryRecvError::Disconnected) => break,
+                Err(TryRecvError::Empty) => {}
+            },
+            Err(report) => {
+                println!("{}", report);
+                break;
+            }
+        }
+    }
+
+    print_location(emulator);
+}
+
+fn print_location(emulator: &Emulator) {
+    println!("{}", format_instruction(emulator, emulator.cpu_state().pc));
+}
+
+/// `address` and the instruction there. `>` marks PC, `*` a breakpoint.
+fn format_instruction(emulator: &Emulator, address: Word) -> String {
+    let instruction = emulator.disassemble(address);
+    let bytes: Vec<String> = (0..instruction.length as Word)
+        .map(|offset| format!("{:02X}", emulator.peek(address.wrapping_add(offset))))
+        .collect();
+
+    let pc = if emulator.cpu_state().pc == address {
+        '>'
+    } else {
+        ' '
+    };
+    let breakpoint = if emulator.breakpoints().contains(&address) {
+        '*'
+    } else {
+        ' '
+    };
+
+    format!(
+        "{}{} ${:04X}  {:<9} {}",
+        pc,
+        breakpoint,
+        address,
+        bytes.join(" "),
+        instruction.text
+    )
+}
+
+/// Hex dump of `length` bytes from `start`, 16 per line.
+fn examine(emulator: &Emulator, start: Word, length: usize) {
+    let addresses: Vec<Word> = (0..length)
+        .map(|offset| start.wrapping_add(offset as Word))
+        .collect();
+
+    for row in addresses.chunks(16) {
+        let bytes: Vec<String> = row
+            .iter()
+            .map(|&address| format!("{:02X}", emulator.peek(address)))
+            .collect();
+        println!("${:04X}  {}", row[0], bytes.join(" "));
+    }
+}
+
+fn parse_address(text: &str) -> Result<Word> {
+    let digits = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+
+    Word::from_str_radix(digits, 16).map_err(|_| anyhow::anyhow!("bad address {}", text))
+}
+
+/// `start-end` inclusive or a single address.
+fn parse_range(text: &str) -> Result<RangeInclusive<Word>> {
+    let (start, end) = match text.split_once('-') {
+        Some((start, end)) => (parse_address(start)?, parse_address(end)?),
+        None => (parse_address(text)?, parse_address(text)?),
+    };
+    if start > end {
+        anyhow::bail!("bad range {}", text);
+    }
+
+    Ok(start..=end)
+}
+
+fn format_range(range: &RangeInclusive<Word>) -> String {
+    if range.start() == range.end() {
+        format!("${:04X}", range.start())
+    } else {
+        format!("${:04X}-${:04X}", range.start(), range.end())
+    }
+}
+
+/// Lines of stdin, read on a thread so running can stop on Enter.
+fn stdin_lines() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
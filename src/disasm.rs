@@ -0,0 +1,129 @@
+//! SM83 disassembler, for debuggers. Independent of the opcodes the CPU
+//! implements, every opcode decodes.
+//!
+//! Mnemonics follow RGBDS with `$` hex operands. Relative jumps show their
+//! target, LDH its full address:
+//!```text
+//! JR NZ, $0150
+//! LDH A, ($FF44)
+//! LD ($FF00+C), A
+//! ```
+
+use crate::{join_half_words, HalfWord, Word};
+
+const R: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const RP: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const RP2: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const CC: [&str; 4] = ["NZ", "Z", "NC", "C"];
+const ALU: [&str; 8] = [
+    "ADD A, ", "ADC A, ", "SUB ", "SBC A, ", "AND ", "XOR ", "OR ", "CP ",
+];
+const ROT: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+const MISC: [&str; 8] = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+
+/// A decoded instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub text: String,
+    /// Bytes including the opcode, 1 to 3
+    pub length: usize,
+}
+
+/// Decode the instruction at `address`, reading its bytes with `read`.
+/// Opcodes the SM83 lacks decode as `DB $xx`.
+pub fn disassemble(address: Word, read: impl Fn(Word) -> HalfWord) -> Instruction {
+    let byte = |offset: Word| read(address.wrapping_add(offset));
+    let opcode = byte(0);
+    let n = byte(1);
+    let nn = join_half_words(byte(2), byte(1));
+    // JR target, relative to the next instruction
+    let target = address.wrapping_add(2).wrapping_add(n as i8 as Word);
+
+    let (x, y, z) = (
+        opcode >> 6,
+        (opcode >> 3 & 7) as usize,
+        (opcode & 7) as usize,
+    );
+    let (p, q) = (y >> 1, y & 1);
+
+    let (text, length) = match (x, z) {
+        (0, 0) => match y {
+            0 => ("NOP".to_string(), 1),
+            1 => (format!("LD (${:04X}), SP", nn), 3),
+            2 => ("STOP".to_string(), 2),
+            3 => (format!("JR ${:04X}", target), 2),
+            _ => (format!("JR {}, ${:04X}", CC[y - 4], target), 2),
+        },
+        (0, 1) if q == 0 => (format!("LD {}, ${:04X}", RP[p], nn), 3),
+        (0, 1) => (format!("ADD HL, {}", RP[p]), 1),
+        (0, 2) => {
+            let pointer = ["(BC)", "(DE)", "(HL+)", "(HL-)"][p];
+            match q {
+                0 => (format!("LD {}, A", pointer), 1),
+                _ => (format!("LD A, {}", pointer), 1),
+            }
+        }
+        (0, 3) => (format!("{} {}", ["INC", "DEC"][q], RP[p]), 1),
+        (0, 4) => (format!("INC {}", R[y]), 1),
+        (0, 5) => (format!("DEC {}", R[y]), 1),
+        (0, 6) => (format!("LD {}, ${:02X}", R[y], n), 2),
+        (0, _) => (MISC[y].to_string(), 1),
+        (1, 6) if y == 6 => ("HALT".to_string(), 1),
+        (1, _) => (format!("LD {}, {}", R[y], R[z]), 1),
+        (2, _) => (format!("{}{}", ALU[y], R[z]), 1),
+        (_, 0) => match y {
+            0..=3 => (format!("RET {}", CC[y]), 1),
+            4 => (format!("LDH (${:04X}), A", 0xFF00 | n as Word), 2),
+            5 => (format!("ADD SP, {}", signed(n)), 2),
+            6 => (format!("LDH A, (${:04X})", 0xFF00 | n as Word), 2),
+            _ => (format!("LD HL, SP{}", signed(n)), 2),
+        },
+        (_, 1) if q == 0 => (format!("POP {}", RP2[p]), 1),
+        (_, 1) => (["RET", "RETI", "JP HL", "LD SP, HL"][p].to_string(), 1),
+        (_, 2) => match y {
+            0..=3 => (format!("JP {}, ${:04X}", CC[y], nn), 3),
+            4 => ("LD ($FF00+C), A".to_string(), 1),
+            5 => (format!("LD (${:04X}), A", nn), 3),
+            6 => ("LD A, ($FF00+C)".to_string(), 1),
+            _ => (format!("LD A, (${:04X})", nn), 3),
+        },
+        (_, 3) => match y {
+            0 => (format!("JP ${:04X}", nn), 3),
+            1 => (prefixed(n), 2),
+            6 => ("DI".to_string(), 1),
+            7 => ("EI".to_string(), 1),
+            _ => (format!("DB ${:02X}", opcode), 1),
+        },
+        (_, 4) if y < 4 => (format!("CALL {}, ${:04X}", CC[y], nn), 3),
+        (_, 5) if q == 0 => (format!("PUSH {}", RP2[p]), 1),
+        (_, 5) if p == 0 => (format!("CALL ${:04X}", nn), 3),
+        (_, 6) => (format!("{}${:02X}", ALU[y], n), 2),
+        (_, 7) => (format!("RST ${:02X}", y * 8), 1),
+        _ => (format!("DB ${:02X}", opcode), 1),
+    };
+
+    Instruction { text, length }
+}
+
+/// Whether `opcode` returns to the instruction after it, as CALL and RST
+/// do. Debuggers step over these.
+pub fn is_call(opcode: HalfWord) -> bool {
+    matches!(opcode, 0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC) || opcode & 0xC7 == 0xC7
+}
+
+/// The CB-prefixed instruction `opcode`.
+fn prefixed(opcode: HalfWord) -> String {
+    let (y, z) = ((opcode >> 3 & 7) as usize, (opcode & 7) as usize);
+
+    match opcode >> 6 {
+        0 => format!("{} {}", ROT[y], R[z]),
+        1 => format!("BIT {}, {}", y, R[z]),
+        2 => format!("RES {}, {}", y, R[z]),
+        _ => format!("SET {}, {}", y, R[z]),
+    }
+}
+
+/// `n` as a signed offset, e.g. `-2` or `+16`.
+fn signed(n: HalfWord) -> String {
+    format!("{:+}", n as i8)
+}
@@ -1,103 +1,1275 @@
-use crate::bus::Bus;
-use crate::cartridge::Cartridge;
+#[cfg(feature = "window")]
+mod window;
+
+use crate::apu::{self, AudioSink};
+use crate::audio_ring::AudioRing;
+use crate::autosplit::AutoSplitter;
+use crate::bus::{BootRom, Bus, MemoryBus};
+use crate::cartridge::{Cartridge, CartridgeHeader, Model};
+use crate::cheat::Cheat;
 use crate::cpu::Cpu;
-use crate::gpu::Gpu;
-use crate::ram::Ram;
-use crate::{SharedBus, SharedGpu};
+use crate::cpu::CpuState;
+use crate::disasm::{self, Instruction};
+use crate::event_viewer::{EventViewer, EventViewerOptions};
+use crate::fault::FaultInjector;
+#[cfg(feature = "scripting")]
+use crate::font;
+use crate::frame_hash::{crc32, FrameHasher};
+use crate::frame_timer::FrameTimer;
+use crate::gpu::{Accuracy, DebugImage, FrameCallback, Gpu, PixelFormat, Shades, TileMap};
+use crate::infrared::IrDevice;
+use crate::joypad::Button;
+use crate::movie::{Movie, MoviePlayer, MovieWriter};
+use crate::png;
+use crate::ram::{BankedRam, Ram};
+use crate::recorder::Recorder;
+use crate::scale::Scaler;
+#[cfg(feature = "scripting")]
+use crate::script::Script;
+use crate::serial::LinkCable;
+use crate::state::{StateReader, StateWriter};
+use crate::storage::{FileStorage, SaveStorage};
+use crate::telemetry::Telemetry;
+use crate::trace::{self, TraceFormat};
+use crate::wav::WavWriter;
+use crate::{HalfWord, Word};
 use anyhow::Result;
-use pixels::{Pixels, SurfaceTexture};
-use std::sync::{Arc, Mutex};
-use winit::dpi::LogicalSize;
-use winit::event::{Event, VirtualKeyCode};
-use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
-use winit_input_helper::WinitInputHelper;
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
 
 const SCREEN_WIDTH: usize = 160;
 const SCREEN_HEIGHT: usize = 144;
 
+/// Clock cycles it takes to draw one frame (154 lines of 456 cycles).
+pub const CYCLES_PER_FRAME: usize = 70224;
+
+/// Starts every save state, followed by the format version.
+const STATE_MAGIC: &[u8] = b"GBSS";
+const STATE_VERSION: u32 = 3;
+
+/// Number of executed instructions kept for crash reports.
+const TRACE_LENGTH: usize = 32;
+
+/// Frames between two autosaves in the frontends, about 10 seconds.
+pub const AUTOSAVE_INTERVAL: usize = 600;
+
+/// The slowest finite speed [`Emulator::set_speed`] accepts.
+pub const MIN_SPEED: f64 = 0.01;
+
+/// The fastest finite speed [`Emulator::set_speed`] accepts, above it only
+/// `f64::INFINITY` runs unpaced.
+pub const MAX_SPEED: f64 = 100.0;
+
 pub struct Emulator {
     cpu: Cpu,
-    gpu: SharedGpu,
+    bus: Bus,
+    // Kept to power cycle after a crash
+    rom: Option<Vec<u8>>,
+    model: Model,
+    accuracy: Accuracy,
+    shades: Shades,
+    scaler: Scaler,
+    pixel_format: PixelFormat,
+    // Kept to map it again on reset
+    boot_rom: Option<BootRom>,
+    // CPU state before each of the last executed instructions
+    trace: VecDeque<CpuState>,
+    frame_hasher: Option<FrameHasher>,
+    auto_splitter: Option<AutoSplitter>,
+    recorder: Option<Recorder>,
+    movie_writer: Option<MovieWriter>,
+    movie_player: Option<MoviePlayer>,
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
+    // Called with the new motor state when the cartridge turns rumble on or off
+    rumble_callback: Option<Box<dyn FnMut(bool) + Send>>,
+    rumble: bool,
+    // Called with the report when the core crashes in one of the `try_` methods
+    crash_callback: Option<CrashCallback>,
+    // Frames between autosaves, the frames run since the last one and the
+    // save state taken then
+    autosave_interval: Option<usize>,
+    frames_since_autosave: usize,
+    autosave: Option<Vec<u8>>,
+    storage: Box<dyn SaveStorage>,
+    // Name of the battery save in `storage`
+    battery_save: Option<String>,
+    // Save state slots are kept in `storage` as `<name>.state<slot>`
+    state_name: Option<String>,
+    pacing: Pacing,
+    // Multiple of the hardware speed, applied to the pacing and the audio
+    speed: f64,
+    paused: bool,
+    // No input from the host clock or files left by earlier runs
+    deterministic: bool,
+    // Clock cycle `run_cycles` runs up to, carrying over the overshoot
+    cycle_target: usize,
+    last_frame: Frame,
+    // `run_to_break` stops before the instructions at these addresses
+    breakpoints: BTreeSet<Word>,
+    // and before every LD B,B if set
+    source_breakpoints: bool,
+    // and after CPU writes into these ranges
+    watchpoints: Vec<RangeInclusive<Word>>,
+    // Writes into the watchpoints by the last instruction
+    watch_hits: Vec<(Word, HalfWord)>,
+}
+
+/// A completed frame, see [`Emulator::run_frame`].
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Frames completed since power on
+    pub number: usize,
+    /// DMG shade (0-3) of every pixel, 160x144 row by row
+    pub shades: Vec<u8>,
+    /// RGB555 color of every pixel in CGB mode
+    pub colors: Option<Vec<u16>>,
+}
+
+/// What keeps the emulation at the speed of the hardware.
+#[derive(Debug, Clone, Default)]
+pub enum Pacing {
+    /// Nothing, it runs as fast as it is stepped
+    #[default]
+    Unlimited,
+    /// The audio device: the emulation blocks on the ring buffer while it
+    /// is full, and only runs when the device needs more samples. Gives
+    /// smoother sound than pacing by video frames.
+    Audio(AudioRing),
+    /// A timer: [`Emulator::run_frame`] waits until the frame is due at the
+    /// 59.73 Hz of the LCD, whatever the refresh rate of the display
+    Frames(FrameTimer),
+}
+
+/// Why [`Emulator::run_to_break`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Break {
+    /// PC reached the breakpoint at this address
+    Breakpoint(Word),
+    /// PC reached an LD B,B at this address, see
+    /// [`Emulator::set_source_breakpoints`]
+    SourceBreakpoint(Word),
+    /// The CPU wrote `value` to `address` in a watchpoint
+    Watchpoint { address: Word, value: HalfWord },
+}
+
+/// What the core was doing when it panicked.
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    pub message: String,
+    /// Oldest first, the last entry is the instruction that crashed
+    pub trace: Vec<CpuState>,
+}
+
+pub type CrashCallback = Box<dyn FnMut(&CrashReport) + Send>;
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "core crashed: {}", self.message)?;
+        for s in &self.trace {
+            writeln!(
+                f,
+                "  PC:{:04X} SP:{:04X} A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X}",
+                s.pc, s.sp, s.a, s.f, s.b, s.c, s.d, s.e, s.h, s.l
+            )?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Emulator {
-    pub fn new(bus: SharedBus, gpu: SharedGpu) -> Self {
+    pub fn new(bus: Bus) -> Self {
         Emulator {
-            cpu: Cpu::new(bus),
-            gpu,
+            cpu: Cpu::new(),
+            bus,
+            rom: None,
+            model: Model::Dmg,
+            accuracy: Accuracy::default(),
+            shades: Shades::default(),
+            scaler: Scaler::default(),
+            pixel_format: PixelFormat::default(),
+            boot_rom: None,
+            trace: VecDeque::with_capacity(TRACE_LENGTH),
+            frame_hasher: None,
+            auto_splitter: None,
+            recorder: None,
+            movie_writer: None,
+            movie_player: None,
+            #[cfg(feature = "scripting")]
+            script: None,
+            rumble_callback: None,
+            rumble: false,
+            crash_callback: None,
+            autosave_interval: None,
+            frames_since_autosave: 0,
+            autosave: None,
+            storage: Box::new(FileStorage::default()),
+            battery_save: None,
+            state_name: None,
+            pacing: Pacing::default(),
+            speed: 1.0,
+            paused: false,
+            deterministic: false,
+            cycle_target: 0,
+            last_frame: Frame {
+                number: 0,
+                shades: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+                colors: None,
+            },
+            breakpoints: BTreeSet::new(),
+            source_breakpoints: false,
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
         }
     }
 
-    pub fn from_rom_byte(bytes: Vec<u8>) -> Emulator {
+    pub fn from_rom_byte(bytes: Vec<u8>) -> Result<Emulator> {
+        let bus = Emulator::build_system(Cartridge::new(bytes.clone())?);
+
+        Ok(Emulator {
+            rom: Some(bytes),
+            ..Emulator::new(bus)
+        })
+    }
+
+    /// Run a cartridge with a custom mapper (see [`Cartridge::with_mbc`]).
+    /// The ROM image is unknown to the emulator, so it can not be reset.
+    pub fn from_cartridge(cartridge: Cartridge) -> Emulator {
+        Emulator::new(Emulator::build_system(cartridge))
+    }
+
+    fn build_system(cartridge: Cartridge) -> Bus {
         // NOTE https://w.atwiki.jp/gbspec/pages/13.html サイズはこれを見て決めた
-        let video_ram = Ram::with_size(0x2000);
-        let h_ram = Ram::with_size(0x2000);
-        let oam_ram = Ram::with_size(0x2000);
-        let mirror_ram = Ram::with_size(0x2000);
-        let working_ram = Ram::with_size(0x2000);
-        let cartridge = Cartridge::new(bytes);
-        let gpu = Gpu::new(1024, None); // TODO implement
-        let gpu = Arc::new(Mutex::new(gpu));
-
-        let bus = Bus::new(
-            cartridge,
-            video_ram,
-            h_ram,
-            oam_ram,
-            mirror_ram,
-            working_ram,
-            gpu.clone(),
-        );
-
-        let bus = Arc::new(Mutex::new(bus));
-        gpu.lock().unwrap().set_bus(bus.clone());
-
-        Emulator::new(bus, gpu)
-    }
-
-    pub fn start(mut self) -> Result<()> {
-        let event_loop = EventLoop::new();
-        let mut input = WinitInputHelper::new();
-        let window = {
-            let size = LogicalSize::new(SCREEN_WIDTH as f64, SCREEN_HEIGHT as f64);
-            WindowBuilder::new()
-                .with_title("gbemu")
-                .with_inner_size(size)
-                .build(&event_loop)
-                .unwrap()
-        };
+        // Two 8 KiB banks on CGB, only bank 0 is used on DMG
+        let video_ram = BankedRam::new(0x2000, 2);
+        let h_ram = Ram::with_size(0x7F);
+        let oam_ram = Ram::with_size(0xA0);
+        // Eight 4 KiB banks on CGB, bank 0 and 1 are used on DMG
+        let mut working_ram = BankedRam::new(0x1000, 8);
+        working_ram.select_bank(1);
+        let gpu = Gpu::new();
 
-        let mut pixels = {
-            let window_size = window.inner_size();
-            let surface_texture =
-                SurfaceTexture::new(window_size.width, window_size.height, &window);
-            Pixels::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, surface_texture).unwrap()
+        Bus::new(cartridge, video_ram, h_ram, oam_ram, working_ram, gpu)
+    }
+
+    /// Run `boot_rom` from address 0 instead of starting at the cartridge
+    /// entry point with the post-boot register values.
+    pub fn set_boot_rom(&mut self, boot_rom: BootRom) {
+        self.bus.install_boot_rom(boot_rom.clone());
+        self.cpu.set_state(CpuState {
+            a: 0,
+            f: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            h: 0,
+            l: 0,
+            sp: 0,
+            pc: 0,
+        });
+        self.boot_rom = Some(boot_rom);
+    }
+
+    /// Power cycle with the same ROM, keeping the emulator settings, the
+    /// cheats, fault injection, the telemetry and what is connected to the serial and
+    /// infrared ports. Only possible for emulators created with
+    /// [`Emulator::from_rom_byte`].
+    pub fn reset(&mut self) -> Result<()> {
+        let rom = match &self.rom {
+            Some(rom) => rom.clone(),
+            None => anyhow::bail!("the ROM image is unknown, can not reset"),
         };
 
-        event_loop.run(move |event, _, control_flow| {
-            self.cpu.step().unwrap();
+        let frame_callback = self.bus.gpu_mut().take_frame_callback();
+        let audio_sink = self.bus.apu_mut().take_audio_sink();
+        let capture = self.bus.apu_mut().set_capture(None);
+        let link = self.bus.take_link();
+        let ir = self.bus.take_ir();
+        let cheats = self.bus.take_cheats();
+        let faults = self.bus.take_fault_injector();
+        let event_viewer = self.bus.take_event_viewer();
+        let telemetry = self.bus.telemetry().clone();
+        self.bus = Emulator::build_system(Cartridge::new(rom)?);
+        self.bus.set_telemetry(telemetry);
+        self.bus.connect_link(link);
+        self.bus.connect_ir(ir);
+        self.bus.set_cheats(cheats);
+        self.bus.set_fault_injector(faults);
+        self.bus.set_event_viewer(event_viewer);
+        self.bus.gpu_mut().on_frame(frame_callback);
+        self.bus.apu_mut().set_audio_sink(audio_sink);
+        self.bus.apu_mut().set_capture(capture);
+        self.bus.apu_mut().set_speed(self.speed);
+        self.bus.set_model(self.model);
+        self.bus.gpu_mut().set_accuracy(self.accuracy);
+        self.bus.gpu_mut().set_shades(self.shades);
+        self.bus.gpu_mut().set_pixel_format(self.pixel_format);
+        self.bus.set_emulated_rtc(self.deterministic);
+        self.update_write_watch();
+        self.cpu.reset();
+        self.cycle_target = 0;
+        if let Some(boot_rom) = self.boot_rom.clone() {
+            self.set_boot_rom(boot_rom);
+        }
+        self.trace.clear();
+        self.end_movie();
+
+        Ok(())
+    }
+
+    /// Swap in the ROM image at `path` and power on, e.g. a file dropped on
+    /// the window. The battery save of the running cartridge is written
+    /// first, and the battery save and save states of the new one are named
+    /// after its file.
+    pub fn load_rom(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        // Fail before touching the running cartridge
+        let header = Cartridge::new(bytes.clone())?.header().clone();
+
+        self.write_battery_save()?;
+        self.rom = Some(bytes);
+        self.reset()?;
+        self.autosave = None;
+        self.frames_since_autosave = 0;
+
+        let stem = path
+            .file_stem()
+            .map_or("save".into(), |stem| stem.to_string_lossy());
+        self.set_state_name(&stem);
+        self.battery_save = None;
+        if header.has_battery() {
+            self.set_battery_save(&format!("{}.sav", stem))?;
+        }
+
+        Ok(())
+    }
+
+    /// Execute a single instruction.
+    pub fn step(&mut self) -> Result<()> {
+        let frame_count = self.bus.gpu().frame_count();
+
+        if self.trace.len() == TRACE_LENGTH {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(self.cpu.state());
 
-            let mut gpu = self.gpu.lock().unwrap();
-            gpu.step();
+        self.cpu.step(&mut self.bus)?;
 
-            if let Event::RedrawRequested(_) = event {
-                // world.draw(pixels.get_frame());
-                pixels.render().unwrap();
+        if self.bus.gpu().frame_count() != frame_count {
+            self.bus.apply_cheat_writes();
+        }
+
+        if let Some(hasher) = self.frame_hasher.as_mut() {
+            let gpu = self.bus.gpu();
+            if gpu.frame_count() != frame_count {
+                hasher.emit(gpu.frame_count(), gpu.frame())?;
             }
+        }
 
-            if input.update(&event) {
-                if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
-                    *control_flow = ControlFlow::Exit;
-                    return;
-                }
+        if let Some(splitter) = self.auto_splitter.as_mut() {
+            if self.bus.gpu().frame_count() != frame_count {
+                splitter.update(&self.bus)?;
+            }
+        }
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            if self.bus.gpu().frame_count() != frame_count {
+                recorder.record(self.bus.gpu());
+            }
+        }
+
+        let writes = self.bus.take_watched_writes();
+        #[cfg(feature = "scripting")]
+        if let Some(script) = self.script.as_mut() {
+            script.writes(&mut self.bus, self.cpu.state(), &writes)?;
+            if self.bus.gpu().frame_count() != frame_count {
+                script.vblank(&mut self.bus, self.cpu.state())?;
+            }
+        }
+        let watchpoints = &self.watchpoints;
+        self.watch_hits = writes
+            .into_iter()
+            .filter(|(address, _)| watchpoints.iter().any(|range| range.contains(address)))
+            .collect();
+
+        if let Some(callback) = self.rumble_callback.as_mut() {
+            let rumble = self.bus.cartridge().mbc().rumble();
+            if rumble != self.rumble {
+                self.rumble = rumble;
+                callback(rumble);
+            }
+        }
+
+        Ok(())
+    }
 
-                if let Some(size) = input.window_resized() {
-                    pixels.resize(size.width, size.height);
+    /// Run until the PPU completes a frame and return it. While the LCD is
+    /// off no frame completes, then the blank screen is returned after the
+    /// time of one frame. With [`Pacing::Frames`] this returns when the frame
+    /// is due. While paused the last frame is returned again.
+    pub fn run_frame(&mut self) -> Result<&Frame> {
+        if !self.paused {
+            self.emulate_frame()?;
+        }
+
+        if let Pacing::Frames(timer) = &mut self.pacing {
+            timer.wait();
+        }
+
+        Ok(&self.last_frame)
+    }
+
+    /// Run a single frame, also while paused, without waiting for the
+    /// pacing.
+    pub fn advance_frame(&mut self) -> Result<&Frame> {
+        self.emulate_frame()?;
+        Ok(&self.last_frame)
+    }
+
+    /// Stop [`Emulator::run_frame`] from running the emulation until
+    /// [`Emulator::resume`].
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    fn emulate_frame(&mut self) -> Result<()> {
+        if let Some(player) = self.movie_player.as_mut() {
+            match player.next_frame() {
+                Some(buttons) => self.bus.set_buttons(buttons),
+                None => {
+                    log::info!("the movie ended");
+                    self.movie_player = None;
                 }
+            }
+        }
+        if let Some(writer) = self.movie_writer.as_mut() {
+            writer.write_frame(self.bus.buttons());
+        }
+
+        let frame_count = self.bus.gpu().frame_count();
+        let deadline = self.cycles() + (CYCLES_PER_FRAME << self.bus.double_speed() as usize);
+
+        while self.bus.gpu().frame_count() == frame_count && self.cycles() < deadline {
+            self.step()?;
+        }
+
+        #[cfg(feature = "scripting")]
+        if let Some(script) = self.script.as_mut() {
+            script.frame(&mut self.bus, self.cpu.state())?;
+        }
+
+        self.copy_last_frame();
 
-                window.request_redraw();
+        if let Some(interval) = self.autosave_interval {
+            self.frames_since_autosave += 1;
+            if self.frames_since_autosave >= interval {
+                self.frames_since_autosave = 0;
+                self.autosave = Some(self.save_state());
             }
-        });
+        }
+
+        Ok(())
+    }
+
+    fn copy_last_frame(&mut self) {
+        let gpu = self.bus.gpu();
+        self.last_frame.number = gpu.frame_count();
+        self.last_frame.shades.copy_from_slice(gpu.frame());
+        self.last_frame.colors = if gpu.cgb() {
+            Some(gpu.color_frame().to_vec())
+        } else {
+            None
+        };
+    }
+
+    /// Run for `cycles` clock cycles. Instructions don't stop exactly there,
+    /// the cycles run over are taken off the next call.
+    pub fn run_cycles(&mut self, cycles: usize) -> Result<()> {
+        self.cycle_target = self.cycle_target.max(self.cycles()) + cycles;
+
+        while self.cycles() < self.cycle_target {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    /// The frame returned by the last [`Emulator::run_frame`].
+    pub fn last_frame(&self) -> &Frame {
+        &self.last_frame
+    }
+
+    /// The current screen as RGBA, with the shades of
+    /// [`Emulator::set_shades`] or the CGB colors, whatever the pixel format.
+    pub fn rgba_frame(&self) -> Vec<u8> {
+        let mut screen = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        self.bus
+            .gpu()
+            .encode_frame(PixelFormat::Rgba8888, &mut screen);
+
+        screen
+    }
+
+    /// Write the current screen to a PNG file at its native 160x144.
+    pub fn screenshot(&self, path: impl AsRef<Path>) -> Result<()> {
+        png::write_rgba(path, SCREEN_WIDTH, SCREEN_HEIGHT, &self.rgba_frame())
+    }
+
+    /// Write the current screen to a PNG file as the window shows it, with
+    /// the scaler of [`Emulator::set_scaler`] applied.
+    pub fn scaled_screenshot(&self, path: impl AsRef<Path>) -> Result<()> {
+        let screen = self.rgba_frame();
+
+        let (width, height) = self.scaler.output_size(SCREEN_WIDTH, SCREEN_HEIGHT);
+        let mut scaled = vec![0; width * height * 4];
+        self.scaler
+            .apply(&screen, SCREEN_WIDTH, SCREEN_HEIGHT, &mut scaled);
+
+        png::write_rgba(path, width, height, &scaled)
+    }
+
+    /// Execute a single instruction, turning a panic of the core into a
+    /// [`CrashReport`] instead of tearing down the process. After a crash the
+    /// core state is unusable until [`Emulator::reset`] or
+    /// [`Emulator::load_autosave`].
+    pub fn try_step(&mut self) -> std::result::Result<(), CrashReport> {
+        self.catch_crash(Emulator::step)
+    }
+
+    /// [`Emulator::run_frame`], turning a panic of the core into a
+    /// [`CrashReport`] like [`Emulator::try_step`].
+    pub fn try_run_frame(&mut self) -> std::result::Result<&Frame, CrashReport> {
+        self.catch_crash(|emu| emu.run_frame().map(|_| ()))?;
+        Ok(&self.last_frame)
+    }
+
+    /// [`Emulator::run_cycles`], turning a panic of the core into a
+    /// [`CrashReport`] like [`Emulator::try_step`].
+    pub fn try_run_cycles(&mut self, cycles: usize) -> std::result::Result<(), CrashReport> {
+        self.catch_crash(|emu| emu.run_cycles(cycles))
+    }
+
+    /// [`Emulator::advance_frame`], turning a panic of the core into a
+    /// [`CrashReport`] like [`Emulator::try_step`].
+    pub fn try_advance_frame(&mut self) -> std::result::Result<&Frame, CrashReport> {
+        self.catch_crash(|emu| emu.advance_frame().map(|_| ()))?;
+        Ok(&self.last_frame)
+    }
+
+    /// [`Emulator::run_to_break`], turning a panic of the core into a
+    /// [`CrashReport`] like [`Emulator::try_step`].
+    pub fn try_run_to_break(
+        &mut self,
+        cycles: usize,
+    ) -> std::result::Result<Option<Break>, CrashReport> {
+        let mut stop = None;
+        self.catch_crash(|emu| {
+            stop = emu.run_to_break(cycles)?;
+            Ok(())
+        })?;
+
+        Ok(stop)
+    }
+
+    fn catch_crash(
+        &mut self,
+        run: impl FnOnce(&mut Emulator) -> Result<()>,
+    ) -> std::result::Result<(), CrashReport> {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run(self)));
+
+        let message = match result {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(e)) => e.to_string(),
+            Err(payload) => match payload.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => match payload.downcast_ref::<String>() {
+                    Some(message) => message.clone(),
+                    None => "unknown panic".to_string(),
+                },
+            },
+        };
+
+        let report = CrashReport {
+            message,
+            trace: self.trace.iter().copied().collect(),
+        };
+        if let Some(callback) = self.crash_callback.as_mut() {
+            callback(&report);
+        }
+
+        Err(report)
+    }
+
+    /// Call `callback` with the report every time the core crashes in one of
+    /// the `try_` methods, e.g. to show it in a frontend that drives the
+    /// emulator from another thread or language.
+    pub fn set_crash_callback(&mut self, callback: Option<CrashCallback>) {
+        self.crash_callback = callback;
+    }
+
+    /// Take a save state every `interval` frames run, to recover from a
+    /// crash with [`Emulator::load_autosave`]. Only the latest one is kept,
+    /// in memory. Off by default.
+    pub fn set_autosave(&mut self, interval: Option<usize>) {
+        self.autosave_interval = interval;
+        self.frames_since_autosave = 0;
+        if interval.is_none() {
+            self.autosave = None;
+        }
+    }
+
+    pub fn has_autosave(&self) -> bool {
+        self.autosave.is_some()
+    }
+
+    /// Go back to the last autosave, see [`Emulator::set_autosave`].
+    pub fn load_autosave(&mut self) -> Result<()> {
+        match self.autosave.clone() {
+            Some(state) => self.load_state(&state),
+            None => anyhow::bail!("there is no autosave yet"),
+        }
+    }
+
+    /// Write a CRC32 of the framebuffer to `output` every time a frame completes.
+    pub fn set_frame_hash_output(&mut self, output: Box<dyn std::io::Write + Send>) {
+        self.frame_hasher = Some(FrameHasher::new(output));
+    }
+
+    /// Watch memory every frame and report speedrun splits, see [`crate::autosplit`].
+    pub fn set_auto_splitter(&mut self, splitter: Option<AutoSplitter>) {
+        self.auto_splitter = splitter;
+    }
+
+    /// Run the top level of `script` and call its callbacks from then on,
+    /// see [`crate::script`]. Replaces the script running, `None` removes
+    /// it.
+    #[cfg(feature = "scripting")]
+    pub fn set_script(&mut self, script: Option<Script>) -> Result<()> {
+        self.script = None;
+        self.bus.set_write_watch(Vec::new());
+
+        if let Some(mut script) = script {
+            script.start(&mut self.bus, self.cpu.state())?;
+            self.script = Some(script);
+        }
+        self.update_write_watch();
+
+        Ok(())
+    }
+
+    /// Draw the text of the script over `rgba`, the screen in RGBA8888.
+    #[cfg(feature = "scripting")]
+    pub fn draw_overlay(&self, rgba: &mut [u8]) {
+        if let Some(script) = &self.script {
+            for text in script.overlay() {
+                font::draw_text(rgba, SCREEN_WIDTH, text.x, text.y, &text.text, text.color);
+            }
+        }
+    }
+
+    /// Stop [`Emulator::run_to_break`] before the instruction at `address`.
+    /// Breakpoints are kept across [`Emulator::reset`].
+    pub fn add_breakpoint(&mut self, address: Word) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Returns whether there was a breakpoint at `address`.
+    pub fn remove_breakpoint(&mut self, address: Word) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// Addresses of the breakpoints, in order.
+    pub fn breakpoints(&self) -> Vec<Word> {
+        self.breakpoints.iter().copied().collect()
+    }
+
+    /// Also stop [`Emulator::run_to_break`] before every LD B,B, the source
+    /// code breakpoint of BGB. Homebrew and test ROMs such as mooneye-gb's
+    /// put it where they want to stop.
+    pub fn set_source_breakpoints(&mut self, enabled: bool) {
+        self.source_breakpoints = enabled;
+    }
+
+    /// Stop [`Emulator::run_to_break`] after the CPU writes into `range`.
+    /// Writes of DMA and [`Emulator::poke`] are not watched.
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<Word>) {
+        if !self.watchpoints.contains(&range) {
+            self.watchpoints.push(range);
+            self.update_write_watch();
+        }
+    }
+
+    /// Returns whether `range` was watched.
+    pub fn remove_watchpoint(&mut self, range: &RangeInclusive<Word>) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|watched| watched != range);
+        self.update_write_watch();
+
+        self.watchpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> &[RangeInclusive<Word>] {
+        &self.watchpoints
+    }
+
+    /// Run until PC reaches a breakpoint, also a source breakpoint, or the
+    /// CPU writes into a watchpoint, for at most `cycles` clock cycles. At least one
+    /// instruction is executed, so this continues from a breakpoint.
+    /// Returns `None` when the cycles ran out.
+    pub fn run_to_break(&mut self, cycles: usize) -> Result<Option<Break>> {
+        let end = self.cycles() + cycles;
+
+        loop {
+            self.step()?;
+
+            if let Some(&(address, value)) = self.watch_hits.first() {
+                return Ok(Some(Break::Watchpoint { address, value }));
+            }
+            let pc = self.cpu.state().pc;
+            if self.breakpoints.contains(&pc) {
+                return Ok(Some(Break::Breakpoint(pc)));
+            }
+            // LD B,B
+            if self.source_breakpoints && self.bus.peek(pc) == 0x40 {
+                return Ok(Some(Break::SourceBreakpoint(pc)));
+            }
+            if self.cycles() >= end {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// The instruction at `address`, read as the CPU would read it now.
+    pub fn disassemble(&self, address: Word) -> Instruction {
+        disasm::disassemble(address, |address| self.bus.peek(address))
+    }
+
+    // The bus watches the writes for the script and the watchpoints
+    fn update_write_watch(&mut self) {
+        let ranges = self.watchpoints.iter().cloned();
+        #[cfg(feature = "scripting")]
+        let ranges = ranges.chain(self.script.iter().flat_map(|script| script.write_watch()));
+
+        self.bus.set_write_watch(ranges.collect());
+    }
+
+    /// Elapsed clock cycles since power on.
+    pub fn cycles(&self) -> usize {
+        self.cpu.cycles()
+    }
+
+    /// Every byte the game sent over the serial port, e.g. the results of
+    /// Blargg's test ROMs.
+    pub fn serial_output(&self) -> Vec<u8> {
+        self.bus.serial_output().to_vec()
+    }
+
+    /// Ignored while a movie plays.
+    pub fn set_button_state(&mut self, button: Button, pressed: bool) {
+        if self.movie_player.is_none() {
+            self.bus.set_button_state(button, pressed)
+        }
+    }
+
+    /// Plug a link cable into the serial port, e.g. one end of an
+    /// [`InProcessLink`](crate::serial::InProcessLink) for two players. The
+    /// cable stays plugged in across [`Emulator::reset`].
+    pub fn connect_link(&mut self, link: Box<dyn LinkCable>) {
+        self.bus.connect_link(link)
+    }
+
+    /// Connect the infrared port, e.g. to another instance for IR minigames.
+    /// Stays connected across [`Emulator::reset`].
+    pub fn connect_ir(&mut self, device: Box<dyn IrDevice>) {
+        self.bus.connect_ir(device)
+    }
+
+    /// Replace where save files are read from and written to. Defaults to
+    /// the current directory.
+    pub fn set_save_storage(&mut self, storage: Box<dyn SaveStorage>) {
+        self.storage = storage;
+    }
+
+    pub fn save_storage(&mut self) -> &mut dyn SaveStorage {
+        self.storage.as_mut()
+    }
+
+    /// Enable a Game Genie (`ABC-DEF-GHI`) or GameShark (`01VVLLHH`) code.
+    pub fn add_cheat(&mut self, code: &str) -> Result<Cheat> {
+        self.bus.cheats_mut().add(code)
+    }
+
+    /// Returns whether the code was in use.
+    pub fn remove_cheat(&mut self, code: &str) -> bool {
+        self.bus.cheats_mut().remove(code)
+    }
+
+    /// Turn a cheat added with [`Emulator::add_cheat`] on or off. Returns
+    /// whether the code was in use.
+    pub fn set_cheat_enabled(&mut self, code: &str, enabled: bool) -> bool {
+        self.bus.cheats_mut().set_enabled(code, enabled)
+    }
+
+    /// Call `callback` with the motor state every time a rumble cartridge
+    /// turns its motor on or off, e.g. to drive a gamepad's rumble.
+    pub fn set_rumble_callback(&mut self, callback: Option<Box<dyn FnMut(bool) + Send>>) {
+        self.rumble_callback = callback;
+    }
+
+    /// Tilt the cartridge, for games with an accelerometer (MBC7). Both axes
+    /// range from -1.0 to 1.0, positive x tilts right and positive y down.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.bus.cartridge_mut().mbc_mut().set_tilt(x, y)
+    }
+
+    /// Load the battery save `name` from the save storage, if it exists.
+    /// The cartridge RAM is written back to it on exit.
+    /// Ignored in deterministic mode.
+    pub fn set_battery_save(&mut self, name: &str) -> Result<()> {
+        if self.deterministic {
+            return Ok(());
+        }
+        if let Some(data) = self.storage.load(name)? {
+            self.bus.cartridge_mut().load_save_data(&data);
+        }
+        self.battery_save = Some(name.to_string());
+
+        Ok(())
+    }
+
+    /// Snapshot of the whole machine, to restore with
+    /// [`Emulator::load_state`] on an emulator running the same ROM and
+    /// model. The settings of the emulator are not part of it.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        for &byte in STATE_MAGIC {
+            state.u8(byte);
+        }
+        state.u32(STATE_VERSION);
+        state.u32(crc32(&self.bus.cartridge().data));
+        state.u8(self.model as u8);
+        self.cpu.save_state(&mut state);
+        self.bus.save_state(&mut state);
+
+        state.into_bytes()
+    }
+
+    /// Restore a snapshot taken by [`Emulator::save_state`]. On error the
+    /// emulator is left as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        let backup = self.save_state();
+        if let Err(e) = self.restore_state(data) {
+            self.restore_state(&backup)
+                .expect("the state before loading can be restored");
+            return Err(e);
+        }
+        self.end_movie();
+
+        Ok(())
+    }
+
+    /// Record the buttons of every frame run by [`Emulator::run_frame`] and
+    /// [`Emulator::advance_frame`] to the movie file `path`, from the
+    /// current state on, until [`Emulator::stop_movie_recording`]. Reset
+    /// first to record from power on.
+    pub fn start_movie_recording(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.stop_movie_recording()?;
+
+        let rom_crc = crc32(&self.bus.cartridge().data);
+        let writer = MovieWriter::create(path, rom_crc, self.bus.buttons(), &self.save_state())?;
+        self.movie_writer = Some(writer);
+
+        Ok(())
+    }
+
+    /// Finish the movie file being recorded, if any.
+    pub fn stop_movie_recording(&mut self) -> Result<()> {
+        if let Some(writer) = self.movie_writer.take() {
+            let frames = writer.finish()?;
+            log::info!("recorded a movie of {} frames", frames);
+        }
+
+        Ok(())
+    }
+
+    /// Load the start state of the movie file `path`, and feed its buttons
+    /// to the joypad frame by frame instead of [`Emulator::set_button_state`]
+    /// until it ends.
+    pub fn play_movie(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let movie = Movie::read(path)?;
+        if movie.rom_crc != crc32(&self.bus.cartridge().data) {
+            anyhow::bail!("the movie was recorded with a different ROM");
+        }
+
+        self.load_state(&movie.start_state)?;
+        self.bus.restore_buttons(movie.held);
+        self.movie_player = Some(MoviePlayer::new(movie.frames));
+
+        Ok(())
+    }
+
+    pub fn movie_playing(&self) -> bool {
+        self.movie_player.is_some()
+    }
+
+    /// Loading a state or a reset breaks the input of a movie, so it ends
+    /// there.
+    fn end_movie(&mut self) {
+        self.movie_player = None;
+        if let Err(e) = self.stop_movie_recording() {
+            log::error!("failed to finish the movie: {}", e);
+        }
+    }
+
+    fn restore_state(&mut self, data: &[u8]) -> Result<()> {
+        if !data.starts_with(STATE_MAGIC) {
+            anyhow::bail!("not a save state");
+        }
+        let mut state = StateReader::new(&data[STATE_MAGIC.len()..]);
+
+        let version = state.u32()?;
+        if version != STATE_VERSION {
+            anyhow::bail!(
+                "save state version {} is not supported, expected {}",
+                version,
+                STATE_VERSION
+            );
+        }
+        if state.u32()? != crc32(&self.bus.cartridge().data) {
+            anyhow::bail!("the save state is of another ROM");
+        }
+        if state.u8()? != self.model as u8 {
+            anyhow::bail!("the save state is of another model than {}", self.model);
+        }
+
+        // Mapped again for states taken while it still was
+        if let Some(boot_rom) = &self.boot_rom {
+            self.bus.install_boot_rom(boot_rom.clone());
+        }
+        self.cpu.load_state(&mut state)?;
+        self.bus.load_state(&mut state)?;
+        state.finish()?;
+
+        self.trace.clear();
+        self.cycle_target = 0;
+        self.copy_last_frame();
+
+        Ok(())
+    }
+
+    /// Name the save state slots in the save storage `<name>.state<slot>`.
+    /// Defaults to the title in the cartridge header.
+    pub fn set_state_name(&mut self, name: &str) {
+        self.state_name = Some(name.to_string());
+    }
+
+    fn state_slot_name(&self, slot: usize) -> String {
+        let name = match &self.state_name {
+            Some(name) => name.as_str(),
+            None => &self.cartridge_header().title,
+        };
+        format!("{}.state{}", name, slot)
+    }
+
+    /// Write [`Emulator::save_state`] to a numbered slot in the save storage.
+    pub fn save_state_slot(&mut self, slot: usize) -> Result<()> {
+        let data = self.save_state();
+        let name = self.state_slot_name(slot);
+        self.storage.store(&name, &data)?;
+
+        Ok(())
+    }
+
+    /// Load a slot written by [`Emulator::save_state_slot`].
+    pub fn load_state_slot(&mut self, slot: usize) -> Result<()> {
+        let name = self.state_slot_name(slot);
+        match self.storage.load(&name)? {
+            Some(data) => self.load_state(&data),
+            None => anyhow::bail!("save state slot {} is empty", slot),
+        }
+    }
+
+    /// Write the cartridge RAM and RTC state to the battery save, if one was
+    /// set with [`Emulator::set_battery_save`].
+    pub fn write_battery_save(&mut self) -> Result<()> {
+        if let Some(name) = &self.battery_save {
+            let data = self.bus.cartridge().save_data();
+            self.storage.store(name, &data)?;
+        }
+
+        Ok(())
+    }
+
+    /// The unimplemented opcodes, I/O registers and mapper registers the ROM
+    /// used, since the emulator was created.
+    pub fn telemetry(&self) -> &Telemetry {
+        self.bus.telemetry()
+    }
+
+    /// Execute unimplemented opcodes as NOP instead of panicking. Every opcode
+    /// skipped this way shows up in [`Emulator::telemetry`].
+    pub fn set_skip_unimplemented(&mut self, enabled: bool) {
+        self.cpu.set_skip_unimplemented(enabled)
+    }
+
+    /// Deterministic mode: runs are bit-identical given the same ROM, boot
+    /// ROM and input, e.g. a movie. Nothing of the host gets into the
+    /// emulated machine:
+    ///
+    /// - The MBC3 real time clock starts at 0 on power on and follows the
+    ///   emulated time instead of the host clock.
+    /// - Battery saves are neither loaded nor written, the cartridge RAM
+    ///   starts cleared. Set this before [`Emulator::set_battery_save`].
+    ///
+    /// The pacing still follows the host clock, it decides when frames run
+    /// but not what happens in them.
+    pub fn set_deterministic(&mut self, enabled: bool) {
+        self.deterministic = enabled;
+        self.bus.set_emulated_rtc(enabled);
+        if enabled {
+            self.battery_save = None;
+        }
+    }
+
+    pub fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Inject faults into bus accesses (bit flips, delayed writes) to exercise
+    /// error paths of games and the emulator. `None` turns injection off.
+    pub fn set_fault_injector(&mut self, faults: Option<FaultInjector>) {
+        self.bus.set_fault_injector(faults)
+    }
+
+    /// Hardware model to emulate, DMG by default.
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.bus.set_model(model)
+    }
+
+    /// Call `callback` with every completed frame, at VBlank.
+    pub fn on_frame(&mut self, callback: Option<FrameCallback>) {
+        self.bus.gpu_mut().on_frame(callback)
+    }
+
+    /// Renderer to use, the scanline renderer by default. The pixel FIFO is
+    /// needed by games changing the PPU registers in the middle of a line.
+    pub fn set_ppu_accuracy(&mut self, accuracy: Accuracy) {
+        self.accuracy = accuracy;
+        self.bus.gpu_mut().set_accuracy(accuracy)
+    }
+
+    /// Colors of the 4 DMG shades in the window, gray by default.
+    pub fn set_shades(&mut self, shades: Shades) {
+        self.shades = shades;
+        self.bus.gpu_mut().set_shades(shades)
+    }
+
+    /// Upscaling of the screen in the window, 2x nearest neighbor by default.
+    /// Only read by [`Emulator::start`] and [`Emulator::scaled_screenshot`].
+    pub fn set_scaler(&mut self, scaler: Scaler) {
+        self.scaler = scaler;
+    }
+
+    /// Opt in to emulating the DMG OAM corruption bug, which some test ROMs
+    /// and a few games depend on.
+    pub fn set_oam_corruption(&mut self, enabled: bool) {
+        self.cpu.set_oam_corruption(enabled)
+    }
+
+    pub fn cpu_state(&self) -> CpuState {
+        self.cpu.state()
+    }
+
+    pub fn set_cpu_state(&mut self, state: CpuState) {
+        self.cpu.set_state(state)
+    }
+
+    /// Read `address` as the CPU would see it, without side effects. See
+    /// [`Bus::peek`].
+    pub fn peek(&self, address: Word) -> HalfWord {
+        self.bus.peek(address)
+    }
+
+    /// Patch `address`, e.g. the RAM of a game, see [`Bus::poke`].
+    pub fn poke(&mut self, address: Word, byte: HalfWord) {
+        self.bus.poke(address, byte)
+    }
+
+    /// Trace line for the instruction about to be executed.
+    pub fn trace_line(&self, format: TraceFormat) -> String {
+        let state = self.cpu.state();
+        let mut pcmem = [0; 4];
+        for (i, byte) in pcmem.iter_mut().enumerate() {
+            *byte = self.bus.peek_byte(state.pc.wrapping_add(i as u16));
+        }
+
+        trace::format_line(format, &state, pcmem, self.cpu.cycles())
+    }
+
+    pub fn cartridge_header(&self) -> &CartridgeHeader {
+        self.bus.cartridge().header()
+    }
+
+    /// Shade id (0-3) of every pixel on the screen, row by row.
+    pub fn frame(&self) -> Vec<u8> {
+        self.bus.gpu().frame().to_vec()
+    }
+
+    /// The screen in the pixel format set by [`Emulator::set_pixel_format`],
+    /// ready to be copied to a display.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.bus.gpu().framebuffer()
+    }
+
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.pixel_format = format;
+        self.bus.gpu_mut().set_pixel_format(format)
+    }
+
+    /// Take the audio generated since the last call, as stereo samples at
+    /// [`apu::SAMPLE_RATE`].
+    pub fn audio_samples(&mut self) -> Vec<[f32; 2]> {
+        self.bus.apu_mut().take_samples()
+    }
+
+    /// Pass the audio to `sink` as it is generated, instead of buffering it
+    /// for [`Emulator::audio_samples`].
+    pub fn set_audio_sink(&mut self, sink: Option<Box<dyn AudioSink + Send>>) {
+        self.bus.apu_mut().set_audio_sink(sink)
+    }
+
+    /// Switch how the emulation is paced. Audio sync replaces the audio
+    /// sink with the ring buffer, switching away from it removes the sink.
+    pub fn set_pacing(&mut self, mut pacing: Pacing) {
+        if let Pacing::Frames(timer) = &mut pacing {
+            timer.set_speed(self.speed);
+        }
+        match &pacing {
+            Pacing::Audio(ring) => self.set_audio_sink(Some(Box::new(ring.clone()))),
+            _ if matches!(self.pacing, Pacing::Audio(_)) => self.set_audio_sink(None),
+            _ => {}
+        }
+        self.pacing = pacing;
+    }
+
+    /// Run at `multiplier` times the speed of the hardware, e.g. 4.0 to
+    /// fast-forward. [`Pacing::Frames`] shortens the frames and
+    /// [`Pacing::Audio`] runs as fast as the audio, which is generated for
+    /// the faster time at a higher pitch. `f64::INFINITY` disables the
+    /// pacing and mutes the audio, other speeds must be between
+    /// [`MIN_SPEED`] and [`MAX_SPEED`].
+    pub fn set_speed(&mut self, multiplier: f64) -> Result<()> {
+        if multiplier != f64::INFINITY && !(MIN_SPEED..=MAX_SPEED).contains(&multiplier) {
+            anyhow::bail!(
+                "invalid speed {}, must be between {} and {}",
+                multiplier,
+                MIN_SPEED,
+                MAX_SPEED
+            );
+        }
+
+        self.speed = multiplier;
+        if let Pacing::Frames(timer) = &mut self.pacing {
+            timer.set_speed(multiplier);
+        }
+        self.bus.apu_mut().set_speed(multiplier);
+
+        Ok(())
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Record the audio to the WAV file `path` until
+    /// [`Emulator::stop_audio_capture`], alongside any audio sink. A capture
+    /// already running is stopped first.
+    pub fn start_audio_capture(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let capture = WavWriter::create(path, apu::SAMPLE_RATE as u32)?;
+
+        match self.bus.apu_mut().set_capture(Some(capture)) {
+            Some(previous) => previous.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Finish the WAV file of the running capture, if any.
+    pub fn stop_audio_capture(&mut self) -> Result<()> {
+        match self.bus.apu_mut().set_capture(None) {
+            Some(capture) => capture.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Record the screen to the animated GIF `path`, and the audio to a WAV
+    /// file next to it, until [`Emulator::stop_recording`]. This replaces an
+    /// audio capture, and a recording already running is stopped first.
+    pub fn start_recording(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        self.stop_recording()?;
+
+        self.recorder = Some(Recorder::create(path)?);
+        self.start_audio_capture(path.with_extension("wav"))
+    }
+
+    /// Finish the files of the running recording, if any.
+    pub fn stop_recording(&mut self) -> Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => {
+                let audio = self.stop_audio_capture();
+                recorder.finish()?;
+                audio
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// The tiles in VRAM, see [`Gpu::debug_render_tiles`].
+    pub fn debug_render_tiles(&self) -> DebugImage {
+        self.bus.gpu().debug_render_tiles(self.bus.video_ram())
+    }
+
+    /// A tile map in VRAM, see [`Gpu::debug_render_tilemap`].
+    pub fn debug_render_tilemap(&self, which: TileMap) -> DebugImage {
+        self.bus
+            .gpu()
+            .debug_render_tilemap(self.bus.video_ram(), which)
+    }
+
+    /// The background plane with the viewport, see [`Gpu::render_full_bg`].
+    pub fn render_full_bg(&self) -> DebugImage {
+        self.bus.gpu().render_full_bg(self.bus.video_ram())
+    }
+
+    /// Record when OAM DMA and VRAM DMA run in every frame, for
+    /// [`Emulator::event_viewer`]. `None` stops recording. Kept across
+    /// [`Emulator::reset`].
+    pub fn set_event_viewer(&mut self, options: Option<EventViewerOptions>) {
+        self.bus.set_event_viewer(options.map(EventViewer::new))
+    }
+
+    /// The last completed frame as recorded since
+    /// [`Emulator::set_event_viewer`], see [`crate::event_viewer`].
+    pub fn event_viewer(&self) -> Option<&EventViewer> {
+        self.bus.event_viewer()
     }
 }
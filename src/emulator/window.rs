@@ -0,0 +1,297 @@
+//! The window of [`Emulator::start`], drawn with `pixels` on a `winit`
+//! event loop.
+
+use super::{Emulator, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gpu::PixelFormat;
+use crate::joypad::Button;
+use crate::speed_meter::SpeedMeter;
+use anyhow::Result;
+use pixels::{Pixels, SurfaceTexture};
+use std::time::{SystemTime, UNIX_EPOCH};
+use winit::dpi::LogicalSize;
+use winit::event::{Event, VirtualKeyCode, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::{Fullscreen, WindowBuilder};
+use winit_input_helper::WinitInputHelper;
+
+/// Speed while the fast-forward key (Tab) is held in the window. Only every
+/// fourth frame is drawn then.
+const TURBO_SPEED: f64 = 4.0;
+
+/// Keyboard layout of the joypad in the window.
+const JOYPAD_KEYS: [(VirtualKeyCode, Button); 9] = [
+    (VirtualKeyCode::Right, Button::Right),
+    (VirtualKeyCode::Left, Button::Left),
+    (VirtualKeyCode::Up, Button::Up),
+    (VirtualKeyCode::Down, Button::Down),
+    (VirtualKeyCode::Z, Button::A),
+    (VirtualKeyCode::X, Button::B),
+    (VirtualKeyCode::Return, Button::Start),
+    (VirtualKeyCode::LShift, Button::Select),
+    (VirtualKeyCode::RShift, Button::Select),
+];
+
+/// Keys of the save state slots in the window, 1-9 load and Shift+1-9 save.
+const STATE_SLOT_KEYS: [VirtualKeyCode; 9] = [
+    VirtualKeyCode::Key1,
+    VirtualKeyCode::Key2,
+    VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5,
+    VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7,
+    VirtualKeyCode::Key8,
+    VirtualKeyCode::Key9,
+];
+
+impl Emulator {
+    pub fn start(mut self) -> Result<()> {
+        let event_loop = EventLoop::new();
+        let mut input = WinitInputHelper::new();
+        let (width, height) = self.scaler.output_size(SCREEN_WIDTH, SCREEN_HEIGHT);
+        let window = {
+            let size = LogicalSize::new(width as f64, height as f64);
+            WindowBuilder::new()
+                .with_title("gbemu")
+                .with_inner_size(size)
+                .with_min_inner_size(size)
+                .build(&event_loop)
+                .unwrap()
+        };
+
+        let mut pixels = {
+            let window_size = window.inner_size();
+            let surface_texture =
+                SurfaceTexture::new(window_size.width, window_size.height, &window);
+            Pixels::new(width as u32, height as u32, surface_texture).unwrap()
+        };
+        // The unscaled screen
+        let mut screen = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+
+        let mut crashed = false;
+        let mut turbo = false;
+        let mut meter = SpeedMeter::new();
+        let mut show_speed = false;
+        let mut shown_title = String::from("gbemu");
+
+        event_loop.run(move |event, _, control_flow| {
+            match event {
+                // Run a frame once the pending events are handled, and skip
+                // drawing the frames in between while fast-forwarding
+                Event::MainEventsCleared if !crashed => {
+                    let frames = if turbo { TURBO_SPEED as usize } else { 1 };
+                    for _ in 0..frames {
+                        if let Err(report) = self.try_run_frame() {
+                            log::error!("{}", report);
+                            crashed = true;
+                            break;
+                        }
+                    }
+                    if self.paused() {
+                        meter.restart();
+                    } else {
+                        meter.add_frames(frames);
+                    }
+
+                    self.bus
+                        .gpu()
+                        .encode_frame(PixelFormat::Rgba8888, &mut screen);
+                    #[cfg(feature = "scripting")]
+                    self.draw_overlay(&mut screen);
+                    self.scaler
+                        .apply(&screen, SCREEN_WIDTH, SCREEN_HEIGHT, pixels.get_frame());
+                    window.request_redraw();
+                }
+                Event::RedrawRequested(_) => pixels.render().unwrap(),
+                Event::WindowEvent {
+                    event: WindowEvent::DroppedFile(ref path),
+                    ..
+                } => match self.load_rom(path) {
+                    Ok(()) => {
+                        log::info!("loaded {}", path.display());
+                        crashed = false;
+                        self.resume();
+                    }
+                    Err(e) => log::error!("failed to load {}: {}", path.display(), e),
+                },
+                _ => {}
+            }
+
+            if input.update(&event) {
+                if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+                    log::info!("{}", self.telemetry().summary());
+                    if let Err(e) = self.write_battery_save() {
+                        log::error!("failed to write the battery save: {}", e);
+                    }
+                    if let Err(e) = self.stop_recording() {
+                        log::error!("failed to finish the recording: {}", e);
+                    }
+                    if let Err(e) = self.stop_movie_recording() {
+                        log::error!("failed to finish the movie: {}", e);
+                    }
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+
+                if crashed && input.key_pressed(VirtualKeyCode::R) {
+                    match self.reset() {
+                        Ok(()) => {
+                            crashed = false;
+                            self.resume();
+                        }
+                        Err(e) => log::error!("{}", e),
+                    }
+                }
+
+                if crashed && input.key_pressed(VirtualKeyCode::L) {
+                    match self.load_autosave() {
+                        Ok(()) => {
+                            log::info!("loaded the autosave");
+                            crashed = false;
+                            self.resume();
+                        }
+                        Err(e) => log::error!("{}", e),
+                    }
+                }
+
+                if !crashed && input.key_pressed(VirtualKeyCode::P) {
+                    if self.paused() {
+                        self.resume();
+                    } else {
+                        self.pause();
+                    }
+                }
+
+                if !crashed && self.paused() && input.key_pressed(VirtualKeyCode::N) {
+                    if let Err(report) = self.try_advance_frame() {
+                        log::error!("{}", report);
+                        crashed = true;
+                    }
+                }
+
+                // Only the edges are passed on, the joypad requests its
+                // interrupt when a button goes down
+                for &(key, button) in JOYPAD_KEYS.iter() {
+                    if input.key_pressed(key) {
+                        self.set_button_state(button, true);
+                    } else if input.key_released(key) {
+                        self.set_button_state(button, false);
+                    }
+                }
+
+                for (slot, &key) in (1..).zip(STATE_SLOT_KEYS.iter()) {
+                    if crashed || !input.key_pressed(key) {
+                        continue;
+                    }
+
+                    let result = if input.held_shift() {
+                        self.save_state_slot(slot)
+                    } else {
+                        self.load_state_slot(slot)
+                    };
+                    match result {
+                        Ok(()) if input.held_shift() => log::info!("saved state {}", slot),
+                        Ok(()) => log::info!("loaded state {}", slot),
+                        Err(e) => log::error!("{}", e),
+                    }
+                }
+
+                if input.key_held(VirtualKeyCode::Tab) != turbo {
+                    turbo = !turbo;
+                    let speed = if turbo { TURBO_SPEED } else { 1.0 };
+                    if let Err(e) = self.set_speed(speed) {
+                        log::error!("{}", e);
+                    }
+                }
+
+                if input.key_pressed(VirtualKeyCode::F10) {
+                    if self.recording() {
+                        match self.stop_recording() {
+                            Ok(()) => log::info!("stopped recording"),
+                            Err(e) => log::error!("failed to finish the recording: {}", e),
+                        }
+                    } else {
+                        let path = timestamped_path("recording", "gif");
+                        match self.start_recording(&path) {
+                            Ok(()) => log::info!("recording to {}", path),
+                            Err(e) => log::error!("failed to start recording {}: {}", path, e),
+                        }
+                    }
+                }
+
+                // Shift saves the screen as shown in the window instead
+                if input.key_pressed(VirtualKeyCode::F12) {
+                    let path = timestamped_path("screenshot", "png");
+                    let result = if input.held_shift() {
+                        self.scaled_screenshot(&path)
+                    } else {
+                        self.screenshot(&path)
+                    };
+                    match result {
+                        Ok(()) => log::info!("saved screenshot {}", path),
+                        Err(e) => log::error!("failed to save screenshot {}: {}", path, e),
+                    }
+                }
+
+                if input.key_pressed(VirtualKeyCode::F9) {
+                    show_speed = !show_speed;
+                }
+
+                if input.key_pressed(VirtualKeyCode::F11) {
+                    window.set_fullscreen(match window.fullscreen() {
+                        Some(_) => None,
+                        None => Some(Fullscreen::Borderless(None)),
+                    });
+                }
+
+                // pixels scales the screen by the largest integer factor that
+                // fits and letterboxes the rest
+                if let Some(size) = input.window_resized() {
+                    pixels.resize_surface(size.width, size.height);
+                }
+
+                window.request_redraw();
+            }
+
+            let title = window_title(
+                &self.cartridge_header().title,
+                self.paused(),
+                crashed,
+                show_speed.then_some(&meter),
+            );
+            if title != shown_title {
+                window.set_title(&title);
+                shown_title = title;
+            }
+        });
+    }
+}
+
+/// "gbemu – <GAME TITLE>", then the state of the emulator, or the speed with
+/// `speed` shown (F9).
+fn window_title(game: &str, paused: bool, crashed: bool, speed: Option<&SpeedMeter>) -> String {
+    let mut title = String::from("gbemu");
+    if !game.is_empty() {
+        title.push_str(" – ");
+        title.push_str(game);
+    }
+
+    if crashed {
+        title.push_str(" - crashed (R: reset, L: load autosave, Esc: quit)");
+    } else if paused {
+        title.push_str(" - paused (P: resume, N: next frame)");
+    } else if let Some(meter) = speed {
+        title.push_str(&format!(" - {}", meter));
+    }
+
+    title
+}
+
+/// A file name in the working directory, unique by the time in
+/// milliseconds, e.g. `screenshot-1700000000000.png`.
+fn timestamped_path(prefix: &str, extension: &str) -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis());
+    format!("{}-{}.{}", prefix, millis, extension)
+}
@@ -0,0 +1,237 @@
+//! Event viewer for debugging, a map of one frame by dot: every line of the
+//! frame is a row of 456 dots colored by PPU mode. The dots during which
+//! OAM DMA or VRAM DMA ran are shaded, and each transfer is listed with
+//! its source and destination, e.g. to see when a tile-streaming engine
+//! copies.
+//!
+//! Enable it with [`Emulator::set_event_viewer`](crate::emulator::Emulator::set_event_viewer),
+//! it records every frame from then on and shows the last completed one.
+
+use crate::gpu::DebugImage;
+use crate::{HalfWord, Word};
+use std::fmt;
+
+pub const DOTS_PER_LINE: usize = 456;
+pub const LINES_PER_FRAME: usize = 154;
+const DOTS_PER_FRAME: usize = DOTS_PER_LINE * LINES_PER_FRAME;
+
+// Flags of a dot, the PPU mode is in the lower 2 bits
+const LCD_ON: u8 = 0x04;
+const OAM_DMA: u8 = 0x08;
+const HDMA: u8 = 0x10;
+
+const LCD_OFF_COLOR: [u8; 3] = [0xFF, 0xFF, 0xFF];
+// H-blank, V-blank, OAM search, pixel transfer
+const MODE_COLORS: [[u8; 3]; 4] = [
+    [0xA8, 0xC8, 0xE8],
+    [0xC0, 0xC0, 0xC0],
+    [0xE8, 0xD8, 0xA0],
+    [0xA8, 0xD8, 0xA8],
+];
+const OAM_DMA_COLOR: [u8; 3] = [0xE0, 0x30, 0x30];
+const HDMA_COLOR: [u8; 3] = [0x80, 0x30, 0xE0];
+
+/// What the event viewer shows, everything by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventViewerOptions {
+    /// Shade and list OAM DMA transfers
+    pub oam_dma: bool,
+    /// Shade and list VRAM DMA transfers, general purpose and H-blank
+    pub hdma: bool,
+}
+
+impl Default for EventViewerOptions {
+    fn default() -> EventViewerOptions {
+        EventViewerOptions {
+            oam_dma: true,
+            hdma: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaKind {
+    /// 0xFF46, 160 bytes into OAM
+    Oam,
+    /// HDMA5 bit 7 clear, all blocks at once
+    GeneralPurpose,
+    /// HDMA5 bit 7 set, one block per H-blank
+    HBlank,
+}
+
+/// A DMA transfer that started during the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaEvent {
+    pub kind: DmaKind,
+    /// LY and dot of the line when it started
+    pub line: usize,
+    pub dot: usize,
+    pub source: Word,
+    pub destination: Word,
+    /// Bytes copied
+    pub length: Word,
+}
+
+impl fmt::Display for DmaEvent {
+    /// `LY  12 dot 260  HDMA $C000 -> $8800, 16 bytes`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            DmaKind::Oam => "OAM DMA",
+            DmaKind::GeneralPurpose => "GDMA",
+            DmaKind::HBlank => "HDMA",
+        };
+        write!(
+            f,
+            "LY {:3} dot {:3}  {} ${:04X} -> ${:04X}, {} bytes",
+            self.line, self.dot, kind, self.source, self.destination, self.length
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FrameEvents {
+    dots: Vec<u8>,
+    transfers: Vec<DmaEvent>,
+}
+
+impl FrameEvents {
+    fn new() -> FrameEvents {
+        FrameEvents {
+            dots: vec![0; DOTS_PER_FRAME],
+            transfers: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.dots.fill(0);
+        self.transfers.clear();
+    }
+}
+
+/// Records the bus activity of the frame being drawn, see the module
+/// documentation.
+#[derive(Debug, Clone)]
+pub struct EventViewer {
+    options: EventViewerOptions,
+    current: FrameEvents,
+    last: FrameEvents,
+    // Machine cycles VRAM DMA keeps the CPU stopped for
+    hdma_cycles: usize,
+}
+
+impl EventViewer {
+    pub fn new(options: EventViewerOptions) -> EventViewer {
+        EventViewer {
+            options,
+            current: FrameEvents::new(),
+            last: FrameEvents::new(),
+            hdma_cycles: 0,
+        }
+    }
+
+    pub fn options(&self) -> EventViewerOptions {
+        self.options
+    }
+
+    /// Record a machine cycle of `dots` dots from `position` on, the LY,
+    /// dot and mode of [`Gpu::position`](crate::gpu::Gpu::position).
+    /// Without a position the LCD is off and nothing is recorded.
+    pub(crate) fn record(
+        &mut self,
+        position: Option<(usize, usize, HalfWord)>,
+        dots: usize,
+        oam_dma: bool,
+    ) {
+        let hdma = self.hdma_cycles > 0;
+        self.hdma_cycles = self.hdma_cycles.saturating_sub(1);
+
+        let (line, dot, mode) = match position {
+            Some(position) => position,
+            None => return,
+        };
+        let mut flags = LCD_ON | mode & 0x03;
+        if oam_dma && self.options.oam_dma {
+            flags |= OAM_DMA;
+        }
+        if hdma && self.options.hdma {
+            flags |= HDMA;
+        }
+
+        let start = line * DOTS_PER_LINE + dot;
+        for offset in 0..dots {
+            self.current.dots[(start + offset) % DOTS_PER_FRAME] = flags;
+        }
+    }
+
+    /// A transfer started. VRAM DMA stops the CPU for `cycles` machine
+    /// cycles, shaded from the next one on.
+    pub(crate) fn transfer(&mut self, event: DmaEvent, cycles: usize) {
+        let shown = match event.kind {
+            DmaKind::Oam => self.options.oam_dma,
+            DmaKind::GeneralPurpose | DmaKind::HBlank => {
+                self.hdma_cycles += cycles;
+                self.options.hdma
+            }
+        };
+        if shown {
+            self.current.transfers.push(event);
+        }
+    }
+
+    /// The PPU completed a frame, it becomes the one shown.
+    pub(crate) fn end_frame(&mut self) {
+        std::mem::swap(&mut self.current, &mut self.last);
+        self.current.clear();
+    }
+
+    /// The last completed frame, 456x154 pixels with a pixel per dot. Dots
+    /// are colored by PPU mode (H-blank blue, V-blank gray, OAM search
+    /// yellow, pixel transfer green, white with the LCD off), OAM DMA shades
+    /// them red and VRAM DMA purple. The dot each transfer started at is
+    /// drawn in the full shading color.
+    pub fn render(&self) -> DebugImage {
+        let mut rgba = Vec::with_capacity(DOTS_PER_FRAME * 4);
+        for &flags in &self.last.dots {
+            let mut color = if flags & LCD_ON == 0 {
+                LCD_OFF_COLOR
+            } else {
+                MODE_COLORS[(flags & 0x03) as usize]
+            };
+            if flags & OAM_DMA != 0 {
+                color = blend(color, OAM_DMA_COLOR);
+            }
+            if flags & HDMA != 0 {
+                color = blend(color, HDMA_COLOR);
+            }
+            rgba.extend_from_slice(&[color[0], color[1], color[2], 0xFF]);
+        }
+
+        for event in &self.last.transfers {
+            let color = match event.kind {
+                DmaKind::Oam => OAM_DMA_COLOR,
+                DmaKind::GeneralPurpose | DmaKind::HBlank => HDMA_COLOR,
+            };
+            let offset = (event.line * DOTS_PER_LINE + event.dot) % DOTS_PER_FRAME * 4;
+            rgba[offset..offset + 3].copy_from_slice(&color);
+        }
+
+        DebugImage {
+            width: DOTS_PER_LINE,
+            height: LINES_PER_FRAME,
+            rgba,
+        }
+    }
+
+    /// The transfers of the last completed frame, in the order they started.
+    pub fn transfers(&self) -> &[DmaEvent] {
+        &self.last.transfers
+    }
+}
+
+fn blend(a: [u8; 3], b: [u8; 3]) -> [u8; 3] {
+    [
+        ((a[0] as u16 + b[0] as u16) / 2) as u8,
+        ((a[1] as u16 + b[1] as u16) / 2) as u8,
+        ((a[2] as u16 + b[2] as u16) / 2) as u8,
+    ]
+}
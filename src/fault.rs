@@ -0,0 +1,114 @@
+//! Bus-level fault injection for robustness testing.
+//!
+//! Flips bits in bytes passing over the bus, or holds writes back for a
+//! while, at configurable addresses and frequencies. Runs are reproducible for
+//! a given seed.
+
+use crate::{HalfWord, Word};
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// Flip one random bit of the byte being read or written
+    BitFlip,
+    /// Hold a write back until this many further writes went over the bus
+    DelayedWrite(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct FaultRule {
+    pub addresses: RangeInclusive<Word>,
+    pub fault: Fault,
+    /// Inject on every n-th matching access
+    pub every: usize,
+}
+
+pub struct FaultInjector {
+    rules: Vec<FaultRule>,
+    // Matching accesses seen per rule
+    counts: Vec<usize>,
+    // Writes held back: (remaining writes, address, byte)
+    delayed: Vec<(usize, Word, HalfWord)>,
+    rng: u32,
+}
+
+impl FaultInjector {
+    pub fn new(seed: u32) -> FaultInjector {
+        FaultInjector {
+            rules: Vec::new(),
+            counts: Vec::new(),
+            delayed: Vec::new(),
+            // xorshift gets stuck at zero
+            rng: seed.max(1),
+        }
+    }
+
+    pub fn add_rule(&mut self, rule: FaultRule) {
+        self.rules.push(rule);
+        self.counts.push(0);
+    }
+
+    /// Possibly corrupt a byte read from the bus.
+    pub fn on_read(&mut self, address: Word, byte: HalfWord) -> HalfWord {
+        match self.triggered(address, false) {
+            Some(Fault::BitFlip) => self.flip_bit(byte),
+            _ => byte,
+        }
+    }
+
+    /// Possibly corrupt or hold back a write. Returns the byte to write now,
+    /// `None` if the write is delayed.
+    pub fn on_write(&mut self, address: Word, byte: HalfWord) -> Option<HalfWord> {
+        match self.triggered(address, true) {
+            Some(Fault::BitFlip) => Some(self.flip_bit(byte)),
+            Some(Fault::DelayedWrite(writes)) => {
+                self.delayed.push((writes, address, byte));
+                None
+            }
+            None => Some(byte),
+        }
+    }
+
+    /// Count one write towards the delayed writes and take the ones now due.
+    /// Call before `on_write` for the same access.
+    pub fn due_writes(&mut self) -> Vec<(Word, HalfWord)> {
+        let mut due = Vec::new();
+
+        self.delayed.retain_mut(|(remaining, address, byte)| {
+            *remaining = remaining.saturating_sub(1);
+            if *remaining == 0 {
+                due.push((*address, *byte));
+            }
+            *remaining > 0
+        });
+
+        due
+    }
+
+    fn triggered(&mut self, address: Word, write: bool) -> Option<Fault> {
+        for (rule, count) in self.rules.iter().zip(self.counts.iter_mut()) {
+            let applies = match rule.fault {
+                Fault::BitFlip => true,
+                Fault::DelayedWrite(_) => write,
+            };
+            if !applies || !rule.addresses.contains(&address) {
+                continue;
+            }
+
+            *count += 1;
+            if *count % rule.every.max(1) == 0 {
+                return Some(rule.fault.clone());
+            }
+        }
+
+        None
+    }
+
+    fn flip_bit(&mut self, byte: HalfWord) -> HalfWord {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+
+        byte ^ (1 << (self.rng % 8))
+    }
+}
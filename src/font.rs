@@ -0,0 +1,69 @@
+//! A 3x5 pixel font to draw text over the screen, e.g. from scripts.
+//!
+//! Only ASCII 0x20-0x5F has glyphs. Lower case letters are drawn as capitals
+//! and other characters as `?`.
+
+/// Width of a character including the space after it.
+pub const CHAR_WIDTH: usize = 4;
+/// Height of a line including the space below it.
+pub const LINE_HEIGHT: usize = 6;
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+/// Glyphs of 0x20-0x5F, a bit per pixel, rows from the top in the high bits
+/// and the leftmost pixel in the highest bit of a row.
+const GLYPHS: [u16; 64] = [
+    0x0000, 0x2482, 0x5A00, 0x5F7D, 0x3C9E, 0x52A5, 0x2AAB, 0x2400, //
+    0x1491, 0x4494, 0x0AA8, 0x05D0, 0x0014, 0x01C0, 0x0002, 0x12A4, //
+    0x7B6F, 0x2C97, 0x62A7, 0x628E, 0x5BC9, 0x798E, 0x39EF, 0x7292, //
+    0x7BEF, 0x7BCE, 0x0410, 0x0414, 0x1511, 0x0E38, 0x4454, 0x6282, //
+    0x2BE3, 0x2BED, 0x6BAE, 0x3923, 0x6B6E, 0x79A7, 0x79A4, 0x396B, //
+    0x5BED, 0x7497, 0x126A, 0x5BAD, 0x4927, 0x5FED, 0x5FFD, 0x2B6A, //
+    0x6BA4, 0x2B7B, 0x6BAD, 0x388E, 0x7492, 0x5B6B, 0x5B52, 0x5BFD, //
+    0x5AAD, 0x5A92, 0x72A7, 0x6926, 0x4889, 0x324B, 0x2A00, 0x0007, //
+];
+
+fn glyph(c: char) -> u16 {
+    match c.to_ascii_uppercase() {
+        c @ ' '..='_' => GLYPHS[c as usize - 0x20],
+        _ => GLYPHS['?' as usize - 0x20],
+    }
+}
+
+/// Draw `text` with its top left corner at `x`, `y` into the RGBA8888 image
+/// `rgba`, `width` pixels wide. Every pixel gets a black shadow below and to
+/// the right so it reads on any background. `\n` starts a new line, pixels
+/// outside the image are clipped.
+pub fn draw_text(rgba: &mut [u8], width: usize, x: i32, y: i32, text: &str, color: [u8; 4]) {
+    let height = rgba.len() / 4 / width;
+    let mut put = |x: i32, y: i32, color: [u8; 4]| {
+        if (0..width as i32).contains(&x) && (0..height as i32).contains(&y) {
+            let offset = (y as usize * width + x as usize) * 4;
+            rgba[offset..offset + 4].copy_from_slice(&color);
+        }
+    };
+
+    for (line, text) in text.split('\n').enumerate() {
+        let top = y + (line * LINE_HEIGHT) as i32;
+        for (column, c) in text.chars().enumerate() {
+            let left = x + (column * CHAR_WIDTH) as i32;
+            let glyph = glyph(c);
+
+            for (shadow, color) in [(1, [0, 0, 0, 0xFF]), (0, color)].iter() {
+                for row in 0..GLYPH_HEIGHT {
+                    for col in 0..GLYPH_WIDTH {
+                        let bit = (GLYPH_HEIGHT - row) * GLYPH_WIDTH - col - 1;
+                        if glyph >> bit & 1 != 0 {
+                            put(
+                                left + col as i32 + shadow,
+                                top + row as i32 + shadow,
+                                *color,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Per-frame framebuffer hashes.
+//!
+//! Emits one `<frame number> <crc32>` line per completed frame, so external
+//! tools can check that two instances (netplay, movie playback) stay in sync
+//! without transferring whole frames.
+
+use std::io::Write;
+
+pub struct FrameHasher {
+    output: Box<dyn Write + Send>,
+}
+
+impl FrameHasher {
+    pub fn new(output: Box<dyn Write + Send>) -> FrameHasher {
+        FrameHasher { output }
+    }
+
+    pub fn emit(&mut self, frame_count: usize, frame: &[u8]) -> std::io::Result<()> {
+        writeln!(self.output, "{} {:08x}", frame_count, crc32(frame))?;
+        self.output.flush()
+    }
+}
+
+/// CRC-32 (IEEE 802.3), the same checksum as zlib and PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
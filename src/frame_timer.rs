@@ -0,0 +1,77 @@
+//! Real-time pacing at the frame rate of the Game Boy, independent of the
+//! refresh rate of the display.
+//!
+//! One frame is 70224 clock cycles at 4.194304 MHz, so the LCD refreshes at
+//! about 59.7275 Hz. Deadlines are kept on an absolute schedule from the
+//! start, so the rounding of each wait doesn't add up to drift.
+
+use crate::apu::CLOCK_RATE;
+use crate::emulator::CYCLES_PER_FRAME;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Frames per second of the LCD.
+pub const FRAME_RATE: f64 = CLOCK_RATE as f64 / CYCLES_PER_FRAME as f64;
+
+/// Time before a deadline spent spinning instead of sleeping, as the OS
+/// scheduler easily oversleeps by a millisecond.
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+/// How far the emulation may fall behind before the schedule is restarted,
+/// instead of running flat out to catch up after a stall.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub struct FrameTimer {
+    frame_duration: Duration,
+    // When the next frame is due
+    deadline: Option<Instant>,
+}
+
+impl Default for FrameTimer {
+    fn default() -> FrameTimer {
+        FrameTimer::new()
+    }
+}
+
+impl FrameTimer {
+    /// A timer at [`FRAME_RATE`].
+    pub fn new() -> FrameTimer {
+        FrameTimer {
+            frame_duration: Duration::from_secs_f64(1.0 / FRAME_RATE),
+            deadline: None,
+        }
+    }
+
+    /// Block until the current frame is due. The first call starts the
+    /// schedule and returns immediately.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        let deadline = match self.deadline {
+            Some(deadline) if now < deadline + MAX_LAG => deadline,
+            _ => now,
+        };
+
+        if let Some(sleep) = deadline.checked_duration_since(now + SPIN_MARGIN) {
+            thread::sleep(sleep);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+
+        self.deadline = Some(deadline + self.frame_duration);
+    }
+
+    /// Run `speed` times as fast as the LCD, an infinite speed doesn't wait
+    /// at all, neither does one too slow for a [`Duration`].
+    pub fn set_speed(&mut self, speed: f64) {
+        self.frame_duration =
+            Duration::try_from_secs_f64(1.0 / (FRAME_RATE * speed)).unwrap_or(Duration::ZERO);
+        self.restart();
+    }
+
+    /// Restart the schedule on the next [`FrameTimer::wait`], e.g. after a
+    /// pause.
+    pub fn restart(&mut self) {
+        self.deadline = None;
+    }
+}
@@ -0,0 +1,260 @@
+//! Animated GIF files, for recordings of the screen.
+//!
+//! Every frame gets its own color table of the colors it uses. DMG frames
+//! have at most 4, a CGB frame changing palettes mid-frame can have more
+//! than 256, and is then reduced to 3-3-2 bit RGB.
+//! Ref https://www.w3.org/Graphics/GIF/spec-gif89a.txt
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Largest LZW code, codes are at most 12 bits
+const MAX_CODE: u16 = 4095;
+// Largest data sub-block
+const MAX_SUB_BLOCK: usize = 255;
+
+pub struct GifWriter {
+    output: BufWriter<File>,
+    width: usize,
+    height: usize,
+    // First write error, reported by `finish`
+    error: Option<io::Error>,
+}
+
+impl GifWriter {
+    /// Create `path` and write the header of a looping animation of
+    /// `width` x `height` frames.
+    pub fn create(path: impl AsRef<Path>, width: usize, height: usize) -> Result<GifWriter> {
+        let mut output = BufWriter::new(File::create(path)?);
+
+        output.write_all(b"GIF89a")?;
+        output.write_all(&(width as u16).to_le_bytes())?;
+        output.write_all(&(height as u16).to_le_bytes())?;
+        // No global color table, background color 0, square pixels
+        output.write_all(&[0, 0, 0])?;
+        // Loop forever
+        output.write_all(&[0x21, 0xFF, 0x0B])?;
+        output.write_all(b"NETSCAPE2.0")?;
+        output.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])?;
+
+        Ok(GifWriter {
+            output,
+            width,
+            height,
+            error: None,
+        })
+    }
+
+    /// Append an RGBA frame shown for `delay` hundredths of a second. Errors
+    /// are kept for [`GifWriter::finish`], the frames after one are dropped.
+    pub fn write_frame(&mut self, rgba: &[u8], delay: u16) {
+        assert_eq!(rgba.len(), self.width * self.height * 4);
+        if self.error.is_some() {
+            return;
+        }
+
+        if let Err(e) = self.encode_frame(rgba, delay) {
+            self.error = Some(e);
+        }
+    }
+
+    fn encode_frame(&mut self, rgba: &[u8], delay: u16) -> io::Result<()> {
+        let (palette, indices) = index_colors(rgba);
+        // Color tables have 2^(n + 1) entries
+        let table_bits = usize::BITS - (palette.len().max(2) - 1).leading_zeros();
+
+        let output = &mut self.output;
+        // Graphic control extension, draw over the previous frame
+        output.write_all(&[0x21, 0xF9, 0x04, 0x04])?;
+        output.write_all(&delay.to_le_bytes())?;
+        output.write_all(&[0x00, 0x00])?;
+
+        // Image descriptor with a local color table
+        output.write_all(&[0x2C, 0, 0, 0, 0])?;
+        output.write_all(&(self.width as u16).to_le_bytes())?;
+        output.write_all(&(self.height as u16).to_le_bytes())?;
+        output.write_all(&[0x80 | (table_bits - 1) as u8])?;
+        for index in 0..1 << table_bits {
+            let color = palette.get(index).copied().unwrap_or_default();
+            output.write_all(&color)?;
+        }
+
+        // LZW codes start at 3 bits even for 2 colors
+        let min_code_size = table_bits.max(2) as u8;
+        output.write_all(&[min_code_size])?;
+        for block in lzw_encode(&indices, min_code_size).chunks(MAX_SUB_BLOCK) {
+            output.write_all(&[block.len() as u8])?;
+            output.write_all(block)?;
+        }
+        output.write_all(&[0])?;
+
+        Ok(())
+    }
+
+    /// Write the trailer and flush the file.
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e.into());
+        }
+
+        self.output.write_all(&[0x3B])?;
+        self.output.flush()?;
+
+        Ok(())
+    }
+}
+
+/// The colors of an RGBA image, and the index of every pixel in them.
+fn index_colors(rgba: &[u8]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let mut palette = Vec::new();
+    let mut lookup = HashMap::new();
+    let mut indices = Vec::with_capacity(rgba.len() / 4);
+
+    for pixel in rgba.chunks_exact(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let index = *lookup.entry(color).or_insert_with(|| {
+            palette.push(color);
+            palette.len() - 1
+        });
+        if index > u8::MAX as usize {
+            return reduce_colors(rgba);
+        }
+        indices.push(index as u8);
+    }
+
+    (palette, indices)
+}
+
+/// Like [`index_colors`], for images of more than 256 colors.
+fn reduce_colors(rgba: &[u8]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let palette = (0..=u8::MAX)
+        .map(|index| {
+            let scale = |value: u8, max: u8| (value as u16 * 255 / max as u16) as u8;
+            [
+                scale(index >> 5, 7),
+                scale(index >> 2 & 7, 7),
+                scale(index & 3, 3),
+            ]
+        })
+        .collect();
+    let indices = rgba
+        .chunks_exact(4)
+        .map(|pixel| pixel[0] & 0xE0 | pixel[1] >> 5 << 2 | pixel[2] >> 6)
+        .collect();
+
+    (palette, indices)
+}
+
+/// Variable length LZW codes of `indices`, packed from the least
+/// significant bit.
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let mut encoder = LzwEncoder::new(min_code_size);
+
+    let mut pixels = indices.iter();
+    let mut prefix = match pixels.next() {
+        Some(&index) => index as u16,
+        None => return encoder.finish(None),
+    };
+    for &index in pixels {
+        match encoder.table.get(&(prefix, index)) {
+            Some(&code) => prefix = code,
+            None => {
+                encoder.emit(prefix);
+                encoder.add(prefix, index);
+                prefix = index as u16;
+            }
+        }
+    }
+
+    encoder.finish(Some(prefix))
+}
+
+struct LzwEncoder {
+    min_code_size: u8,
+    // Codes of the strings after the built-in ones, by prefix code and index
+    table: HashMap<(u16, u8), u16>,
+    // Last code assigned
+    last_code: u16,
+    code_size: u8,
+    bits: u32,
+    bit_count: u8,
+    output: Vec<u8>,
+}
+
+impl LzwEncoder {
+    fn new(min_code_size: u8) -> LzwEncoder {
+        let mut encoder = LzwEncoder {
+            min_code_size,
+            table: HashMap::new(),
+            last_code: 0,
+            code_size: min_code_size + 1,
+            bits: 0,
+            bit_count: 0,
+            output: Vec::new(),
+        };
+        encoder.clear();
+
+        encoder
+    }
+
+    fn clear_code(&self) -> u16 {
+        1 << self.min_code_size
+    }
+
+    /// Emit a clear code and start over with the built-in codes.
+    fn clear(&mut self) {
+        self.emit(self.clear_code());
+        self.code_size = self.min_code_size + 1;
+        self.table.clear();
+        // The end of information code
+        self.last_code = self.clear_code() + 1;
+    }
+
+    fn emit(&mut self, code: u16) {
+        self.bits |= (code as u32) << self.bit_count;
+        self.bit_count += self.code_size;
+        while self.bit_count >= 8 {
+            self.output.push(self.bits as u8);
+            self.bits >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    /// Assign the next code, growing the codes along with the decoder, which
+    /// assigns it one code later.
+    fn next_code(&mut self) -> Option<u16> {
+        self.last_code += 1;
+        if self.last_code == 1 << self.code_size {
+            self.code_size += 1;
+        }
+
+        if self.last_code == MAX_CODE {
+            self.clear();
+            None
+        } else {
+            Some(self.last_code)
+        }
+    }
+
+    fn add(&mut self, prefix: u16, index: u8) {
+        if let Some(code) = self.next_code() {
+            self.table.insert((prefix, index), code);
+        }
+    }
+
+    fn finish(mut self, prefix: Option<u16>) -> Vec<u8> {
+        if let Some(prefix) = prefix {
+            self.emit(prefix);
+            self.next_code();
+        }
+        self.emit(self.clear_code() + 1);
+        if self.bit_count > 0 {
+            self.output.push(self.bits as u8);
+        }
+
+        self.output
+    }
+}
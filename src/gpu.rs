@@ -1,99 +1,776 @@
-use crate::SharedBus;
+mod debug;
+mod fifo;
+mod palette;
+
+use crate::interrupt::Interrupt;
+use crate::ram::{BankedRam, Ram};
+use crate::state::{StateReader, StateWriter};
 use crate::{HalfWord, Word};
+use anyhow::Result;
+pub use debug::{DebugImage, TileMap};
+use fifo::{PixelFifo, Registers};
+use palette::PaletteRam;
+use std::str::FromStr;
 
 const CYCLE_PER_LINE: usize = 456;
+const OAM_SEARCH_CYCLES: usize = 80;
+const PIXEL_TRANSFER_CYCLES: usize = 172;
 const SCREEN_WIDTH: usize = 160;
 const SCREEN_HEIGHT: usize = 144;
+// 144 visible lines and 10 lines of VBlank
+const LINES_PER_FRAME: usize = 154;
 const TILEMAP0: Word = 0x9800;
 const TILEMAP1: Word = 0x9C00;
 
+/// How the PPU draws a line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Accuracy {
+    /// Whole lines at the end of each line, mode 3 always lasts 172 dots
+    #[default]
+    Scanline,
+    /// Pixel by pixel through the pixel FIFO, mid-scanline register writes
+    /// take effect and mode 3 has its real length. Slower.
+    PixelFifo,
+}
+
+impl FromStr for Accuracy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Accuracy> {
+        match s {
+            "scanline" => Ok(Accuracy::Scanline),
+            "fifo" => Ok(Accuracy::PixelFifo),
+            _ => anyhow::bail!("unknown PPU accuracy {} (scanline, fifo)", s),
+        }
+    }
+}
+
+/// A completed frame, passed to the [`Gpu::on_frame`] callback.
+pub struct FrameEvent<'a> {
+    /// Number of frames completed since power on, this one included
+    pub number: usize,
+    /// Shade id (0-3) of every pixel, 160x144 row by row
+    pub shades: &'a [u8],
+    /// RGB555 color of every pixel, in CGB mode only
+    pub colors: Option<&'a [u16]>,
+}
+
+pub type FrameCallback = Box<dyn FnMut(FrameEvent) + Send>;
+
 pub struct Gpu {
-    data: Vec<u8>,
-    bus: Option<SharedBus>,
+    // Shade id (0-3) of every pixel on the screen
+    frame: Vec<u8>,
     cycles: usize,
     ly: usize,
+    // Number of frames completed since power on
+    frames: usize,
     scroll_x: usize,
     scroll_y: usize,
     lcdc: u8,
+    // STAT interrupt sources (bits 6-3)
+    stat: u8,
+    lyc: usize,
+    // Any enabled STAT condition holds, the interrupt fires on its rising edge
+    stat_line: bool,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    // Line of the window drawn next. Only advances on lines the window was
+    // drawn on, so hiding it mid-frame resumes where it left off.
+    window_counter: usize,
+    // The window was drawn on line LY
+    window_drawn: bool,
+    // LY matched WY at the start of a line of this frame, which the window
+    // needs to be drawn at all
+    wy_triggered: bool,
+    accuracy: Accuracy,
+    fifo: PixelFifo,
+    // Dot of the line at which mode 3 ends
+    transfer_end: usize,
+    cgb: bool,
+    bg_palettes: PaletteRam,
+    obj_palettes: PaletteRam,
+    // Palette of each pixel of the line being drawn, see OBJ_PALETTE
+    line_palettes: [HalfWord; SCREEN_WIDTH],
+    // CGB background pixels of the line drawn over sprites
+    line_priority: [bool; SCREEN_WIDTH],
+    // RGB555 color of every pixel in CGB mode
+    colors: Vec<Word>,
+    shades: Shades,
+    pixel_format: PixelFormat,
+    frame_callback: Option<FrameCallback>,
+}
+
+// Set in `line_palettes` for sprite pixels, the lower 3 bits are the palette
+const OBJ_PALETTE: HalfWord = 0x08;
+
+impl Default for Gpu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Gpu {
-    pub fn new(size: usize, bus: Option<SharedBus>) -> Gpu {
+    /// In the state the DMG boot ROM leaves it.
+    pub fn new() -> Gpu {
         Gpu {
-            data: vec![0; size],
-            bus,
+            frame: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
             cycles: 0,
             ly: 0,
+            frames: 0,
             scroll_x: 0,
             scroll_y: 0,
             lcdc: 0x91,
+            stat: 0,
+            lyc: 0,
+            stat_line: false,
+            bgp: 0xFC,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            window_counter: 0,
+            window_drawn: false,
+            wy_triggered: false,
+            accuracy: Accuracy::default(),
+            fifo: PixelFifo::default(),
+            transfer_end: OAM_SEARCH_CYCLES + PIXEL_TRANSFER_CYCLES,
+            cgb: false,
+            bg_palettes: PaletteRam::default(),
+            obj_palettes: PaletteRam::default(),
+            line_palettes: [0; SCREEN_WIDTH],
+            line_priority: [false; SCREEN_WIDTH],
+            colors: vec![0x7FFF; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame_callback: None,
+            shades: Shades::default(),
+            pixel_format: PixelFormat::default(),
+        }
+    }
+
+    /// Render with the CGB color palettes.
+    pub fn set_cgb(&mut self, cgb: bool) {
+        self.cgb = cgb;
+    }
+
+    pub fn cgb(&self) -> bool {
+        self.cgb
+    }
+
+    /// Call `callback` once per frame, at VBlank (or when the LCD is turned
+    /// off), with the completed frame. Replaces the previous callback.
+    pub fn on_frame(&mut self, callback: Option<FrameCallback>) {
+        self.frame_callback = callback;
+    }
+
+    /// Colors of the DMG shades, for frontends and the VRAM viewers.
+    pub fn set_shades(&mut self, shades: Shades) {
+        self.shades = shades;
+    }
+
+    pub fn shades(&self) -> Shades {
+        self.shades
+    }
+
+    /// Format of [`Gpu::framebuffer`], RGBA8888 by default.
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.pixel_format = format;
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    /// The screen in the configured pixel format, 160x144 row by row, with
+    /// the DMG shades or the CGB colors applied.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        let mut buffer =
+            vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * self.pixel_format.bytes_per_pixel()];
+        self.encode_frame(self.pixel_format, &mut buffer);
+        buffer
+    }
+
+    /// Write the screen in `format` to `buffer`, which holds 160x144 pixels.
+    pub fn encode_frame(&self, format: PixelFormat, buffer: &mut [u8]) {
+        let pixels = buffer.chunks_exact_mut(format.bytes_per_pixel());
+
+        if self.cgb {
+            for (pixel, &color) in pixels.zip(&self.colors) {
+                format.encode(rgb555_to_rgba(color), pixel);
+            }
+        } else {
+            for (pixel, &shade) in pixels.zip(&self.frame) {
+                format.encode(self.shades.rgba(shade), pixel);
+            }
+        }
+    }
+
+    pub fn take_frame_callback(&mut self) -> Option<FrameCallback> {
+        self.frame_callback.take()
+    }
+
+    fn complete_frame(&mut self) {
+        self.frames += 1;
+
+        if let Some(callback) = self.frame_callback.as_mut() {
+            callback(FrameEvent {
+                number: self.frames,
+                shades: &self.frame,
+                colors: if self.cgb { Some(&self.colors) } else { None },
+            });
+        }
+    }
+
+    pub fn set_accuracy(&mut self, accuracy: Accuracy) {
+        self.accuracy = accuracy;
+        self.transfer_end = self.line_transfer_end();
+    }
+
+    /// The registers, the line being drawn and the last frame. The model,
+    /// accuracy and output settings are not part of it.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.frame);
+        state.usize(self.cycles);
+        state.usize(self.ly);
+        state.usize(self.frames);
+        state.usize(self.scroll_x);
+        state.usize(self.scroll_y);
+        state.u8(self.lcdc);
+        state.u8(self.stat);
+        state.usize(self.lyc);
+        state.bool(self.stat_line);
+        state.u8(self.bgp);
+        state.u8(self.obp0);
+        state.u8(self.obp1);
+        state.u8(self.wy);
+        state.u8(self.wx);
+        state.usize(self.window_counter);
+        state.bool(self.window_drawn);
+        state.bool(self.wy_triggered);
+        self.fifo.save_state(state);
+        state.usize(self.transfer_end);
+        self.bg_palettes.save_state(state);
+        self.obj_palettes.save_state(state);
+        state.bytes(&self.line_palettes);
+        for &priority in self.line_priority.iter() {
+            state.bool(priority);
+        }
+        for &color in self.colors.iter() {
+            state.u16(color);
+        }
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        state.bytes_into(&mut self.frame)?;
+        self.cycles = state.usize()? % CYCLE_PER_LINE;
+        self.ly = state.usize()? % LINES_PER_FRAME;
+        self.frames = state.usize()?;
+        self.scroll_x = state.usize()?;
+        self.scroll_y = state.usize()?;
+        self.lcdc = state.u8()?;
+        self.stat = state.u8()?;
+        self.lyc = state.usize()?;
+        self.stat_line = state.bool()?;
+        self.bgp = state.u8()?;
+        self.obp0 = state.u8()?;
+        self.obp1 = state.u8()?;
+        self.wy = state.u8()?;
+        self.wx = state.u8()?;
+        self.window_counter = state.usize()?;
+        self.window_drawn = state.bool()?;
+        self.wy_triggered = state.bool()?;
+        self.fifo.load_state(state)?;
+        self.transfer_end = state.usize()?;
+        self.bg_palettes.load_state(state)?;
+        self.obj_palettes.load_state(state)?;
+        state.bytes_into(&mut self.line_palettes)?;
+        for priority in self.line_priority.iter_mut() {
+            *priority = state.bool()?;
+        }
+        for color in self.colors.iter_mut() {
+            *color = state.u16()?;
+        }
+
+        Ok(())
+    }
+
+    /// Advance by `dots` dot clocks (4.19 MHz, also in CGB double speed mode).
+    /// Returns the interrupts to request, as IF bits.
+    pub fn step(&mut self, video_ram: &BankedRam, oam: &Ram, dots: usize) -> HalfWord {
+        let mut interrupts = 0;
+
+        // The PPU is stopped while the LCD is off
+        if !self.lcd_enabled() {
+            return interrupts;
+        }
+
+        for _ in 0..dots {
+            interrupts |= self.dot(video_ram, oam);
+        }
+
+        interrupts
+    }
+
+    fn dot(&mut self, video_ram: &BankedRam, oam: &Ram) -> HalfWord {
+        let mut interrupts = 0;
+
+        self.cycles += 1;
+        if self.accuracy == Accuracy::PixelFifo && self.ly < SCREEN_HEIGHT {
+            self.fifo_dot(video_ram, oam);
+        }
+
+        if self.cycles == CYCLE_PER_LINE {
+            self.cycles = 0;
+
+            if self.accuracy == Accuracy::Scanline && self.ly < SCREEN_HEIGHT {
+                self.build_gb_tile(video_ram);
+                self.build_sprites(video_ram, oam);
+                self.finish_line();
+            }
+
+            if self.window_drawn {
+                self.window_counter += 1;
+                self.window_drawn = false;
+            }
+
+            self.ly += 1;
+            self.transfer_end = self.line_transfer_end();
+            if self.ly == SCREEN_HEIGHT {
+                // VBlank, the frame is complete
+                self.complete_frame();
+                interrupts |= Interrupt::VBlank.bit();
+            } else if self.ly == LINES_PER_FRAME {
+                self.ly = 0;
+                self.window_counter = 0;
+                self.wy_triggered = false;
+            }
+            self.check_wy();
+        }
+
+        if self.update_stat_line() {
+            interrupts |= Interrupt::LcdStat.bit();
+        }
+
+        interrupts
+    }
+
+    /// Mode 3 of a visible line with the pixel FIFO.
+    fn fifo_dot(&mut self, video_ram: &BankedRam, oam: &Ram) {
+        let registers = Registers {
+            lcdc: self.lcdc,
+            scroll_x: self.scroll_x,
+            scroll_y: self.scroll_y,
+            ly: self.ly,
+            wx: self.wx as usize,
+            window_line: self.window_line(),
+        };
+
+        if self.cycles == OAM_SEARCH_CYCLES {
+            let sprites = self.line_sprites(oam);
+            self.fifo.start(registers, sprites, self.cgb);
+        } else if self.mode() == 3 {
+            if let Some((x, color, palette)) = self.fifo.dot(registers, video_ram) {
+                self.frame[self.ly * SCREEN_WIDTH + x] = color;
+                self.line_palettes[x] = palette;
+                self.window_drawn |= self.fifo.window();
+                if x == SCREEN_WIDTH - 1 {
+                    self.transfer_end = self.cycles;
+                    self.finish_line();
+                }
+            }
         }
     }
 
-    pub fn step(&mut self) {
-        if self.bus.is_none() {
-            // TODO error handle
-            panic!("hogehoge")
+    /// Resolve the colors of line LY once it is drawn.
+    fn finish_line(&mut self) {
+        let line = self.ly * SCREEN_WIDTH;
+
+        if !self.cgb {
+            // Map color ids to shades with BGP, OBP0 and OBP1
+            for x in 0..SCREEN_WIDTH {
+                let palette = match self.line_palettes[x] {
+                    p if p == OBJ_PALETTE => self.obp0,
+                    p if p == OBJ_PALETTE | 1 => self.obp1,
+                    _ => self.bgp,
+                };
+                let color = self.frame[line + x];
+                self.frame[line + x] = palette >> (color * 2) & 0x03;
+            }
+            return;
         }
 
-        self.cycles += 4;
+        for x in 0..SCREEN_WIDTH {
+            let palette = self.line_palettes[x];
+            let palettes = if palette & OBJ_PALETTE == OBJ_PALETTE {
+                &self.obj_palettes
+            } else {
+                &self.bg_palettes
+            };
+            self.colors[line + x] = palettes.color(palette, self.frame[line + x]);
+        }
+    }
 
-        if self.cycles < CYCLE_PER_LINE {
+    /// Mode 3 length is only known once the FIFO has drawn the line.
+    fn line_transfer_end(&self) -> usize {
+        match self.accuracy {
+            Accuracy::Scanline => OAM_SEARCH_CYCLES + PIXEL_TRANSFER_CYCLES,
+            Accuracy::PixelFifo => CYCLE_PER_LINE,
+        }
+    }
+
+    /// Returns whether the STAT interrupt fires.
+    fn update_stat_line(&mut self) -> bool {
+        let line = (self.stat & 0x40 != 0 && self.ly == self.lyc)
+            || match self.mode() {
+                0 => self.stat & 0x08 != 0,
+                1 => self.stat & 0x10 != 0,
+                2 => self.stat & 0x20 != 0,
+                _ => false,
+            };
+
+        let rising = line && !self.stat_line;
+        self.stat_line = line;
+
+        rising
+    }
+
+    /// LCDC bit 7
+    fn lcd_enabled(&self) -> bool {
+        self.lcdc & 0x80 == 0x80
+    }
+
+    /// Handle a write to LCDC bit 7.
+    fn set_lcd_enabled(&mut self, enabled: bool) {
+        if enabled == self.lcd_enabled() {
             return;
         }
 
-        if self.ly < 144 {
-            self.build_gb_tile();
-        } else if self.ly == 144 {
-            self.build_sprites();
-        } else if self.ly >= 144 {
-            self.ly = 0
+        if !enabled {
+            // Damages the screen of a real DMG
+            if self.ly < SCREEN_HEIGHT {
+                log::warn!("LCD turned off outside VBlank (LY = {})", self.ly);
+            }
+            // Present a blank (white) screen
+            self.frame.iter_mut().for_each(|pixel| *pixel = 0);
+            self.colors.iter_mut().for_each(|pixel| *pixel = 0x7FFF);
+            self.complete_frame();
         }
 
-        self.ly += 1;
-        self.cycles -= CYCLE_PER_LINE;
+        // Off: LY stays at 0. On: start over from mode 2 of line 0
+        self.ly = 0;
+        self.cycles = 0;
+        self.window_counter = 0;
+        self.window_drawn = false;
+        self.wy_triggered = false;
+        self.check_wy();
+        self.transfer_end = self.line_transfer_end();
+        self.stat_line = false;
+    }
+
+    /// 0: H-blank, 1: V-blank, 2: OAM search, 3: pixel transfer.
+    /// Reads 0 while the LCD is off.
+    fn mode(&self) -> HalfWord {
+        if !self.lcd_enabled() {
+            0
+        } else if self.ly >= SCREEN_HEIGHT {
+            1
+        } else if self.cycles < OAM_SEARCH_CYCLES {
+            2
+        } else if self.cycles < self.transfer_end {
+            3
+        } else {
+            0
+        }
     }
 
-    fn build_gb_tile(&mut self) {
+    /// Draw the background and the window of line LY into the framebuffer,
+    /// with the registers at the end of the line.
+    fn build_gb_tile(&mut self, video_ram: &BankedRam) {
         for x in 0..SCREEN_WIDTH {
-            let tile_y = ((self.ly + self.scroll_y) % 0x100) / 8 * 32;
-            let tile_x = (x + self.scroll_x) / 8 % 32;
+            // Pixel of the window or the background plane
+            let (map, plane_x, plane_y) = match self.window_line() {
+                Some(line) if x + 7 >= self.wx as usize => {
+                    self.window_drawn = true;
+                    (
+                        self.get_window_tilemap_affr(),
+                        x + 7 - self.wx as usize,
+                        line,
+                    )
+                }
+                _ => (
+                    self.get_bg_tilemap_addr(),
+                    (x + self.scroll_x) % 0x100,
+                    (self.ly + self.scroll_y) % 0x100,
+                ),
+            };
+            let tile_y = plane_y / 8 * 32;
+            let tile_x = plane_x / 8;
 
-            let tile_id = self.get_tile_id(tile_y, tile_x, self.get_bg_tilemap_addr());
+            let tile_id = self.get_tile_id(video_ram, tile_y, tile_x, map);
+            let attributes = self.get_tile_attributes(video_ram, tile_y, tile_x, map);
             let palette_id = {
-                let offset = (self.scroll_x % 8) + x;
-                let addr = (self.ly + self.scroll_y) % 8;
+                let mut offset = plane_x % 8;
+                let mut addr = plane_y % 8;
+                if attributes & 0x20 == 0x20 {
+                    offset = 7 - offset;
+                }
+                if attributes & 0x40 == 0x40 {
+                    addr = 7 - addr;
+                }
+                let bank = (attributes >> 3 & 0x01) as usize;
+
+                self.get_bg_palette_id(video_ram, bank, tile_id, offset, addr)
+            };
 
-                self.get_bg_palette_id(tile_id, offset, addr)
+            // On DMG, LCDC bit 0 clear blanks the background and the window
+            self.frame[self.ly * SCREEN_WIDTH + x] = if !self.cgb && self.lcdc & 0x01 == 0 {
+                0
+            } else {
+                palette_id as u8
             };
+            self.line_palettes[x] = attributes & 0x07;
+            self.line_priority[x] = attributes & 0x80 == 0x80;
+        }
+    }
+
+    /// Line of the window drawn on line LY, `None` when the window is off or
+    /// LY hasn't reached WY yet in this frame. This is the window's own line
+    /// counter rather than LY - WY: lines where the window is hidden (LCDC
+    /// bit 5 clear or WX > 166) don't count.
+    /// Ref https://gbdev.io/pandocs/Scrolling.html#window
+    fn window_line(&self) -> Option<usize> {
+        if self.lcdc & 0x20 == 0 || !self.wy_triggered {
+            return None;
+        }
+        Some(self.window_counter)
+    }
+
+    /// Latch the WY condition at the start of line LY. Moving WY below LY
+    /// afterwards doesn't hide the window for the rest of the frame.
+    fn check_wy(&mut self) {
+        if self.ly == self.wy as usize {
+            self.wy_triggered = true;
+        }
+    }
+
+    /// Draw the sprites of line LY over the background. Only the first 10
+    /// sprites on the line in OAM order are drawn, and where they overlap the
+    /// one with the lower X wins, then the one earlier in OAM (DMG rules).
+    /// Ref https://gbdev.io/pandocs/OAM.html#drawing-priority
+    fn build_sprites(&mut self, video_ram: &BankedRam, oam: &Ram) {
+        if self.lcdc & 0x02 == 0 {
+            return;
+        }
+
+        let height = if self.lcdc & 0x04 == 0x04 { 16 } else { 8 };
+        let line = self.ly as isize;
+
+        let mut sprites = self.line_sprites(oam);
+        sprites.sort_by_key(|s| (s.x, s.index));
+
+        for x in 0..SCREEN_WIDTH {
+            let pixel = self.ly * SCREEN_WIDTH + x;
+
+            for sprite in &sprites {
+                let column = x as isize - sprite.left();
+                if !(0..8).contains(&column) {
+                    continue;
+                }
 
-            todo!() // TODO return image data
+                let color = sprite.color(video_ram, self.cgb, height, line, column);
+                // Transparent, a sprite with lower priority may show
+                if color == 0 {
+                    continue;
+                }
+                if sprite_over_background(
+                    self.cgb,
+                    self.lcdc,
+                    sprite.flags & 0x80 == 0x80,
+                    self.frame[pixel],
+                    self.line_priority[x],
+                ) {
+                    self.frame[pixel] = color;
+                    self.line_palettes[x] = OBJ_PALETTE | sprite.palette(self.cgb);
+                }
+                break;
+            }
         }
     }
 
-    fn build_sprites(&mut self) {}
+    /// The first 10 sprites on line LY in OAM order, as found by the OAM search.
+    fn line_sprites(&self, oam: &Ram) -> Vec<Sprite> {
+        let height = if self.lcdc & 0x04 == 0x04 { 16 } else { 8 };
+        let line = self.ly as isize;
+
+        (0..SPRITE_COUNT)
+            .map(|index| Sprite::read(oam, index))
+            .filter(|s| (s.top()..s.top() + height).contains(&line))
+            .take(SPRITES_PER_LINE)
+            .collect()
+    }
+
+    /// Index of the OAM row the PPU reads while in mode 2 (OAM search),
+    /// `None` in any other mode.
+    pub fn oam_scan_row(&self) -> Option<usize> {
+        if self.mode() == 2 {
+            Some(self.cycles / 4)
+        } else {
+            None
+        }
+    }
+
+    /// The PPU is in mode 0 (H-blank) of a visible line.
+    pub fn in_hblank(&self) -> bool {
+        self.lcd_enabled() && self.ly < SCREEN_HEIGHT && self.cycles >= self.transfer_end
+    }
+
+    /// LY, the dot of the line and the mode, `None` while the LCD is off.
+    pub fn position(&self) -> Option<(usize, usize, HalfWord)> {
+        if self.lcd_enabled() {
+            Some((self.ly, self.cycles, self.mode()))
+        } else {
+            None
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames
+    }
+
+    /// Shade id (0-3) of every pixel, 160x144 row by row.
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// RGB555 color of every pixel, 160x144 row by row. Only drawn in CGB
+    /// mode.
+    pub fn color_frame(&self) -> &[Word] {
+        &self.colors
+    }
 
-    pub fn set_bus(&mut self, bus: SharedBus) {
-        self.bus = Some(bus)
+    /// `frame()` holds a complete frame, from VBlank until the PPU starts
+    /// drawing the next one.
+    pub fn frame_ready(&self) -> bool {
+        self.ly >= SCREEN_HEIGHT
     }
 
+    /// `address` relative to LCDC (0xFF40).
+    ///```text
+    /// FF40  LCDC  LCD control
+    /// FF41  STAT  Bit 6: LYC=LY interrupt, Bit 5: mode 2 interrupt,
+    ///             Bit 4: mode 1 interrupt, Bit 3: mode 0 interrupt,
+    ///             Bit 2: LYC=LY (read only), Bit 1-0: mode (read only)
+    /// FF42  SCY   Background scroll Y
+    /// FF43  SCX   Background scroll X
+    /// FF44  LY    Line being drawn (read only)
+    /// FF45  LYC   Line compared with LY
+    /// FF46  DMA   OAM DMA, handled by the bus
+    /// FF47  BGP   Background palette
+    /// FF48  OBP0  Sprite palette 0
+    /// FF49  OBP1  Sprite palette 1
+    /// FF4A  WY    Window Y
+    /// FF4B  WX    Window X + 7
+    /// ```
+    /// Ref https://gbdev.io/pandocs/STAT.html
     pub fn read(&self, address: Word) -> HalfWord {
-        self.data[address as usize]
+        match address {
+            0 => self.lcdc,
+            1 => self.stat | ((self.ly == self.lyc) as HalfWord) << 2 | self.mode(),
+            2 => self.scroll_y as HalfWord,
+            3 => self.scroll_x as HalfWord,
+            4 => self.ly as HalfWord,
+            5 => self.lyc as HalfWord,
+            7 => self.bgp,
+            8 => self.obp0,
+            9 => self.obp1,
+            0xA => self.wy,
+            0xB => self.wx,
+            _ => 0xFF,
+        }
     }
 
+    /// `address` relative to LCDC (0xFF40).
     pub fn write(&mut self, address: Word, byte: HalfWord) {
-        self.data[address as usize] = byte;
+        match address {
+            0 => {
+                self.set_lcd_enabled(byte & 0x80 == 0x80);
+                self.lcdc = byte;
+            }
+            1 => self.stat = byte & 0x78,
+            2 => self.scroll_y = byte as usize,
+            3 => self.scroll_x = byte as usize,
+            5 => self.lyc = byte as usize,
+            7 => self.bgp = byte,
+            8 => self.obp0 = byte,
+            9 => self.obp1 = byte,
+            0xA => self.wy = byte,
+            0xB => self.wx = byte,
+            // LY is read only
+            _ => {}
+        }
+    }
 
-        let bus = self.bus.as_ref().unwrap();
-        let mut bus = bus.lock().unwrap();
-        bus.write_byte(address, byte);
+    /// `address` relative to BCPS (0xFF68).
+    ///```text
+    /// FF68  BCPS  Background palette index
+    /// FF69  BCPD  Background palette data
+    /// FF6A  OCPS  Sprite palette index
+    /// FF6B  OCPD  Sprite palette data
+    /// ```
+    pub fn read_palette(&self, address: Word) -> HalfWord {
+        match address {
+            0 => self.bg_palettes.read_index(),
+            1 => self.bg_palettes.read_data(),
+            2 => self.obj_palettes.read_index(),
+            3 => self.obj_palettes.read_data(),
+            _ => 0xFF,
+        }
+    }
+
+    /// `address` relative to BCPS (0xFF68).
+    pub fn write_palette(&mut self, address: Word, byte: HalfWord) {
+        match address {
+            0 => self.bg_palettes.write_index(byte),
+            1 => self.bg_palettes.write_data(byte),
+            2 => self.obj_palettes.write_index(byte),
+            3 => self.obj_palettes.write_data(byte),
+            _ => {}
+        }
     }
 
-    fn get_tile_id(&self, tile_y: usize, line_offset: usize, offset_addr: Word) -> HalfWord {
+    fn get_tile_id(
+        &self,
+        video_ram: &BankedRam,
+        tile_y: usize,
+        line_offset: usize,
+        offset_addr: Word,
+    ) -> HalfWord {
         let addr = tile_y as u16 + line_offset as u16 + offset_addr;
-        let bus = self.bus.as_ref().unwrap();
-        let id = bus.lock().unwrap().read_byte(addr);
-        id
+        read_vram(video_ram, 0, addr)
+    }
+
+    /// CGB map attributes of a tile, from VRAM bank 1. Always 0 on DMG.
+    ///```text
+    /// Bit 7    Drawn over sprites
+    /// Bit 6    Y flip
+    /// Bit 5    X flip
+    /// Bit 3    Tile data bank
+    /// Bit 2-0  Palette
+    /// ```
+    /// Ref https://gbdev.io/pandocs/Tile_Maps.html#bg-map-attributes-cgb-mode-only
+    fn get_tile_attributes(
+        &self,
+        video_ram: &BankedRam,
+        tile_y: usize,
+        line_offset: usize,
+        offset_addr: Word,
+    ) -> HalfWord {
+        if !self.cgb {
+            return 0;
+        }
+        let addr = tile_y as u16 + line_offset as u16 + offset_addr;
+        read_vram(video_ram, 1, addr)
     }
 
     fn get_window_tilemap_affr(&self) -> Word {
@@ -110,32 +787,297 @@ impl Gpu {
         return TILEMAP0;
     }
 
-    fn get_bg_palette_id(&self, tile_id: HalfWord, x: usize, y: usize) -> Word {
-        // TODO implement switch tile data
-        let addr = u16::from((tile_id + 128) * 0x10);
-        let base = self.get_tile_data_addr() + addr + (y * 2) as u16;
+    fn get_bg_palette_id(
+        &self,
+        video_ram: &BankedRam,
+        bank: usize,
+        tile_id: HalfWord,
+        x: usize,
+        y: usize,
+    ) -> Word {
+        tile_pixel(video_ram, bank, self.get_tile_data_addr(tile_id), x, y) as Word
+    }
+
+    /// Address of the background/window tile `tile_id`, by LCDC bit 4:
+    /// 1 = tiles 0-255 at 0x8000, 0 = tiles -128-127 around 0x9000.
+    fn get_tile_data_addr(&self, tile_id: HalfWord) -> Word {
+        tile_data_addr(self.lcdc, tile_id)
+    }
+}
+
+const SPRITE_COUNT: usize = 40;
+const SPRITES_PER_LINE: usize = 10;
+
+/// An OAM entry
+///```text
+/// Byte 0  Y position + 16
+/// Byte 1  X position + 8
+/// Byte 2  Tile index
+/// Byte 3  Bit 7: behind BG colors 1-3, Bit 6: Y flip, Bit 5: X flip,
+///         Bit 4: DMG palette (OBP0/OBP1), Bit 3: CGB VRAM bank,
+///         Bit 2-0: CGB palette
+/// ```
+#[derive(Debug)]
+struct Sprite {
+    index: usize,
+    y: HalfWord,
+    x: HalfWord,
+    tile: HalfWord,
+    flags: HalfWord,
+}
+
+impl Sprite {
+    fn read(oam: &Ram, index: usize) -> Sprite {
+        let base = index as Word * 4;
+        Sprite {
+            index,
+            y: oam.read(base),
+            x: oam.read(base + 1),
+            tile: oam.read(base + 2),
+            flags: oam.read(base + 3),
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.usize(self.index);
+        state.u8(self.y);
+        state.u8(self.x);
+        state.u8(self.tile);
+        state.u8(self.flags);
+    }
+
+    fn load_state(state: &mut StateReader) -> Result<Sprite> {
+        Ok(Sprite {
+            index: state.usize()?,
+            y: state.u8()?,
+            x: state.u8()?,
+            tile: state.u8()?,
+            flags: state.u8()?,
+        })
+    }
+
+    fn top(&self) -> isize {
+        self.y as isize - 16
+    }
+
+    fn left(&self) -> isize {
+        self.x as isize - 8
+    }
+
+    /// OBP0/OBP1 on DMG, one of the 8 sprite palettes on CGB.
+    fn palette(&self, cgb: bool) -> HalfWord {
+        if cgb {
+            self.flags & 0x07
+        } else {
+            self.flags >> 4 & 0x01
+        }
+    }
+
+    /// Color id (0-3) of the sprite pixel on `line` in `column` (0-7).
+    fn color(
+        &self,
+        video_ram: &BankedRam,
+        cgb: bool,
+        height: isize,
+        line: isize,
+        column: isize,
+    ) -> u8 {
+        let mut row = line - self.top();
+        if self.flags & 0x40 == 0x40 {
+            row = height - 1 - row;
+        }
+        let column = if self.flags & 0x20 == 0x20 {
+            7 - column
+        } else {
+            column
+        };
+
+        // 8x16 sprites ignore bit 0 of the tile index
+        let tile = if height == 16 {
+            self.tile & 0xFE
+        } else {
+            self.tile
+        };
+        let addr = 0x8000 + tile as Word * 0x10;
+
+        // Attribute bit 3 selects the VRAM bank on CGB
+        let bank = if cgb {
+            (self.flags >> 3 & 0x01) as usize
+        } else {
+            0
+        };
+        tile_pixel(video_ram, bank, addr, column as usize, row as usize)
+    }
+}
+
+/// Whether an opaque sprite pixel is drawn over a background pixel of color
+/// id `background`. `behind`: the sprite attribute, `priority`: the CGB map
+/// attribute.
+fn sprite_over_background(
+    cgb: bool,
+    lcdc: HalfWord,
+    behind: bool,
+    background: HalfWord,
+    priority: bool,
+) -> bool {
+    // On CGB, LCDC bit 0 clear puts all sprites in front
+    if background == 0 || (cgb && lcdc & 0x01 == 0) {
+        return true;
+    }
+    !(behind || (cgb && priority))
+}
+
+fn tile_data_addr(lcdc: HalfWord, tile_id: HalfWord) -> Word {
+    if lcdc & 0x10 == 0x10 {
+        return 0x8000 + tile_id as Word * 0x10;
+    }
+    0x8800 + tile_id.wrapping_add(128) as Word * 0x10
+}
+
+/// Layout of a pixel in [`Gpu::framebuffer`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 4 bytes, R G B A
+    #[default]
+    Rgba8888,
+    /// 4 bytes, B G R A
+    Bgra8888,
+    /// 2 bytes, little endian RRRRRGGG GGGBBBBB
+    Rgb565,
+}
 
-        let l1 = self.read_bus_byte(base);
-        let l2 = self.read_bus_byte(base + 1);
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba8888 | PixelFormat::Bgra8888 => 4,
+            PixelFormat::Rgb565 => 2,
+        }
+    }
 
-        let mut palette_id = 0;
-        if l1 & (0x01 << (7 - x)) != 0 {
-            palette_id = 1
+    fn encode(self, [r, g, b, a]: [u8; 4], pixel: &mut [u8]) {
+        match self {
+            PixelFormat::Rgba8888 => pixel.copy_from_slice(&[r, g, b, a]),
+            PixelFormat::Bgra8888 => pixel.copy_from_slice(&[b, g, r, a]),
+            PixelFormat::Rgb565 => {
+                let color = (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3;
+                pixel.copy_from_slice(&color.to_le_bytes());
+            }
         }
-        if l2 & (0x01 << (7 - x)) != 0 {
-            palette_id += 2
+    }
+}
+
+impl FromStr for PixelFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<PixelFormat> {
+        match s {
+            "rgba8888" => Ok(PixelFormat::Rgba8888),
+            "bgra8888" => Ok(PixelFormat::Bgra8888),
+            "rgb565" => Ok(PixelFormat::Rgb565),
+            _ => anyhow::bail!("unknown pixel format {} (rgba8888, bgra8888, rgb565)", s),
         }
+    }
+}
+
+/// RGBA color of each DMG shade id, lightest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shades(pub [[u8; 4]; 4]);
+
+impl Shades {
+    pub const GRAY: Shades = Shades([
+        [0xFF, 0xFF, 0xFF, 0xFF],
+        [0xAA, 0xAA, 0xAA, 0xFF],
+        [0x55, 0x55, 0x55, 0xFF],
+        [0x00, 0x00, 0x00, 0xFF],
+    ]);
+    /// The green tinted screen of the original Game Boy
+    pub const GREEN: Shades = Shades([
+        [0x9B, 0xBC, 0x0F, 0xFF],
+        [0x8B, 0xAC, 0x0F, 0xFF],
+        [0x30, 0x62, 0x30, 0xFF],
+        [0x0F, 0x38, 0x0F, 0xFF],
+    ]);
+    /// The Game Boy Pocket screen
+    pub const POCKET: Shades = Shades([
+        [0xC4, 0xCF, 0xA1, 0xFF],
+        [0x8B, 0x95, 0x6D, 0xFF],
+        [0x4D, 0x53, 0x3C, 0xFF],
+        [0x1F, 0x1F, 0x1F, 0xFF],
+    ]);
 
-        palette_id
+    pub fn rgba(&self, shade: HalfWord) -> [u8; 4] {
+        self.0[shade as usize & 0x03]
     }
+}
 
-    fn get_tile_data_addr(&self) -> Word {
-        // TODO implement switch tile data
-        0x8800
+impl Default for Shades {
+    fn default() -> Self {
+        Shades::GRAY
     }
+}
+
+impl FromStr for Shades {
+    type Err = anyhow::Error;
 
-    fn read_bus_byte(&self, addr: Word) -> HalfWord {
-        let bus = self.bus.as_ref().unwrap().lock().unwrap();
-        bus.read_byte(addr)
+    /// `gray`, `green`, `pocket` or 4 comma separated RRGGBB colors, lightest
+    /// first.
+    fn from_str(s: &str) -> anyhow::Result<Shades> {
+        match s {
+            "gray" => return Ok(Shades::GRAY),
+            "green" => return Ok(Shades::GREEN),
+            "pocket" => return Ok(Shades::POCKET),
+            _ => {}
+        }
+
+        let colors = s
+            .split(',')
+            .map(|color| {
+                let color = color.trim().trim_start_matches('#');
+                match u32::from_str_radix(color, 16) {
+                    Ok(rgb) if color.len() == 6 => {
+                        let [_, r, g, b] = rgb.to_be_bytes();
+                        Ok([r, g, b, 0xFF])
+                    }
+                    _ => anyhow::bail!("invalid color {}, expected RRGGBB", color),
+                }
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        match colors[..] {
+            [c0, c1, c2, c3] => Ok(Shades([c0, c1, c2, c3])),
+            _ => anyhow::bail!(
+                "invalid palette {} (gray, green, pocket or 4 RRGGBB colors)",
+                s
+            ),
+        }
     }
 }
+
+/// Scale the 5 bit channels of a CGB color to 8 bits, filling the low bits
+/// from the high ones.
+pub fn rgb555_to_rgba(color: u16) -> [u8; 4] {
+    let channel = |shift: u16| {
+        let c = (color >> shift & 0x1F) as u8;
+        c << 3 | c >> 2
+    };
+
+    [channel(0), channel(5), channel(10), 0xFF]
+}
+
+/// Color id (0-3) of pixel (`x`, `y`) of the tile at `addr`. Each row of a
+/// tile is two bytes, with the low and the high bits of its 8 pixels.
+/// Ref https://gbdev.io/pandocs/Tile_Data.html
+fn tile_pixel(video_ram: &BankedRam, bank: usize, addr: Word, x: usize, y: usize) -> HalfWord {
+    let row = addr + (y * 2) as Word;
+    let l1 = read_vram(video_ram, bank, row);
+    let l2 = read_vram(video_ram, bank, row + 1);
+    let bit = 7 - x;
+
+    (l1 >> bit & 0x01) | (l2 >> bit & 0x01) << 1
+}
+
+/// Read VRAM by its bus address (0x8000-0x9FFF). The PPU reads both banks
+/// regardless of VBK, on CGB bank 1 holds the tile attributes.
+fn read_vram(video_ram: &BankedRam, bank: usize, addr: Word) -> HalfWord {
+    video_ram.read_bank(bank, addr - 0x8000)
+}
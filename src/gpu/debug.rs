@@ -0,0 +1,147 @@
+//! VRAM viewers for debugging, decoding tiles and tile maps regardless of
+//! what the PPU is drawing.
+
+use super::{
+    read_vram, rgb555_to_rgba, tile_data_addr, tile_pixel, Gpu, SCREEN_HEIGHT, SCREEN_WIDTH,
+    TILEMAP0, TILEMAP1,
+};
+use crate::ram::BankedRam;
+use crate::Word;
+
+const TILES_PER_BANK: usize = 384;
+// Tiles per row of the tile sheet of one bank
+const SHEET_COLUMNS: usize = 16;
+const MAP_SIZE: usize = 32;
+const VIEWPORT_COLOR: [u8; 4] = [0xFF, 0x00, 0x00, 0xFF];
+
+/// An RGBA image, row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugImage {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+impl DebugImage {
+    fn new(width: usize, height: usize) -> DebugImage {
+        DebugImage {
+            width,
+            height,
+            rgba: vec![0xFF; width * height * 4],
+        }
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 4]) {
+        let offset = (y * self.width + x) * 4;
+        self.rgba[offset..offset + 4].copy_from_slice(&color);
+    }
+}
+
+/// One of the two 32x32 tile maps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileMap {
+    /// 0x9800-0x9BFF
+    Low,
+    /// 0x9C00-0x9FFF
+    High,
+}
+
+impl TileMap {
+    fn base(self) -> Word {
+        match self {
+            TileMap::Low => TILEMAP0,
+            TileMap::High => TILEMAP1,
+        }
+    }
+}
+
+impl Gpu {
+    /// All 384 tiles at 0x8000-0x97FF, 16 tiles per row, by color id. In
+    /// CGB mode the tiles of VRAM bank 1 are drawn on the right of bank 0.
+    pub fn debug_render_tiles(&self, video_ram: &BankedRam) -> DebugImage {
+        let banks = if self.cgb { 2 } else { 1 };
+        let rows = TILES_PER_BANK / SHEET_COLUMNS;
+        let mut image = DebugImage::new(SHEET_COLUMNS * 8 * banks, rows * 8);
+
+        for bank in 0..banks {
+            for tile in 0..TILES_PER_BANK {
+                let left = (bank * SHEET_COLUMNS + tile % SHEET_COLUMNS) * 8;
+                let top = tile / SHEET_COLUMNS * 8;
+                let addr = 0x8000 + (tile * 0x10) as Word;
+
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let color = tile_pixel(video_ram, bank, addr, x, y);
+                        image.set_pixel(left + x, top + y, self.shades.rgba(color));
+                    }
+                }
+            }
+        }
+
+        image
+    }
+
+    /// The 256x256 pixels of tile map `which`, with the tile data addressing
+    /// of LCDC bit 4, through BGP. In CGB mode the map attributes and color
+    /// palettes are applied.
+    pub fn debug_render_tilemap(&self, video_ram: &BankedRam, which: TileMap) -> DebugImage {
+        let mut image = DebugImage::new(MAP_SIZE * 8, MAP_SIZE * 8);
+
+        for row in 0..MAP_SIZE {
+            for column in 0..MAP_SIZE {
+                let map_addr = which.base() + (row * MAP_SIZE + column) as Word;
+                let tile_id = read_vram(video_ram, 0, map_addr);
+                let attributes = if self.cgb {
+                    read_vram(video_ram, 1, map_addr)
+                } else {
+                    0
+                };
+                let bank = (attributes >> 3 & 0x01) as usize;
+                let addr = tile_data_addr(self.lcdc, tile_id);
+
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let tile_x = if attributes & 0x20 == 0x20 { 7 - x } else { x };
+                        let tile_y = if attributes & 0x40 == 0x40 { 7 - y } else { y };
+                        let color = tile_pixel(video_ram, bank, addr, tile_x, tile_y);
+
+                        let rgba = if self.cgb {
+                            rgb555_to_rgba(self.bg_palettes.color(attributes & 0x07, color))
+                        } else {
+                            self.shades.rgba(self.bgp >> (color * 2) & 0x03)
+                        };
+                        image.set_pixel(column * 8 + x, row * 8 + y, rgba);
+                    }
+                }
+            }
+        }
+
+        image
+    }
+
+    /// The whole 256x256 background plane (the tile map selected by LCDC
+    /// bit 3) with the 160x144 viewport at SCX/SCY outlined in red. The
+    /// outline wraps around the edges like the viewport does.
+    pub fn render_full_bg(&self, video_ram: &BankedRam) -> DebugImage {
+        let which = if self.lcdc & 0x08 == 0x08 {
+            TileMap::High
+        } else {
+            TileMap::Low
+        };
+        let mut image = self.debug_render_tilemap(video_ram, which);
+
+        let size = MAP_SIZE * 8;
+        let (left, top) = (self.scroll_x, self.scroll_y);
+        let (right, bottom) = (left + SCREEN_WIDTH - 1, top + SCREEN_HEIGHT - 1);
+        for x in left..=right {
+            image.set_pixel(x % size, top % size, VIEWPORT_COLOR);
+            image.set_pixel(x % size, bottom % size, VIEWPORT_COLOR);
+        }
+        for y in top..=bottom {
+            image.set_pixel(left % size, y % size, VIEWPORT_COLOR);
+            image.set_pixel(right % size, y % size, VIEWPORT_COLOR);
+        }
+
+        image
+    }
+}
@@ -0,0 +1,390 @@
+//! Pixel FIFO renderer, drawing line LY one pixel per dot during mode 3.
+//!
+//! The background fetcher reads the tile map and tile data while the line is
+//! being drawn, so mid-scanline register writes take effect at once: SCX,
+//! SCY and LCDC on the next tile fetch, WX on the next pixel. Only the SCX
+//! fine scroll is latched at the start of the line. Mode 3 lasts 172 dots
+//! plus the SCX fine scroll, 6 dots per sprite fetch and the restart of the
+//! fetcher when the window starts.
+//! Ref https://gbdev.io/pandocs/pixel_fifo.html
+
+use super::{
+    read_vram, sprite_over_background, tile_data_addr, Sprite, OBJ_PALETTE, SCREEN_WIDTH, TILEMAP0,
+    TILEMAP1,
+};
+use crate::ram::BankedRam;
+use crate::state::{StateReader, StateWriter};
+use crate::{HalfWord, Word};
+use anyhow::Result;
+use std::collections::VecDeque;
+
+// The first tile fetch of a line is thrown away
+const STARTUP_DOTS: usize = 6;
+const SPRITE_FETCH_DOTS: usize = 6;
+
+#[derive(Debug, Default)]
+pub(super) struct PixelFifo {
+    background: VecDeque<BackgroundPixel>,
+    sprites: VecDeque<SpritePixel>,
+    fetcher: Fetcher,
+    // Sprites of the line not fetched yet, in OAM order
+    pending: Vec<Sprite>,
+    sprite_height: isize,
+    // Screen X of the next pixel
+    x: usize,
+    // Pixels left to drop for SCX fine scroll
+    discard: usize,
+    // Dots left before the fetcher or the FIFO may run again
+    stall: usize,
+    // Sprite to merge when the stall ends
+    fetching: Option<Sprite>,
+    cgb: bool,
+    // The window started on this line
+    window: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BackgroundPixel {
+    color: HalfWord,
+    // CGB palette
+    palette: HalfWord,
+    // CGB attribute, drawn over sprites
+    priority: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct SpritePixel {
+    color: HalfWord,
+    palette: HalfWord,
+    // Behind background colors 1-3
+    behind: bool,
+}
+
+#[derive(Debug, Default)]
+struct Fetcher {
+    // 0: tile id, 1: data low, 2: data high, 3: push
+    step: usize,
+    // Fetching window tiles instead of background tiles
+    window: bool,
+    // Each step but push takes 2 dots
+    dots: usize,
+    // Tiles fetched on this line
+    tile: usize,
+    tile_id: HalfWord,
+    // CGB map attributes of the tile
+    attributes: HalfWord,
+    low: HalfWord,
+    high: HalfWord,
+}
+
+/// Registers the renderer reads, sampled every dot.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Registers {
+    pub lcdc: HalfWord,
+    pub scroll_x: usize,
+    pub scroll_y: usize,
+    pub ly: usize,
+    pub wx: usize,
+    // Line of the window to draw, if the window is on this line
+    pub window_line: Option<usize>,
+}
+
+impl PixelFifo {
+    /// Start mode 3 of a line with the sprites found by the OAM search.
+    pub fn start(&mut self, registers: Registers, sprites: Vec<Sprite>, cgb: bool) {
+        self.background.clear();
+        self.sprites.clear();
+        self.fetcher = Fetcher::default();
+        self.pending = sprites;
+        self.sprite_height = if registers.lcdc & 0x04 == 0x04 { 16 } else { 8 };
+        self.x = 0;
+        self.discard = registers.scroll_x % 8;
+        self.stall = STARTUP_DOTS;
+        self.fetching = None;
+        self.cgb = cgb;
+        self.window = false;
+    }
+
+    /// Run one dot. Returns the screen X, color id and palette (as in
+    /// `Gpu::line_palettes`) of the pixel drawn, if any.
+    pub fn dot(
+        &mut self,
+        registers: Registers,
+        video_ram: &BankedRam,
+    ) -> Option<(usize, HalfWord, HalfWord)> {
+        if self.x >= SCREEN_WIDTH {
+            return None;
+        }
+
+        if self.stall > 0 {
+            self.stall -= 1;
+            if self.stall == 0 {
+                if let Some(sprite) = self.fetching.take() {
+                    self.merge_sprite(&sprite, registers.ly, video_ram);
+                }
+            }
+            return None;
+        }
+
+        // The window starts at WX - 7, throwing away the background pixels
+        // fetched so far. With WX < 7 its first pixels are off screen.
+        if !self.window
+            && registers.window_line.is_some()
+            && (self.x + 7 == registers.wx || (self.x == 0 && registers.wx < 7))
+        {
+            self.window = true;
+            self.background.clear();
+            self.fetcher = Fetcher {
+                window: true,
+                ..Fetcher::default()
+            };
+            self.discard = 7usize.saturating_sub(registers.wx);
+        } else if self.window && registers.window_line.is_none() {
+            // Turned off mid-line, the background comes back from the next fetch
+            self.window = false;
+            self.fetcher.window = false;
+        }
+
+        // A sprite starts at this pixel, fetching it stalls the FIFO
+        if registers.lcdc & 0x02 == 0x02 && self.discard == 0 {
+            let x = self.x as isize;
+            let due = self
+                .pending
+                .iter()
+                .position(|s| s.left() == x || (x == 0 && (-7..0).contains(&s.left())));
+            if let Some(index) = due {
+                self.fetching = Some(self.pending.remove(index));
+                self.stall = SPRITE_FETCH_DOTS;
+                return None;
+            }
+        }
+
+        self.fetch(registers, video_ram);
+
+        let background = self.background.pop_front()?;
+        if self.discard > 0 {
+            self.discard -= 1;
+            return None;
+        }
+
+        // On DMG, LCDC bit 0 clear blanks the background and the window
+        let background_color = if !self.cgb && registers.lcdc & 0x01 == 0 {
+            0
+        } else {
+            background.color
+        };
+
+        let sprite = self.sprites.pop_front().unwrap_or_default();
+        let (color, palette) = if sprite.color != 0
+            && sprite_over_background(
+                self.cgb,
+                registers.lcdc,
+                sprite.behind,
+                background_color,
+                background.priority,
+            ) {
+            (sprite.color, OBJ_PALETTE | sprite.palette)
+        } else {
+            (background_color, background.palette)
+        };
+
+        let x = self.x;
+        self.x += 1;
+        Some((x, color, palette))
+    }
+
+    /// The window has been drawn on this line.
+    pub fn window(&self) -> bool {
+        self.window
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.usize(self.background.len());
+        for pixel in &self.background {
+            state.u8(pixel.color);
+            state.u8(pixel.palette);
+            state.bool(pixel.priority);
+        }
+        state.usize(self.sprites.len());
+        for pixel in &self.sprites {
+            state.u8(pixel.color);
+            state.u8(pixel.palette);
+            state.bool(pixel.behind);
+        }
+
+        let fetcher = &self.fetcher;
+        state.usize(fetcher.step);
+        state.bool(fetcher.window);
+        state.usize(fetcher.dots);
+        state.usize(fetcher.tile);
+        state.u8(fetcher.tile_id);
+        state.u8(fetcher.attributes);
+        state.u8(fetcher.low);
+        state.u8(fetcher.high);
+
+        state.usize(self.pending.len());
+        for sprite in &self.pending {
+            sprite.save_state(state);
+        }
+        state.usize(self.sprite_height as usize);
+        state.usize(self.x);
+        state.usize(self.discard);
+        state.usize(self.stall);
+        state.bool(self.fetching.is_some());
+        if let Some(sprite) = &self.fetching {
+            sprite.save_state(state);
+        }
+        state.bool(self.cgb);
+        state.bool(self.window);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.background.clear();
+        for _ in 0..state.usize()? {
+            self.background.push_back(BackgroundPixel {
+                color: state.u8()? & 0x03,
+                palette: state.u8()?,
+                priority: state.bool()?,
+            });
+        }
+        self.sprites.clear();
+        for _ in 0..state.usize()? {
+            self.sprites.push_back(SpritePixel {
+                color: state.u8()? & 0x03,
+                palette: state.u8()?,
+                behind: state.bool()?,
+            });
+        }
+
+        self.fetcher = Fetcher {
+            step: state.usize()? % 4,
+            window: state.bool()?,
+            dots: state.usize()?,
+            tile: state.usize()?,
+            tile_id: state.u8()?,
+            attributes: state.u8()?,
+            low: state.u8()?,
+            high: state.u8()?,
+        };
+
+        self.pending.clear();
+        for _ in 0..state.usize()? {
+            self.pending.push(Sprite::load_state(state)?);
+        }
+        self.sprite_height = state.usize()? as isize;
+        self.x = state.usize()?;
+        self.discard = state.usize()? % 8;
+        self.stall = state.usize()?;
+        self.fetching = if state.bool()? {
+            Some(Sprite::load_state(state)?)
+        } else {
+            None
+        };
+        self.cgb = state.bool()?;
+        self.window = state.bool()?;
+
+        Ok(())
+    }
+
+    fn fetch(&mut self, registers: Registers, video_ram: &BankedRam) {
+        let cgb = self.cgb;
+        let fetcher = &mut self.fetcher;
+
+        if fetcher.step < 3 {
+            fetcher.dots += 1;
+            if fetcher.dots < 2 {
+                return;
+            }
+            fetcher.dots = 0;
+        }
+
+        // Line and tile of the window or the background plane
+        let (y, map_bit, x) = match registers.window_line {
+            Some(line) if fetcher.window => (line, 0x40, fetcher.tile % 32),
+            _ => (
+                (registers.ly + registers.scroll_y) % 0x100,
+                0x08,
+                (registers.scroll_x / 8 + fetcher.tile) % 32,
+            ),
+        };
+        match fetcher.step {
+            0 => {
+                let map = if registers.lcdc & map_bit == map_bit {
+                    TILEMAP1
+                } else {
+                    TILEMAP0
+                };
+                let addr = map + (y / 8 * 32 + x) as Word;
+                fetcher.tile_id = read_vram(video_ram, 0, addr);
+                fetcher.attributes = if cgb {
+                    read_vram(video_ram, 1, addr)
+                } else {
+                    0
+                };
+                fetcher.step = 1;
+            }
+            1 | 2 => {
+                let row = if fetcher.attributes & 0x40 == 0x40 {
+                    7 - y % 8
+                } else {
+                    y % 8
+                };
+                let addr = tile_data_addr(registers.lcdc, fetcher.tile_id) + (row * 2) as Word;
+                let bank = (fetcher.attributes >> 3 & 0x01) as usize;
+                if fetcher.step == 1 {
+                    fetcher.low = read_vram(video_ram, bank, addr);
+                } else {
+                    fetcher.high = read_vram(video_ram, bank, addr + 1);
+                }
+                fetcher.step += 1;
+            }
+            _ => {
+                // Waits until the FIFO is empty
+                if !self.background.is_empty() {
+                    return;
+                }
+                for column in 0..8 {
+                    let bit = if fetcher.attributes & 0x20 == 0x20 {
+                        column
+                    } else {
+                        7 - column
+                    };
+                    self.background.push_back(BackgroundPixel {
+                        color: (fetcher.low >> bit & 0x01) | (fetcher.high >> bit & 0x01) << 1,
+                        palette: fetcher.attributes & 0x07,
+                        priority: fetcher.attributes & 0x80 == 0x80,
+                    });
+                }
+                fetcher.tile += 1;
+                fetcher.step = 0;
+            }
+        }
+    }
+
+    /// Mix the pixels of `sprite` into the sprite FIFO. Pixels already there
+    /// belong to a sprite fetched earlier, which has priority.
+    fn merge_sprite(&mut self, sprite: &Sprite, ly: usize, video_ram: &BankedRam) {
+        while self.sprites.len() < 8 {
+            self.sprites.push_back(SpritePixel::default());
+        }
+
+        for column in 0..8 {
+            let position = sprite.left() + column;
+            if position < self.x as isize {
+                // Off the left edge of the screen
+                continue;
+            }
+
+            let slot = &mut self.sprites[(position - self.x as isize) as usize];
+            if slot.color != 0 {
+                continue;
+            }
+
+            *slot = SpritePixel {
+                color: sprite.color(video_ram, self.cgb, self.sprite_height, ly as isize, column),
+                palette: sprite.palette(self.cgb),
+                behind: sprite.flags & 0x80 == 0x80,
+            };
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! CGB color palette RAM, 8 palettes of 4 RGB555 colors each for the
+//! background (BCPS/BCPD) and the sprites (OCPS/OCPD).
+//! Ref https://gbdev.io/pandocs/Palettes.html#lcd-color-palettes-cgb-only
+
+use crate::state::{StateReader, StateWriter};
+use crate::{HalfWord, Word};
+use anyhow::Result;
+
+const PALETTE_RAM_SIZE: usize = 64;
+
+#[derive(Debug)]
+pub(super) struct PaletteRam {
+    data: [HalfWord; PALETTE_RAM_SIZE],
+    // Byte accessed through the data register
+    index: usize,
+    auto_increment: bool,
+}
+
+impl Default for PaletteRam {
+    /// All white, as the CGB boot ROM leaves the background palettes.
+    fn default() -> Self {
+        PaletteRam {
+            data: [0xFF; PALETTE_RAM_SIZE],
+            index: 0,
+            auto_increment: false,
+        }
+    }
+}
+
+impl PaletteRam {
+    ///```text
+    /// Bit 7    Increment the index after writing the data register
+    /// Bit 5-0  Byte index, palette * 8 + color * 2 (+ 1 for the upper byte)
+    /// ```
+    pub fn read_index(&self) -> HalfWord {
+        (self.auto_increment as HalfWord) << 7 | self.index as HalfWord
+    }
+
+    pub fn write_index(&mut self, byte: HalfWord) {
+        self.auto_increment = byte & 0x80 == 0x80;
+        self.index = byte as usize & 0x3F;
+    }
+
+    pub fn read_data(&self) -> HalfWord {
+        self.data[self.index]
+    }
+
+    /// Reads do not increment the index, only writes do.
+    pub fn write_data(&mut self, byte: HalfWord) {
+        self.data[self.index] = byte;
+        if self.auto_increment {
+            self.index = (self.index + 1) % PALETTE_RAM_SIZE;
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.data);
+        state.u8(self.read_index());
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        state.bytes_into(&mut self.data)?;
+        self.write_index(state.u8()?);
+
+        Ok(())
+    }
+
+    /// Color `color_id` (0-3) of `palette` (0-7) as RGB555, little endian in
+    /// palette RAM.
+    pub fn color(&self, palette: HalfWord, color_id: HalfWord) -> Word {
+        let index = (palette as usize & 0x07) * 8 + (color_id as usize & 0x03) * 2;
+        (self.data[index + 1] as Word) << 8 & 0x7F00 | self.data[index] as Word
+    }
+}
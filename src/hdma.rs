@@ -0,0 +1,116 @@
+use crate::state::{StateReader, StateWriter};
+use crate::{join_half_words, HalfWord, Word};
+use anyhow::Result;
+
+/// Bytes copied per block, one block per H-blank in H-blank mode.
+pub const BLOCK_SIZE: Word = 0x10;
+
+/// CGB VRAM DMA registers
+///```text
+/// FF51  HDMA1  Source, upper byte
+/// FF52  HDMA2  Source, lower byte (lower 4 bits ignored)
+/// FF53  HDMA3  Destination in VRAM, upper byte (upper 3 bits ignored)
+/// FF54  HDMA4  Destination in VRAM, lower byte (lower 4 bits ignored)
+/// FF55  HDMA5  Bit 7: mode (0 = general purpose, 1 = H-blank)
+///              Bit 6-0: number of 16 byte blocks - 1
+/// ```
+/// Ref https://gbdev.io/pandocs/CGB_Registers.html#lcd-vram-dma-transfers
+#[derive(Debug)]
+pub struct Hdma {
+    source: Word,
+    destination: Word,
+    // Blocks left - 1, 0x7F once the transfer completed
+    length: HalfWord,
+    // An H-blank transfer is in progress
+    active: bool,
+}
+
+impl Default for Hdma {
+    fn default() -> Hdma {
+        Hdma {
+            source: 0,
+            destination: 0,
+            length: 0x7F,
+            active: false,
+        }
+    }
+}
+
+impl Hdma {
+    /// Read a register, `address` relative to 0xFF51.
+    pub fn read(&self, address: Word) -> HalfWord {
+        match address {
+            // HDMA1-4 are write only
+            0..=3 => 0xFF,
+            // Bit 7 reads 0 while an H-blank transfer is active
+            _ if self.active => self.length,
+            _ => 0x80 | self.length,
+        }
+    }
+
+    /// Write a register, `address` relative to 0xFF51. Returns whether a
+    /// general purpose transfer was started, which the bus copies at once.
+    pub fn write(&mut self, address: Word, byte: HalfWord) -> bool {
+        match address {
+            0 => self.source = join_half_words(byte, self.source as HalfWord),
+            1 => self.source = self.source & 0xFF00 | (byte & 0xF0) as Word,
+            2 => self.destination = join_half_words(byte & 0x1F, self.destination as HalfWord),
+            3 => self.destination = self.destination & 0xFF00 | (byte & 0xF0) as Word,
+            _ => {
+                // Clearing bit 7 during an H-blank transfer cancels it
+                if self.active && byte & 0x80 == 0 {
+                    self.active = false;
+                    return false;
+                }
+
+                self.length = byte & 0x7F;
+                self.active = byte & 0x80 != 0;
+
+                return !self.active;
+            }
+        }
+
+        false
+    }
+
+    /// An H-blank transfer wants a block copied at the next H-blank.
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// Blocks of a transfer that were not copied yet.
+    pub fn remaining_blocks(&self) -> usize {
+        self.length as usize + 1
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.source);
+        state.u16(self.destination);
+        state.u8(self.length);
+        state.bool(self.active);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.source = state.u16()?;
+        self.destination = state.u16()? & 0x1FF0;
+        self.length = state.u8()? & 0x7F;
+        self.active = state.bool()?;
+
+        Ok(())
+    }
+
+    /// Source and VRAM destination of the next block, advancing the transfer.
+    pub fn next_block(&mut self) -> (Word, Word) {
+        let block = (self.source, 0x8000 | self.destination);
+
+        self.source = self.source.wrapping_add(BLOCK_SIZE);
+        self.destination = self.destination.wrapping_add(BLOCK_SIZE) & 0x1FF0;
+        self.length = self.length.wrapping_sub(1);
+        if self.length == 0xFF {
+            self.length = 0x7F;
+            self.active = false;
+        }
+
+        block
+    }
+}
@@ -0,0 +1,264 @@
+//! Infrared port (CGB RP register, 0xFF56) and transports connecting the IR
+//! LEDs of two emulator instances.
+//!
+//! Games measure the length of IR pulses by polling RP, so the devices are
+//! told the emulated time of every access, in dots since power on.
+
+use crate::apu::CLOCK_RATE;
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Bytes of an edge sent over TCP: the time as a little endian u64 and the
+/// level.
+const EDGE_SIZE: usize = 9;
+/// How far replayed edges may drift from the timing of the sender before
+/// the replay is re-anchored to the local time, 100 ms.
+const MAX_DRIFT: i64 = CLOCK_RATE as i64 / 10;
+
+/// The other side of the infrared port. `time` is the emulated time of the
+/// access in dots since power on.
+pub trait IrDevice: Send {
+    /// Our LED was switched on or off.
+    fn set_led(&mut self, on: bool, time: u64);
+
+    /// Whether the remote LED shines at us at `time`.
+    fn receiving(&self, time: u64) -> bool;
+}
+
+/// Nothing in front of the sensor.
+pub struct NoIrDevice;
+
+impl IrDevice for NoIrDevice {
+    fn set_led(&mut self, _on: bool, _time: u64) {}
+
+    fn receiving(&self, _time: u64) -> bool {
+        false
+    }
+}
+
+/// One end of an in-process IR connection, see [`InProcessIr::pair`]. The
+/// cores are run in lockstep, e.g. by [`crate::splitscreen`], so the LED
+/// state is shared as is.
+pub struct InProcessIr {
+    local: Arc<AtomicBool>,
+    remote: Arc<AtomicBool>,
+}
+
+impl InProcessIr {
+    /// Two ends facing each other, for two cores in the same process.
+    pub fn pair() -> (InProcessIr, InProcessIr) {
+        let a = Arc::new(AtomicBool::new(false));
+        let b = Arc::new(AtomicBool::new(false));
+
+        (
+            InProcessIr {
+                local: a.clone(),
+                remote: b.clone(),
+            },
+            InProcessIr {
+                local: b,
+                remote: a,
+            },
+        )
+    }
+}
+
+impl IrDevice for InProcessIr {
+    fn set_led(&mut self, on: bool, _time: u64) {
+        self.local.store(on, Ordering::SeqCst);
+    }
+
+    fn receiving(&self, _time: u64) -> bool {
+        self.remote.load(Ordering::SeqCst)
+    }
+}
+
+/// IR connection to another gbemu instance over TCP.
+///
+/// Every LED change is sent as an edge, the emulated time of the sender and
+/// the level (0 = off, 1 = on). The receiver replays the edges against its
+/// own emulated time, so the pulses keep their lengths however the network
+/// bunched them up. The two instances were started at different times, the
+/// replay is anchored to the local time by the first edge, and again when
+/// an edge is more than 100 ms early or late, e.g. after loading a state.
+pub struct TcpIr {
+    stream: TcpStream,
+    replay: RefCell<Replay>,
+}
+
+/// Edges received from the other instance, see [`TcpIr`].
+#[derive(Default)]
+struct Replay {
+    // Bytes of an edge not completely received yet
+    partial: Vec<u8>,
+    // Remote time and level
+    edges: VecDeque<(u64, bool)>,
+    // Local time minus remote time
+    offset: i64,
+    level: bool,
+}
+
+impl Replay {
+    fn receive(&mut self, bytes: &[u8]) {
+        self.partial.extend_from_slice(bytes);
+
+        let complete = self.partial.len() / EDGE_SIZE * EDGE_SIZE;
+        for edge in self.partial[..complete].chunks(EDGE_SIZE) {
+            let time = u64::from_le_bytes(edge[..8].try_into().unwrap());
+            self.edges.push_back((time, edge[8] != 0));
+        }
+        self.partial.drain(..complete);
+    }
+
+    /// The remote LED at local `time`, after the edges due by then.
+    fn level_at(&mut self, time: u64) -> bool {
+        let now = time as i64;
+
+        while let Some(&(remote, on)) = self.edges.front() {
+            let mut due = remote as i64 + self.offset;
+            if (due - now).abs() > MAX_DRIFT {
+                self.offset = now - remote as i64;
+                due = now;
+            }
+            if due > now {
+                break;
+            }
+
+            self.level = on;
+            self.edges.pop_front();
+        }
+
+        self.level
+    }
+
+    fn disconnect(&mut self) {
+        *self = Replay::default();
+    }
+}
+
+impl TcpIr {
+    pub fn listen(address: impl ToSocketAddrs) -> std::io::Result<TcpIr> {
+        let (stream, _) = TcpListener::bind(address)?.accept()?;
+        TcpIr::from_stream(stream)
+    }
+
+    pub fn connect(address: impl ToSocketAddrs) -> std::io::Result<TcpIr> {
+        TcpIr::from_stream(TcpStream::connect(address)?)
+    }
+
+    /// Use a connection made elsewhere.
+    pub fn from_stream(stream: TcpStream) -> std::io::Result<TcpIr> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+
+        Ok(TcpIr {
+            stream,
+            replay: RefCell::new(Replay::default()),
+        })
+    }
+}
+
+impl IrDevice for TcpIr {
+    fn set_led(&mut self, on: bool, time: u64) {
+        let mut edge = [0; EDGE_SIZE];
+        edge[..8].copy_from_slice(&time.to_le_bytes());
+        edge[8] = on as u8;
+
+        // A write interrupted half way would garble every following edge
+        self.stream.set_nonblocking(false).ok();
+        if let Err(e) = self.stream.write_all(&edge) {
+            log::warn!("infrared: failed to send LED state: {}", e);
+        }
+        self.stream.set_nonblocking(true).ok();
+    }
+
+    fn receiving(&self, time: u64) -> bool {
+        let mut replay = self.replay.borrow_mut();
+        let mut buf = [0; 64 * EDGE_SIZE];
+
+        loop {
+            match (&self.stream).read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => replay.receive(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("infrared: connection lost: {}", e);
+                    replay.disconnect();
+                    break;
+                }
+            }
+        }
+
+        replay.level_at(time)
+    }
+}
+
+/// RP register
+///```text
+/// Bit 7-6  Data read enable (3 = enable)
+/// Bit 1    Read data (0 = receiving IR signal, 1 = normal)
+/// Bit 0    Write data (0 = LED off, 1 = LED on)
+/// ```
+pub struct InfraredPort {
+    rp: u8,
+    device: Box<dyn IrDevice>,
+}
+
+impl Default for InfraredPort {
+    fn default() -> InfraredPort {
+        InfraredPort {
+            rp: 0,
+            device: Box::new(NoIrDevice),
+        }
+    }
+}
+
+impl InfraredPort {
+    pub fn connect(&mut self, device: Box<dyn IrDevice>) {
+        self.device = device;
+    }
+
+    /// Disconnect the device at `time`, e.g. to connect it to a new core.
+    /// Our LED goes off.
+    pub fn take_device(&mut self, time: u64) -> Box<dyn IrDevice> {
+        self.device.set_led(false, time);
+        std::mem::replace(&mut self.device, Box::new(NoIrDevice))
+    }
+
+    pub fn read(&self, time: u64) -> u8 {
+        let receiving = self.rp & 0xC0 == 0xC0 && self.device.receiving(time);
+
+        // Bits 2-5 are unused and read as 1
+        self.rp | 0x3C | if receiving { 0x00 } else { 0x02 }
+    }
+
+    /// The RP register. The LED state is sent to the device again, the
+    /// other side may have missed the change.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.rp);
+    }
+
+    /// `time` is the emulated time of the state.
+    pub fn load_state(&mut self, state: &mut StateReader, time: u64) -> Result<()> {
+        self.rp = state.u8()? & 0xC1;
+        self.device.set_led(self.rp & 0x01 != 0, time);
+
+        Ok(())
+    }
+
+    pub fn write(&mut self, byte: u8, time: u64) {
+        let led_changed = (self.rp ^ byte) & 0x01 != 0;
+        self.rp = byte & 0xC1;
+
+        if led_changed {
+            self.device.set_led(byte & 0x01 != 0, time);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::state::{StateReader, StateWriter};
+use crate::{HalfWord, Word};
+use anyhow::Result;
+
+/// Interrupt sources, in priority order.
+///```text
+/// Bit  Source    Vector
+/// 0    VBlank    0x40
+/// 1    LCD STAT  0x48
+/// 2    Timer     0x50
+/// 3    Serial    0x58
+/// 4    Joypad    0x60
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank = 0,
+    LcdStat = 1,
+    Timer = 2,
+    Serial = 3,
+    Joypad = 4,
+}
+
+impl Interrupt {
+    const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::LcdStat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    pub fn bit(self) -> HalfWord {
+        1 << self as u8
+    }
+
+    /// Address the CPU jumps to when servicing this interrupt.
+    pub fn vector(self) -> Word {
+        0x40 + 8 * self as Word
+    }
+}
+
+/// IF (interrupt flags) and IE (interrupt enable) registers.
+#[derive(Debug, Default)]
+pub struct Interrupts {
+    pub flags: HalfWord,
+    pub enable: HalfWord,
+}
+
+impl Interrupts {
+    pub fn request(&mut self, interrupt: Interrupt) {
+        self.flags |= interrupt.bit();
+    }
+
+    /// Interrupts that are both requested and enabled.
+    pub fn pending(&self) -> HalfWord {
+        self.flags & self.enable & 0x1F
+    }
+
+    /// When several interrupts are pending at once, the lowest-numbered bit wins.
+    pub fn highest_priority(&self) -> Option<Interrupt> {
+        let pending = self.pending();
+
+        Interrupt::ALL
+            .iter()
+            .copied()
+            .find(|interrupt| pending & interrupt.bit() != 0)
+    }
+
+    /// Take the highest priority pending interrupt for servicing, clearing
+    /// only its own IF bit. Other pending interrupts stay requested.
+    pub fn acknowledge(&mut self) -> Option<Interrupt> {
+        let interrupt = self.highest_priority()?;
+        self.flags &= !interrupt.bit();
+
+        Some(interrupt)
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.flags);
+        state.u8(self.enable);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.flags = state.u8()?;
+        self.enable = state.u8()?;
+
+        Ok(())
+    }
+}
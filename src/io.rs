@@ -0,0 +1,107 @@
+//! Addresses of the I/O registers (0xFF00-0xFF7F) and IE.
+//! Ref https://gbdev.io/pandocs/Hardware_Reg_List.html
+
+use crate::{HalfWord, Word};
+
+pub const P1: Word = 0xFF00;
+pub const SB: Word = 0xFF01;
+pub const SC: Word = 0xFF02;
+pub const DIV: Word = 0xFF04;
+pub const TIMA: Word = 0xFF05;
+pub const TMA: Word = 0xFF06;
+pub const TAC: Word = 0xFF07;
+pub const IF: Word = 0xFF0F;
+
+// Sound
+pub const NR10: Word = 0xFF10;
+pub const NR11: Word = 0xFF11;
+pub const NR12: Word = 0xFF12;
+pub const NR13: Word = 0xFF13;
+pub const NR14: Word = 0xFF14;
+pub const NR21: Word = 0xFF16;
+pub const NR22: Word = 0xFF17;
+pub const NR23: Word = 0xFF18;
+pub const NR24: Word = 0xFF19;
+pub const NR30: Word = 0xFF1A;
+pub const NR31: Word = 0xFF1B;
+pub const NR32: Word = 0xFF1C;
+pub const NR33: Word = 0xFF1D;
+pub const NR34: Word = 0xFF1E;
+pub const NR41: Word = 0xFF20;
+pub const NR42: Word = 0xFF21;
+pub const NR43: Word = 0xFF22;
+pub const NR44: Word = 0xFF23;
+pub const NR50: Word = 0xFF24;
+pub const NR51: Word = 0xFF25;
+pub const NR52: Word = 0xFF26;
+pub const WAVE_RAM: Word = 0xFF30;
+pub const WAVE_RAM_END: Word = 0xFF3F;
+
+// LCD
+pub const LCDC: Word = 0xFF40;
+pub const STAT: Word = 0xFF41;
+pub const SCY: Word = 0xFF42;
+pub const SCX: Word = 0xFF43;
+pub const LY: Word = 0xFF44;
+pub const LYC: Word = 0xFF45;
+pub const DMA: Word = 0xFF46;
+pub const BGP: Word = 0xFF47;
+pub const OBP0: Word = 0xFF48;
+pub const OBP1: Word = 0xFF49;
+pub const WY: Word = 0xFF4A;
+pub const WX: Word = 0xFF4B;
+// Unmaps the boot ROM
+pub const BOOT: Word = 0xFF50;
+
+// CGB only
+pub const KEY1: Word = 0xFF4D;
+pub const VBK: Word = 0xFF4F;
+pub const HDMA1: Word = 0xFF51;
+pub const HDMA2: Word = 0xFF52;
+pub const HDMA3: Word = 0xFF53;
+pub const HDMA4: Word = 0xFF54;
+pub const HDMA5: Word = 0xFF55;
+pub const RP: Word = 0xFF56;
+pub const BCPS: Word = 0xFF68;
+pub const BCPD: Word = 0xFF69;
+pub const OCPS: Word = 0xFF6A;
+pub const OCPD: Word = 0xFF6B;
+pub const SVBK: Word = 0xFF70;
+pub const PCM12: Word = 0xFF76;
+pub const PCM34: Word = 0xFF77;
+
+pub const IE: Word = 0xFFFF;
+
+/// Bits of an I/O register that are not implemented in hardware and always
+/// read as 1. Unmapped registers read as 0xFF.
+/// Ref https://gbdev.io/pandocs/Hardware_Reg_List.html
+pub fn unused_bits(address: Word) -> HalfWord {
+    match address {
+        P1 => 0xC0,
+        SC => 0x7E,
+        TAC => 0xF8,
+        IF => 0xE0,
+        // Write-only sound registers read as 1 as well
+        NR10 => 0x80,
+        NR11 | NR21 => 0x3F,
+        NR13 | NR23 | NR31 | NR33 | NR41 => 0xFF,
+        NR14 | NR24 | NR34 | NR44 => 0xBF,
+        NR30 => 0x7F,
+        NR32 => 0x9F,
+        NR12 | NR22 | NR42 | NR43 | NR50 | NR51 => 0x00,
+        NR52 => 0x70,
+        WAVE_RAM..=WAVE_RAM_END => 0x00,
+        STAT => 0x80,
+        LCDC | SCY | SCX | LY | LYC | DMA | BGP | OBP0 | OBP1 | WY | WX => 0x00,
+        RP => 0x3C,
+        KEY1 => 0x7E,
+        VBK => 0xFE,
+        HDMA5 => 0x00,
+        BCPS | OCPS => 0x40,
+        BCPD | OCPD => 0x00,
+        SVBK => 0xF8,
+        PCM12 | PCM34 => 0x00,
+        IE => 0x00,
+        _ => 0xFF,
+    }
+}
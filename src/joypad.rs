@@ -0,0 +1,128 @@
+use crate::state::{StateReader, StateWriter};
+use crate::HalfWord;
+use anyhow::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+/// P1/JOYP register (0xFF00)
+///```text
+/// Bit 5  P15 Select action buttons  (0 = select)
+/// Bit 4  P14 Select direction keys  (0 = select)
+/// Bit 3  P13 Down  or Start         (0 = pressed)
+/// Bit 2  P12 Up    or Select        (0 = pressed)
+/// Bit 1  P11 Left  or B             (0 = pressed)
+/// Bit 0  P10 Right or A             (0 = pressed)
+/// ```
+/// Ref https://gbdev.io/pandocs/Joypad_Input.html
+#[derive(Debug)]
+pub struct Joypad {
+    // Bits 5-4 as last written
+    select: HalfWord,
+    // Pressed buttons, 1 = pressed, in P10-P13 order
+    directions: HalfWord,
+    actions: HalfWord,
+}
+
+impl Default for Joypad {
+    fn default() -> Joypad {
+        Joypad {
+            select: 0x30,
+            directions: 0,
+            actions: 0,
+        }
+    }
+}
+
+impl Joypad {
+    pub fn read(&self) -> HalfWord {
+        // Bits 7-6 are unused and read as 1
+        0xC0 | self.select | self.lines()
+    }
+
+    /// Returns whether the joypad interrupt is requested.
+    pub fn write(&mut self, byte: HalfWord) -> bool {
+        let before = self.lines();
+        self.select = byte & 0x30;
+
+        Joypad::falling_edge(before, self.lines())
+    }
+
+    /// Returns whether the joypad interrupt is requested.
+    pub fn set_button_state(&mut self, button: Button, pressed: bool) -> bool {
+        let before = self.lines();
+
+        let (keys, bit) = match button {
+            Button::Right => (&mut self.directions, 0),
+            Button::Left => (&mut self.directions, 1),
+            Button::Up => (&mut self.directions, 2),
+            Button::Down => (&mut self.directions, 3),
+            Button::A => (&mut self.actions, 0),
+            Button::B => (&mut self.actions, 1),
+            Button::Select => (&mut self.actions, 2),
+            Button::Start => (&mut self.actions, 3),
+        };
+        if pressed {
+            *keys |= 1 << bit;
+        } else {
+            *keys &= !(1 << bit);
+        }
+
+        Joypad::falling_edge(before, self.lines())
+    }
+
+    /// The pressed buttons, a bit each: Right, Left, Up, Down, A, B, Select,
+    /// Start from bit 0.
+    pub fn buttons(&self) -> HalfWord {
+        self.actions << 4 | self.directions
+    }
+
+    /// Press the buttons of [`Joypad::buttons`] and release the others.
+    /// Returns whether the joypad interrupt is requested.
+    pub fn set_buttons(&mut self, buttons: HalfWord) -> bool {
+        let before = self.lines();
+        self.directions = buttons & 0x0F;
+        self.actions = buttons >> 4;
+
+        Joypad::falling_edge(before, self.lines())
+    }
+
+    /// The selected rows. The pressed buttons are input of the host, they
+    /// are not restored.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.select);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.select = state.u8()? & 0x30;
+
+        Ok(())
+    }
+
+    /// State of P10-P13 for the selected rows, 0 = pressed.
+    fn lines(&self) -> HalfWord {
+        let mut pressed = 0;
+        if self.select & 0x10 == 0 {
+            pressed |= self.directions;
+        }
+        if self.select & 0x20 == 0 {
+            pressed |= self.actions;
+        }
+
+        !pressed & 0x0F
+    }
+
+    /// The interrupt fires when any input line goes from high to low.
+    fn falling_edge(before: HalfWord, after: HalfWord) -> bool {
+        before & !after != 0
+    }
+}
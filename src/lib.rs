@@ -2,20 +2,60 @@
 
 //! Game Boy emulator core implementation.
 
-use std::sync::{Arc, Mutex};
-
+pub mod apu;
+#[cfg(feature = "audio")]
+pub mod audio_output;
+pub mod audio_ring;
+pub mod autosplit;
 pub mod bus;
 pub mod cartridge;
-pub(crate) mod cpu;
+pub mod cheat;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
 pub mod emulator;
+pub mod event_viewer;
+pub mod fault;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod font;
+pub mod frame_hash;
+pub mod frame_timer;
+pub mod gif;
 pub mod gpu;
+pub mod hdma;
+pub mod infrared;
+pub mod interrupt;
+pub mod io;
+pub mod joypad;
+pub mod licensee;
 pub(crate) mod logger;
+pub mod mbc;
+pub mod movie;
+pub mod png;
 pub mod ram;
+pub mod recorder;
+pub mod resampler;
+pub mod scale;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod serial;
+pub mod speed_meter;
+#[cfg(feature = "window")]
+pub mod splitscreen;
+pub mod state;
+pub mod storage;
+pub mod telemetry;
+pub mod timer;
+pub mod trace;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod wav;
+
+pub use cpu::CpuState;
 
 pub(crate) type Word = u16;
 pub(crate) type HalfWord = u8;
-pub type SharedBus = Arc<Mutex<bus::Bus>>;
-pub type SharedGpu = Arc<Mutex<gpu::Gpu>>;
 
 pub(crate) fn join_half_words(upper: HalfWord, lower: HalfWord) -> Word {
     (upper as u16) << 8 ^ lower as u16
@@ -25,5 +65,5 @@ pub(crate) fn split_word(word: Word) -> (HalfWord, HalfWord) {
     ((word >> 8) as HalfWord, (word & 0x00FF) as HalfWord)
 }
 
-const SCREEN_WIDTH: usize = 160;
-const SCREEN_HEIGHT: usize = 144;
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
@@ -1,7 +1,171 @@
-use gbemu::emulator::Emulator;
-use log::info;
+use gbemu::autosplit::{self, AutoSplitter};
+use gbemu::bus::BootRom;
+use gbemu::cartridge::{self, CartridgeHeader, CgbFlag, ChecksumMismatch, Destination, Model};
+use gbemu::emulator::{Emulator, Pacing, AUTOSAVE_INTERVAL};
+use gbemu::frame_timer::{FrameTimer, FRAME_RATE};
+use gbemu::gpu::{Accuracy, Shades};
+use gbemu::infrared::{InProcessIr, TcpIr};
+use gbemu::mbc;
+use gbemu::scale::{Filter, Scaler};
+use gbemu::serial::InProcessLink;
+use log::{info, warn};
 
 use anyhow::Result;
+use std::time::{Duration, Instant};
+
+/// Largest `--scale` factor, 960x864.
+const MAX_SCALE: usize = 6;
+
+#[derive(Default)]
+struct Options {
+    filename: String,
+    model: Option<Model>,
+    accuracy: Option<Accuracy>,
+    shades: Option<Shades>,
+    scale: Option<usize>,
+    filter: Option<Filter>,
+    boot_rom: Option<String>,
+    // Game Genie or GameShark codes
+    cheats: Vec<String>,
+    // Refuse images with a wrong global checksum, not only a wrong header checksum
+    strict_checksum: bool,
+    tui: bool,
+    // Record unimplemented opcodes instead of panicking on the first one
+    coverage: bool,
+    // Output file of per-frame hashes, "-" for stdout
+    frame_hash: Option<String>,
+    // Wait for another instance to connect its infrared port
+    ir_listen: Option<String>,
+    // Connect the infrared port to a listening instance
+    ir_connect: Option<String>,
+    // Auto-splitter rules, sent to the LiveSplit server at `livesplit`
+    autosplit: Option<String>,
+    livesplit: Option<String>,
+    // Run headless and uncapped for this many seconds and print the speed
+    bench: Option<f64>,
+    // ROM of a second core linked by cable, side by side in the window
+    link: Option<String>,
+    // Movie file to record the input to from power on, or to play back
+    record_movie: Option<String>,
+    play_movie: Option<String>,
+    // Bit-identical runs: emulated RTC, no battery saves, no infrared over TCP
+    deterministic: bool,
+    // Rhai script hooked into the emulation
+    script: Option<String>,
+    // Debugger REPL on the terminal instead of the window
+    debug: bool,
+    // Play the sound and pace the emulation by the audio device
+    audio_pacing: bool,
+}
+
+fn parse_args() -> Result<Options> {
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tui" => options.tui = true,
+            "--debug" => options.debug = true,
+            "--coverage" => options.coverage = true,
+            "--strict-checksum" => options.strict_checksum = true,
+            "--deterministic" => options.deterministic = true,
+            "--model" => match args.next() {
+                Some(model) => options.model = Some(model.parse()?),
+                None => anyhow::bail!("--model requires a model (dmg, sgb, cgb)"),
+            },
+            "--ppu" => match args.next() {
+                Some(accuracy) => options.accuracy = Some(accuracy.parse()?),
+                None => anyhow::bail!("--ppu requires a renderer (scanline, fifo)"),
+            },
+            "--palette" => match args.next() {
+                Some(shades) => options.shades = Some(shades.parse()?),
+                None => anyhow::bail!(
+                    "--palette requires gray, green, pocket or 4 RRGGBB colors, lightest first"
+                ),
+            },
+            "--scale" => match args.next().map(|factor| factor.parse()) {
+                Some(Ok(factor)) if (1..=MAX_SCALE).contains(&factor) => {
+                    options.scale = Some(factor)
+                }
+                _ => anyhow::bail!("--scale requires an integer factor from 1 to {}", MAX_SCALE),
+            },
+            "--filter" => match args.next() {
+                Some(filter) => options.filter = Some(filter.parse()?),
+                None => anyhow::bail!("--filter requires a filter (nearest, scanlines, scale2x)"),
+            },
+            "--boot-rom" => match args.next() {
+                Some(path) => options.boot_rom = Some(path),
+                None => anyhow::bail!("--boot-rom requires a boot ROM image"),
+            },
+            "--cheat" => match args.next() {
+                Some(code) => options.cheats.push(code),
+                None => anyhow::bail!("--cheat requires a Game Genie or GameShark code"),
+            },
+            "--pacing" => match args.next().as_deref() {
+                Some("frames") => options.audio_pacing = false,
+                Some("audio") => options.audio_pacing = true,
+                _ => anyhow::bail!("--pacing requires frames or audio"),
+            },
+            "--frame-hash" => match args.next() {
+                Some(path) => options.frame_hash = Some(path),
+                None => anyhow::bail!("--frame-hash requires an output path (or - for stdout)"),
+            },
+            "--ir-listen" => match args.next() {
+                Some(address) => options.ir_listen = Some(address),
+                None => anyhow::bail!("--ir-listen requires an address"),
+            },
+            "--ir-connect" => match args.next() {
+                Some(address) => options.ir_connect = Some(address),
+                None => anyhow::bail!("--ir-connect requires an address"),
+            },
+            "--autosplit" => match args.next() {
+                Some(path) => options.autosplit = Some(path),
+                None => anyhow::bail!("--autosplit requires a rule file"),
+            },
+            "--livesplit" => match args.next() {
+                Some(address) => options.livesplit = Some(address),
+                None => anyhow::bail!("--livesplit requires an address"),
+            },
+            "--bench" => match args.next().map(|seconds| seconds.parse()) {
+                Some(Ok(seconds)) if seconds > 0.0 && seconds < f64::INFINITY => {
+                    options.bench = Some(seconds)
+                }
+                _ => anyhow::bail!("--bench requires a duration in seconds"),
+            },
+            "--record-movie" => match args.next() {
+                Some(path) => options.record_movie = Some(path),
+                None => anyhow::bail!("--record-movie requires an output path"),
+            },
+            "--play-movie" => match args.next() {
+                Some(path) => options.play_movie = Some(path),
+                None => anyhow::bail!("--play-movie requires a movie file"),
+            },
+            "--script" => match args.next() {
+                Some(path) => options.script = Some(path),
+                None => anyhow::bail!("--script requires a script file"),
+            },
+            "--link" => match args.next() {
+                Some(path) => options.link = Some(path),
+                None => anyhow::bail!("--link requires the ROM image of the second player"),
+            },
+            _ if arg.starts_with("--") => anyhow::bail!("unknown option {}", arg),
+            _ => options.filename = arg,
+        }
+    }
+
+    if options.filename.is_empty() {
+        anyhow::bail!("Plese speficy filepath")
+    }
+    if options.debug && (options.tui || options.link.is_some()) {
+        anyhow::bail!("--debug can not be combined with --tui or --link");
+    }
+    // The other instance sends at times of the host
+    if options.deterministic && (options.ir_listen.is_some() || options.ir_connect.is_some()) {
+        anyhow::bail!("--deterministic can not be combined with infrared over TCP");
+    }
+
+    Ok(options)
+}
 
 fn main() -> Result<()> {
     if std::env::var("RUST_LOG").is_err() {
@@ -9,18 +173,344 @@ fn main() -> Result<()> {
     }
     env_logger::init();
 
-    let args: Vec<String> = std::env::args().collect();
-    if args.is_empty() {
-        anyhow::bail!("Plese speficy filepath")
+    if std::env::args().nth(1).as_deref() == Some("rominfo") {
+        return match std::env::args().nth(2) {
+            Some(path) => print_rom_info(&path),
+            None => anyhow::bail!("rominfo requires a ROM image"),
+        };
     }
 
-    let filename = &args[1];
-    info!("loading file {}", filename);
-    let bytes = std::fs::read(filename).unwrap();
+    let options = parse_args()?;
+
+    info!("loading file {}", options.filename);
+    let bytes = std::fs::read(&options.filename).unwrap();
+
+    let mismatches = cartridge::checksum_mismatches(&bytes);
+    let fatal = mismatches
+        .iter()
+        .any(|m| options.strict_checksum || matches!(m, ChecksumMismatch::Header { .. }));
+    if fatal {
+        let reasons: Vec<String> = mismatches.iter().map(|m| m.to_string()).collect();
+        anyhow::bail!(
+            "{} is corrupt or truncated: {}",
+            options.filename,
+            reasons.join(", ")
+        );
+    }
+    for mismatch in &mismatches {
+        warn!("{}", mismatch);
+    }
+
+    let model = options.model.unwrap_or(Model::Dmg);
+    for warning in cartridge::header_warnings(&bytes, model) {
+        warn!("{}", warning);
+    }
 
     info!("start emulator");
-    let emu = Emulator::from_rom_byte(bytes);
+    let mut emu = Emulator::from_rom_byte(bytes)?;
+    info!("title: {}", emu.cartridge_header().title);
+
+    // Report what the ROM needed from the emulator before dying on a todo!()
+    let telemetry = emu.telemetry().clone();
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        eprintln!("{}", telemetry.summary());
+        default_hook(info);
+    }));
+
+    emu.set_model(model);
+    emu.set_skip_unimplemented(options.coverage);
+    if options.deterministic {
+        info!("deterministic mode: emulated RTC, battery saves disabled");
+        emu.set_deterministic(true);
+    }
+    if let Some(accuracy) = options.accuracy {
+        emu.set_ppu_accuracy(accuracy);
+    }
+    if let Some(shades) = options.shades {
+        emu.set_shades(shades);
+    }
+    if options.scale.is_some() || options.filter.is_some() {
+        let default = Scaler::default();
+        let factor = options.scale.unwrap_or_else(|| default.factor());
+        emu.set_scaler(Scaler::new(factor, options.filter.unwrap_or_default())?);
+    }
+
+    let stem = std::path::Path::new(&options.filename)
+        .file_stem()
+        .map_or("save".into(), |s| s.to_string_lossy());
+    emu.set_state_name(&stem);
+    if emu.cartridge_header().has_battery() {
+        emu.set_battery_save(&format!("{}.sav", stem))?;
+    }
+
+    for code in &options.cheats {
+        let cheat = emu.add_cheat(code)?;
+        info!("cheat {}: {}", code, cheat);
+    }
+
+    if let Some(path) = &options.boot_rom {
+        emu.set_boot_rom(BootRom::from_file(path)?);
+    }
+
+    match options.frame_hash.as_deref() {
+        Some("-") => emu.set_frame_hash_output(Box::new(std::io::stdout())),
+        Some(path) => emu.set_frame_hash_output(Box::new(std::fs::File::create(path)?)),
+        None => {}
+    }
+
+    if let Some(address) = &options.ir_listen {
+        info!("waiting for infrared connection on {}", address);
+        emu.connect_ir(Box::new(TcpIr::listen(address.as_str())?));
+    } else if let Some(address) = &options.ir_connect {
+        emu.connect_ir(Box::new(TcpIr::connect(address.as_str())?));
+    }
+
+    if let Some(path) = &options.autosplit {
+        let rules = autosplit::parse_rules(&std::fs::read_to_string(path)?)?;
+        let address = options.livesplit.as_deref().unwrap_or("127.0.0.1:16834");
+        info!("connecting to LiveSplit server on {}", address);
+        let stream = std::net::TcpStream::connect(address)?;
+        emu.set_auto_splitter(Some(AutoSplitter::new(rules, Box::new(stream))));
+    }
+
+    if let Some(path) = &options.script {
+        #[cfg(feature = "scripting")]
+        emu.set_script(Some(gbemu::script::Script::load(path)?))?;
+
+        #[cfg(not(feature = "scripting"))]
+        anyhow::bail!(
+            "gbemu was built without the scripting feature, can not run {}",
+            path
+        );
+    }
+
+    if let Some(path) = &options.play_movie {
+        emu.play_movie(path)?;
+        info!("playing movie {}", path);
+    }
+    if let Some(path) = &options.record_movie {
+        emu.start_movie_recording(path)?;
+        info!("recording movie {}", path);
+    }
+
+    if let Some(seconds) = options.bench {
+        return bench(&mut emu, seconds);
+    }
+
+    if options.debug {
+        return gbemu::debugger::start(emu);
+    }
+
+    if let Some(path) = &options.link {
+        if options.tui {
+            anyhow::bail!("--link needs the window, not --tui");
+        }
+        if options.audio_pacing {
+            anyhow::bail!("--link paces by frames, not --pacing audio");
+        }
+
+        let mut other = Emulator::from_rom_byte(std::fs::read(path)?)?;
+        info!("linked with {}", other.cartridge_header().title);
+        other.set_model(model);
+        other.set_skip_unimplemented(options.coverage);
+        other.set_deterministic(options.deterministic);
+        if let Some(accuracy) = options.accuracy {
+            other.set_ppu_accuracy(accuracy);
+        }
+        if let Some(shades) = options.shades {
+            other.set_shades(shades);
+        }
+
+        // Keep apart the saves of two copies of the same game
+        let other_stem = std::path::Path::new(path)
+            .file_stem()
+            .map_or("save".into(), |s| s.to_string_lossy());
+        let other_stem = if other_stem == stem {
+            format!("{}-2", other_stem)
+        } else {
+            other_stem.into_owned()
+        };
+        other.set_state_name(&other_stem);
+        if other.cartridge_header().has_battery() {
+            other.set_battery_save(&format!("{}.sav", other_stem))?;
+        }
+
+        let (cable, other_cable) = InProcessLink::pair();
+        emu.connect_link(Box::new(cable));
+        other.connect_link(Box::new(other_cable));
+        // Face the IR ports of the two cores, unless ours goes over TCP
+        if options.ir_listen.is_none() && options.ir_connect.is_none() {
+            let (ir, other_ir) = InProcessIr::pair();
+            emu.connect_ir(Box::new(ir));
+            other.connect_ir(Box::new(other_ir));
+        }
+        return gbemu::splitscreen::start(emu, other);
+    }
+
+    emu.set_autosave(Some(AUTOSAVE_INTERVAL));
+
+    // Plays while the emulator runs
+    let _audio = if options.audio_pacing {
+        Some(pace_by_audio(&mut emu)?)
+    } else {
+        emu.set_pacing(Pacing::Frames(FrameTimer::new()));
+        None
+    };
+
+    if options.tui {
+        #[cfg(feature = "tui")]
+        return gbemu::tui::start(emu);
+
+        #[cfg(not(feature = "tui"))]
+        anyhow::bail!("gbemu was built without the tui feature");
+    }
+
     emu.start()?;
 
     Ok(())
 }
+
+/// `--pacing audio`: play the sound on the default output device and run as
+/// fast as it plays.
+#[cfg(feature = "audio")]
+fn pace_by_audio(emu: &mut Emulator) -> Result<gbemu::audio_output::AudioOutput> {
+    let output = gbemu::audio_output::AudioOutput::open()?;
+    emu.set_pacing(Pacing::Audio(output.ring()));
+    Ok(output)
+}
+
+#[cfg(not(feature = "audio"))]
+fn pace_by_audio(_emu: &mut Emulator) -> Result<()> {
+    anyhow::bail!("gbemu was built without the audio feature, can not pace by audio")
+}
+
+/// `--bench`: run as fast as possible for `seconds` of wall-clock time, and
+/// print the emulated frames per second and clock rate.
+fn bench(emu: &mut Emulator, seconds: f64) -> Result<()> {
+    let duration = Duration::from_secs_f64(seconds);
+    info!("running uncapped for {:.1} seconds", seconds);
+
+    let start_cycles = emu.cycles();
+    let start = Instant::now();
+    let mut frames = 0;
+    while start.elapsed() < duration {
+        emu.run_frame()?;
+        frames += 1;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let fps = frames as f64 / elapsed;
+    println!("frames:  {} in {:.2} s", frames, elapsed);
+    println!(
+        "fps:     {:.1} ({:.0}% speed)",
+        fps,
+        fps / FRAME_RATE * 100.0
+    );
+    println!(
+        "clock:   {:.2} MHz",
+        (emu.cycles() - start_cycles) as f64 / elapsed / 1e6
+    );
+
+    Ok(())
+}
+
+/// `gbemu rominfo <rom>`: print the cartridge header without running it.
+fn print_rom_info(path: &str) -> Result<()> {
+    let rom = std::fs::read(path)?;
+    if rom.len() < 0x0150 {
+        anyhow::bail!(
+            "{} is {} bytes, too small to contain a cartridge header",
+            path,
+            rom.len()
+        );
+    }
+    let header = CartridgeHeader::parse(&rom);
+
+    let size = |bytes: usize| match bytes {
+        0 => "none".to_string(),
+        _ if bytes < 1024 => format!("{} bytes", bytes),
+        _ => format!("{} KiB", bytes / 1024),
+    };
+
+    println!("title:            {}", header.title);
+    println!(
+        "cartridge type:   {:02X} {}{}",
+        header.cartridge_type,
+        header.cartridge_type_name().unwrap_or("(unknown)"),
+        match mbc::for_cartridge(&header, &rom) {
+            Some(_) => "",
+            None => " (not emulated)",
+        }
+    );
+    match header.rom_size {
+        Some(bytes) => println!(
+            "ROM size:         {} ({} banks)",
+            size(bytes),
+            bytes / mbc::ROM_BANK_SIZE
+        ),
+        None => println!("ROM size:         unknown code {:02X}", rom[0x0148]),
+    }
+    if header.rom_size != Some(rom.len()) {
+        println!("image size:       {} bytes", rom.len());
+    }
+    match header.ram_size {
+        Some(bytes) => println!("RAM size:         {}", size(bytes)),
+        None => println!("RAM size:         unknown code {:02X}", rom[0x0149]),
+    }
+    println!("battery:          {}", yes_no(header.has_battery()));
+    println!(
+        "CGB:              {}",
+        match header.cgb {
+            CgbFlag::None => "no",
+            CgbFlag::Enhanced => "enhanced, also runs on DMG",
+            CgbFlag::Only => "only",
+        }
+    );
+    println!("SGB:              {}", yes_no(header.sgb));
+    println!(
+        "destination:      {}",
+        match header.destination {
+            Destination::Japan => "Japan",
+            Destination::Overseas => "overseas",
+        }
+    );
+    println!("licensee:         {}", header.licensee);
+
+    let mismatches = cartridge::checksum_mismatches(&rom);
+    let header_mismatch = mismatches.iter().find_map(|m| match m {
+        ChecksumMismatch::Header { computed, .. } => Some(format!("{:02X}", computed)),
+        _ => None,
+    });
+    let global_mismatch = mismatches.iter().find_map(|m| match m {
+        ChecksumMismatch::Global { computed, .. } => Some(format!("{:04X}", computed)),
+        _ => None,
+    });
+    println!(
+        "header checksum:  {:02X} {}",
+        header.header_checksum,
+        checksum_status(header_mismatch)
+    );
+    println!(
+        "global checksum:  {:04X} {}",
+        header.global_checksum,
+        checksum_status(global_mismatch)
+    );
+
+    Ok(())
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn checksum_status(computed: Option<String>) -> String {
+    match computed {
+        Some(computed) => format!("bad, computed {}", computed),
+        None => "ok".to_string(),
+    }
+}
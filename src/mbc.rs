@@ -0,0 +1,1001 @@
+//! Memory bank controllers, the mappers that switch ROM and RAM banks.
+//!
+//! Mappers the crate does not know can be plugged in by implementing [`Mbc`]
+//! and passing it to [`Cartridge::with_mbc`](crate::cartridge::Cartridge::with_mbc).
+
+use crate::cartridge::CartridgeHeader;
+use crate::state::{StateReader, StateWriter};
+use crate::{HalfWord, Word};
+use anyhow::Result;
+use std::convert::TryInto;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+pub trait Mbc: Send {
+    /// ROM bank mapped at `address` (0x0000-0x7FFF).
+    fn rom_bank(&self, address: Word) -> usize;
+
+    /// RAM bank mapped at 0xA000-0xBFFF, `None` while RAM is disabled.
+    fn ram_bank(&self) -> Option<usize>;
+
+    /// Write a mapper register in 0x0000-0x7FFF.
+    fn write_register(&mut self, address: Word, byte: HalfWord);
+
+    /// External RAM, to persist battery saves.
+    fn ram(&self) -> &[u8];
+
+    fn ram_mut(&mut self) -> &mut [u8];
+
+    /// Read 0x0000-0x7FFF from the `rom` image.
+    fn read_rom(&self, rom: &[u8], address: Word) -> HalfWord {
+        let offset = rom_offset(rom.len(), self.rom_bank(address), address);
+
+        // Images smaller than the address space read as an unconnected bus
+        rom.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    /// Read 0xA000-0xBFFF, 0xFF when RAM is disabled or missing.
+    fn read_ram(&self, address: Word) -> HalfWord {
+        match self.ram_offset(address) {
+            Some(offset) => self.ram()[offset],
+            None => 0xFF,
+        }
+    }
+
+    /// Write a register in 0x0000-0x7FFF or RAM in 0xA000-0xBFFF.
+    fn write(&mut self, address: Word, byte: HalfWord) {
+        if address < 0x8000 {
+            self.write_register(address, byte)
+        } else if let Some(offset) = self.ram_offset(address) {
+            self.ram_mut()[offset] = byte
+        }
+    }
+
+    /// Contents of the battery save file, the external RAM by default.
+    fn save_data(&self) -> Vec<u8> {
+        self.ram().to_vec()
+    }
+
+    /// Restore a battery save written by `save_data` or another emulator.
+    fn load_save_data(&mut self, data: &[u8]) {
+        let ram = self.ram_mut();
+        let len = ram.len().min(data.len());
+        ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    /// Write the mapper registers and RAM to a save state. Only the RAM by
+    /// default, mappers with registers should override this and
+    /// `load_state`.
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(self.ram());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        state.bytes_into(self.ram_mut())
+    }
+
+    /// Tilt sensor input for cartridges with an accelerometer (MBC7), from -1.0
+    /// to 1.0 on both axes. Positive x tilts right, positive y tilts down.
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+
+    /// The rumble motor is on, for cartridges with one (MBC5 rumble carts).
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    /// Run the real time clock, for cartridges with one, on `seconds` of
+    /// emulated time since power on instead of the host clock. `None` goes
+    /// back to the host clock.
+    fn set_emulated_time(&mut self, _seconds: Option<u64>) {}
+
+    /// Offset into `ram()` of `address` (0xA000-0xBFFF), `None` when nothing
+    /// responds.
+    fn ram_offset(&self, address: Word) -> Option<usize> {
+        let bank = self.ram_bank()?;
+        if self.ram().is_empty() {
+            return None;
+        }
+
+        let offset = (address as usize - 0xA000) % RAM_BANK_SIZE;
+        Some((bank * RAM_BANK_SIZE + offset) % self.ram().len())
+    }
+}
+
+/// Offset into a ROM image of `rom_len` bytes of `address` in `bank`.
+/// Bank numbers wrap around at the ROM size.
+pub fn rom_offset(rom_len: usize, bank: usize, address: Word) -> usize {
+    let banks = (rom_len / ROM_BANK_SIZE).max(1);
+
+    bank % banks * ROM_BANK_SIZE + address as usize % ROM_BANK_SIZE
+}
+
+/// The built in mapper for the cartridge type (0x0147) in `header`, `None`
+/// for mappers that are not implemented.
+pub fn for_cartridge(header: &CartridgeHeader, rom: &[u8]) -> Option<Box<dyn Mbc>> {
+    let ram = vec![0; header.ram_size.unwrap_or(0)];
+
+    match header.cartridge_type {
+        0x00 | 0x08 | 0x09 => Some(Box::new(NoMbc { ram })),
+        0x01..=0x03 if is_mbc1_multicart(rom) => Some(Box::new(Mbc1::multicart(ram))),
+        0x01..=0x03 => Some(Box::new(Mbc1::new(ram))),
+        0x0F | 0x10 => Some(Box::new(Mbc3::new(ram, Some(Rtc::default())))),
+        0x11..=0x13 => Some(Box::new(Mbc3::new(ram, None))),
+        0x19..=0x1B => Some(Box::new(Mbc5::new(ram))),
+        0x1C..=0x1E => Some(Box::new(Mbc5::with_rumble(ram))),
+        0x22 => Some(Box::new(Mbc7::default())),
+        _ => None,
+    }
+}
+
+/// MBC1 multicarts are 1 MiB images of four 256 KiB games, each starting
+/// with its own header. There is no flag for them, but the second game's
+/// header at bank 0x10 repeats the Nintendo logo.
+/// Ref https://gbdev.io/pandocs/MBC1.html#mbc1m-1-mib-multi-game-compilation-carts
+fn is_mbc1_multicart(rom: &[u8]) -> bool {
+    const LOGO: std::ops::Range<usize> = 0x0104..0x0134;
+    const SECOND_GAME: usize = 0x10 * ROM_BANK_SIZE;
+
+    rom.len() == 0x100000 && rom[LOGO] == rom[SECOND_GAME + LOGO.start..SECOND_GAME + LOGO.end]
+}
+
+/// 32 KiB ROM mapped as is, optionally with up to 8 KiB of RAM.
+/// Writes to the ROM area are ignored.
+#[derive(Debug)]
+pub struct NoMbc {
+    ram: Vec<u8>,
+}
+
+impl NoMbc {
+    pub fn new(ram: Vec<u8>) -> NoMbc {
+        NoMbc { ram }
+    }
+}
+
+impl Mbc for NoMbc {
+    fn rom_bank(&self, address: Word) -> usize {
+        address as usize / ROM_BANK_SIZE
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        Some(0)
+    }
+
+    fn write_register(&mut self, _address: Word, _byte: HalfWord) {}
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+/// MBC1 registers
+///```text
+/// 0000-1FFF  RAM enable, 0x0A in the lower 4 bits enables
+/// 2000-3FFF  ROM bank, lower 5 bits (0 selects 1)
+/// 4000-5FFF  RAM bank, or bits 5-6 of the ROM bank
+/// 6000-7FFF  Banking mode, 1 also applies the upper bits to 0000-3FFF and RAM
+/// ```
+/// Multicarts (MBC1M) leave bit 4 of the ROM bank unconnected, so the upper
+/// bits are shifted by 4 instead of 5.
+/// Ref https://gbdev.io/pandocs/MBC1.html
+#[derive(Debug)]
+pub struct Mbc1 {
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    bank1: usize,
+    bank2: usize,
+    advanced_mode: bool,
+    multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn new(ram: Vec<u8>) -> Mbc1 {
+        Mbc1 {
+            ram,
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            advanced_mode: false,
+            multicart: false,
+        }
+    }
+
+    pub fn multicart(ram: Vec<u8>) -> Mbc1 {
+        Mbc1 {
+            multicart: true,
+            ..Mbc1::new(ram)
+        }
+    }
+
+    /// Lower ROM bank bits and the shift of the upper bits (bank2).
+    fn bank1_wiring(&self) -> (usize, usize) {
+        if self.multicart {
+            (self.bank1 & 0x0F, 4)
+        } else {
+            (self.bank1, 5)
+        }
+    }
+}
+
+impl Mbc for Mbc1 {
+    fn rom_bank(&self, address: Word) -> usize {
+        let (bank1, shift) = self.bank1_wiring();
+
+        match address {
+            0x0000..0x4000 if self.advanced_mode => self.bank2 << shift,
+            0x0000..0x4000 => 0,
+            _ => self.bank2 << shift | bank1,
+        }
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        if !self.ram_enabled {
+            None
+        } else if self.advanced_mode {
+            Some(self.bank2)
+        } else {
+            Some(0)
+        }
+    }
+
+    fn write_register(&mut self, address: Word, byte: HalfWord) {
+        match address {
+            0x0000..0x2000 => self.ram_enabled = byte & 0x0F == 0x0A,
+            0x2000..0x4000 => self.bank1 = (byte as usize & 0x1F).max(1),
+            0x4000..0x6000 => self.bank2 = byte as usize & 0x03,
+            _ => self.advanced_mode = byte & 0x01 != 0,
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.ram);
+        state.bool(self.ram_enabled);
+        state.usize(self.bank1);
+        state.usize(self.bank2);
+        state.bool(self.advanced_mode);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        state.bytes_into(&mut self.ram)?;
+        self.ram_enabled = state.bool()?;
+        self.bank1 = (state.usize()? & 0x1F).max(1);
+        self.bank2 = state.usize()? & 0x03;
+        self.advanced_mode = state.bool()?;
+
+        Ok(())
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+/// MBC3 registers
+///```text
+/// 0000-1FFF  RAM and RTC enable, 0x0A enables
+/// 2000-3FFF  ROM bank, 7 bits (0 selects 1)
+/// 4000-5FFF  RAM bank (0-3), or RTC register (8-C) mapped at A000-BFFF
+/// 6000-7FFF  Writing 0x00 then 0x01 latches the clock
+/// ```
+/// Ref https://gbdev.io/pandocs/MBC3.html
+#[derive(Debug)]
+pub struct Mbc3 {
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: usize,
+    // RAM bank or RTC register
+    select: usize,
+    // 0x00 was written to the latch register
+    latch_armed: bool,
+    rtc: Option<Rtc>,
+    // Seconds since power on the clock runs on, instead of the UNIX time
+    emulated_time: Option<u64>,
+}
+
+impl Mbc3 {
+    pub fn new(ram: Vec<u8>, rtc: Option<Rtc>) -> Mbc3 {
+        Mbc3 {
+            ram,
+            ram_enabled: false,
+            rom_bank: 1,
+            select: 0,
+            latch_armed: false,
+            rtc,
+            emulated_time: None,
+        }
+    }
+
+    /// The time the clock runs on, in seconds.
+    fn now(&self) -> u64 {
+        self.emulated_time.unwrap_or_else(unix_time)
+    }
+
+    /// The clock register mapped at 0xA000-0xBFFF, if any.
+    fn rtc_register(&self) -> Option<usize> {
+        match self.select {
+            0x08..=0x0C if self.ram_enabled && self.rtc.is_some() => Some(self.select),
+            _ => None,
+        }
+    }
+}
+
+impl Mbc for Mbc3 {
+    fn rom_bank(&self, address: Word) -> usize {
+        match address {
+            0x0000..0x4000 => 0,
+            _ => self.rom_bank,
+        }
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        match self.select {
+            0x00..=0x03 if self.ram_enabled => Some(self.select),
+            _ => None,
+        }
+    }
+
+    fn write_register(&mut self, address: Word, byte: HalfWord) {
+        match address {
+            0x0000..0x2000 => self.ram_enabled = byte & 0x0F == 0x0A,
+            0x2000..0x4000 => self.rom_bank = (byte as usize & 0x7F).max(1),
+            0x4000..0x6000 => self.select = byte as usize & 0x0F,
+            _ => {
+                if self.latch_armed && byte == 0x01 {
+                    let now = self.now();
+                    if let Some(rtc) = &mut self.rtc {
+                        rtc.latch(now);
+                    }
+                }
+                self.latch_armed = byte == 0x00;
+            }
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_ram(&self, address: Word) -> HalfWord {
+        match (self.rtc_register(), &self.rtc) {
+            (Some(register), Some(rtc)) => rtc.read(register),
+            _ => self
+                .ram_offset(address)
+                .map_or(0xFF, |offset| self.ram[offset]),
+        }
+    }
+
+    fn write(&mut self, address: Word, byte: HalfWord) {
+        if address < 0x8000 {
+            return self.write_register(address, byte);
+        }
+
+        let now = self.now();
+        match (self.rtc_register(), &mut self.rtc) {
+            (Some(register), Some(rtc)) => rtc.write(register, byte, now),
+            _ => {
+                if let Some(offset) = self.ram_offset(address) {
+                    self.ram[offset] = byte
+                }
+            }
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        if let Some(rtc) = &self.rtc {
+            data.extend_from_slice(&rtc.footer());
+        }
+
+        data
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = self.ram.len().min(data.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+
+        let now = self.now();
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_footer(&data[len..], now);
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.ram);
+        state.bool(self.ram_enabled);
+        state.usize(self.rom_bank);
+        state.usize(self.select);
+        state.bool(self.latch_armed);
+        if let Some(rtc) = &self.rtc {
+            state.bytes(&rtc.footer());
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        state.bytes_into(&mut self.ram)?;
+        self.ram_enabled = state.bool()?;
+        self.rom_bank = (state.usize()? & 0x7F).max(1);
+        self.select = state.usize()? & 0x0F;
+        self.latch_armed = state.bool()?;
+        let now = self.now();
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_footer(state.bytes()?, now);
+        }
+
+        Ok(())
+    }
+
+    fn set_emulated_time(&mut self, seconds: Option<u64>) {
+        if let Some(rtc) = &mut self.rtc {
+            // Switching clocks neither advances nor rewinds the registers
+            match (self.emulated_time, seconds) {
+                (None, Some(now)) => rtc.updated = now,
+                (Some(_), None) => rtc.updated = unix_time(),
+                _ => {}
+            }
+        }
+        self.emulated_time = seconds;
+    }
+}
+
+/// Seconds since the UNIX epoch, the RTC follows the real time.
+#[cfg(not(target_arch = "wasm32"))]
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// `SystemTime` panics on wasm32-unknown-unknown, the RTC only runs there in
+/// the deterministic mode (see [`Mbc::set_emulated_time`]).
+#[cfg(target_arch = "wasm32")]
+fn unix_time() -> u64 {
+    0
+}
+
+/// MBC3 real time clock registers
+///```text
+/// 08  Seconds (0-59)
+/// 09  Minutes (0-59)
+/// 0A  Hours (0-23)
+/// 0B  Day counter, lower 8 bits
+/// 0C  Bit 0: day counter bit 8, Bit 6: halt, Bit 7: day counter carry
+/// ```
+#[derive(Debug)]
+pub struct Rtc {
+    live: [HalfWord; 5],
+    latched: [HalfWord; 5],
+    // UNIX time the live registers were last brought up to date
+    updated: u64,
+}
+
+impl Default for Rtc {
+    fn default() -> Rtc {
+        Rtc {
+            live: [0; 5],
+            latched: [0; 5],
+            updated: unix_time(),
+        }
+    }
+}
+
+impl Rtc {
+    fn read(&self, register: usize) -> HalfWord {
+        self.latched[register - 0x08]
+    }
+
+    fn write(&mut self, register: usize, byte: HalfWord, now: u64) {
+        self.update(now);
+        self.live[register - 0x08] = byte;
+    }
+
+    fn latch(&mut self, now: u64) {
+        self.update(now);
+        self.latched = self.live;
+    }
+
+    /// Advance the live registers by the time passed since the last update.
+    fn update(&mut self, now: u64) {
+        let elapsed = now.saturating_sub(self.updated);
+        self.updated = now;
+
+        let flags = self.live[4];
+        if flags & 0x40 != 0 || elapsed == 0 {
+            return;
+        }
+
+        let [seconds, minutes, hours, days_low, _] = self.live;
+        let days = (flags as u64 & 0x01) << 8 | days_low as u64;
+        let total =
+            seconds as u64 + minutes as u64 * 60 + hours as u64 * 3600 + days * 86400 + elapsed;
+
+        let days = total / 86400;
+        let carry = if days > 0x1FF { 0x80 } else { flags & 0x80 };
+        self.live = [
+            (total % 60) as HalfWord,
+            (total / 60 % 60) as HalfWord,
+            (total / 3600 % 24) as HalfWord,
+            days as HalfWord,
+            carry | (days >> 8) as HalfWord & 0x01,
+        ];
+    }
+
+    /// The 48 byte footer VBA and BGB append to the save file: the live and
+    /// latched registers as 32 bit little endian values, then the UNIX time
+    /// as a 64 bit value.
+    fn footer(&self) -> Vec<u8> {
+        let mut footer = Vec::with_capacity(48);
+        for &register in self.live.iter().chain(self.latched.iter()) {
+            footer.extend_from_slice(&(register as u32).to_le_bytes());
+        }
+        footer.extend_from_slice(&self.updated.to_le_bytes());
+
+        footer
+    }
+
+    /// Read a footer written by `footer`, or the older 44 byte variant with a
+    /// 32 bit UNIX time, and advance the clock to `now`.
+    fn load_footer(&mut self, footer: &[u8], now: u64) {
+        let updated = match footer.len() {
+            48 => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            44 => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+            _ => return,
+        };
+
+        let registers: Vec<HalfWord> = footer[..40].chunks(4).map(|r| r[0]).collect();
+        self.live.copy_from_slice(&registers[..5]);
+        self.latched.copy_from_slice(&registers[5..]);
+        self.updated = updated;
+        self.update(now);
+    }
+}
+
+/// MBC5 registers
+///```text
+/// 0000-1FFF  RAM enable, 0x0A enables
+/// 2000-2FFF  ROM bank, lower 8 bits (0 selects bank 0)
+/// 3000-3FFF  ROM bank, bit 8
+/// 4000-5FFF  RAM bank (0-F), bit 3 drives the rumble motor on rumble carts
+/// ```
+/// Ref https://gbdev.io/pandocs/MBC5.html
+#[derive(Debug)]
+pub struct Mbc5 {
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: usize,
+    ram_bank: usize,
+    // Bit 3 of the RAM bank drives the motor instead of selecting a bank
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl Mbc5 {
+    pub fn new(ram: Vec<u8>) -> Mbc5 {
+        Mbc5 {
+            ram,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble: false,
+            rumble: false,
+        }
+    }
+
+    pub fn with_rumble(ram: Vec<u8>) -> Mbc5 {
+        Mbc5 {
+            has_rumble: true,
+            ..Mbc5::new(ram)
+        }
+    }
+}
+
+impl Mbc for Mbc5 {
+    fn rom_bank(&self, address: Word) -> usize {
+        match address {
+            0x0000..0x4000 => 0,
+            _ => self.rom_bank,
+        }
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        if self.ram_enabled {
+            Some(self.ram_bank)
+        } else {
+            None
+        }
+    }
+
+    fn write_register(&mut self, address: Word, byte: HalfWord) {
+        match address {
+            0x0000..0x2000 => self.ram_enabled = byte == 0x0A,
+            0x2000..0x3000 => self.rom_bank = self.rom_bank & 0x100 | byte as usize,
+            0x3000..0x4000 => self.rom_bank = (byte as usize & 0x01) << 8 | self.rom_bank & 0xFF,
+            0x4000..0x6000 if self.has_rumble => {
+                self.ram_bank = byte as usize & 0x07;
+                self.rumble = byte & 0x08 != 0;
+            }
+            0x4000..0x6000 => self.ram_bank = byte as usize & 0x0F,
+            _ => {}
+        }
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.ram);
+        state.bool(self.ram_enabled);
+        state.usize(self.rom_bank);
+        state.usize(self.ram_bank);
+        state.bool(self.rumble);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        state.bytes_into(&mut self.ram)?;
+        self.ram_enabled = state.bool()?;
+        self.rom_bank = state.usize()? & 0x1FF;
+        self.ram_bank = state.usize()? & 0x0F;
+        self.rumble = state.bool()?;
+
+        Ok(())
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+/// MBC7 registers, with a 93LC56 EEPROM and a two-axis accelerometer instead
+/// of RAM
+///```text
+/// 0000-1FFF  RAM enable 1, 0x0A enables
+/// 2000-3FFF  ROM bank
+/// 4000-5FFF  RAM enable 2, 0x40 enables
+/// A000-AFFF  With both enabled, the register is selected by bits 7-4:
+///   Ax0x  Write 0x55 to reset the accelerometer latch
+///   Ax1x  Write 0xAA to latch the accelerometer
+///   Ax2x  X, lower byte   Ax3x  X, upper byte
+///   Ax4x  Y, lower byte   Ax5x  Y, upper byte
+///   Ax8x  EEPROM, Bit 7: CS, Bit 6: CLK, Bit 1: DI, Bit 0: DO
+/// ```
+/// Ref https://gbdev.io/pandocs/MBC7.html
+#[derive(Debug)]
+pub struct Mbc7 {
+    ram_enabled: bool,
+    ram_enabled2: bool,
+    rom_bank: usize,
+    // Current sensor input
+    tilt: (f32, f32),
+    // Latched accelerometer values
+    x: Word,
+    y: Word,
+    eeprom: Eeprom,
+}
+
+impl Default for Mbc7 {
+    fn default() -> Mbc7 {
+        Mbc7 {
+            ram_enabled: false,
+            ram_enabled2: false,
+            rom_bank: 1,
+            tilt: (0.0, 0.0),
+            x: ACCELEROMETER_LATCH_RESET,
+            y: ACCELEROMETER_LATCH_RESET,
+            eeprom: Eeprom::default(),
+        }
+    }
+}
+
+// Accelerometer value when level
+const ACCELEROMETER_CENTER: f32 = 0x81D0 as f32;
+// Accelerometer change at full tilt (1 g)
+const ACCELEROMETER_RANGE: f32 = 0x70 as f32;
+const ACCELEROMETER_LATCH_RESET: Word = 0x8000;
+
+impl Mbc7 {
+    fn registers_enabled(&self) -> bool {
+        self.ram_enabled && self.ram_enabled2
+    }
+}
+
+impl Mbc for Mbc7 {
+    fn rom_bank(&self, address: Word) -> usize {
+        match address {
+            0x0000..0x4000 => 0,
+            _ => self.rom_bank,
+        }
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        None
+    }
+
+    fn write_register(&mut self, address: Word, byte: HalfWord) {
+        match address {
+            0x0000..0x2000 => self.ram_enabled = byte == 0x0A,
+            0x2000..0x4000 => self.rom_bank = byte as usize & 0x7F,
+            0x4000..0x6000 => self.ram_enabled2 = byte == 0x40,
+            _ => {}
+        }
+    }
+
+    /// The EEPROM contents, as little endian words.
+    fn ram(&self) -> &[u8] {
+        &self.eeprom.data
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.eeprom.data
+    }
+
+    fn read_ram(&self, address: Word) -> HalfWord {
+        if !self.registers_enabled() || address >= 0xB000 {
+            return 0xFF;
+        }
+
+        match address >> 4 & 0x0F {
+            0x2 => self.x as HalfWord,
+            0x3 => (self.x >> 8) as HalfWord,
+            0x4 => self.y as HalfWord,
+            0x5 => (self.y >> 8) as HalfWord,
+            0x6 => 0x00,
+            0x8 => self.eeprom.read(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: Word, byte: HalfWord) {
+        if address < 0x8000 {
+            return self.write_register(address, byte);
+        }
+        if !self.registers_enabled() || address >= 0xB000 {
+            return;
+        }
+
+        match address >> 4 & 0x0F {
+            0x0 if byte == 0x55 => {
+                self.x = ACCELEROMETER_LATCH_RESET;
+                self.y = ACCELEROMETER_LATCH_RESET;
+            }
+            // Only latches after a reset
+            0x1 if byte == 0xAA && self.x == ACCELEROMETER_LATCH_RESET => {
+                let (x, y) = self.tilt;
+                self.x = (ACCELEROMETER_CENTER - x * ACCELEROMETER_RANGE) as Word;
+                self.y = (ACCELEROMETER_CENTER + y * ACCELEROMETER_RANGE) as Word;
+            }
+            0x8 => self.eeprom.write(byte),
+            _ => {}
+        }
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0));
+    }
+
+    /// The tilt is input of the host, it is not restored.
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.ram_enabled);
+        state.bool(self.ram_enabled2);
+        state.usize(self.rom_bank);
+        state.u16(self.x);
+        state.u16(self.y);
+        self.eeprom.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.ram_enabled = state.bool()?;
+        self.ram_enabled2 = state.bool()?;
+        self.rom_bank = state.usize()? & 0x7F;
+        self.x = state.u16()?;
+        self.y = state.u16()?;
+        self.eeprom.load_state(state)
+    }
+}
+
+/// 93LC56 serial EEPROM, 128 words of 16 bits. Commands are shifted in MSB
+/// first on rising CLK edges while CS is high: a start bit, a 2 bit opcode
+/// and an 8 bit address (the top bit is ignored).
+///```text
+/// 10 aaaaaaaa          READ   shift out the word on DO
+/// 01 aaaaaaaa dddd...  WRITE  write 16 data bits
+/// 11 aaaaaaaa          ERASE  set the word to 0xFFFF
+/// 00 11xxxxxx          EWEN   enable writes
+/// 00 00xxxxxx          EWDS   disable writes
+/// 00 10xxxxxx          ERAL   set every word to 0xFFFF
+/// 00 01xxxxxx dddd...  WRAL   write 16 data bits to every word
+/// ```
+#[derive(Debug)]
+struct Eeprom {
+    data: Vec<u8>,
+    cs: bool,
+    clk: bool,
+    di: bool,
+    do_: bool,
+    write_enabled: bool,
+    state: EepromState,
+    // Bits shifted in or out in the current state
+    shift: Word,
+    bits: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EepromState {
+    /// Waiting for the start bit
+    Idle,
+    Command,
+    Read,
+    /// Data bits for WRITE, `None` for WRAL
+    Write(Option<usize>),
+}
+
+impl Default for Eeprom {
+    fn default() -> Eeprom {
+        Eeprom {
+            data: vec![0xFF; 0x100],
+            cs: false,
+            clk: false,
+            di: false,
+            do_: true,
+            write_enabled: false,
+            state: EepromState::Idle,
+            shift: 0,
+            bits: 0,
+        }
+    }
+}
+
+impl Eeprom {
+    fn read(&self) -> HalfWord {
+        (self.cs as HalfWord) << 7
+            | (self.clk as HalfWord) << 6
+            | (self.di as HalfWord) << 1
+            | self.do_ as HalfWord
+    }
+
+    fn write(&mut self, byte: HalfWord) {
+        let rising = !self.clk && byte & 0x40 != 0;
+        self.cs = byte & 0x80 != 0;
+        self.clk = byte & 0x40 != 0;
+        self.di = byte & 0x02 != 0;
+
+        if !self.cs {
+            self.state = EepromState::Idle;
+            return;
+        }
+        if rising {
+            self.clock_bit(self.di);
+        }
+    }
+
+    fn clock_bit(&mut self, bit: bool) {
+        match self.state {
+            EepromState::Idle if bit => {
+                self.state = EepromState::Command;
+                self.shift = 0;
+                self.bits = 0;
+            }
+            EepromState::Idle => {}
+            EepromState::Command => {
+                self.shift = self.shift << 1 | bit as Word;
+                self.bits += 1;
+                if self.bits == 10 {
+                    self.execute(self.shift >> 8, self.shift as usize & 0xFF);
+                }
+            }
+            EepromState::Read => {
+                self.do_ = self.shift & 0x8000 != 0;
+                self.shift <<= 1;
+                self.bits -= 1;
+                if self.bits == 0 {
+                    self.state = EepromState::Idle;
+                }
+            }
+            EepromState::Write(address) => {
+                self.shift = self.shift << 1 | bit as Word;
+                self.bits += 1;
+                if self.bits == 16 {
+                    match address {
+                        Some(address) => self.write_word(address, self.shift),
+                        None => (0..0x80).for_each(|a| self.write_word(a, self.shift)),
+                    }
+                    self.state = EepromState::Idle;
+                    self.do_ = true;
+                }
+            }
+        }
+    }
+
+    fn execute(&mut self, opcode: Word, operand: usize) {
+        // 128 words, the top address bit is ignored
+        let address = operand & 0x7F;
+        self.shift = 0;
+        self.bits = 0;
+        self.state = EepromState::Idle;
+
+        match opcode {
+            0b10 => {
+                self.shift = self.read_word(address);
+                self.bits = 16;
+                self.state = EepromState::Read;
+                // A dummy 0 bit precedes the data
+                self.do_ = false;
+            }
+            0b01 => self.state = EepromState::Write(Some(address)),
+            0b11 => self.write_word(address, 0xFFFF),
+            _ => match operand >> 6 {
+                0b11 => self.write_enabled = true,
+                0b00 => self.write_enabled = false,
+                0b10 => (0..0x80).for_each(|a| self.write_word(a, 0xFFFF)),
+                _ => self.state = EepromState::Write(None),
+            },
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.data);
+        state.u8(self.read());
+        state.bool(self.write_enabled);
+        match self.state {
+            EepromState::Idle => state.u8(0),
+            EepromState::Command => state.u8(1),
+            EepromState::Read => state.u8(2),
+            EepromState::Write(Some(address)) => {
+                state.u8(3);
+                state.usize(address);
+            }
+            EepromState::Write(None) => state.u8(4),
+        }
+        state.u16(self.shift);
+        state.usize(self.bits);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        state.bytes_into(&mut self.data)?;
+        let pins = state.u8()?;
+        self.cs = pins & 0x80 != 0;
+        self.clk = pins & 0x40 != 0;
+        self.di = pins & 0x02 != 0;
+        self.do_ = pins & 0x01 != 0;
+        self.write_enabled = state.bool()?;
+        self.state = match state.u8()? {
+            0 => EepromState::Idle,
+            1 => EepromState::Command,
+            2 => EepromState::Read,
+            3 => EepromState::Write(Some(state.usize()? & 0x7F)),
+            4 => EepromState::Write(None),
+            tag => anyhow::bail!("save state is corrupt: unknown EEPROM state {}", tag),
+        };
+        self.shift = state.u16()?;
+        self.bits = state.usize()? % 17;
+
+        Ok(())
+    }
+
+    fn read_word(&self, address: usize) -> Word {
+        Word::from_le_bytes([self.data[address * 2], self.data[address * 2 + 1]])
+    }
+
+    fn write_word(&mut self, address: usize, word: Word) {
+        if self.write_enabled {
+            self.data[address * 2..address * 2 + 2].copy_from_slice(&word.to_le_bytes());
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Movie files: the joypad state of every frame from a save state on, to
+//! replay a run exactly, e.g. for TAS work or to reproduce a bug report.
+//!
+//! Little endian, like save states:
+//!```text
+//! "GBMV"  magic
+//! u32     version
+//! u32     CRC-32 of the ROM image
+//! u8      buttons held at the start
+//! u64     length of the start state, then the save state
+//! u8...   buttons held during each frame
+//! ```
+//! Buttons are a bit each: Right, Left, Up, Down, A, B, Select, Start from
+//! bit 0.
+
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: &[u8] = b"GBMV";
+const VERSION: u32 = 1;
+
+pub struct Movie {
+    pub rom_crc: u32,
+    /// Buttons held when the start state was saved
+    pub held: u8,
+    pub start_state: Vec<u8>,
+    /// Buttons of every frame
+    pub frames: Vec<u8>,
+}
+
+impl Movie {
+    pub fn read(path: impl AsRef<Path>) -> Result<Movie> {
+        let data = std::fs::read(path)?;
+        let mut movie = StateReader::new(&data);
+
+        for &byte in MAGIC {
+            if movie.u8()? != byte {
+                anyhow::bail!("not a gbemu movie");
+            }
+        }
+        let version = movie.u32()?;
+        if version != VERSION {
+            anyhow::bail!("movie version {} is not supported", version);
+        }
+
+        Ok(Movie {
+            rom_crc: movie.u32()?,
+            held: movie.u8()?,
+            start_state: movie.bytes()?.to_vec(),
+            frames: movie.rest().to_vec(),
+        })
+    }
+}
+
+/// Writes a movie frame by frame, so a crash keeps what was recorded.
+pub struct MovieWriter {
+    output: BufWriter<File>,
+    frames: usize,
+    // First write error, reported by `finish`
+    error: Option<io::Error>,
+}
+
+impl MovieWriter {
+    /// Create `path` and write the header.
+    pub fn create(
+        path: impl AsRef<Path>,
+        rom_crc: u32,
+        held: u8,
+        start_state: &[u8],
+    ) -> Result<MovieWriter> {
+        let mut header = StateWriter::new();
+        for &byte in MAGIC {
+            header.u8(byte);
+        }
+        header.u32(VERSION);
+        header.u32(rom_crc);
+        header.u8(held);
+        header.bytes(start_state);
+
+        let mut output = BufWriter::new(File::create(path)?);
+        output.write_all(&header.into_bytes())?;
+
+        Ok(MovieWriter {
+            output,
+            frames: 0,
+            error: None,
+        })
+    }
+
+    /// Append the buttons held during a frame. Errors are kept for
+    /// [`MovieWriter::finish`], the frames after one are dropped.
+    pub fn write_frame(&mut self, buttons: u8) {
+        if self.error.is_some() {
+            return;
+        }
+
+        match self.output.write_all(&[buttons]) {
+            Ok(()) => self.frames += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Flush the file, returns the number of frames recorded.
+    pub fn finish(mut self) -> Result<usize> {
+        if let Some(e) = self.error.take() {
+            return Err(e.into());
+        }
+        self.output.flush()?;
+
+        Ok(self.frames)
+    }
+}
+
+/// Feeds the frames of a [`Movie`] to the joypad.
+pub struct MoviePlayer {
+    frames: Vec<u8>,
+    position: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(frames: Vec<u8>) -> MoviePlayer {
+        MoviePlayer {
+            frames,
+            position: 0,
+        }
+    }
+
+    /// Buttons of the next frame, `None` at the end of the movie.
+    pub fn next_frame(&mut self) -> Option<u8> {
+        let buttons = self.frames.get(self.position).copied()?;
+        self.position += 1;
+
+        Some(buttons)
+    }
+}
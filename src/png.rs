@@ -0,0 +1,96 @@
+//! PNG files of RGBA images, for screenshots.
+//!
+//! The image data is stored without compression, in zlib stored blocks, so
+//! no deflate implementation is needed. A 160x144 screenshot is about 90 KiB.
+//! Ref https://www.w3.org/TR/png/
+
+use crate::frame_hash::crc32;
+use anyhow::Result;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// Largest length of a stored deflate block
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Write `rgba`, `width` x `height` pixels of 4 bytes row by row, to `path`.
+pub fn write_rgba(path: impl AsRef<Path>, width: usize, height: usize, rgba: &[u8]) -> Result<()> {
+    if rgba.len() != width * height * 4 {
+        anyhow::bail!(
+            "{} bytes is not a {}x{} RGBA image",
+            rgba.len(),
+            width,
+            height
+        );
+    }
+
+    let mut output = BufWriter::new(File::create(path)?);
+    output.write_all(&SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut output, b"IHDR", &header)?;
+
+    // Every row starts with its filter type, 0 for none
+    let mut scanlines = Vec::with_capacity(height * (width * 4 + 1));
+    for row in rgba.chunks_exact(width * 4) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+    write_chunk(&mut output, b"IDAT", &zlib_stored(&scanlines))?;
+
+    write_chunk(&mut output, b"IEND", &[])?;
+    output.flush()?;
+
+    Ok(())
+}
+
+fn write_chunk(output: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    output.write_all(&(data.len() as u32).to_be_bytes())?;
+    output.write_all(kind)?;
+    output.write_all(data)?;
+
+    // The CRC covers the chunk type and data
+    let mut checked = Vec::with_capacity(4 + data.len());
+    checked.extend_from_slice(kind);
+    checked.extend_from_slice(data);
+    output.write_all(&crc32(&checked).to_be_bytes())?;
+
+    Ok(())
+}
+
+/// A zlib stream of `data` in uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32 KiB window, the lowest compression level
+    let mut stream = vec![0x78, 0x01];
+
+    let blocks: Vec<&[u8]> = if data.is_empty() {
+        vec![&[]]
+    } else {
+        data.chunks(MAX_STORED_BLOCK).collect()
+    };
+    for (i, block) in blocks.iter().enumerate() {
+        // BFINAL on the last block, BTYPE 00 for stored
+        stream.push((i + 1 == blocks.len()) as u8);
+        stream.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        stream.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    b << 16 | a
+}
@@ -1,4 +1,6 @@
+use crate::state::{StateReader, StateWriter};
 use crate::{HalfWord, Word};
+use anyhow::Result;
 
 pub struct Ram {
     data: Vec<u8>,
@@ -11,11 +13,94 @@ impl Ram {
         }
     }
 
+    /// Out of range reads return 0xFF like an unconnected bus.
     pub fn read(&self, address: Word) -> HalfWord {
-        self.data[address as usize]
+        self.data.get(address as usize).copied().unwrap_or(0xFF)
     }
 
+    /// Out of range writes are ignored.
     pub fn write(&mut self, address: Word, byte: HalfWord) {
-        self.data[address as usize] = byte
+        if let Some(b) = self.data.get_mut(address as usize) {
+            *b = byte
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.data);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        state.bytes_into(&mut self.data)
+    }
+}
+
+/// RAM with switchable banks, e.g. CGB working RAM and video RAM.
+pub struct BankedRam {
+    data: Vec<u8>,
+    bank_size: usize,
+    bank: usize,
+}
+
+impl BankedRam {
+    pub fn new(bank_size: usize, banks: usize) -> BankedRam {
+        BankedRam {
+            data: vec![0; bank_size * banks],
+            bank_size,
+            bank: 0,
+        }
+    }
+
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+
+    /// Out of range banks wrap around.
+    pub fn select_bank(&mut self, bank: usize) {
+        self.bank = bank % (self.data.len() / self.bank_size);
+    }
+
+    /// Read from the selected bank.
+    pub fn read(&self, address: Word) -> HalfWord {
+        self.read_bank(self.bank, address)
+    }
+
+    /// Write to the selected bank.
+    pub fn write(&mut self, address: Word, byte: HalfWord) {
+        self.write_bank(self.bank, address, byte)
+    }
+
+    /// Out of range reads return 0xFF like an unconnected bus.
+    pub fn read_bank(&self, bank: usize, address: Word) -> HalfWord {
+        if address as usize >= self.bank_size {
+            return 0xFF;
+        }
+
+        self.data
+            .get(bank * self.bank_size + address as usize)
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    /// Out of range writes are ignored.
+    pub fn write_bank(&mut self, bank: usize, address: Word, byte: HalfWord) {
+        if address as usize >= self.bank_size {
+            return;
+        }
+
+        if let Some(b) = self.data.get_mut(bank * self.bank_size + address as usize) {
+            *b = byte
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.data);
+        state.usize(self.bank);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        state.bytes_into(&mut self.data)?;
+        self.select_bank(state.usize()?);
+
+        Ok(())
     }
 }
@@ -0,0 +1,60 @@
+//! Recordings of the screen to an animated GIF.
+//!
+//! GIF delays are in hundredths of a second, and most viewers slow down
+//! delays under 2, so every other frame is kept for about 30 fps. The delays
+//! alternate between 3 and 4 to follow the LCD without drifting.
+
+use crate::frame_timer::FRAME_RATE;
+use crate::gif::GifWriter;
+use crate::gpu::{Gpu, PixelFormat};
+use anyhow::Result;
+use std::path::Path;
+
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+
+// Every how many frames one is recorded
+const FRAME_STEP: u64 = 2;
+
+pub struct Recorder {
+    gif: GifWriter,
+    screen: Vec<u8>,
+    frames: u64,
+    // Number of the next frame recorded
+    next_frame: u64,
+    // Hundredths of a second recorded so far
+    time: u64,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> Result<Recorder> {
+        Ok(Recorder {
+            gif: GifWriter::create(path, SCREEN_WIDTH, SCREEN_HEIGHT)?,
+            screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            frames: 0,
+            next_frame: FRAME_STEP,
+            time: 0,
+        })
+    }
+
+    /// Called with every completed frame.
+    pub fn record(&mut self, gpu: &Gpu) {
+        self.frames += 1;
+        if self.frames < self.next_frame {
+            return;
+        }
+        self.next_frame += FRAME_STEP;
+
+        let end = (self.frames as f64 * 100.0 / FRAME_RATE).round() as u64;
+        let delay = end - self.time;
+        self.time = end;
+
+        gpu.encode_frame(PixelFormat::Rgba8888, &mut self.screen);
+        self.gif.write_frame(&self.screen, delay as u16);
+    }
+
+    /// Finish the GIF file, reporting the first write error.
+    pub fn finish(self) -> Result<()> {
+        self.gif.finish()
+    }
+}
@@ -0,0 +1,66 @@
+//! Resampling from the emulated sample rate to the host audio rate, with
+//! dynamic rate control.
+//!
+//! The emulated clock (59.73 frames per second) and the host audio clock never
+//! agree exactly, so a fixed ratio slowly over- or underruns the output ring
+//! buffer and pops. The resampler nudges its ratio by a fraction of a percent
+//! based on how full that buffer is, so it settles at a fill level where
+//! the rates match instead of running dry.
+//! Ref https://docs.libretro.com/development/cores/dynamic-rate-control/
+
+/// Largest deviation from the nominal ratio (0.5%), small enough to be inaudible.
+const MAX_DEVIATION: f64 = 0.005;
+/// Weight of a new fill level measurement, smoothing out jitter.
+const FILL_SMOOTHING: f64 = 0.05;
+
+pub struct Resampler {
+    // Input samples consumed per output sample at the nominal rates
+    nominal_step: f64,
+    // Smoothed ring buffer fill level, 0.0 (empty) to 1.0 (full)
+    fill_level: f64,
+    // Position of the next output sample, in input samples after `previous`
+    position: f64,
+    previous: [f32; 2],
+}
+
+impl Resampler {
+    pub fn new(input_rate: f64, output_rate: f64) -> Resampler {
+        Resampler {
+            nominal_step: input_rate / output_rate,
+            fill_level: 0.5,
+            position: 0.0,
+            previous: [0.0; 2],
+        }
+    }
+
+    /// Report the fill level of the output ring buffer (0.0 to 1.0).
+    pub fn update_fill_level(&mut self, fill_level: f64) {
+        let fill_level = fill_level.clamp(0.0, 1.0);
+        self.fill_level += (fill_level - self.fill_level) * FILL_SMOOTHING;
+    }
+
+    /// Current input samples per output sample. Above half full the step
+    /// grows so fewer samples are produced, below half full it shrinks.
+    pub fn step(&self) -> f64 {
+        self.nominal_step * (1.0 + MAX_DEVIATION * (2.0 * self.fill_level - 1.0))
+    }
+
+    /// Resample stereo frames with linear interpolation, appending to `output`.
+    pub fn process(&mut self, input: &[[f32; 2]], output: &mut Vec<[f32; 2]>) {
+        let step = self.step();
+
+        for &frame in input {
+            while self.position < 1.0 {
+                let t = self.position as f32;
+                output.push([
+                    self.previous[0] + (frame[0] - self.previous[0]) * t,
+                    self.previous[1] + (frame[1] - self.previous[1]) * t,
+                ]);
+                self.position += step;
+            }
+
+            self.position -= 1.0;
+            self.previous = frame;
+        }
+    }
+}
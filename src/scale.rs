@@ -0,0 +1,171 @@
+//! Upscaling of the 160x144 screen for the window.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// How scaled pixels are filled in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Blocks of the source pixel
+    #[default]
+    Nearest,
+    /// Nearest neighbor with the last row of every pixel darkened, like the
+    /// gaps between the lines of a CRT
+    Scanlines,
+    /// Scale2x (EPX) edge smoothing, then nearest neighbor for the rest of
+    /// the factor. Needs an even factor.
+    /// Ref https://www.scale2x.it/algorithm
+    Scale2x,
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Filter> {
+        match s {
+            "nearest" => Ok(Filter::Nearest),
+            "scanlines" => Ok(Filter::Scanlines),
+            "scale2x" => Ok(Filter::Scale2x),
+            _ => anyhow::bail!("unknown filter {} (nearest, scanlines, scale2x)", s),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Filter::Nearest => "nearest",
+            Filter::Scanlines => "scanlines",
+            Filter::Scale2x => "scale2x",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Integer upscaling of an RGBA image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scaler {
+    factor: usize,
+    filter: Filter,
+}
+
+impl Default for Scaler {
+    /// 2x nearest neighbor
+    fn default() -> Self {
+        Scaler {
+            factor: 2,
+            filter: Filter::Nearest,
+        }
+    }
+}
+
+impl Scaler {
+    pub fn new(factor: usize, filter: Filter) -> anyhow::Result<Scaler> {
+        if factor == 0 {
+            anyhow::bail!("the scale factor must be at least 1");
+        }
+        if filter == Filter::Scale2x && factor & 1 == 1 {
+            anyhow::bail!(
+                "the {} filter needs an even scale factor, not {}",
+                filter,
+                factor
+            );
+        }
+
+        Ok(Scaler { factor, filter })
+    }
+
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Size of the scaled image of a `width` x `height` image.
+    pub fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+        (width * self.factor, height * self.factor)
+    }
+
+    /// Scale the `width` x `height` RGBA image `source` into `output`, which
+    /// has the size given by [`Scaler::output_size`].
+    pub fn apply(&self, source: &[u8], width: usize, height: usize, output: &mut [u8]) {
+        match self.filter {
+            Filter::Nearest => nearest(source, width, height, self.factor, output),
+            Filter::Scanlines => {
+                nearest(source, width, height, self.factor, output);
+                if self.factor > 1 {
+                    darken_scanlines(output, width * self.factor, self.factor);
+                }
+            }
+            Filter::Scale2x => {
+                let smooth = scale2x(source, width, height);
+                nearest(&smooth, width * 2, height * 2, self.factor / 2, output);
+            }
+        }
+    }
+}
+
+fn nearest(source: &[u8], width: usize, height: usize, factor: usize, output: &mut [u8]) {
+    let output_width = width * factor;
+
+    for y in 0..height * factor {
+        for x in 0..output_width {
+            let from = ((y / factor) * width + x / factor) * 4;
+            let to = (y * output_width + x) * 4;
+            output[to..to + 4].copy_from_slice(&source[from..from + 4]);
+        }
+    }
+}
+
+/// Halve the brightness of the last output row of every source row.
+fn darken_scanlines(output: &mut [u8], output_width: usize, factor: usize) {
+    for (y, row) in output.chunks_exact_mut(output_width * 4).enumerate() {
+        if y % factor != factor - 1 {
+            continue;
+        }
+        for pixel in row.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                *channel /= 2;
+            }
+        }
+    }
+}
+
+/// Each pixel P becomes 4 pixels, taking the color of a neighbor where two
+/// neighbors agree, which rounds diagonal edges.
+///```text
+///    A         E0 E1
+///  C P B  ->   E2 E3
+///    D
+/// ```
+fn scale2x(source: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let pixel = |x: usize, y: usize| -> &[u8] {
+        let offset = (y * width + x) * 4;
+        &source[offset..offset + 4]
+    };
+    let mut output = vec![0; width * height * 16];
+    let output_width = width * 2;
+
+    for y in 0..height {
+        for x in 0..width {
+            let p = pixel(x, y);
+            let a = pixel(x, y.saturating_sub(1));
+            let b = pixel((x + 1).min(width - 1), y);
+            let c = pixel(x.saturating_sub(1), y);
+            let d = pixel(x, (y + 1).min(height - 1));
+
+            let smooth = c != b && a != d;
+            let e = [
+                if smooth && c == a { a } else { p },
+                if smooth && a == b { b } else { p },
+                if smooth && d == c { c } else { p },
+                if smooth && b == d { d } else { p },
+            ];
+
+            for (i, color) in e.iter().enumerate() {
+                let to = ((y * 2 + i / 2) * output_width + x * 2 + i % 2) * 4;
+                output[to..to + 4].copy_from_slice(color);
+            }
+        }
+    }
+
+    output
+}
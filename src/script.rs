@@ -0,0 +1,365 @@
+//! Rhai scripts hooked into the emulation, for trainers, auto-splitters and
+//! research tooling without recompiling the crate.
+//!
+//! The top level of a script runs once when it is loaded and registers
+//! callbacks, closures or `Fn("name")` pointers:
+//!```text
+//! on_frame(f)             after every frame run, also while the LCD is off
+//! on_vblank(f)            when the PPU completes a frame
+//! on_write(address, f)    f(address, value) after the CPU writes address
+//! on_write(start, end, f) the same for start..=end
+//! ```
+//! Callbacks can use:
+//!```text
+//! read(address)           byte at address, read16 for a little endian word
+//! write(address, value)   patch memory, see Bus::poke
+//! reg(name)               register a, f, b, c, d, e, h, l, af, bc, de, hl, sp or pc
+//! frame_count()           frames completed by the PPU
+//! draw_text(x, y, text)   text over the screen until the next frame, in
+//!                         white or draw_text(x, y, text, 0xRRGGBB)
+//! fault_bit_flip(start, end, every)
+//!                         flip a random bit of every n-th read or write of
+//!                         start..=end, see crate::fault
+//! fault_delay_write(start, end, every, writes)
+//!                         hold every n-th write to start..=end back until
+//!                         `writes` further writes went over the bus
+//! clear_faults()          stop injecting faults
+//! ```
+//! `print` goes to the log.
+//!
+//! Write callbacks run after the instruction that wrote, all other callbacks
+//! see the machine between two instructions. Memory is read from a snapshot
+//! taken before the callbacks run, writes and fault rules are applied after
+//! they return. Faults of scripts are seeded with a fixed seed, so a run with
+//! the same inputs is reproducible.
+
+use crate::bus::Bus;
+use crate::cpu::CpuState;
+use crate::fault::{Fault, FaultInjector, FaultRule};
+use crate::{join_half_words, HalfWord, Word};
+use anyhow::Result;
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, AST};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const FAULT_SEED: u32 = 1;
+
+/// Text drawn by a script over the screen.
+#[derive(Debug, Clone)]
+pub struct OverlayText {
+    pub x: i32,
+    pub y: i32,
+    pub text: String,
+    /// RGBA
+    pub color: [u8; 4],
+}
+
+/// What the script functions work on.
+struct Context {
+    memory: Vec<HalfWord>,
+    registers: CpuState,
+    frame_count: usize,
+    // Writes of the script, applied after the callbacks return
+    writes: Vec<(Word, HalfWord)>,
+    // Fault rules added by the script, `None` clears them
+    faults: Vec<Option<FaultRule>>,
+    overlay: Vec<OverlayText>,
+    on_frame: Vec<FnPtr>,
+    on_vblank: Vec<FnPtr>,
+    on_write: Vec<(RangeInclusive<Word>, FnPtr)>,
+}
+
+impl Context {
+    fn read(&self, address: i64) -> i64 {
+        self.memory[address as Word as usize] as i64
+    }
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    context: Arc<Mutex<Context>>,
+}
+
+impl Script {
+    /// Compile the script at `path`. Its top level runs in
+    /// [`Script::start`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Script> {
+        let context = Arc::new(Mutex::new(Context {
+            memory: vec![0xFF; 0x10000],
+            registers: CpuState::default(),
+            frame_count: 0,
+            writes: Vec::new(),
+            faults: Vec::new(),
+            overlay: Vec::new(),
+            on_frame: Vec::new(),
+            on_vblank: Vec::new(),
+            on_write: Vec::new(),
+        }));
+        let engine = engine(&context);
+        let ast = engine.compile_file(path.as_ref().to_path_buf())?;
+
+        Ok(Script {
+            engine,
+            ast,
+            context,
+        })
+    }
+
+    /// Run the top level of the script, which registers the callbacks.
+    pub fn start(&mut self, bus: &mut Bus, registers: CpuState) -> Result<()> {
+        self.prepare(bus, registers);
+        let result = self.engine.run_ast(&self.ast);
+        self.apply_writes(bus);
+        result?;
+
+        bus.set_write_watch(self.write_watch());
+        Ok(())
+    }
+
+    /// Address ranges with a write callback, to watch on the bus.
+    pub fn write_watch(&self) -> Vec<RangeInclusive<Word>> {
+        let context = self.context.lock().unwrap();
+        context
+            .on_write
+            .iter()
+            .map(|(range, _)| range.clone())
+            .collect()
+    }
+
+    /// Call the frame callbacks. The overlay drawn in the frame before is
+    /// cleared first.
+    pub fn frame(&mut self, bus: &mut Bus, registers: CpuState) -> Result<()> {
+        let callbacks = {
+            let mut context = self.context.lock().unwrap();
+            context.overlay.clear();
+            context.on_frame.clone()
+        };
+
+        self.call_each(bus, registers, callbacks)
+    }
+
+    pub fn vblank(&mut self, bus: &mut Bus, registers: CpuState) -> Result<()> {
+        let callbacks = self.context.lock().unwrap().on_vblank.clone();
+        self.call_each(bus, registers, callbacks)
+    }
+
+    /// Call the write callbacks of each of `writes`, which the bus watched.
+    pub fn writes(
+        &mut self,
+        bus: &mut Bus,
+        registers: CpuState,
+        writes: &[(Word, HalfWord)],
+    ) -> Result<()> {
+        let calls: Vec<_> = {
+            let context = self.context.lock().unwrap();
+            writes
+                .iter()
+                .flat_map(|&(address, value)| {
+                    context
+                        .on_write
+                        .iter()
+                        .filter(move |(range, _)| range.contains(&address))
+                        .map(move |(_, callback)| (callback.clone(), address, value))
+                })
+                .collect()
+        };
+        if calls.is_empty() {
+            return Ok(());
+        }
+
+        self.prepare(bus, registers);
+        let result = calls
+            .into_iter()
+            .try_for_each(|(callback, address, value)| {
+                let _: Dynamic =
+                    callback.call(&self.engine, &self.ast, (address as i64, value as i64))?;
+                Ok(())
+            });
+        self.apply_writes(bus);
+        result
+    }
+
+    /// Text to draw over the screen.
+    pub fn overlay(&self) -> Vec<OverlayText> {
+        self.context.lock().unwrap().overlay.clone()
+    }
+
+    fn call_each(
+        &mut self,
+        bus: &mut Bus,
+        registers: CpuState,
+        callbacks: Vec<FnPtr>,
+    ) -> Result<()> {
+        if callbacks.is_empty() {
+            return Ok(());
+        }
+
+        self.prepare(bus, registers);
+        let result = callbacks.iter().try_for_each(|callback| {
+            let _: Dynamic = callback.call(&self.engine, &self.ast, ())?;
+            Ok(())
+        });
+        self.apply_writes(bus);
+        result
+    }
+
+    /// Snapshot the machine for the script functions.
+    fn prepare(&mut self, bus: &Bus, registers: CpuState) {
+        let mut context = self.context.lock().unwrap();
+        bus.copy_memory(&mut context.memory);
+        context.registers = registers;
+        context.frame_count = bus.gpu().frame_count();
+    }
+
+    /// Apply the writes and fault rules of the callbacks.
+    fn apply_writes(&mut self, bus: &mut Bus) {
+        let (writes, faults) = {
+            let mut context = self.context.lock().unwrap();
+            (
+                std::mem::take(&mut context.writes),
+                std::mem::take(&mut context.faults),
+            )
+        };
+        for (address, byte) in writes {
+            bus.poke(address, byte);
+        }
+
+        for rule in faults {
+            let rule = match rule {
+                Some(rule) => rule,
+                None => {
+                    bus.set_fault_injector(None);
+                    continue;
+                }
+            };
+            match bus.fault_injector_mut() {
+                Some(faults) => faults.add_rule(rule),
+                None => {
+                    let mut faults = FaultInjector::new(FAULT_SEED);
+                    faults.add_rule(rule);
+                    bus.set_fault_injector(Some(faults));
+                }
+            }
+        }
+    }
+}
+
+/// An engine with the script functions on `context`.
+fn engine(context: &Arc<Mutex<Context>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.on_print(|text| log::info!("script: {}", text));
+
+    let c = context.clone();
+    engine.register_fn("on_frame", move |callback: FnPtr| {
+        c.lock().unwrap().on_frame.push(callback)
+    });
+    let c = context.clone();
+    engine.register_fn("on_vblank", move |callback: FnPtr| {
+        c.lock().unwrap().on_vblank.push(callback)
+    });
+    let c = context.clone();
+    engine.register_fn("on_write", move |address: i64, callback: FnPtr| {
+        let address = address as Word;
+        c.lock()
+            .unwrap()
+            .on_write
+            .push((address..=address, callback))
+    });
+    let c = context.clone();
+    engine.register_fn("on_write", move |start: i64, end: i64, callback: FnPtr| {
+        c.lock()
+            .unwrap()
+            .on_write
+            .push((start as Word..=end as Word, callback))
+    });
+
+    let c = context.clone();
+    engine.register_fn("read", move |address: i64| c.lock().unwrap().read(address));
+    let c = context.clone();
+    engine.register_fn("read16", move |address: i64| {
+        let context = c.lock().unwrap();
+        context.read(address) | context.read(address + 1) << 8
+    });
+    let c = context.clone();
+    engine.register_fn("write", move |address: i64, value: i64| {
+        let mut context = c.lock().unwrap();
+        let (address, byte) = (address as Word, value as HalfWord);
+        context.memory[address as usize] = byte;
+        context.writes.push((address, byte));
+    });
+
+    let c = context.clone();
+    engine.register_fn(
+        "reg",
+        move |name: &str| -> Result<i64, Box<EvalAltResult>> {
+            let s = c.lock().unwrap().registers;
+            let value = match name {
+                "a" => s.a as Word,
+                "f" => s.f as Word,
+                "b" => s.b as Word,
+                "c" => s.c as Word,
+                "d" => s.d as Word,
+                "e" => s.e as Word,
+                "h" => s.h as Word,
+                "l" => s.l as Word,
+                "af" => join_half_words(s.a, s.f),
+                "bc" => join_half_words(s.b, s.c),
+                "de" => join_half_words(s.d, s.e),
+                "hl" => join_half_words(s.h, s.l),
+                "sp" => s.sp,
+                "pc" => s.pc,
+                _ => return Err(format!("unknown register {}", name).into()),
+            };
+            Ok(value as i64)
+        },
+    );
+    let c = context.clone();
+    engine.register_fn("frame_count", move || c.lock().unwrap().frame_count as i64);
+
+    let c = context.clone();
+    engine.register_fn("draw_text", move |x: i64, y: i64, text: &str| {
+        c.lock().unwrap().overlay.push(OverlayText {
+            x: x as i32,
+            y: y as i32,
+            text: text.to_string(),
+            color: WHITE,
+        })
+    });
+    let c = context.clone();
+    engine.register_fn("draw_text", move |x: i64, y: i64, text: &str, rgb: i64| {
+        let [_, r, g, b] = (rgb as u32).to_be_bytes();
+        c.lock().unwrap().overlay.push(OverlayText {
+            x: x as i32,
+            y: y as i32,
+            text: text.to_string(),
+            color: [r, g, b, 0xFF],
+        })
+    });
+
+    let c = context.clone();
+    engine.register_fn("fault_bit_flip", move |start: i64, end: i64, every: i64| {
+        c.lock().unwrap().faults.push(Some(FaultRule {
+            addresses: start as Word..=end as Word,
+            fault: Fault::BitFlip,
+            every: every.max(1) as usize,
+        }))
+    });
+    let c = context.clone();
+    engine.register_fn(
+        "fault_delay_write",
+        move |start: i64, end: i64, every: i64, writes: i64| {
+            c.lock().unwrap().faults.push(Some(FaultRule {
+                addresses: start as Word..=end as Word,
+                fault: Fault::DelayedWrite(writes.max(1) as usize),
+                every: every.max(1) as usize,
+            }))
+        },
+    );
+    let c = context.clone();
+    engine.register_fn("clear_faults", move || c.lock().unwrap().faults.push(None));
+
+    engine
+}
@@ -0,0 +1,247 @@
+//! Serial port, and link cables connecting the serial ports of two
+//! emulator instances.
+
+use crate::state::{StateReader, StateWriter};
+use crate::{HalfWord, Word};
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// Clock cycles per bit with the internal clock (8192Hz).
+const CYCLES_PER_BIT: usize = 512;
+
+/// The other end of the link cable. Bytes are exchanged whole: the side
+/// with the internal clock exchanges a byte once its 8 bits are shifted,
+/// with the byte the other side offered while waiting for the clock.
+pub trait LinkCable: Send {
+    /// Our clock shifted out `byte`. Returns the byte shifted in, 0xFF when
+    /// the other side is not waiting for a transfer.
+    fn exchange(&mut self, byte: HalfWord) -> HalfWord;
+
+    /// Offer `byte` to the next transfer clocked by the other side, or
+    /// withdraw the offer with `None`.
+    fn listen(&mut self, byte: Option<HalfWord>);
+
+    /// The byte the other side shifted in for our offer, once.
+    fn receive(&mut self) -> Option<HalfWord>;
+}
+
+/// Nothing plugged into the link port.
+pub struct NoLinkCable;
+
+impl LinkCable for NoLinkCable {
+    fn exchange(&mut self, _byte: HalfWord) -> HalfWord {
+        0xFF
+    }
+
+    fn listen(&mut self, _byte: Option<HalfWord>) {}
+
+    fn receive(&mut self) -> Option<HalfWord> {
+        None
+    }
+}
+
+/// One end of an in-process link cable, see [`InProcessLink::pair`].
+pub struct InProcessLink {
+    local: Arc<Mutex<LinkEnd>>,
+    remote: Arc<Mutex<LinkEnd>>,
+}
+
+#[derive(Default)]
+struct LinkEnd {
+    // Byte waiting for the other side's clock
+    offer: Option<HalfWord>,
+    // Byte the other side shifted in for the offer
+    received: Option<HalfWord>,
+}
+
+impl InProcessLink {
+    /// The two ends of a cable, for two cores in the same process.
+    pub fn pair() -> (InProcessLink, InProcessLink) {
+        let a = Arc::new(Mutex::new(LinkEnd::default()));
+        let b = Arc::new(Mutex::new(LinkEnd::default()));
+
+        (
+            InProcessLink {
+                local: a.clone(),
+                remote: b.clone(),
+            },
+            InProcessLink {
+                local: b,
+                remote: a,
+            },
+        )
+    }
+}
+
+impl LinkCable for InProcessLink {
+    fn exchange(&mut self, byte: HalfWord) -> HalfWord {
+        let mut remote = self.remote.lock().unwrap();
+
+        match remote.offer.take() {
+            Some(theirs) => {
+                remote.received = Some(byte);
+                theirs
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn listen(&mut self, byte: Option<HalfWord>) {
+        self.local.lock().unwrap().offer = byte;
+    }
+
+    fn receive(&mut self) -> Option<HalfWord> {
+        self.local.lock().unwrap().received.take()
+    }
+}
+
+/// Serial port registers
+///```text
+/// FF01  SB  Data to send, replaced bit by bit with the received data
+/// FF02  SC  Bit 7: transfer start/in progress, Bit 0: clock (1 = internal)
+/// ```
+/// Without a link cable the received bits are all 1, and transfers with the
+/// external clock never complete.
+/// Ref https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+pub struct Serial {
+    sb: HalfWord,
+    sc: HalfWord,
+    // Clock cycles until the next bit is shifted
+    counter: usize,
+    // Bits left in the current transfer
+    bits: usize,
+    // Byte being sent with the internal clock, SB fills up with 1s meanwhile
+    sending: HalfWord,
+    // Every byte sent, test ROMs print their results this way
+    output: Vec<u8>,
+    link: Box<dyn LinkCable>,
+}
+
+impl Default for Serial {
+    fn default() -> Serial {
+        Serial {
+            sb: 0,
+            sc: 0,
+            counter: 0,
+            bits: 0,
+            sending: 0,
+            output: Vec::new(),
+            link: Box::new(NoLinkCable),
+        }
+    }
+}
+
+impl Serial {
+    pub fn connect(&mut self, link: Box<dyn LinkCable>) {
+        self.link = link;
+        self.offer();
+    }
+
+    /// Unplug the link cable, e.g. to plug it into a new core.
+    pub fn take_link(&mut self) -> Box<dyn LinkCable> {
+        self.link.listen(None);
+        std::mem::replace(&mut self.link, Box::new(NoLinkCable))
+    }
+
+    /// Returns whether a transfer completed, which requests the serial interrupt.
+    pub fn tick(&mut self, cycles: usize) -> bool {
+        if !self.transferring() {
+            return false;
+        }
+
+        if !self.internal_clock() {
+            return match self.link.receive() {
+                Some(byte) => {
+                    self.sb = byte;
+                    self.sc &= !0x80;
+                    true
+                }
+                None => false,
+            };
+        }
+
+        self.counter += cycles;
+        while self.counter >= CYCLES_PER_BIT && self.bits > 0 {
+            self.counter -= CYCLES_PER_BIT;
+            self.sb = self.sb << 1 | 1;
+            self.bits -= 1;
+        }
+
+        if self.bits > 0 {
+            return false;
+        }
+
+        self.sb = self.link.exchange(self.sending);
+        self.sc &= !0x80;
+        true
+    }
+
+    /// Read a serial register, `address` relative to 0xFF01.
+    pub fn read(&self, address: Word) -> HalfWord {
+        match address {
+            0 => self.sb,
+            // Bits 6-1 are unused
+            _ => self.sc | 0x7E,
+        }
+    }
+
+    /// Write a serial register, `address` relative to 0xFF01.
+    pub fn write(&mut self, address: Word, byte: HalfWord) {
+        match address {
+            0 => self.sb = byte,
+            _ => {
+                self.sc = byte & 0x81;
+
+                if self.transferring() {
+                    self.output.push(self.sb);
+                }
+                if self.transferring() && self.internal_clock() {
+                    self.sending = self.sb;
+                    self.counter = 0;
+                    self.bits = 8;
+                }
+            }
+        }
+
+        self.offer();
+    }
+
+    /// Offer SB to the other side while waiting for its clock.
+    fn offer(&mut self) {
+        let waiting = self.transferring() && !self.internal_clock();
+        self.link.listen(if waiting { Some(self.sb) } else { None });
+    }
+
+    /// The transfer in progress, without the bytes sent so far. An offer to
+    /// the other side of the link cable is made again.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.sb);
+        state.u8(self.sc);
+        state.usize(self.counter);
+        state.usize(self.bits);
+        state.u8(self.sending);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.sb = state.u8()?;
+        self.sc = state.u8()? & 0x81;
+        self.counter = state.usize()?;
+        self.bits = state.usize()?.min(8);
+        self.sending = state.u8()?;
+        self.offer();
+
+        Ok(())
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    fn transferring(&self) -> bool {
+        self.sc & 0x80 != 0
+    }
+
+    fn internal_clock(&self) -> bool {
+        self.sc & 0x01 != 0
+    }
+}
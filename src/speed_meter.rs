@@ -0,0 +1,74 @@
+//! Emulated frames per second, and the speed relative to the hardware, for
+//! diagnosing performance problems.
+//!
+//! The numbers are averaged over windows of a second, so they stay readable
+//! in the window title.
+
+use crate::frame_timer::FRAME_RATE;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct SpeedMeter {
+    // Start of the current window
+    start: Instant,
+    frames: usize,
+    fps: f64,
+}
+
+impl Default for SpeedMeter {
+    fn default() -> SpeedMeter {
+        SpeedMeter::new()
+    }
+}
+
+impl SpeedMeter {
+    pub fn new() -> SpeedMeter {
+        SpeedMeter {
+            start: Instant::now(),
+            frames: 0,
+            fps: 0.0,
+        }
+    }
+
+    /// Count `frames` emulated frames. Returns whether a window ended and the
+    /// numbers changed.
+    pub fn add_frames(&mut self, frames: usize) -> bool {
+        self.frames += frames;
+
+        let elapsed = self.start.elapsed();
+        if elapsed < WINDOW {
+            return false;
+        }
+
+        self.fps = self.frames as f64 / elapsed.as_secs_f64();
+        self.start = Instant::now();
+        self.frames = 0;
+        true
+    }
+
+    /// Start a new window without counting the time since the last frame,
+    /// e.g. while paused.
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+        self.frames = 0;
+    }
+
+    /// Emulated frames per second in the last window.
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    /// Speed in the last window, 1.0 for as fast as the hardware.
+    pub fn speed(&self) -> f64 {
+        self.fps / FRAME_RATE
+    }
+}
+
+impl fmt::Display for SpeedMeter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} fps, {:.0}%", self.fps, self.speed() * 100.0)
+    }
+}
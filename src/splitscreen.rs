@@ -0,0 +1,184 @@
+//! Two cores side by side in one window, for two-player games over a link
+//! cable, e.g. Tetris VS.
+//!
+//! The cores take turns running a scanline worth of cycles, so a byte sent
+//! over the cable reaches the other side in well under a frame. `gbemu
+//! --link` also faces their infrared ports, see
+//! [`InProcessIr`](crate::infrared::InProcessIr).
+//!
+//! Both cores are autosaved together, so going back after a crash keeps
+//! them in step.
+
+use crate::emulator::{Emulator, AUTOSAVE_INTERVAL, CYCLES_PER_FRAME};
+use crate::frame_timer::FrameTimer;
+use crate::joypad::Button;
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use anyhow::Result;
+use pixels::{Pixels, SurfaceTexture};
+use winit::dpi::LogicalSize;
+use winit::event::{Event, VirtualKeyCode};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::WindowBuilder;
+use winit_input_helper::WinitInputHelper;
+
+/// Clock cycles a core runs before the other one gets its turn.
+const SLICE: usize = 456;
+
+/// Keys of the left player.
+const LEFT_KEYS: [(VirtualKeyCode, Button); 8] = [
+    (VirtualKeyCode::W, Button::Up),
+    (VirtualKeyCode::S, Button::Down),
+    (VirtualKeyCode::A, Button::Left),
+    (VirtualKeyCode::D, Button::Right),
+    (VirtualKeyCode::H, Button::A),
+    (VirtualKeyCode::G, Button::B),
+    (VirtualKeyCode::Space, Button::Start),
+    (VirtualKeyCode::LShift, Button::Select),
+];
+
+/// Keys of the right player.
+const RIGHT_KEYS: [(VirtualKeyCode, Button); 8] = [
+    (VirtualKeyCode::Up, Button::Up),
+    (VirtualKeyCode::Down, Button::Down),
+    (VirtualKeyCode::Left, Button::Left),
+    (VirtualKeyCode::Right, Button::Right),
+    (VirtualKeyCode::Period, Button::A),
+    (VirtualKeyCode::Comma, Button::B),
+    (VirtualKeyCode::Return, Button::Start),
+    (VirtualKeyCode::RShift, Button::Select),
+];
+
+/// Run `left` and `right` in one window at 2x until it is closed. Connect
+/// their serial ports with [`crate::serial::InProcessLink::pair`] first.
+pub fn start(mut left: Emulator, mut right: Emulator) -> Result<()> {
+    let event_loop = EventLoop::new();
+    let mut input = WinitInputHelper::new();
+    let title = format!(
+        "gbemu – {} vs {}",
+        left.cartridge_header().title,
+        right.cartridge_header().title
+    );
+    let window = {
+        let size = LogicalSize::new(SCREEN_WIDTH as f64 * 4.0, SCREEN_HEIGHT as f64 * 2.0);
+        WindowBuilder::new()
+            .with_title(&title)
+            .with_inner_size(size)
+            .with_min_inner_size(size)
+            .build(&event_loop)?
+    };
+
+    let mut pixels = {
+        let window_size = window.inner_size();
+        let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
+        Pixels::new(
+            SCREEN_WIDTH as u32 * 2,
+            SCREEN_HEIGHT as u32,
+            surface_texture,
+        )?
+    };
+    let mut timer = FrameTimer::new();
+    let mut crashed = false;
+    // Save states of both cores, taken every AUTOSAVE_INTERVAL frames
+    let mut autosave: Option<(Vec<u8>, Vec<u8>)> = None;
+    let mut frames = 0;
+
+    event_loop.run(move |event, _, control_flow| {
+        match event {
+            Event::MainEventsCleared if !crashed => {
+                for _ in 0..CYCLES_PER_FRAME / SLICE {
+                    let result = left
+                        .try_run_cycles(SLICE)
+                        .and_then(|()| right.try_run_cycles(SLICE));
+                    if let Err(report) = result {
+                        log::error!("{}", report);
+                        window.set_title(&format!(
+                            "{} - crashed (L: load autosave, Esc: quit)",
+                            title
+                        ));
+                        crashed = true;
+                        break;
+                    }
+                }
+                timer.wait();
+
+                frames += 1;
+                if !crashed && frames % AUTOSAVE_INTERVAL == 0 {
+                    autosave = Some((left.save_state(), right.save_state()));
+                }
+
+                draw(&left, &right, pixels.get_frame());
+                window.request_redraw();
+            }
+            Event::RedrawRequested(_) => pixels.render().unwrap(),
+            _ => {}
+        }
+
+        if input.update(&event) {
+            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+                for emulator in [&left, &right] {
+                    log::info!("{}", emulator.telemetry().summary());
+                }
+                for emulator in [&mut left, &mut right].iter_mut() {
+                    if let Err(e) = emulator.write_battery_save() {
+                        log::error!("failed to write the battery save: {}", e);
+                    }
+                }
+                // `--record-movie` records the left core
+                if let Err(e) = left.stop_movie_recording() {
+                    log::error!("failed to finish the movie: {}", e);
+                }
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+
+            if crashed && input.key_pressed(VirtualKeyCode::L) {
+                let result = match &autosave {
+                    Some((left_state, right_state)) => left
+                        .load_state(left_state)
+                        .and_then(|()| right.load_state(right_state)),
+                    None => Err(anyhow::anyhow!("there is no autosave yet")),
+                };
+                match result {
+                    Ok(()) => {
+                        log::info!("loaded the autosave");
+                        window.set_title(&title);
+                        timer.restart();
+                        crashed = false;
+                    }
+                    Err(e) => log::error!("{}", e),
+                }
+            }
+
+            for (emulator, keys) in [(&mut left, &LEFT_KEYS), (&mut right, &RIGHT_KEYS)].iter_mut()
+            {
+                for &(key, button) in keys.iter() {
+                    if input.key_pressed(key) {
+                        emulator.set_button_state(button, true);
+                    } else if input.key_released(key) {
+                        emulator.set_button_state(button, false);
+                    }
+                }
+            }
+
+            if let Some(size) = input.window_resized() {
+                pixels.resize_surface(size.width, size.height);
+            }
+
+            window.request_redraw();
+        }
+    });
+}
+
+/// Interleave the rows of both screens into one image twice as wide.
+fn draw(left: &Emulator, right: &Emulator, output: &mut [u8]) {
+    let row = SCREEN_WIDTH * 4;
+
+    for (side, emulator) in [left, right].iter().enumerate() {
+        // RGBA8888 by default, as pixels expects
+        let screen = emulator.framebuffer();
+        for (y, source) in screen.chunks_exact(row).enumerate() {
+            let offset = y * row * 2 + side * row;
+            output[offset..offset + row].copy_from_slice(source);
+        }
+    }
+}
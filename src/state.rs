@@ -0,0 +1,155 @@
+//! Save states, snapshots of the whole machine.
+//!
+//! Every component writes its state in a fixed order as little endian
+//! values, and reads it back in the same order. Settings of the host side
+//! (PPU accuracy, palettes, callbacks, audio sinks) are not part of it.
+
+use anyhow::Result;
+use std::convert::TryInto;
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl Default for StateWriter {
+    fn default() -> StateWriter {
+        StateWriter::new()
+    }
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// As 64 bits, so states move between 32 and 64 bit hosts.
+    pub fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// A length prefixed byte string.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.usize(bytes.len());
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            anyhow::bail!("save state is truncated");
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => anyhow::bail!("save state is corrupt: {:#04x} is not a bool", byte),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn usize(&mut self) -> Result<usize> {
+        let value = self.u64()?;
+        if value > usize::MAX as u64 {
+            anyhow::bail!("save state is corrupt: {} is out of range", value);
+        }
+
+        Ok(value as usize)
+    }
+
+    pub fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.usize()?;
+        self.take(len)
+    }
+
+    /// Read a byte string of exactly the size of `buffer` into it, e.g. a
+    /// RAM whose size is given by the cartridge.
+    pub fn bytes_into(&mut self, buffer: &mut [u8]) -> Result<()> {
+        let bytes = self.bytes()?;
+        if bytes.len() != buffer.len() {
+            anyhow::bail!(
+                "save state is corrupt: {} bytes where {} were expected",
+                bytes.len(),
+                buffer.len()
+            );
+        }
+        buffer.copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    /// Everything not read yet.
+    pub fn rest(self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Fails unless everything was read.
+    pub fn finish(self) -> Result<()> {
+        if !self.data.is_empty() {
+            anyhow::bail!("save state is corrupt: {} trailing bytes", self.data.len());
+        }
+
+        Ok(())
+    }
+}
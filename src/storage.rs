@@ -0,0 +1,51 @@
+//! Where battery saves (`.sav`) and save states (`.state`) are kept.
+//!
+//! Frontends can plug in their own [`SaveStorage`] (cloud sync, a database,
+//! browser local storage) instead of the filesystem.
+
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+pub trait SaveStorage: Send {
+    /// Read a save file, `None` if it does not exist yet.
+    fn load(&self, name: &str) -> io::Result<Option<Vec<u8>>>;
+
+    fn store(&mut self, name: &str, data: &[u8]) -> io::Result<()>;
+}
+
+/// Save files in a directory on the local filesystem.
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> FileStorage {
+        FileStorage { dir: dir.into() }
+    }
+}
+
+impl Default for FileStorage {
+    fn default() -> FileStorage {
+        FileStorage::new(".")
+    }
+}
+
+impl SaveStorage for FileStorage {
+    fn load(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.dir.join(name)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn store(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+
+        // Write to a temporary file first so a crash never leaves a torn save
+        let path = self.dir.join(name);
+        let tmp = self.dir.join(format!("{}.tmp", name));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(tmp, path)
+    }
+}
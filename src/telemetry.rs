@@ -0,0 +1,88 @@
+//! Records every unimplemented feature the running ROM touches, so users can
+//! file actionable reports and maintainers can see what to implement next.
+//!
+//! The records are kept per core, see
+//! [`Emulator::telemetry`](crate::emulator::Emulator::telemetry).
+
+use crate::{HalfWord, Word};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Unimplemented {
+    Opcode(HalfWord),
+    CbOpcode(HalfWord),
+    IoRegister(Word),
+    /// Write to a mapper register of the cartridge, keyed by its address range
+    MapperRegister(Word),
+}
+
+impl fmt::Display for Unimplemented {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unimplemented::Opcode(opcode) => write!(f, "opcode {:02X}", opcode),
+            Unimplemented::CbOpcode(opcode) => write!(f, "opcode CB {:02X}", opcode),
+            Unimplemented::IoRegister(address) => write!(f, "I/O register {:04X}", address),
+            Unimplemented::MapperRegister(address) => write!(f, "mapper register {:04X}", address),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Record {
+    pub count: usize,
+    /// Address of the instruction that first touched the feature
+    pub first_pc: Word,
+}
+
+/// The unimplemented features one core touched, kept across
+/// [`Emulator::reset`](crate::emulator::Emulator::reset). Clones share the
+/// records, e.g. to print them from a panic hook.
+#[derive(Debug, Clone, Default)]
+pub struct Telemetry {
+    records: Arc<Mutex<BTreeMap<Unimplemented, Record>>>,
+}
+
+impl Telemetry {
+    /// Count a use of `feature` by the instruction at `pc`.
+    pub(crate) fn record(&self, feature: Unimplemented, pc: Word) {
+        self.records
+            .lock()
+            .unwrap()
+            .entry(feature)
+            .or_insert(Record {
+                count: 0,
+                first_pc: pc,
+            })
+            .count += 1;
+    }
+
+    pub fn records(&self) -> Vec<(Unimplemented, Record)> {
+        let records = self.records.lock().unwrap();
+        records.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    /// Human readable table of everything recorded so far.
+    pub fn summary(&self) -> String {
+        let records = self.records();
+        if records.is_empty() {
+            return "no unimplemented features were used".to_string();
+        }
+
+        let mut summary = format!(
+            "{:<24} {:>10} {:>9}\n",
+            "unimplemented", "count", "first pc"
+        );
+        for (feature, record) in records {
+            summary += &format!(
+                "{:<24} {:>10} {:>9}\n",
+                feature.to_string(),
+                record.count,
+                format!("{:04X}", record.first_pc)
+            );
+        }
+
+        summary
+    }
+}
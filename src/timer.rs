@@ -0,0 +1,142 @@
+use crate::state::{StateReader, StateWriter};
+use crate::{HalfWord, Word};
+use anyhow::Result;
+
+/// Timer registers
+///```text
+/// FF04  DIV   Upper 8 bits of the internal 16 bit counter, incremented at 16384Hz
+/// FF05  TIMA  Timer counter, incremented at the frequency selected by TAC
+/// FF06  TMA   Loaded into TIMA when it overflows
+/// FF07  TAC   Bit 2: enable, Bit 1-0: clock select
+///             (00: 4096Hz, 01: 262144Hz, 10: 65536Hz, 11: 16384Hz)
+/// ```
+/// Ref https://gbdev.io/pandocs/Timer_and_Divider_Registers.html
+/// Ref https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
+#[derive(Debug, Default)]
+pub struct Timer {
+    // DIV is the upper half of this counter, incremented every clock cycle
+    counter: u16,
+    tima: HalfWord,
+    tma: HalfWord,
+    tac: HalfWord,
+    // TIMA overflowed and reads 0 for one machine cycle before the reload
+    reload_pending: bool,
+    // TIMA was reloaded from TMA during the current machine cycle
+    reloading: bool,
+}
+
+impl Timer {
+    /// Advance by one machine cycle worth of `cycles` clock cycles. Returns
+    /// whether TIMA was reloaded from TMA, which requests the timer interrupt.
+    pub fn tick(&mut self, cycles: usize) -> bool {
+        self.reloading = false;
+
+        // The reload and the interrupt happen one machine cycle after the overflow
+        let reloaded = self.reload_pending;
+        if self.reload_pending {
+            self.reload_pending = false;
+            self.reloading = true;
+            self.tima = self.tma;
+        }
+
+        for _ in 0..cycles {
+            let before = self.signal();
+            self.counter = self.counter.wrapping_add(1);
+
+            if before && !self.signal() {
+                self.increment_tima();
+            }
+        }
+
+        reloaded
+    }
+
+    /// Read a timer register, `address` relative to 0xFF04.
+    pub fn read(&self, address: Word) -> HalfWord {
+        match address {
+            0 => (self.counter >> 8) as HalfWord,
+            1 => self.tima,
+            2 => self.tma,
+            // Upper 5 bits are unused
+            _ => self.tac | 0xF8,
+        }
+    }
+
+    /// Write a timer register, `address` relative to 0xFF04.
+    pub fn write(&mut self, address: Word, byte: HalfWord) {
+        let before = self.signal();
+
+        match address {
+            // Any write resets the whole counter
+            0 => self.counter = 0,
+            1 => {
+                // Writing during the delay cancels the reload and the interrupt,
+                // while on the reload cycle itself TMA wins
+                if !self.reloading {
+                    self.tima = byte;
+                    self.reload_pending = false;
+                }
+            }
+            2 => {
+                self.tma = byte;
+                if self.reloading {
+                    self.tima = byte;
+                }
+            }
+            _ => self.tac = byte & 0x07,
+        }
+
+        // Resetting DIV or changing TAC can drop the signal TIMA counts on,
+        // which is seen as a falling edge
+        if before && !self.signal() {
+            self.increment_tima();
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.counter);
+        state.u8(self.tima);
+        state.u8(self.tma);
+        state.u8(self.tac);
+        state.bool(self.reload_pending);
+        state.bool(self.reloading);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        self.counter = state.u16()?;
+        self.tima = state.u8()?;
+        self.tma = state.u8()?;
+        self.tac = state.u8()? & 0x07;
+        self.reload_pending = state.bool()?;
+        self.reloading = state.bool()?;
+
+        Ok(())
+    }
+
+    fn increment_tima(&mut self) {
+        let (tima, overflowed) = self.tima.overflowing_add(1);
+
+        // TIMA reads 0 until the reload on the next machine cycle
+        self.tima = tima;
+        self.reload_pending |= overflowed;
+    }
+
+    /// TIMA counts on the falling edge of this signal: the counter bit
+    /// selected by TAC, ANDed with the enable bit.
+    fn signal(&self) -> bool {
+        self.enabled() && self.counter & self.selected_bit() != 0
+    }
+
+    fn enabled(&self) -> bool {
+        self.tac & 0x04 != 0
+    }
+
+    fn selected_bit(&self) -> u16 {
+        match self.tac & 0x03 {
+            0b00 => 1 << 9, // 4096Hz
+            0b01 => 1 << 3, // 262144Hz
+            0b10 => 1 << 5, // 65536Hz
+            _ => 1 << 7,    // 16384Hz
+        }
+    }
+}
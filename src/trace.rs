@@ -0,0 +1,71 @@
+//! One line of CPU state per executed instruction, in the formats of common
+//! reference emulators so a trace can be diffed against theirs.
+
+use crate::cpu::CpuState;
+use crate::HalfWord;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// Gameboy Doctor
+    ///```text
+    /// A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+    /// ```
+    /// Ref https://github.com/robert/gameboy-doctor
+    Doctor,
+    /// BGB's debugger log with its default format string
+    ///```text
+    /// A:01 F:Z-HC BC:0013 DE:00D8 HL:014D SP:FFFE PC:0100 (cy: 0)
+    /// ```
+    Bgb,
+    /// SameBoy's `registers` debugger command, joined on one line
+    ///```text
+    /// AF = $01B0 (Z-HC) BC = $0013 DE = $00D8 HL = $014D SP = $FFFE PC = $0100
+    /// ```
+    SameBoy,
+}
+
+impl FromStr for TraceFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<TraceFormat> {
+        match s {
+            "doctor" => Ok(TraceFormat::Doctor),
+            "bgb" => Ok(TraceFormat::Bgb),
+            "sameboy" => Ok(TraceFormat::SameBoy),
+            _ => anyhow::bail!("unknown trace format {} (doctor, bgb, sameboy)", s),
+        }
+    }
+}
+
+/// Format the state before executing the instruction at `s.pc`. `pcmem` is
+/// the memory at PC..PC+4 and `cycles` the elapsed clock cycles.
+pub fn format_line(
+    format: TraceFormat,
+    s: &CpuState,
+    pcmem: [HalfWord; 4],
+    cycles: usize,
+) -> String {
+    match format {
+        TraceFormat::Doctor => format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            s.a, s.f, s.b, s.c, s.d, s.e, s.h, s.l, s.sp, s.pc, pcmem[0], pcmem[1], pcmem[2], pcmem[3]
+        ),
+        TraceFormat::Bgb => format!(
+            "A:{:02X} F:{} BC:{:02X}{:02X} DE:{:02X}{:02X} HL:{:02X}{:02X} SP:{:04X} PC:{:04X} (cy: {})",
+            s.a, flags(s.f), s.b, s.c, s.d, s.e, s.h, s.l, s.sp, s.pc, cycles
+        ),
+        TraceFormat::SameBoy => format!(
+            "AF = ${:02X}{:02X} ({}) BC = ${:02X}{:02X} DE = ${:02X}{:02X} HL = ${:02X}{:02X} SP = ${:04X} PC = ${:04X}",
+            s.a, s.f, flags(s.f), s.b, s.c, s.d, s.e, s.h, s.l, s.sp, s.pc
+        ),
+    }
+}
+
+/// Flags as letters, `-` for cleared ones, e.g. `Z-HC`.
+fn flags(f: HalfWord) -> String {
+    [(0x80, 'Z'), (0x40, 'N'), (0x20, 'H'), (0x10, 'C')]
+        .iter()
+        .map(|&(mask, name)| if f & mask != 0 { name } else { '-' })
+        .collect()
+}
@@ -0,0 +1,175 @@
+//! Terminal frontend.
+//!
+//! Draws the screen with half-block characters, so one terminal cell holds two
+//! pixels stacked vertically. Needs a terminal of at least 160x72 cells with
+//! true color support.
+//!
+//! The keys are those of the window: arrows, Z (A), X (B), Enter (Start),
+//! and Backspace (Select) as terminals don't report Shift alone. Terminals
+//! don't report key releases either, a button is released
+//! [`HOLD_FRAMES`] frames after the last press of its key, so holding a key
+//! relies on key repeat. Esc or Q quits.
+//!
+//! When the core crashes the last frame stays on screen, R resets and L
+//! loads the autosave.
+
+use crate::emulator::Emulator;
+use crate::joypad::Button;
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+use std::io::{stdout, Write};
+use std::time::Duration;
+
+/// Frames a button stays pressed after a press of its key.
+pub const HOLD_FRAMES: usize = 10;
+
+const JOYPAD_KEYS: [(KeyCode, Button); 8] = [
+    (KeyCode::Right, Button::Right),
+    (KeyCode::Left, Button::Left),
+    (KeyCode::Up, Button::Up),
+    (KeyCode::Down, Button::Down),
+    (KeyCode::Char('z'), Button::A),
+    (KeyCode::Char('x'), Button::B),
+    (KeyCode::Enter, Button::Start),
+    (KeyCode::Backspace, Button::Select),
+];
+
+pub fn start(mut emulator: Emulator) -> Result<()> {
+    let mut stdout = stdout();
+
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    let result = run(&mut emulator, &mut stdout);
+
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+
+    if let Err(e) = emulator.stop_movie_recording() {
+        log::error!("failed to finish the movie: {}", e);
+    }
+    log::info!("{}", emulator.telemetry().summary());
+
+    result
+}
+
+fn run(emulator: &mut Emulator, stdout: &mut impl Write) -> Result<()> {
+    // Pressed buttons and the frames left until they are released
+    let mut held: Vec<(Button, usize)> = Vec::new();
+    let mut crashed = false;
+
+    loop {
+        if crashed {
+            // Nothing runs, wait for a key
+            event::poll(Duration::from_millis(100))?;
+        } else {
+            match emulator.try_run_frame() {
+                Ok(_) => draw(stdout, &emulator.rgba_frame())?,
+                Err(report) => {
+                    log::error!("{}", report);
+                    crashed = true;
+                    draw_status(stdout, "crashed (R: reset, L: load autosave, Q: quit)")?;
+                }
+            }
+        }
+
+        for (button, frames) in held.iter_mut() {
+            *frames -= 1;
+            if *frames == 0 {
+                emulator.set_button_state(*button, false);
+            }
+        }
+        held.retain(|&(_, frames)| frames > 0);
+
+        while event::poll(Duration::ZERO)? {
+            let code = match event::read()? {
+                Event::Key(KeyEvent { code, .. }) => code,
+                _ => continue,
+            };
+            if let KeyCode::Esc | KeyCode::Char('q' | 'Q') = code {
+                return Ok(());
+            }
+
+            if crashed {
+                let result = match code {
+                    KeyCode::Char('r' | 'R') => emulator.reset(),
+                    KeyCode::Char('l' | 'L') => emulator.load_autosave(),
+                    _ => continue,
+                };
+                match result {
+                    Ok(()) => {
+                        crashed = false;
+                        draw_status(stdout, "")?;
+                    }
+                    Err(e) => log::error!("{}", e),
+                }
+                continue;
+            }
+
+            if let Some(button) = joypad_button(code) {
+                emulator.set_button_state(button, true);
+                held.retain(|&(pressed, _)| pressed != button);
+                held.push((button, HOLD_FRAMES));
+            }
+        }
+    }
+}
+
+/// The button mapped to `code`, letters also with Shift or Caps Lock.
+fn joypad_button(code: KeyCode) -> Option<Button> {
+    let code = match code {
+        KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+        code => code,
+    };
+
+    JOYPAD_KEYS
+        .iter()
+        .find(|&&(key, _)| key == code)
+        .map(|&(_, button)| button)
+}
+
+/// A line of text under the screen.
+fn draw_status(stdout: &mut impl Write, text: &str) -> Result<()> {
+    queue!(
+        stdout,
+        cursor::MoveTo(0, (SCREEN_HEIGHT / 2) as u16),
+        ResetColor,
+        Print(text),
+        terminal::Clear(terminal::ClearType::UntilNewLine)
+    )?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// Draw an RGBA `frame`, as returned by [`Emulator::rgba_frame`].
+fn draw(stdout: &mut impl Write, frame: &[u8]) -> Result<()> {
+    let color = |x: usize, y: usize| {
+        let pixel = &frame[(y * SCREEN_WIDTH + x) * 4..];
+        Color::Rgb {
+            r: pixel[0],
+            g: pixel[1],
+            b: pixel[2],
+        }
+    };
+
+    for row in 0..SCREEN_HEIGHT / 2 {
+        queue!(stdout, cursor::MoveTo(0, row as u16))?;
+
+        for x in 0..SCREEN_WIDTH {
+            queue!(
+                stdout,
+                SetForegroundColor(color(x, row * 2)),
+                SetBackgroundColor(color(x, row * 2 + 1)),
+                Print('▀')
+            )?;
+        }
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+//! WAV files of the emulated audio, as 16-bit stereo PCM.
+//! Ref http://soundfile.sapp.org/doc/WaveFormat/
+
+use anyhow::Result;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const HEADER_SIZE: u32 = 44;
+// Bytes per stereo sample
+const BLOCK_ALIGN: u32 = 4;
+
+pub struct WavWriter {
+    output: BufWriter<File>,
+    samples: u32,
+    // First write error, reported by `finish`
+    error: Option<io::Error>,
+}
+
+impl WavWriter {
+    /// Create `path` and write the header, with the sizes filled in by
+    /// [`WavWriter::finish`].
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> Result<WavWriter> {
+        let mut output = BufWriter::new(File::create(path)?);
+
+        output.write_all(b"RIFF")?;
+        output.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        output.write_all(b"WAVE")?;
+        output.write_all(b"fmt ")?;
+        output.write_all(&16u32.to_le_bytes())?;
+        // PCM, 2 channels
+        output.write_all(&1u16.to_le_bytes())?;
+        output.write_all(&2u16.to_le_bytes())?;
+        output.write_all(&sample_rate.to_le_bytes())?;
+        output.write_all(&(sample_rate * BLOCK_ALIGN).to_le_bytes())?;
+        output.write_all(&(BLOCK_ALIGN as u16).to_le_bytes())?;
+        output.write_all(&16u16.to_le_bytes())?;
+        output.write_all(b"data")?;
+        output.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter {
+            output,
+            samples: 0,
+            error: None,
+        })
+    }
+
+    /// Append a stereo sample from -1.0 to 1.0. Errors are kept for
+    /// [`WavWriter::finish`], the samples after one are dropped.
+    pub fn write_sample(&mut self, sample: [f32; 2]) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let mut bytes = [0; BLOCK_ALIGN as usize];
+        for (channel, level) in sample.iter().enumerate() {
+            let level = (level.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            bytes[channel * 2..channel * 2 + 2].copy_from_slice(&level.to_le_bytes());
+        }
+
+        match self.output.write_all(&bytes) {
+            Ok(()) => self.samples += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Fill in the sizes in the header and flush the file.
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e.into());
+        }
+
+        let data_size = self.samples * BLOCK_ALIGN;
+        self.output.seek(SeekFrom::Start(4))?;
+        self.output
+            .write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.output.seek(SeekFrom::Start(40))?;
+        self.output.write_all(&data_size.to_le_bytes())?;
+        self.output.flush()?;
+
+        Ok(())
+    }
+}
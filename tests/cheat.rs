@@ -0,0 +1,85 @@
+mod common;
+
+use common::rom;
+use gbemu::cheat::Cheat;
+use gbemu::emulator::Emulator;
+
+// JR -2
+const LOOP: [u8; 2] = [0x18, 0xFE];
+
+// GameShark: write 0x42 to 0xC000 every frame
+const CODE: &str = "014200C0";
+
+#[test]
+fn gameshark_writes_every_frame() {
+    let mut emu = Emulator::from_rom_byte(rom(&LOOP)).unwrap();
+    emu.add_cheat(CODE).unwrap();
+    emu.run_frame().unwrap();
+
+    assert_eq!(emu.peek(0xC000), 0x42);
+}
+
+#[test]
+fn cheats_survive_reset() {
+    let mut emu = Emulator::from_rom_byte(rom(&LOOP)).unwrap();
+    emu.add_cheat(CODE).unwrap();
+    emu.run_frame().unwrap();
+
+    emu.reset().unwrap();
+    assert_eq!(emu.peek(0xC000), 0x00);
+    emu.run_frame().unwrap();
+
+    assert_eq!(emu.peek(0xC000), 0x42);
+    assert!(emu.remove_cheat(CODE));
+}
+
+#[test]
+fn gameshark_rejects_rom_addresses() {
+    let mut emu = Emulator::from_rom_byte(rom(&LOOP)).unwrap();
+    assert!(emu.add_cheat("01420040").is_err());
+}
+
+#[test]
+fn game_genie_decodes() {
+    let cheat: Cheat = "00A-17B-C49".parse().unwrap();
+    assert_eq!(
+        cheat,
+        Cheat::GameGenie {
+            address: 0x4A17,
+            data: 0x00,
+            compare: Some(0xC8),
+        }
+    );
+
+    let cheat: Cheat = "221-50F".parse().unwrap();
+    assert_eq!(
+        cheat,
+        Cheat::GameGenie {
+            address: 0x0150,
+            data: 0x22,
+            compare: None,
+        }
+    );
+}
+
+/// Runs a ROM reading 0x11 from 0x0150 into 0xFF80 with `code` added.
+fn read_with_cheat(code: &str) -> u8 {
+    // LD HL, $0150; LD A, (HL); LDH ($80), A; JR -8
+    let mut rom = rom(&[0x21, 0x50, 0x01, 0x7E, 0xE0, 0x80, 0x18, 0xF8]);
+    rom[0x0150] = 0x11;
+
+    let mut emu = Emulator::from_rom_byte(rom).unwrap();
+    emu.add_cheat(code).unwrap();
+    emu.run_frame().unwrap();
+    emu.peek(0xFF80)
+}
+
+#[test]
+fn game_genie_compares_the_rom_byte() {
+    // 0x0150 = 0x22
+    assert_eq!(read_with_cheat("221-50F"), 0x22);
+    // ... if it is 0x11
+    assert_eq!(read_with_cheat("221-50F-A0E"), 0x22);
+    // ... if it is 0x33
+    assert_eq!(read_with_cheat("221-50F-206"), 0x11);
+}
@@ -0,0 +1,8 @@
+//! Helpers shared by the integration tests.
+
+/// A ROM running `program` from the entry point at 0x0100.
+pub fn rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    rom
+}
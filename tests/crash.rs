@@ -0,0 +1,52 @@
+mod common;
+
+use common::rom;
+use gbemu::emulator::Emulator;
+use std::sync::{Arc, Mutex};
+
+// Loop while 0xC000 is 0, then run the unimplemented opcode D3:
+// LD HL, $C000; LD A, (HL); CP $00; JR Z, -5; DB $D3
+const CRASH_ON_FLAG: [u8; 9] = [0x21, 0x00, 0xC0, 0x7E, 0xFE, 0x00, 0x28, 0xFB, 0xD3];
+
+fn crashing_core() -> Emulator {
+    let mut emu = Emulator::from_rom_byte(rom(&CRASH_ON_FLAG)).unwrap();
+    emu.set_skip_unimplemented(false);
+    emu.poke(0xC000, 0x00);
+    emu
+}
+
+#[test]
+fn crash_callback_gets_the_report() {
+    let mut emu = crashing_core();
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let seen = messages.clone();
+    emu.set_crash_callback(Some(Box::new(move |report| {
+        seen.lock().unwrap().push(report.message.clone())
+    })));
+
+    emu.try_run_frame().unwrap();
+    emu.poke(0xC000, 0x01);
+    let report = emu.try_run_frame().unwrap_err();
+
+    assert_eq!(*messages.lock().unwrap(), vec![report.message]);
+    assert_eq!(report.trace.last().unwrap().pc, 0x0108);
+}
+
+#[test]
+fn autosave_recovers_from_a_crash() {
+    let mut emu = crashing_core();
+    emu.set_autosave(Some(2));
+    assert!(emu.load_autosave().is_err());
+
+    emu.try_run_frame().unwrap();
+    assert!(!emu.has_autosave());
+    emu.try_run_frame().unwrap();
+    assert!(emu.has_autosave());
+
+    emu.poke(0xC000, 0x01);
+    assert!(emu.try_run_frame().is_err());
+
+    emu.load_autosave().unwrap();
+    assert_eq!(emu.peek(0xC000), 0x00);
+    emu.try_run_frame().unwrap();
+}
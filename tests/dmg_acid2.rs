@@ -0,0 +1,61 @@
+//! Rendering conformance test with the dmg-acid2 test ROM.
+//! Ref https://github.com/mattcurrie/dmg-acid2
+//!
+//! The ROM is not vendored. Point `DMG_ACID2` at `dmg-acid2.gb` to run it,
+//! and `DMG_ACID2_CRC32` at the hash of the reference image to check it:
+//!
+//! ```sh
+//! DMG_ACID2=path/to/dmg-acid2.gb DMG_ACID2_CRC32=xxxxxxxx \
+//!     cargo test --test dmg_acid2 -- --nocapture
+//! ```
+//!
+//! The ROM draws its face once and then loops forever. The last frame after
+//! `FRAMES` frames is hashed with the same CRC-32 as `--frame-hash`, over the
+//! shades (0-3) so the hash doesn't depend on the palette. Without
+//! `DMG_ACID2_CRC32` the hash is only printed, to record it from a frame that
+//! matches the reference image.
+
+use gbemu::emulator::Emulator;
+use gbemu::frame_hash::crc32;
+use gbemu::gpu::Accuracy;
+
+const FRAMES: usize = 60;
+
+fn render(rom: &[u8], accuracy: Accuracy) -> u32 {
+    let mut emu = Emulator::from_rom_byte(rom.to_vec()).unwrap();
+    emu.set_ppu_accuracy(accuracy);
+
+    for _ in 0..FRAMES {
+        emu.run_frame().unwrap();
+    }
+
+    crc32(&emu.last_frame().shades)
+}
+
+#[test]
+fn dmg_acid2() {
+    let path = match std::env::var("DMG_ACID2") {
+        Ok(path) => path,
+        Err(_) => {
+            eprintln!("DMG_ACID2 is not set, skipping");
+            return;
+        }
+    };
+    let expected = std::env::var("DMG_ACID2_CRC32")
+        .ok()
+        .map(|hash| u32::from_str_radix(&hash, 16).expect("DMG_ACID2_CRC32 is not hex"));
+    let rom = std::fs::read(&path).unwrap();
+
+    for &accuracy in &[Accuracy::Scanline, Accuracy::PixelFifo] {
+        let hash = render(&rom, accuracy);
+        println!("{:?}: {:08x}", accuracy, hash);
+
+        if let Some(expected) = expected {
+            assert_eq!(
+                hash, expected,
+                "{:?} frame {:08x}, expected {:08x}",
+                accuracy, hash, expected
+            );
+        }
+    }
+}
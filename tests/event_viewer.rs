@@ -0,0 +1,66 @@
+mod common;
+
+use common::rom;
+use gbemu::emulator::Emulator;
+use gbemu::event_viewer::{DmaKind, EventViewerOptions, DOTS_PER_LINE, LINES_PER_FRAME};
+
+// Start OAM DMA from 0xC000 once and loop, in HRAM as the CPU can't read
+// the ROM during the transfer:
+// LD HL, $FF80; LD (HL+), A for LDH ($46), A; JR -2; LD A, $C0; JP $FF80
+const OAM_DMA: [u8; 20] = [
+    0x21, 0x80, 0xFF, 0x3E, 0xE0, 0x22, 0x3E, 0x46, 0x22, 0x3E, 0x18, 0x22, 0x3E, 0xFE, 0x22, 0x3E,
+    0xC0, 0xC3, 0x80, 0xFF,
+];
+
+fn first_frame(options: EventViewerOptions) -> Emulator {
+    let mut emu = Emulator::from_rom_byte(rom(&OAM_DMA)).unwrap();
+    emu.set_event_viewer(Some(options));
+    emu.run_frame().unwrap();
+    emu
+}
+
+#[test]
+fn oam_dma_is_listed() {
+    let emu = first_frame(EventViewerOptions::default());
+    let transfers = emu.event_viewer().unwrap().transfers();
+
+    assert_eq!(transfers.len(), 1);
+    assert_eq!(transfers[0].kind, DmaKind::Oam);
+    assert_eq!(transfers[0].line, 0);
+    assert_eq!(transfers[0].source, 0xC000);
+    assert_eq!(transfers[0].destination, 0xFE00);
+    assert_eq!(transfers[0].length, 0xA0);
+}
+
+#[test]
+fn oam_dma_shades_its_machine_cycles() {
+    let shaded = first_frame(EventViewerOptions::default());
+    let plain = first_frame(EventViewerOptions {
+        oam_dma: false,
+        ..EventViewerOptions::default()
+    });
+    let shaded = shaded.event_viewer().unwrap().render();
+    let plain = plain.event_viewer().unwrap();
+    assert!(plain.transfers().is_empty());
+    let plain = plain.render();
+
+    assert_eq!(shaded.width, DOTS_PER_LINE);
+    assert_eq!(shaded.height, LINES_PER_FRAME);
+    let differing = shaded
+        .rgba
+        .chunks(4)
+        .zip(plain.rgba.chunks(4))
+        .filter(|(a, b)| a != b)
+        .count();
+    // 160 machine cycles of 4 dots after a machine cycle of setup, which
+    // starts with the marker of the transfer
+    assert_eq!(differing, 160 * 4 + 1);
+}
+
+#[test]
+fn shows_the_last_completed_frame() {
+    let mut emu = first_frame(EventViewerOptions::default());
+    emu.run_frame().unwrap();
+
+    assert!(emu.event_viewer().unwrap().transfers().is_empty());
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::rom;
+use gbemu::emulator::Emulator;
+use gbemu::fault::{Fault, FaultInjector, FaultRule};
+
+fn faults(address: u16, fault: Fault) -> Option<FaultInjector> {
+    let mut faults = FaultInjector::new(7);
+    faults.add_rule(FaultRule {
+        addresses: address..=address,
+        fault,
+        every: 1,
+    });
+    Some(faults)
+}
+
+// LD HL, $C000; LD A, $00; LD (HL), A; JR -3
+const STORE: [u8; 8] = [0x21, 0x00, 0xC0, 0x3E, 0x00, 0x77, 0x18, 0xFD];
+
+#[test]
+fn bit_flip_corrupts_writes() {
+    let mut emu = Emulator::from_rom_byte(rom(&STORE)).unwrap();
+    emu.set_fault_injector(faults(0xC000, Fault::BitFlip));
+    emu.run_frame().unwrap();
+
+    assert_eq!(emu.peek(0xC000).count_ones(), 1);
+}
+
+#[test]
+fn bit_flip_corrupts_reads() {
+    // LD HL, $C000; LD A, (HL); LDH ($80), A; JR -5
+    let program = [0x21, 0x00, 0xC0, 0x7E, 0xE0, 0x80, 0x18, 0xFB];
+    let mut emu = Emulator::from_rom_byte(rom(&program)).unwrap();
+    emu.poke(0xC000, 0x00);
+    emu.set_fault_injector(faults(0xC000, Fault::BitFlip));
+    emu.run_frame().unwrap();
+
+    assert_eq!(emu.peek(0xC000), 0x00);
+    assert_eq!(emu.peek(0xFF80).count_ones(), 1);
+}
+
+#[test]
+fn delayed_write_lands_later() {
+    // LD HL, $C000; LD A, $12; LD (HL), A; LDH ($80), A; LDH ($81), A
+    let program = [0x21, 0x00, 0xC0, 0x3E, 0x12, 0x77, 0xE0, 0x80, 0xE0, 0x81];
+    let mut emu = Emulator::from_rom_byte(rom(&program)).unwrap();
+    emu.poke(0xC000, 0x00);
+    emu.set_fault_injector(faults(0xC000, Fault::DelayedWrite(2)));
+
+    for _ in 0..4 {
+        emu.step().unwrap();
+    }
+    assert_eq!(emu.peek(0xC000), 0x00);
+    emu.step().unwrap();
+    assert_eq!(emu.peek(0xC000), 0x12);
+}
+
+#[test]
+fn without_a_rule_nothing_changes() {
+    let mut emu = Emulator::from_rom_byte(rom(&STORE)).unwrap();
+    emu.poke(0xC000, 0xFF);
+    emu.set_fault_injector(Some(FaultInjector::new(7)));
+    emu.run_frame().unwrap();
+
+    assert_eq!(emu.peek(0xC000), 0x00);
+}
+
+#[cfg(feature = "scripting")]
+#[test]
+fn scripts_inject_faults() {
+    use gbemu::script::Script;
+
+    let path = std::env::temp_dir().join(format!("gbemu-fault-{}.rhai", std::process::id()));
+    std::fs::write(&path, "fault_bit_flip(0xC000, 0xC000, 1);").unwrap();
+    let script = Script::load(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let mut emu = Emulator::from_rom_byte(rom(&STORE)).unwrap();
+    emu.set_script(Some(script.unwrap())).unwrap();
+    emu.run_frame().unwrap();
+
+    assert_eq!(emu.peek(0xC000).count_ones(), 1);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::rom;
+use gbemu::cartridge::Model;
+use gbemu::emulator::Emulator;
+use gbemu::infrared::{InProcessIr, IrDevice, TcpIr};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+// LD A, $C1; LDH ($56), A; JR -2: read enable and the LED on
+const LED_ON: [u8; 6] = [0x3E, 0xC1, 0xE0, 0x56, 0x18, 0xFE];
+// LD A, $C0; LDH ($56), A; JR -2: read enable
+const LISTEN: [u8; 6] = [0x3E, 0xC0, 0xE0, 0x56, 0x18, 0xFE];
+
+fn core(program: &[u8], model: Model) -> Emulator {
+    let mut emu = Emulator::from_rom_byte(rom(program)).unwrap();
+    emu.set_model(model);
+    emu
+}
+
+#[test]
+fn rp_is_cgb_only() {
+    let mut dmg = core(&LISTEN, Model::Dmg);
+    dmg.run_frame().unwrap();
+    assert_eq!(dmg.peek(0xFF56), 0xFF);
+
+    let mut cgb = core(&LISTEN, Model::Cgb);
+    cgb.run_frame().unwrap();
+    // Not receiving
+    assert_eq!(cgb.peek(0xFF56), 0xFE);
+}
+
+#[test]
+fn in_process_ports_face_each_other() {
+    let (ir, other_ir) = InProcessIr::pair();
+    let mut sender = core(&LED_ON, Model::Cgb);
+    let mut receiver = core(&LISTEN, Model::Cgb);
+    sender.connect_ir(Box::new(ir));
+    receiver.connect_ir(Box::new(other_ir));
+
+    sender.run_frame().unwrap();
+    receiver.run_frame().unwrap();
+
+    // Bit 1 clear while receiving
+    assert_eq!(receiver.peek(0xFF56), 0xFC);
+}
+
+fn tcp_pair() -> (TcpIr, TcpIr) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+
+    (
+        TcpIr::from_stream(server).unwrap(),
+        TcpIr::from_stream(client).unwrap(),
+    )
+}
+
+#[test]
+fn tcp_edges_keep_their_timing() {
+    let (mut sender, receiver) = tcp_pair();
+    // A 500 dot pulse, sent long before the receiver polls
+    sender.set_led(true, 100_000);
+    sender.set_led(false, 100_500);
+
+    // The first edge anchors the replay at local time 5_000_000
+    let start = Instant::now();
+    while !receiver.receiving(5_000_000) {
+        assert!(start.elapsed() < Duration::from_secs(5), "nothing received");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    assert!(receiver.receiving(5_000_499));
+    assert!(!receiver.receiving(5_000_500));
+}
@@ -0,0 +1,47 @@
+use gbemu::interrupt::{Interrupt, Interrupts};
+
+#[test]
+fn nothing_pending_without_enable() {
+    let mut interrupts = Interrupts::default();
+    interrupts.request(Interrupt::VBlank);
+
+    assert_eq!(interrupts.pending(), 0);
+    assert_eq!(interrupts.acknowledge(), None);
+    assert_eq!(interrupts.flags, Interrupt::VBlank.bit());
+}
+
+#[test]
+fn lowest_bit_is_serviced_first() {
+    let mut interrupts = Interrupts {
+        flags: 0,
+        enable: 0x1F,
+    };
+    interrupts.request(Interrupt::Joypad);
+    interrupts.request(Interrupt::Timer);
+    interrupts.request(Interrupt::LcdStat);
+
+    assert_eq!(interrupts.acknowledge(), Some(Interrupt::LcdStat));
+    assert_eq!(interrupts.acknowledge(), Some(Interrupt::Timer));
+    assert_eq!(interrupts.acknowledge(), Some(Interrupt::Joypad));
+    assert_eq!(interrupts.acknowledge(), None);
+}
+
+#[test]
+fn acknowledge_clears_only_the_serviced_bit() {
+    let mut interrupts = Interrupts {
+        flags: 0x1F,
+        enable: Interrupt::Serial.bit() | Interrupt::Joypad.bit(),
+    };
+
+    assert_eq!(interrupts.acknowledge(), Some(Interrupt::Serial));
+    assert_eq!(interrupts.flags, 0x1F & !Interrupt::Serial.bit());
+}
+
+#[test]
+fn vectors() {
+    assert_eq!(Interrupt::VBlank.vector(), 0x40);
+    assert_eq!(Interrupt::LcdStat.vector(), 0x48);
+    assert_eq!(Interrupt::Timer.vector(), 0x50);
+    assert_eq!(Interrupt::Serial.vector(), 0x58);
+    assert_eq!(Interrupt::Joypad.vector(), 0x60);
+}
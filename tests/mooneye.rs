@@ -0,0 +1,138 @@
+//! Accuracy tracking with the mooneye-gb acceptance test ROMs.
+//! Ref https://github.com/Gekkio/mooneye-test-suite
+//!
+//! The ROMs are not vendored. Point `MOONEYE` at the directory of the built
+//! ROMs, e.g. `acceptance`, to run every `*.gb` below it:
+//!
+//! ```sh
+//! MOONEYE=path/to/mts/acceptance cargo test --test mooneye -- --nocapture
+//! ```
+//!
+//! A test ROM finishes at an LD B,B with the Fibonacci numbers 3, 5, 8, 13,
+//! 21, 34 in B, C, D, E, H, L when it passed, or 0x42 in all of them when it
+//! failed. Every ROM is only reported, `MOONEYE_EXPECT` can name a file
+//! listing ROMs (paths relative to `MOONEYE`, one per line) that must pass,
+//! to catch regressions.
+
+use gbemu::emulator::{Break, Emulator};
+use gbemu::CpuState;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+/// Emulated time a ROM gets to finish. The slowest ones take a few seconds.
+const TIMEOUT_CYCLES: usize = 4_194_304 * 30;
+
+const PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Outcome {
+    Passed,
+    Failed,
+    /// Stopped at LD B,B with neither signature
+    Unknown(CpuState),
+    Crashed(String),
+    TimedOut,
+}
+
+fn run(rom: &[u8]) -> Outcome {
+    let mut emu = match Emulator::from_rom_byte(rom.to_vec()) {
+        Ok(emu) => emu,
+        Err(e) => return Outcome::Crashed(e.to_string()),
+    };
+    emu.set_deterministic(true);
+    emu.set_source_breakpoints(true);
+
+    match emu.try_run_to_break(TIMEOUT_CYCLES) {
+        Ok(Some(Break::SourceBreakpoint(_))) => {
+            let s = emu.cpu_state();
+            match [s.b, s.c, s.d, s.e, s.h, s.l] {
+                PASS => Outcome::Passed,
+                [0x42, 0x42, 0x42, 0x42, 0x42, 0x42] => Outcome::Failed,
+                _ => Outcome::Unknown(s),
+            }
+        }
+        Ok(_) => Outcome::TimedOut,
+        Err(report) => Outcome::Crashed(report.message),
+    }
+}
+
+/// Every `*.gb` below `dir`, sorted.
+fn roms(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            found.extend(roms(&path));
+        } else if path.extension() == Some("gb".as_ref()) {
+            found.push(path);
+        }
+    }
+    found.sort();
+
+    found
+}
+
+#[test]
+fn mooneye_acceptance() {
+    let dir = match std::env::var("MOONEYE") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => {
+            eprintln!("MOONEYE is not set, skipping");
+            return;
+        }
+    };
+    let expected: Vec<String> = match std::env::var("MOONEYE_EXPECT") {
+        Ok(path) => std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    // Unimplemented opcodes panic by design, keep the output readable
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut passed = Vec::new();
+    let roms = roms(&dir);
+    for path in &roms {
+        let name = path
+            .strip_prefix(&dir)
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let outcome = run(&std::fs::read(path).unwrap());
+
+        let result = match &outcome {
+            Outcome::Passed => "pass".to_string(),
+            Outcome::Failed => "FAIL".to_string(),
+            Outcome::Unknown(s) => format!(
+                "FAIL registers B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X}",
+                s.b, s.c, s.d, s.e, s.h, s.l
+            ),
+            Outcome::Crashed(message) => format!("CRASH {}", message),
+            Outcome::TimedOut => "TIMEOUT".to_string(),
+        };
+        println!("{:<50} {}", name, result);
+
+        if outcome == Outcome::Passed {
+            passed.push(name);
+        }
+    }
+
+    panic::set_hook(hook);
+
+    println!("passed {} of {}", passed.len(), roms.len());
+    let regressions: Vec<&String> = expected
+        .iter()
+        .filter(|name| !passed.contains(name))
+        .collect();
+    assert!(
+        regressions.is_empty(),
+        "expected to pass: {:?}",
+        regressions
+    );
+}
@@ -0,0 +1,116 @@
+use gbemu::apu::{AudioSink, SAMPLE_RATE};
+use gbemu::audio_ring::{AudioRing, RingReader};
+use gbemu::resampler::Resampler;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn step_follows_the_fill_level() {
+    let mut resampler = Resampler::new(48_000.0, 48_000.0);
+    assert_eq!(resampler.step(), 1.0);
+
+    for _ in 0..200 {
+        resampler.update_fill_level(1.0);
+    }
+    let full = resampler.step();
+    assert!(full > 1.0 && full <= 1.005, "step {} when full", full);
+
+    for _ in 0..400 {
+        resampler.update_fill_level(0.0);
+    }
+    let empty = resampler.step();
+    assert!((0.995..1.0).contains(&empty), "step {} when empty", empty);
+
+    // Out of range levels count as empty or full
+    for _ in 0..400 {
+        resampler.update_fill_level(-3.0);
+    }
+    assert!(resampler.step() >= 0.995);
+}
+
+#[test]
+fn ring_reader_plays_at_the_device_rate() {
+    let ring = AudioRing::new(4096);
+    ring.clone().push_samples(&[0.5; 1000], &[-0.5; 1000]);
+
+    // Twice the emulated rate takes about half as many samples out
+    let mut reader = RingReader::new(ring.clone(), SAMPLE_RATE as f64 * 2.0);
+    let mut output = vec![[0.0; 2]; 1000];
+    reader.fill(&mut output);
+
+    let left = (ring.fill_level() * 4096.0).round() as usize;
+    assert!((495..=505).contains(&left), "{} samples left", left);
+    // After interpolating from silence to the first sample
+    assert!(output[3..].iter().all(|&sample| sample == [0.5, -0.5]));
+}
+
+#[test]
+fn ring_reader_underruns_with_silence() {
+    let ring = AudioRing::new(4096);
+    ring.clone().push_samples(&[0.5; 10], &[0.5; 10]);
+
+    let mut reader = RingReader::new(ring, SAMPLE_RATE as f64);
+    let mut output = vec![[1.0; 2]; 100];
+    reader.fill(&mut output);
+
+    assert!(output[2..10].iter().all(|&sample| sample == [0.5, 0.5]));
+    assert!(output[20..].iter().all(|&sample| sample == [0.0, 0.0]));
+}
+
+#[test]
+fn blocking_producer_keeps_the_step_nominal() {
+    let ring = AudioRing::new(4096);
+    let stop = Arc::new(AtomicBool::new(false));
+    let producer = {
+        let mut ring = ring.clone();
+        let stop = stop.clone();
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                ring.push_samples(&[0.5; 64], &[0.5; 64]);
+            }
+        })
+    };
+
+    let mut reader = RingReader::new(ring.clone(), SAMPLE_RATE as f64);
+    let mut output = vec![[0.0; 2]; 256];
+    for _ in 0..200 {
+        thread::sleep(Duration::from_millis(1));
+        reader.fill(&mut output);
+        assert!(ring.fill_level() <= 0.5);
+    }
+    stop.store(true, Ordering::Relaxed);
+    reader.fill(&mut output);
+    producer.join().unwrap();
+
+    let step = reader.step();
+    assert!((0.999..=1.0).contains(&step), "step {}", step);
+}
+
+#[test]
+fn ring_reader_absorbs_a_fast_device_clock() {
+    let mut ring = AudioRing::new(4096);
+    ring.push_samples(&[0.5; 1024], &[0.5; 1024]);
+
+    // The device plays 801 samples in the time the emulation makes 800
+    let mut reader = RingReader::new(ring.clone(), SAMPLE_RATE as f64);
+    let mut output = vec![[0.0; 2]; 801];
+    for tick in 0..3000 {
+        reader.fill(&mut output);
+        if tick > 0 {
+            assert!(
+                output.iter().all(|&sample| sample == [0.5, 0.5]),
+                "underrun at {}",
+                tick
+            );
+        }
+        ring.push_samples(&[0.5; 800], &[0.5; 800]);
+    }
+
+    // Settles where the step takes exactly 800 samples per 801
+    let level = ring.fill_level();
+    assert!((0.3..0.45).contains(&level), "fill level {}", level);
+    let step = reader.step();
+    assert!((step * 801.0 - 800.0).abs() < 0.5, "step {}", step);
+}
@@ -0,0 +1,95 @@
+use gbemu::cartridge::Cartridge;
+
+const NOW: u64 = 1_700_000_000;
+const RAM_SIZE: usize = 0x2000;
+
+/// An MBC3+TIMER+RAM+BATTERY cartridge with 8 KiB of RAM, its clock at `NOW`.
+fn cartridge() -> Cartridge {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0147] = 0x10;
+    rom[0x0149] = 0x02;
+
+    let mut cartridge = Cartridge::new(rom).unwrap();
+    cartridge.mbc_mut().set_emulated_time(Some(NOW));
+    cartridge
+}
+
+/// A save file with the VBA/BGB footer, its time 32 bits wide unless `wide`.
+fn save(live: [u8; 5], latched: [u8; 5], updated: u64, wide: bool) -> Vec<u8> {
+    let mut save = vec![0x5A; RAM_SIZE];
+    for &register in live.iter().chain(latched.iter()) {
+        save.extend_from_slice(&(register as u32).to_le_bytes());
+    }
+    if wide {
+        save.extend_from_slice(&updated.to_le_bytes());
+    } else {
+        save.extend_from_slice(&(updated as u32).to_le_bytes());
+    }
+    save
+}
+
+/// Latch the clock and read its registers.
+fn clock(cartridge: &mut Cartridge) -> [u8; 5] {
+    cartridge.write(0x0000, 0x0A);
+    cartridge.write(0x6000, 0x00);
+    cartridge.write(0x6000, 0x01);
+
+    let mut registers = [0; 5];
+    for (register, value) in (0x08..).zip(registers.iter_mut()) {
+        cartridge.write(0x4000, register);
+        *value = cartridge.read(0xA000);
+    }
+    registers
+}
+
+#[test]
+fn footer_round_trips() {
+    let data = save([1, 2, 3, 4, 0x01], [5, 6, 7, 8, 0x00], NOW, true);
+
+    let mut cartridge = cartridge();
+    cartridge.load_save_data(&data);
+
+    assert_eq!(cartridge.save_data(), data);
+}
+
+#[test]
+fn short_footer_loads() {
+    let mut cartridge = cartridge();
+    cartridge.load_save_data(&save([1, 2, 3, 4, 0x01], [0; 5], NOW, false));
+
+    assert_eq!(clock(&mut cartridge), [1, 2, 3, 4, 0x01]);
+    // Written back with the 64 bit time
+    let data = cartridge.save_data();
+    assert_eq!(data.len(), RAM_SIZE + 48);
+    assert_eq!(data[RAM_SIZE + 40..], NOW.to_le_bytes());
+}
+
+#[test]
+fn clock_advances_by_the_elapsed_time() {
+    // 1 day, 1 hour, 1 minute and 1 second ago
+    let elapsed = 86400 + 3600 + 60 + 1;
+    let mut cartridge = cartridge();
+    cartridge.load_save_data(&save([0; 5], [0; 5], NOW - elapsed, true));
+
+    assert_eq!(clock(&mut cartridge), [1, 1, 1, 1, 0x00]);
+
+    cartridge.mbc_mut().set_emulated_time(Some(NOW + 30));
+    assert_eq!(clock(&mut cartridge), [31, 1, 1, 1, 0x00]);
+}
+
+#[test]
+fn day_counter_carries_past_511() {
+    let mut cartridge = cartridge();
+    cartridge.load_save_data(&save([59, 59, 23, 0xFF, 0x01], [0; 5], NOW - 1, true));
+
+    // Day 512 wraps to 0 with the carry flag set
+    assert_eq!(clock(&mut cartridge), [0, 0, 0, 0, 0x80]);
+}
+
+#[test]
+fn halted_clock_stands_still() {
+    let mut cartridge = cartridge();
+    cartridge.load_save_data(&save([10, 20, 5, 0x42, 0x40], [0; 5], NOW - 86400, true));
+
+    assert_eq!(clock(&mut cartridge), [10, 20, 5, 0x42, 0x40]);
+}
@@ -0,0 +1,265 @@
+//! Single-step CPU tests driven by the community SM83 JSON test vectors.
+//! Ref https://github.com/SingleStepTests/sm83
+//!
+//! The vectors are not vendored. Point `SM83_TESTS` at the directory holding
+//! the `*.json` files to run them:
+//!
+//! ```sh
+//! SM83_TESTS=path/to/sm83/v1 cargo test --test sm83 -- --nocapture
+//! ```
+//!
+//! Each case loads the initial registers, IME and memory, executes one
+//! instruction and compares the final registers, IME, memory and the bus
+//! activity of every machine cycle. The address and data of a cycle are
+//! only compared for reads and writes, the bus is not modelled on idle
+//! cycles.
+//! Opcodes that panic (still `todo!()`) are reported as unimplemented; any
+//! mismatch in an implemented opcode fails the test.
+
+use gbemu::bus::MemoryBus;
+use gbemu::cpu::Cpu;
+use gbemu::CpuState;
+use serde_json::Value;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+enum Outcome {
+    Passed,
+    Failed(String),
+    Unimplemented,
+}
+
+/// What the CPU did on the bus in a machine cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cycle {
+    Read(u16, u8),
+    Write(u16, u8),
+    Idle,
+}
+
+/// 64 KiB of RAM without any memory mapped devices, as the vectors expect.
+/// Records the bus activity of every machine cycle.
+struct FlatBus {
+    memory: Vec<u8>,
+    cycles: RefCell<Vec<Cycle>>,
+    // The CPU accessed the bus since the last tick
+    accessed: Cell<bool>,
+}
+
+impl FlatBus {
+    fn access(&self, cycle: Cycle) {
+        self.cycles.borrow_mut().push(cycle);
+        self.accessed.set(true);
+    }
+}
+
+impl MemoryBus for FlatBus {
+    fn read_byte(&self, address: u16) -> u8 {
+        let byte = self.memory[address as usize];
+        self.access(Cycle::Read(address, byte));
+        byte
+    }
+
+    fn write_byte(&mut self, address: u16, byte: u8) {
+        self.access(Cycle::Write(address, byte));
+        self.memory[address as usize] = byte
+    }
+
+    fn tick(&mut self, _cycles: usize) {
+        if !self.accessed.replace(false) {
+            self.cycles.get_mut().push(Cycle::Idle);
+        }
+    }
+}
+
+fn flat_bus() -> FlatBus {
+    FlatBus {
+        memory: vec![0; 0x10000],
+        cycles: RefCell::new(Vec::new()),
+        accessed: Cell::new(false),
+    }
+}
+
+fn number(state: &Value, key: &str) -> u16 {
+    state[key]
+        .as_u64()
+        .unwrap_or_else(|| panic!("missing {}", key)) as u16
+}
+
+fn cpu_state(state: &Value) -> CpuState {
+    CpuState {
+        a: number(state, "a") as u8,
+        f: number(state, "f") as u8,
+        b: number(state, "b") as u8,
+        c: number(state, "c") as u8,
+        d: number(state, "d") as u8,
+        e: number(state, "e") as u8,
+        h: number(state, "h") as u8,
+        l: number(state, "l") as u8,
+        sp: number(state, "sp"),
+        pc: number(state, "pc"),
+    }
+}
+
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|pair| {
+            (
+                pair[0].as_u64().unwrap() as u16,
+                pair[1].as_u64().unwrap() as u8,
+            )
+        })
+        .collect()
+}
+
+/// A cycle of `case["cycles"]`: `[address, data, "rwm"]` with `-` for the
+/// pins that are not active, address and data can be null on idle cycles.
+fn cycle(entry: &Value) -> Cycle {
+    let pins = entry[2].as_str().unwrap_or("---");
+    let address = entry[0].as_u64().unwrap_or(0) as u16;
+    let data = entry[1].as_u64().unwrap_or(0) as u8;
+
+    if pins.starts_with('r') {
+        Cycle::Read(address, data)
+    } else if pins.get(1..2) == Some("w") {
+        Cycle::Write(address, data)
+    } else {
+        Cycle::Idle
+    }
+}
+
+fn run_case(case: &Value) -> Outcome {
+    let initial = &case["initial"];
+    let expected = &case["final"];
+    let expected_cycles: Vec<Cycle> = case["cycles"]
+        .as_array()
+        .map_or(Vec::new(), |cycles| cycles.iter().map(cycle).collect());
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut cpu = Cpu::new();
+        let mut bus = flat_bus();
+
+        for (address, byte) in ram(initial) {
+            bus.memory[address as usize] = byte;
+        }
+        cpu.set_state(cpu_state(initial));
+        cpu.set_ime(number(initial, "ime") != 0);
+
+        cpu.step(&mut bus).unwrap();
+
+        let ram: Vec<(u16, u8)> = ram(expected)
+            .into_iter()
+            .map(|(address, _)| (address, bus.memory[address as usize]))
+            .collect();
+
+        (cpu.state(), cpu.ime(), ram, bus.cycles.into_inner())
+    }));
+
+    let (state, ime, ram_after, cycles) = match result {
+        Ok(result) => result,
+        Err(_) => return Outcome::Unimplemented,
+    };
+
+    let mut errors = Vec::new();
+    if state != cpu_state(expected) {
+        errors.push(format!(
+            "registers {:X?}, expected {:X?}",
+            state,
+            cpu_state(expected)
+        ));
+    }
+    if ime != (number(expected, "ime") != 0) {
+        errors.push(format!("IME {}, expected {}", ime, !ime));
+    }
+    if ram_after != ram(expected) {
+        errors.push(format!(
+            "ram {:X?}, expected {:X?}",
+            ram_after,
+            ram(expected)
+        ));
+    }
+    if cycles.len() != expected_cycles.len() {
+        errors.push(format!(
+            "{} machine cycles, expected {}",
+            cycles.len(),
+            expected_cycles.len()
+        ));
+    }
+    for (i, (cycle, expected)) in cycles.iter().zip(&expected_cycles).enumerate() {
+        if cycle != expected {
+            errors.push(format!(
+                "machine cycle {} {:X?}, expected {:X?}",
+                i + 1,
+                cycle,
+                expected
+            ));
+            break;
+        }
+    }
+
+    if errors.is_empty() {
+        Outcome::Passed
+    } else {
+        Outcome::Failed(errors.join("; "))
+    }
+}
+
+#[test]
+fn sm83_single_step() {
+    let dir = match std::env::var("SM83_TESTS") {
+        Ok(dir) => dir,
+        Err(_) => {
+            eprintln!("SM83_TESTS is not set, skipping");
+            return;
+        }
+    };
+
+    let mut files: Vec<_> = std::fs::read_dir(Path::new(&dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("json".as_ref()))
+        .collect();
+    files.sort();
+
+    // Unimplemented opcodes panic by design, keep the output readable
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut failures = Vec::new();
+    println!(
+        "{:<10} {:>7} {:>7} {:>7}",
+        "opcode", "passed", "failed", "todo"
+    );
+
+    for path in files {
+        let cases: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let (mut passed, mut failed, mut unimplemented) = (0, 0, 0);
+
+        for case in cases.as_array().unwrap() {
+            match run_case(case) {
+                Outcome::Passed => passed += 1,
+                Outcome::Unimplemented => unimplemented += 1,
+                Outcome::Failed(reason) => {
+                    if failed == 0 {
+                        failures.push(format!("{}: {}", case["name"], reason));
+                    }
+                    failed += 1;
+                }
+            }
+        }
+
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        println!(
+            "{:<10} {:>7} {:>7} {:>7}",
+            name, passed, failed, unimplemented
+        );
+    }
+
+    panic::set_hook(hook);
+
+    assert!(failures.is_empty(), "mismatches:\n{}", failures.join("\n"));
+}
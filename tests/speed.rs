@@ -0,0 +1,47 @@
+mod common;
+
+use common::rom;
+use gbemu::apu::Apu;
+use gbemu::emulator::{Emulator, CYCLES_PER_FRAME, MAX_SPEED, MIN_SPEED};
+use gbemu::frame_timer::FrameTimer;
+
+/// An emulator spinning on `JR -2` at the entry point.
+fn emulator() -> Emulator {
+    Emulator::from_rom_byte(rom(&[0x18, 0xFE])).unwrap()
+}
+
+#[test]
+fn out_of_range_speeds_are_rejected() {
+    let mut emu = emulator();
+    for speed in [
+        0.0,
+        -1.0,
+        1e-8,
+        f64::NAN,
+        MAX_SPEED * 2.0,
+        f64::NEG_INFINITY,
+    ] {
+        assert!(emu.set_speed(speed).is_err(), "speed {}", speed);
+    }
+    assert_eq!(emu.speed(), 1.0);
+}
+
+#[test]
+fn speeds_in_range_still_run() {
+    let mut emu = emulator();
+    for speed in [MIN_SPEED, MAX_SPEED, f64::INFINITY] {
+        emu.set_speed(speed).unwrap();
+        emu.run_frame().unwrap();
+    }
+}
+
+#[test]
+fn tiny_speeds_do_not_hang_the_apu() {
+    let mut apu = Apu::new();
+    apu.set_speed(1e-12);
+    apu.step(CYCLES_PER_FRAME);
+
+    let mut timer = FrameTimer::new();
+    timer.set_speed(1e-300);
+    timer.set_speed(0.0);
+}
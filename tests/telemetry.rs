@@ -0,0 +1,53 @@
+mod common;
+
+use common::rom;
+use gbemu::emulator::Emulator;
+use gbemu::telemetry::Unimplemented;
+
+// JR -2
+const LOOP: [u8; 2] = [0x18, 0xFE];
+
+#[test]
+fn records_are_kept_per_emulator() {
+    // CB 00 (RLC B) is not implemented
+    let mut touched = Emulator::from_rom_byte(rom(&[0xCB, 0x00, 0x18, 0xFE])).unwrap();
+    touched.set_skip_unimplemented(true);
+    let mut other = Emulator::from_rom_byte(rom(&LOOP)).unwrap();
+
+    touched.step().unwrap();
+    other.step().unwrap();
+
+    let records = touched.telemetry().records();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].0, Unimplemented::CbOpcode(0x00));
+    assert_eq!(records[0].1.count, 1);
+    assert_eq!(records[0].1.first_pc, 0x0100);
+    assert!(other.telemetry().records().is_empty());
+}
+
+#[test]
+fn records_survive_reset() {
+    let mut emu = Emulator::from_rom_byte(rom(&[0xCB, 0x00, 0x18, 0xFE])).unwrap();
+    emu.set_skip_unimplemented(true);
+    let telemetry = emu.telemetry().clone();
+
+    emu.step().unwrap();
+    emu.reset().unwrap();
+    emu.step().unwrap();
+
+    assert_eq!(telemetry.records()[0].1.count, 2);
+}
+
+#[test]
+fn peek_records_nothing() {
+    // LDH A, ($03) reads an unimplemented I/O register
+    let mut emu = Emulator::from_rom_byte(rom(&[0xF0, 0x03, 0x18, 0xFE])).unwrap();
+
+    assert_eq!(emu.peek(0xFF03), 0xFF);
+    assert!(emu.telemetry().records().is_empty());
+
+    emu.step().unwrap();
+    let records = emu.telemetry().records();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].0, Unimplemented::IoRegister(0xFF03));
+}
@@ -0,0 +1,114 @@
+use gbemu::timer::Timer;
+
+const DIV: u16 = 0;
+const TIMA: u16 = 1;
+const TMA: u16 = 2;
+const TAC: u16 = 3;
+
+/// A timer at 262144Hz, TIMA counting every 4 machine cycles.
+fn timer(tima: u8) -> Timer {
+    let mut timer = Timer::default();
+    timer.write(TMA, 0xAB);
+    timer.write(TIMA, tima);
+    timer.write(TAC, 0x05);
+    timer
+}
+
+/// Run `cycles` machine cycles, returning whether any requested the interrupt.
+fn run(timer: &mut Timer, cycles: usize) -> bool {
+    (0..cycles).fold(false, |interrupt, _| timer.tick(4) | interrupt)
+}
+
+#[test]
+fn tima_reads_zero_for_a_cycle_before_the_reload() {
+    let mut timer = timer(0xFF);
+    assert!(!run(&mut timer, 4));
+    assert_eq!(timer.read(TIMA), 0x00);
+
+    assert!(timer.tick(4));
+    assert_eq!(timer.read(TIMA), 0xAB);
+}
+
+#[test]
+fn writing_tima_during_the_delay_cancels_the_reload() {
+    let mut timer = timer(0xFF);
+    run(&mut timer, 4);
+    timer.write(TIMA, 0x42);
+
+    assert!(!timer.tick(4));
+    assert_eq!(timer.read(TIMA), 0x42);
+}
+
+#[test]
+fn writing_tima_on_the_reload_cycle_is_ignored() {
+    let mut timer = timer(0xFF);
+    run(&mut timer, 4);
+    assert!(timer.tick(4));
+
+    timer.write(TIMA, 0x42);
+    assert_eq!(timer.read(TIMA), 0xAB);
+
+    // TMA written on the same cycle goes to TIMA too
+    timer.write(TMA, 0x77);
+    assert_eq!(timer.read(TIMA), 0x77);
+
+    // One cycle later TIMA is writable again
+    timer.tick(4);
+    timer.write(TIMA, 0x42);
+    assert_eq!(timer.read(TIMA), 0x42);
+}
+
+#[test]
+fn resetting_div_while_the_bit_is_set_increments_tima() {
+    let mut timer = timer(0x10);
+    // Bit 3 of the counter is set after 2 machine cycles
+    run(&mut timer, 2);
+    timer.write(DIV, 0x00);
+    assert_eq!(timer.read(TIMA), 0x11);
+    assert_eq!(timer.read(DIV), 0x00);
+
+    // Cleared, nothing falls
+    timer.write(DIV, 0x00);
+    assert_eq!(timer.read(TIMA), 0x11);
+}
+
+#[test]
+fn resetting_div_can_overflow_tima() {
+    let mut timer = timer(0xFF);
+    run(&mut timer, 2);
+    timer.write(DIV, 0x00);
+    assert_eq!(timer.read(TIMA), 0x00);
+
+    assert!(timer.tick(4));
+    assert_eq!(timer.read(TIMA), 0xAB);
+}
+
+#[test]
+fn selecting_a_clear_bit_increments_tima() {
+    let mut timer = timer(0x10);
+    run(&mut timer, 2);
+
+    // Bit 9 is clear
+    timer.write(TAC, 0x04);
+    assert_eq!(timer.read(TIMA), 0x11);
+}
+
+#[test]
+fn disabling_the_timer_while_the_bit_is_set_increments_tima() {
+    let mut timer = timer(0x10);
+    run(&mut timer, 2);
+
+    timer.write(TAC, 0x01);
+    assert_eq!(timer.read(TIMA), 0x11);
+}
+
+#[test]
+fn changing_tac_to_a_set_bit_does_not_increment_tima() {
+    let mut timer = timer(0x10);
+    // Counter at 40, bits 3 and 5 set
+    run(&mut timer, 10);
+    assert_eq!(timer.read(TIMA), 0x12);
+
+    timer.write(TAC, 0x06);
+    assert_eq!(timer.read(TIMA), 0x12);
+}
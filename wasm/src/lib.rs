@@ -0,0 +1,89 @@
+//! gbemu in the browser: a thin wasm-bindgen layer over the core, built
+//! without the `window` feature.
+//!
+//!```text
+//! wasm-pack build --target web wasm
+//! ```
+//! then serve `wasm/` and open `index.html`, see there for the JavaScript
+//! side. The page drives the frames, e.g. from `requestAnimationFrame`.
+
+use gbemu::emulator::Emulator;
+use gbemu::joypad::Button;
+use wasm_bindgen::prelude::*;
+
+/// A Game Boy without a cartridge until [`Gameboy::load_rom`].
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Gameboy {
+    emulator: Option<Emulator>,
+}
+
+#[wasm_bindgen]
+impl Gameboy {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Gameboy {
+        Gameboy::default()
+    }
+
+    /// Power on with the ROM image `rom`. There is no host clock nor file
+    /// system, so it runs in the deterministic mode: the RTC follows the
+    /// emulated time and battery saves are not kept.
+    pub fn load_rom(&mut self, rom: Vec<u8>) -> Result<(), JsValue> {
+        let mut emulator = Emulator::from_rom_byte(rom).map_err(to_js)?;
+        emulator.set_deterministic(true);
+        self.emulator = Some(emulator);
+
+        Ok(())
+    }
+
+    /// Run until the PPU completes a frame, does nothing without a ROM.
+    pub fn run_frame(&mut self) -> Result<(), JsValue> {
+        if let Some(emulator) = &mut self.emulator {
+            emulator.run_frame().map_err(to_js)?;
+        }
+
+        Ok(())
+    }
+
+    /// The screen as RGBA8888, 160x144 row by row, for an `ImageData`.
+    /// Empty without a ROM.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.emulator
+            .as_ref()
+            .map_or_else(Vec::new, |emulator| emulator.framebuffer())
+    }
+
+    /// Press the button `name`: right, left, up, down, a, b, select or start.
+    pub fn key_down(&mut self, name: &str) -> Result<(), JsValue> {
+        self.set_button(name, true)
+    }
+
+    pub fn key_up(&mut self, name: &str) -> Result<(), JsValue> {
+        self.set_button(name, false)
+    }
+}
+
+impl Gameboy {
+    fn set_button(&mut self, name: &str, pressed: bool) -> Result<(), JsValue> {
+        let button = match name {
+            "right" => Button::Right,
+            "left" => Button::Left,
+            "up" => Button::Up,
+            "down" => Button::Down,
+            "a" => Button::A,
+            "b" => Button::B,
+            "select" => Button::Select,
+            "start" => Button::Start,
+            _ => return Err(JsValue::from_str(&format!("unknown button {}", name))),
+        };
+        if let Some(emulator) = &mut self.emulator {
+            emulator.set_button_state(button, pressed);
+        }
+
+        Ok(())
+    }
+}
+
+fn to_js(e: impl std::fmt::Display) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}